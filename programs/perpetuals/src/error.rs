@@ -60,4 +60,56 @@ pub enum PerpetualsError {
     PermissionlessOracleSignerMismatch,
     #[msg("Signed message does not match instruction params")]
     PermissionlessOracleMessageMismatch,
+    #[msg("Invalid collateral custody")]
+    InvalidCollateralCustody,
+    #[msg("Invalid trigger order config")]
+    InvalidTriggerOrder,
+    #[msg("Trigger price has not been reached")]
+    TriggerPriceNotReached,
+    #[msg("Invalid limit order config")]
+    InvalidLimitOrder,
+    #[msg("Limit price has not been reached")]
+    LimitPriceNotReached,
+    #[msg("Custody solvency is above the auto-deleveraging threshold")]
+    AutoDeleverageNotAllowed,
+    #[msg("Position is not the highest ranked for auto-deleveraging")]
+    InvalidAutoDeleverageRank,
+    #[msg("Invalid position request config")]
+    InvalidPositionRequest,
+    #[msg("Position request can't be executed before an oracle update or after expiry")]
+    PositionRequestNotExecutable,
+    #[msg("Position request can only be cancelled after expiry")]
+    PositionRequestNotExpired,
+    #[msg("Invalid delegate authority config")]
+    InvalidDelegateAuthority,
+    #[msg("Signer is not authorized to act on behalf of the owner")]
+    UnauthorizedDelegate,
+    #[msg("Position token account doesn't hold the position token")]
+    InvalidPositionTokenAccount,
+    #[msg("Token account isn't owned by the position holder")]
+    InvalidTokenAccountOwner,
+    #[msg("Instruction is not supported for tokenized positions")]
+    TokenizedPosition,
+    #[msg("Invalid margin account")]
+    InvalidMarginAccount,
+    #[msg("Margin account has no free collateral or position slots")]
+    MarginAccountFull,
+    #[msg("Insufficient margin account collateral or equity")]
+    InsufficientMargin,
+    #[msg("Instruction is not supported for cross margin positions")]
+    CrossMarginPosition,
+    #[msg("Liquidity cooldown has not passed since the last deposit")]
+    LiquidityCooldown,
+    #[msg("Invalid withdrawal request")]
+    InvalidWithdrawalRequest,
+    #[msg("Withdrawal request is not within its execution window")]
+    WithdrawalRequestNotExecutable,
+    #[msg("Order or request was not created after the position was opened")]
+    StalePositionOrder,
+    #[msg("Delegate authority rent deposit is too low")]
+    InsufficientRentDeposit,
+    #[msg("Position has pending trigger orders or requests")]
+    PendingPositionOrders,
+    #[msg("Withdrawal request can't be cancelled within its execution window")]
+    WithdrawalRequestNotCancellable,
 }
@@ -1,44 +1,144 @@
-use anchor_lang::prelude::*;
+use {
+    crate::state::{position::Side, trigger_order::TriggerOrderType},
+    anchor_lang::prelude::*,
+};
 
 #[event]
 pub struct AddCollateral {
     // Common Position fields
     pub collateral_amount: u64,
+    pub collateral_custody: Pubkey,
     pub custody: Pubkey,
     pub owner: Pubkey,
     pub pool: Pubkey,
     pub price: u64,
+    pub side: Side,
     pub size_usd: u64,
     pub time: i64,
     pub transfer_amount: u64,
 }
 
+#[event]
+pub struct AutoDeleverage {
+    // Common Position fields
+    pub collateral_amount: u64,
+    pub collateral_custody: Pubkey,
+    pub custody: Pubkey,
+    pub owner: Pubkey,
+    pub pool: Pubkey,
+    pub price: u64,
+    pub side: Side,
+    pub size_usd: u64,
+    pub time: i64,
+    // Common with Close position
+    pub loss_usd: u64,
+    pub profit_usd: u64,
+    pub transfer_amount: u64,
+    // Unique fields
+    pub adl_score: u64,
+    pub signer: Pubkey,
+    pub size_delta_usd: u64,
+    pub solvency: u64,
+}
+
 #[event]
 pub struct ClosePosition {
     // Common Position fields
     pub collateral_amount: u64,
+    pub collateral_custody: Pubkey,
+    pub custody: Pubkey,
+    pub owner: Pubkey,
+    pub pool: Pubkey,
+    pub price: u64,
+    pub side: Side,
+    pub size_usd: u64,
+    pub time: i64,
+    // Unique fields
+    pub fee_amount: u64,
+    pub loss_usd: u64,
+    pub profit_usd: u64,
+    pub protocol_fee: u64,
+    pub transfer_amount: u64,
+}
+
+#[event]
+pub struct DecreasePosition {
+    // Common Position fields
+    pub collateral_amount: u64,
+    pub collateral_custody: Pubkey,
     pub custody: Pubkey,
     pub owner: Pubkey,
     pub pool: Pubkey,
     pub price: u64,
+    pub side: Side,
     pub size_usd: u64,
     pub time: i64,
+    // Common with Close position
+    pub fee_amount: u64,
+    pub loss_usd: u64,
+    pub profit_usd: u64,
+    pub protocol_fee: u64,
+    pub transfer_amount: u64,
     // Unique fields
+    pub size_delta_usd: u64,
+}
+
+#[event]
+pub struct ExecuteTriggerOrder {
+    // Common Position fields
+    pub collateral_amount: u64,
+    pub collateral_custody: Pubkey,
+    pub custody: Pubkey,
+    pub owner: Pubkey,
+    pub pool: Pubkey,
+    pub price: u64,
+    pub side: Side,
+    pub size_usd: u64,
+    pub time: i64,
+    // Common with Close position
     pub fee_amount: u64,
     pub loss_usd: u64,
     pub profit_usd: u64,
     pub protocol_fee: u64,
     pub transfer_amount: u64,
+    // Unique fields
+    pub keeper: Pubkey,
+    pub order_type: TriggerOrderType,
+    pub reward_amount: u64,
+    pub size_delta_usd: u64,
+    pub trigger_price: u64,
+}
+
+#[event]
+pub struct IncreasePosition {
+    // Common Position fields
+    pub collateral_amount: u64,
+    pub collateral_custody: Pubkey,
+    pub custody: Pubkey,
+    pub owner: Pubkey,
+    pub pool: Pubkey,
+    pub price: u64,
+    pub side: Side,
+    pub size_usd: u64,
+    pub time: i64,
+    pub transfer_amount: u64,
+    // Unique fields
+    pub entry_price: u64,
+    pub fee_amount: u64,
+    pub interest_usd: u64,
+    pub size_delta_usd: u64,
 }
 
 #[event]
 pub struct LiquidatePosition {
     // Common Position fields
     pub collateral_amount: u64,
+    pub collateral_custody: Pubkey,
     pub custody: Pubkey,
     pub owner: Pubkey,
     pub pool: Pubkey,
     pub price: u64,
+    pub side: Side,
     pub size_usd: u64,
     pub time: i64,
     // Common with Close position
@@ -50,16 +150,19 @@ pub struct LiquidatePosition {
     // Unique fields
     pub signer: Pubkey,
     pub reward_amount: u64,
+    pub size_delta_usd: u64,
 }
 
 #[event]
 pub struct OpenPosition {
     // Common Position fields
     pub collateral_amount: u64,
+    pub collateral_custody: Pubkey,
     pub custody: Pubkey,
     pub owner: Pubkey,
     pub pool: Pubkey,
     pub price: u64,
+    pub side: Side,
     pub size_usd: u64,
     pub time: i64,
     pub transfer_amount: u64,
@@ -73,11 +176,67 @@ pub struct OpenPosition {
 pub struct RemoveCollateral {
     // Common Position fields
     pub collateral_amount: u64,
+    pub collateral_custody: Pubkey,
     pub custody: Pubkey,
     pub owner: Pubkey,
     pub pool: Pubkey,
     pub price: u64,
+    pub side: Side,
     pub size_usd: u64,
     pub time: i64,
     pub transfer_amount: u64,
 }
+
+#[event]
+pub struct TransferPosition {
+    // Common Position fields
+    pub collateral_amount: u64,
+    pub collateral_custody: Pubkey,
+    pub custody: Pubkey,
+    pub owner: Pubkey,
+    pub pool: Pubkey,
+    pub price: u64,
+    pub side: Side,
+    pub size_usd: u64,
+    pub time: i64,
+    // Unique fields
+    pub index: u64,
+    pub new_index: u64,
+    pub new_owner: Pubkey,
+}
+
+#[event]
+pub struct TokenizePosition {
+    // Common Position fields
+    pub collateral_amount: u64,
+    pub collateral_custody: Pubkey,
+    pub custody: Pubkey,
+    pub owner: Pubkey,
+    pub pool: Pubkey,
+    pub price: u64,
+    pub side: Side,
+    pub size_usd: u64,
+    pub time: i64,
+    // Unique fields
+    pub index: u64,
+    pub token_mint: Pubkey,
+}
+
+#[event]
+pub struct RedeemPosition {
+    // Common Position fields
+    pub collateral_amount: u64,
+    pub collateral_custody: Pubkey,
+    pub custody: Pubkey,
+    pub owner: Pubkey,
+    pub pool: Pubkey,
+    pub price: u64,
+    pub side: Side,
+    pub size_usd: u64,
+    pub time: i64,
+    // Unique fields
+    pub index: u64,
+    pub new_index: u64,
+    pub new_owner: Pubkey,
+    pub token_mint: Pubkey,
+}
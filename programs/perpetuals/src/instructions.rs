@@ -10,13 +10,32 @@ pub mod set_admin_signers;
 pub mod set_custody_config;
 pub mod set_custom_oracle_price;
 pub mod set_permissions;
+pub mod set_pool_config;
+pub mod upgrade_custody;
+pub mod upgrade_perpetuals;
+pub mod upgrade_pool;
 pub mod withdraw_fees;
+pub mod withdraw_insurance_fund;
 pub mod withdraw_sol_fees;
 
 // public instructions
 pub mod add_collateral;
 pub mod add_liquidity;
+pub mod add_liquidity_basket;
+pub mod auto_deleverage;
+pub mod cancel_limit_order;
+pub mod cancel_position_request;
+pub mod cancel_trigger_order;
+pub mod cancel_withdrawal_request;
 pub mod close_position;
+pub mod decrease_position;
+pub mod deposit_margin_collateral;
+pub mod execute_close_position_request;
+pub mod execute_limit_order;
+pub mod execute_open_position_request;
+pub mod execute_trigger_order;
+pub mod execute_withdrawal_request;
+pub mod execute_withdrawal_request_proportional;
 pub mod get_add_liquidity_amount_and_fee;
 pub mod get_assets_under_management;
 pub mod get_entry_price_and_fee;
@@ -26,22 +45,48 @@ pub mod get_lp_token_price;
 pub mod get_oracle_price;
 pub mod get_position;
 pub mod get_remove_liquidity_amount_and_fee;
+pub mod get_swap_amount_and_fee;
+pub mod increase_position;
 pub mod liquidate;
 pub mod open_position;
+pub mod place_limit_order;
+pub mod place_trigger_order;
+pub mod redeem_position;
 pub mod remove_collateral;
 pub mod remove_liquidity;
+pub mod remove_liquidity_proportional;
+pub mod request_close_position;
+pub mod request_open_position;
+pub mod request_withdrawal;
 pub mod set_custom_oracle_price_permissionless;
+pub mod set_delegate_authority;
+pub mod swap;
+pub mod tokenize_position;
+pub mod transfer_position;
 pub mod update_pool_aum;
+pub mod upgrade_position;
+pub mod withdraw_margin_collateral;
 
 // bring everything in scope
 pub use {
-    add_collateral::*, add_custody::*, add_custody_init::*, add_liquidity::*, add_pool::*,
-    close_position::*, force_close::*, get_add_liquidity_amount_and_fee::*,
-    get_assets_under_management::*, get_entry_price_and_fee::*, get_exit_price_and_fee::*,
-    get_liquidation_price::*, get_lp_token_price::*, get_oracle_price::*, get_position::*,
-    get_remove_liquidity_amount_and_fee::*, init::*, liquidate::*, open_position::*,
-    remove_collateral::*, remove_custody::*, remove_liquidity::*, remove_pool::*,
-    set_admin_signers::*, set_custody_config::*, set_custom_oracle_price::*,
-    set_custom_oracle_price_permissionless::*, set_permissions::*, update_pool_aum::*,
-    withdraw_fees::*, withdraw_sol_fees::*,
+    add_collateral::*, add_custody::*, add_custody_init::*, add_liquidity::*,
+    add_liquidity_basket::*, add_pool::*, auto_deleverage::*, cancel_limit_order::*,
+    cancel_position_request::*, cancel_trigger_order::*, cancel_withdrawal_request::*,
+    close_position::*, decrease_position::*, deposit_margin_collateral::*,
+    execute_close_position_request::*, execute_limit_order::*, execute_open_position_request::*,
+    execute_trigger_order::*, execute_withdrawal_request::*,
+    execute_withdrawal_request_proportional::*, force_close::*,
+    get_add_liquidity_amount_and_fee::*, get_assets_under_management::*,
+    get_entry_price_and_fee::*, get_exit_price_and_fee::*, get_liquidation_price::*,
+    get_lp_token_price::*, get_oracle_price::*, get_position::*,
+    get_remove_liquidity_amount_and_fee::*, get_swap_amount_and_fee::*, increase_position::*,
+    init::*, liquidate::*, open_position::*, place_limit_order::*, place_trigger_order::*,
+    redeem_position::*, remove_collateral::*, remove_custody::*, remove_liquidity::*,
+    remove_liquidity_proportional::*, remove_pool::*, request_close_position::*,
+    request_open_position::*, request_withdrawal::*, set_admin_signers::*, set_custody_config::*,
+    set_custom_oracle_price::*, set_custom_oracle_price_permissionless::*,
+    set_delegate_authority::*, set_permissions::*, set_pool_config::*, swap::*,
+    tokenize_position::*, transfer_position::*, update_pool_aum::*, upgrade_custody::*,
+    upgrade_perpetuals::*, upgrade_pool::*, upgrade_position::*, withdraw_fees::*,
+    withdraw_insurance_fund::*, withdraw_margin_collateral::*, withdraw_sol_fees::*,
 };
@@ -6,10 +6,11 @@ use {
         events, math,
         state::{
             custody::Custody,
+            delegate_authority::{DelegateAction, DelegateAuthority},
             oracle::OraclePrice,
             perpetuals::Perpetuals,
             pool::Pool,
-            position::{Position, Side},
+            position::Position,
         },
     },
     anchor_lang::prelude::*,
@@ -21,12 +22,23 @@ use {
 #[instruction(params: AddCollateralParams)]
 pub struct AddCollateral<'info> {
     #[account(mut)]
-    pub owner: Signer<'info>,
+    pub authority: Signer<'info>,
+
+    #[account(mut)]
+    pub owner: SystemAccount<'info>,
+
+    #[account(
+        seeds = [b"delegate_authority", owner.key().as_ref()],
+        bump = delegate_authority.bump
+    )]
+    pub delegate_authority: Option<Box<Account<'info, DelegateAuthority>>>,
+
+    // holder's position token account, required for tokenized positions
+    pub position_token_account: Option<Box<Account<'info, TokenAccount>>>,
 
     #[account(
         mut,
-        constraint = funding_account.mint == custody.mint,
-        has_one = owner
+        constraint = funding_account.mint == collateral_custody.mint
     )]
     pub funding_account: Box<Account<'info, TokenAccount>>,
 
@@ -57,7 +69,8 @@ pub struct AddCollateral<'info> {
             owner.key().as_ref(),
             pool.key().as_ref(),
             custody.key().as_ref(),
-            &[Side::Long as u8]
+            &[position.side as u8],
+            &position.index.to_le_bytes()
         ],
         bump = position.bump
     )]
@@ -69,22 +82,34 @@ pub struct AddCollateral<'info> {
     )]
     pub custody: Box<Account<'info, Custody>>,
 
-    /// CHECK: oracle account for the collateral token
+    /// CHECK: oracle account for the position token
     #[account(
         constraint = custody_oracle_account.key() == custody.oracle.oracle_account
     )]
     pub custody_oracle_account: UncheckedAccount<'info>,
 
+    #[account(
+        mut,
+        constraint = position.collateral_custody == collateral_custody.key()
+    )]
+    pub collateral_custody: Box<Account<'info, Custody>>,
+
+    /// CHECK: oracle account for the collateral token
+    #[account(
+        constraint = collateral_custody_oracle_account.key() == collateral_custody.oracle.oracle_account
+    )]
+    pub collateral_custody_oracle_account: UncheckedAccount<'info>,
+
     #[account(
         mut,
         seeds = [
             b"custody_token_account",
             pool.key().as_ref(),
-            custody.mint.as_ref()
+            collateral_custody.mint.as_ref()
         ],
-        bump = custody.token_account_bump
+        bump = collateral_custody.token_account_bump
     )]
-    pub custody_token_account: Box<Account<'info, TokenAccount>>,
+    pub collateral_custody_token_account: Box<Account<'info, TokenAccount>>,
 
     token_program: Program<'info, Token>,
 }
@@ -98,6 +123,28 @@ pub fn add_collateral<'info>(
     ctx: Context<'_, '_, '_, 'info, AddCollateral<'info>>,
     params: &AddCollateralParams,
 ) -> Result<()> {
+    // check permissions
+    msg!("Check permissions");
+    let holder = ctx
+        .accounts
+        .position
+        .get_holder(ctx.accounts.position_token_account.as_deref())?;
+    DelegateAuthority::validate_authority(
+        &ctx.accounts.authority.key(),
+        &holder,
+        // delegates are granted by the position account owner, not the token holder
+        ctx.accounts
+            .delegate_authority
+            .as_deref()
+            .filter(|_| !ctx.accounts.position.is_tokenized()),
+        DelegateAction::CollateralChange,
+    )?;
+    require_keys_eq!(
+        ctx.accounts.funding_account.owner,
+        holder,
+        PerpetualsError::InvalidTokenAccountOwner
+    );
+
     // validate inputs
     msg!("Validate inputs");
     if params.collateral == 0 {
@@ -105,6 +152,7 @@ pub fn add_collateral<'info>(
     }
     let perpetuals = ctx.accounts.perpetuals.as_mut();
     let custody = ctx.accounts.custody.as_mut();
+    let collateral_custody = ctx.accounts.collateral_custody.as_mut();
     let position = ctx.accounts.position.as_mut();
     let pool = ctx.accounts.pool.as_mut();
 
@@ -117,8 +165,17 @@ pub fn add_collateral<'info>(
         curtime,
     )?;
 
+    let collateral_token_price = OraclePrice::new_from_oracle(
+        &ctx.accounts
+            .collateral_custody_oracle_account
+            .to_account_info(),
+        &collateral_custody.oracle,
+        curtime,
+    )?;
+
     // compute amount to transfer
-    let collateral_usd = token_price.get_asset_amount_usd(params.collateral, custody.decimals)?;
+    let collateral_usd = collateral_token_price
+        .get_asset_amount_usd(params.collateral, collateral_custody.decimals)?;
     msg!("Amount in: {}", params.collateral);
     msg!("Collateral added in USD: {}", collateral_usd);
 
@@ -131,7 +188,15 @@ pub fn add_collateral<'info>(
     // check position risk
     msg!("Check position risks");
     require!(
-        pool.check_leverage(position, &token_price, custody, curtime, true)?,
+        pool.check_leverage(
+            position,
+            &token_price,
+            custody,
+            &collateral_token_price,
+            collateral_custody,
+            curtime,
+            true
+        )?,
         PerpetualsError::MaxLeverage
     );
 
@@ -139,24 +204,29 @@ pub fn add_collateral<'info>(
     msg!("Transfer tokens");
     perpetuals.transfer_tokens_from_user(
         ctx.accounts.funding_account.to_account_info(),
-        ctx.accounts.custody_token_account.to_account_info(),
-        ctx.accounts.owner.to_account_info(),
+        ctx.accounts
+            .collateral_custody_token_account
+            .to_account_info(),
+        ctx.accounts.authority.to_account_info(),
         ctx.accounts.token_program.to_account_info(),
         params.collateral,
     )?;
 
     // update custody stats
     msg!("Update custody stats");
-    custody.assets.collateral = math::checked_add(custody.assets.collateral, params.collateral)?;
+    collateral_custody.assets.collateral =
+        math::checked_add(collateral_custody.assets.collateral, params.collateral)?;
 
     emit!(events::AddCollateral {
         collateral_amount: position.collateral_amount,
+        collateral_custody: position.collateral_custody,
         custody: position.custody,
         owner: position.owner,
         pool: position.pool,
         price: token_price
             .scale_to_exponent(-(Perpetuals::PRICE_DECIMALS as i32))?
             .price,
+        side: position.side,
         size_usd: position.size_usd,
         time: curtime,
         transfer_amount: params.collateral,
@@ -5,6 +5,7 @@ use {
         error::PerpetualsError,
         state::{
             custody::{BorrowRateParams, Custody, Fees, PricingParams},
+            insurance_fund::InsuranceFund,
             multisig::{AdminInstruction, Multisig},
             oracle::OracleParams,
             perpetuals::{Permissions, Perpetuals},
@@ -42,7 +43,7 @@ pub struct AddCustody<'info> {
 
     #[account(
         mut,
-        realloc = Pool::LEN + (pool.custodies.len() + 1) * std::mem::size_of::<Pubkey>(),
+        realloc = Pool::LEN + (pool.custodies.len() + 1) * (std::mem::size_of::<Pubkey>() + std::mem::size_of::<u64>()),
         realloc::payer = admin,
         realloc::zero = false,
         seeds = [b"pool", pool.name.as_bytes()],
@@ -50,6 +51,16 @@ pub struct AddCustody<'info> {
     )]
     pub pool: Box<Account<'info, Pool>>,
 
+    #[account(
+        mut,
+        realloc = InsuranceFund::LEN + (pool.custodies.len() + 1) * std::mem::size_of::<u64>(),
+        realloc::payer = admin,
+        realloc::zero = false,
+        seeds = [b"insurance_fund", pool.key().as_ref()],
+        bump = insurance_fund.bump
+    )]
+    pub insurance_fund: Box<Account<'info, InsuranceFund>>,
+
     #[account(
         mut,
         seeds = [
@@ -85,6 +96,7 @@ pub struct AddCustody<'info> {
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone)]
 pub struct AddCustodyParams {
+    pub is_stable: bool,
     pub oracle: OracleParams,
     pub pricing: PricingParams,
     pub permissions: Permissions,
@@ -120,6 +132,9 @@ pub fn add_custody<'info>(
 
     // update pool data
     pool.custodies.push(ctx.accounts.custody.key());
+    ctx.accounts.insurance_fund.balances.push(0);
+    // new custodies start without a target weight, set with set_pool_config
+    pool.target_weights.push(0);
     if !pool.validate() {
         return err!(PerpetualsError::InvalidPoolConfig);
     }
@@ -130,6 +145,7 @@ pub fn add_custody<'info>(
     custody.mint = ctx.accounts.custody_token_mint.key();
     custody.token_account = ctx.accounts.custody_token_account.key();
     custody.decimals = ctx.accounts.custody_token_mint.decimals;
+    custody.is_stable = params.is_stable;
     custody.oracle = params.oracle;
     custody.pricing = params.pricing;
     custody.permissions = params.permissions;
@@ -34,7 +34,7 @@ pub struct AddCustodyInit<'info> {
 
     #[account(
         mut,
-        realloc = Pool::LEN + (pool.custodies.len() + 1) * std::mem::size_of::<Pubkey>(),
+        realloc = Pool::LEN + (pool.custodies.len() + 1) * (std::mem::size_of::<Pubkey>() + std::mem::size_of::<u64>()),
         realloc::payer = admin,
         realloc::zero = false,
         seeds = [b"pool", pool.name.as_bytes()],
@@ -4,7 +4,10 @@ use {
     crate::{
         error::PerpetualsError,
         math,
-        state::{custody::Custody, oracle::OraclePrice, perpetuals::Perpetuals, pool::Pool},
+        state::{
+            custody::Custody, lp_account::LpAccount, oracle::OraclePrice, perpetuals::Perpetuals,
+            pool::Pool,
+        },
     },
     anchor_lang::prelude::*,
     anchor_spl::token::{Mint, Token, TokenAccount},
@@ -86,6 +89,16 @@ pub struct AddLiquidity<'info> {
     )]
     pub lp_token_mint: Box<Account<'info, Mint>>,
 
+    #[account(
+        init_if_needed,
+        payer = owner,
+        space = LpAccount::LEN,
+        seeds = [b"lp_account", owner.key().as_ref(), pool.key().as_ref()],
+        bump
+    )]
+    pub lp_account: Box<Account<'info, LpAccount>>,
+
+    system_program: Program<'info, System>,
     token_program: Program<'info, Token>,
     // remaining accounts:
     //   pool.tokens.len() custody accounts (read-only, unsigned)
@@ -214,5 +227,12 @@ pub fn add_liquidity<'info>(
     custody.exit(&crate::ID)?;
     pool.aum_usd = pool.get_assets_under_management_usd(ctx.remaining_accounts, curtime)?;
 
+    // record deposit, the liquidity cooldown restarts with every deposit
+    let lp_account = ctx.accounts.lp_account.as_mut();
+    lp_account.owner = ctx.accounts.owner.key();
+    lp_account.pool = ctx.accounts.pool.key();
+    lp_account.last_deposit_time = curtime;
+    lp_account.bump = ctx.bumps.lp_account;
+
     Ok(())
 }
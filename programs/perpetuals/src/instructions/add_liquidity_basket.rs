@@ -0,0 +1,250 @@
+//! AddLiquidityBasket instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError,
+        math,
+        state::{
+            custody::Custody, lp_account::LpAccount, oracle::OraclePrice, perpetuals::Perpetuals,
+            pool::Pool,
+        },
+    },
+    anchor_lang::prelude::*,
+    anchor_spl::token::{Mint, Token, TokenAccount},
+    solana_program::program_error::ProgramError,
+};
+
+#[derive(Accounts)]
+#[instruction(params: AddLiquidityBasketParams)]
+pub struct AddLiquidityBasket<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint = lp_token_account.mint == lp_token_mint.key(),
+        has_one = owner
+    )]
+    pub lp_token_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: empty PDA, authority for token accounts
+    #[account(
+        seeds = [b"transfer_authority"],
+        bump = perpetuals.transfer_authority_bump
+    )]
+    pub transfer_authority: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        mut,
+        seeds = [b"pool", pool.name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        seeds = [b"lp_token_mint", pool.key().as_ref()],
+        bump = pool.lp_token_bump
+    )]
+    pub lp_token_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        init_if_needed,
+        payer = owner,
+        space = LpAccount::LEN,
+        seeds = [b"lp_account", owner.key().as_ref(), pool.key().as_ref()],
+        bump
+    )]
+    pub lp_account: Box<Account<'info, LpAccount>>,
+
+    system_program: Program<'info, System>,
+    token_program: Program<'info, Token>,
+    // remaining accounts:
+    //   pool.tokens.len() custody accounts (writable, unsigned)
+    //   pool.tokens.len() custody oracles (read-only, unsigned)
+    //   pool.tokens.len() custody token accounts (writable, unsigned)
+    //   pool.tokens.len() funding token accounts (writable, unsigned)
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct AddLiquidityBasketParams {
+    // one amount per pool custody, in pool order, zero to skip a token
+    pub amounts_in: Vec<u64>,
+    pub min_lp_amount_out: u64,
+}
+
+pub fn add_liquidity_basket<'info>(
+    ctx: Context<'_, '_, 'info, 'info, AddLiquidityBasket<'info>>,
+    params: &AddLiquidityBasketParams,
+) -> Result<()> {
+    // check permissions
+    msg!("Check permissions");
+    let perpetuals = ctx.accounts.perpetuals.as_ref();
+    require!(
+        perpetuals.permissions.allow_add_liquidity,
+        PerpetualsError::InstructionNotAllowed
+    );
+
+    // validate inputs
+    msg!("Validate inputs");
+    let pool = ctx.accounts.pool.as_mut();
+    let custodies_len = pool.custodies.len();
+    if params.amounts_in.len() != custodies_len || params.amounts_in.iter().all(|&x| x == 0) {
+        return Err(ProgramError::InvalidArgument.into());
+    }
+    if ctx.remaining_accounts.len() < custodies_len * 4 {
+        return Err(ProgramError::NotEnoughAccountKeys.into());
+    }
+
+    // load and price every custody once
+    let curtime = perpetuals.get_time()?;
+    let mut custodies = Vec::with_capacity(custodies_len);
+    let mut token_prices = Vec::with_capacity(custodies_len);
+    let mut pool_amount_usd: u128 = 0;
+    for (idx, &custody_key) in pool.custodies.iter().enumerate() {
+        let custody_info = &ctx.remaining_accounts[idx];
+        let oracle_info = &ctx.remaining_accounts[custodies_len + idx];
+
+        require_keys_eq!(custody_info.key(), custody_key);
+        let custody = Account::<Custody>::try_from(custody_info)?;
+        require_keys_eq!(oracle_info.key(), custody.oracle.oracle_account);
+
+        let token_price = OraclePrice::new_from_oracle(oracle_info, &custody.oracle, curtime)?;
+
+        pool_amount_usd =
+            pool.add_custody_amount_usd(pool_amount_usd, &custody, &token_price, curtime)?;
+
+        custodies.push(custody);
+        token_prices.push(token_price);
+    }
+
+    // fees depend on the custody shares of the pool value before the deposit
+    msg!("Compute assets under management");
+    pool.aum_usd = pool_amount_usd;
+
+    let mut deposit_amount_usd: u64 = 0;
+    let mut no_fee_amount_usd: u64 = 0;
+    for (idx, custody) in custodies.iter_mut().enumerate() {
+        let amount_in = params.amounts_in[idx];
+        if amount_in == 0 {
+            continue;
+        }
+        require!(
+            custody.permissions.allow_add_liquidity,
+            PerpetualsError::InstructionNotAllowed
+        );
+
+        let custody_token_account_info = &ctx.remaining_accounts[custodies_len * 2 + idx];
+        let funding_account_info = &ctx.remaining_accounts[custodies_len * 3 + idx];
+        require_keys_eq!(custody_token_account_info.key(), custody.token_account);
+
+        let funding_account = Account::<TokenAccount>::try_from(funding_account_info)?;
+        require_keys_eq!(funding_account.mint, custody.mint);
+        require_keys_eq!(
+            funding_account.owner,
+            ctx.accounts.owner.key(),
+            PerpetualsError::InvalidTokenAccountOwner
+        );
+
+        // calculate fee
+        let token_price = &token_prices[idx];
+        let fee_amount = pool.get_add_liquidity_fee(idx, amount_in, custody, token_price)?;
+        msg!("Collected fee: {}", fee_amount);
+
+        let protocol_fee = Pool::get_fee_amount(custody.fees.protocol_share, fee_amount)?;
+        let deposit_amount = math::checked_sub(amount_in, protocol_fee)?;
+        let no_fee_amount = math::checked_sub(amount_in, fee_amount)?;
+
+        // transfer tokens
+        msg!("Transfer tokens");
+        perpetuals.transfer_tokens_from_user(
+            funding_account_info.clone(),
+            custody_token_account_info.clone(),
+            ctx.accounts.owner.to_account_info(),
+            ctx.accounts.token_program.to_account_info(),
+            amount_in,
+        )?;
+
+        no_fee_amount_usd = math::checked_add(
+            no_fee_amount_usd,
+            token_price.get_asset_amount_usd(no_fee_amount, custody.decimals)?,
+        )?;
+        deposit_amount_usd = math::checked_add(
+            deposit_amount_usd,
+            token_price.get_asset_amount_usd(deposit_amount, custody.decimals)?,
+        )?;
+
+        // update custody stats
+        custody.collected_fees.add_liquidity_usd = custody
+            .collected_fees
+            .add_liquidity_usd
+            .wrapping_add(token_price.get_asset_amount_usd(fee_amount, custody.decimals)?);
+
+        custody.volume_stats.add_liquidity_usd = custody
+            .volume_stats
+            .add_liquidity_usd
+            .wrapping_add(token_price.get_asset_amount_usd(amount_in, custody.decimals)?);
+
+        custody.assets.protocol_fees =
+            math::checked_add(custody.assets.protocol_fees, protocol_fee)?;
+
+        custody.assets.owned = math::checked_add(custody.assets.owned, deposit_amount)?;
+
+        custody.update_borrow_rate(curtime)?;
+
+        custody.exit(&crate::ID)?;
+    }
+
+    // compute amount of lp tokens to mint for the combined value
+    require_gte!(
+        no_fee_amount_usd,
+        1u64,
+        PerpetualsError::InsufficientAmountReturned
+    );
+
+    let lp_amount = if pool_amount_usd == 0 {
+        no_fee_amount_usd
+    } else {
+        math::checked_as_u64(math::checked_div(
+            math::checked_mul(
+                no_fee_amount_usd as u128,
+                ctx.accounts.lp_token_mint.supply as u128,
+            )?,
+            pool_amount_usd,
+        )?)?
+    };
+    msg!("LP tokens to mint: {}", lp_amount);
+
+    require!(
+        lp_amount >= params.min_lp_amount_out,
+        PerpetualsError::MaxPriceSlippage
+    );
+
+    // mint lp tokens
+    perpetuals.mint_tokens(
+        ctx.accounts.lp_token_mint.to_account_info(),
+        ctx.accounts.lp_token_account.to_account_info(),
+        ctx.accounts.transfer_authority.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        lp_amount,
+    )?;
+
+    // update pool stats, the deposits only add to the owned amounts
+    msg!("Update pool stats");
+    pool.aum_usd = math::checked_add(pool_amount_usd, deposit_amount_usd as u128)?;
+
+    // record deposit, the liquidity cooldown restarts with every deposit
+    let lp_account = ctx.accounts.lp_account.as_mut();
+    lp_account.owner = ctx.accounts.owner.key();
+    lp_account.pool = ctx.accounts.pool.key();
+    lp_account.last_deposit_time = curtime;
+    lp_account.bump = ctx.bumps.lp_account;
+
+    Ok(())
+}
@@ -4,6 +4,7 @@ use {
     crate::{
         error::PerpetualsError,
         state::{
+            insurance_fund::InsuranceFund,
             multisig::{AdminInstruction, Multisig},
             perpetuals::Perpetuals,
             pool::Pool,
@@ -68,6 +69,15 @@ pub struct AddPool<'info> {
     )]
     pub lp_token_mint: Box<Account<'info, Mint>>,
 
+    #[account(
+        init_if_needed,
+        payer = admin,
+        space = InsuranceFund::LEN,
+        seeds = [b"insurance_fund", pool.key().as_ref()],
+        bump
+    )]
+    pub insurance_fund: Box<Account<'info, InsuranceFund>>,
+
     system_program: Program<'info, System>,
     token_program: Program<'info, Token>,
     rent: Sysvar<'info, Rent>,
@@ -76,6 +86,9 @@ pub struct AddPool<'info> {
 #[derive(AnchorSerialize, AnchorDeserialize)]
 pub struct AddPoolParams {
     pub name: String,
+    pub lp_cooldown_sec: i64,
+    pub lp_withdrawal_window_sec: i64,
+    pub lp_expired_cancel_fee: u64,
 }
 
 pub fn add_pool<'info>(
@@ -114,9 +127,16 @@ pub fn add_pool<'info>(
     msg!("Record pool: {}", params.name);
     pool.inception_time = perpetuals.get_time()?;
     pool.name = params.name.clone();
+    pool.lp_cooldown_sec = params.lp_cooldown_sec;
+    pool.lp_withdrawal_window_sec = params.lp_withdrawal_window_sec;
+    pool.lp_expired_cancel_fee = params.lp_expired_cancel_fee;
     pool.bump = ctx.bumps.pool;
     pool.lp_token_bump = ctx.bumps.lp_token_mint;
 
+    let insurance_fund = ctx.accounts.insurance_fund.as_mut();
+    insurance_fund.pool = pool.key();
+    insurance_fund.bump = ctx.bumps.insurance_fund;
+
     if !pool.validate() {
         return err!(PerpetualsError::InvalidPoolConfig);
     }
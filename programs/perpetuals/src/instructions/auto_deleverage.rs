@@ -0,0 +1,382 @@
+//! AutoDeleverage instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError,
+        events, math,
+        state::{
+            custody::Custody, insurance_fund::InsuranceFund, margin_account::MarginAccount,
+            oracle::OraclePrice, perpetuals::Perpetuals, pool::Pool, position::Position,
+        },
+    },
+    anchor_lang::prelude::*,
+    anchor_spl::token::{Token, TokenAccount},
+};
+
+#[derive(Accounts)]
+pub struct AutoDeleverage<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(mut)]
+    pub owner: SystemAccount<'info>,
+
+    #[account(
+        mut,
+        constraint = receiving_account.mint == collateral_custody.mint
+    )]
+    pub receiving_account: Box<Account<'info, TokenAccount>>,
+
+    // required for cross margin positions
+    #[account(
+        mut,
+        seeds = [b"margin_account", position.owner.as_ref(), pool.key().as_ref()],
+        bump = margin_account.bump
+    )]
+    pub margin_account: Option<Box<Account<'info, MarginAccount>>>,
+
+    // holder's position token account, required for tokenized positions
+    pub position_token_account: Option<Box<Account<'info, TokenAccount>>>,
+
+    /// CHECK: empty PDA, authority for token accounts
+    #[account(
+        seeds = [b"transfer_authority"],
+        bump = perpetuals.transfer_authority_bump
+    )]
+    pub transfer_authority: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        mut,
+        seeds = [b"pool", pool.name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        seeds = [b"insurance_fund", pool.key().as_ref()],
+        bump = insurance_fund.bump
+    )]
+    pub insurance_fund: Box<Account<'info, InsuranceFund>>,
+
+    #[account(
+        mut,
+        has_one = owner,
+        seeds = [
+            b"position",
+            owner.key().as_ref(),
+            pool.key().as_ref(),
+            custody.key().as_ref(),
+            &[position.side as u8],
+            &position.index.to_le_bytes()
+        ],
+        bump = position.bump
+    )]
+    pub position: Box<Account<'info, Position>>,
+
+    #[account(
+        mut,
+        constraint = position.custody == custody.key()
+    )]
+    pub custody: Box<Account<'info, Custody>>,
+
+    /// CHECK: oracle account for the position token
+    #[account(
+        constraint = custody_oracle_account.key() == custody.oracle.oracle_account
+    )]
+    pub custody_oracle_account: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        constraint = position.collateral_custody == collateral_custody.key()
+    )]
+    pub collateral_custody: Box<Account<'info, Custody>>,
+
+    /// CHECK: oracle account for the collateral token
+    #[account(
+        constraint = collateral_custody_oracle_account.key() == collateral_custody.oracle.oracle_account
+    )]
+    pub collateral_custody_oracle_account: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"custody_token_account",
+            pool.key().as_ref(),
+            collateral_custody.mint.as_ref()
+        ],
+        bump = collateral_custody.token_account_bump
+    )]
+    pub collateral_custody_token_account: Box<Account<'info, TokenAccount>>,
+
+    token_program: Program<'info, Token>,
+    // remaining accounts:
+    //   all other open positions on the same custody and side (read-only, unsigned)
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct AutoDeleverageParams {}
+
+pub fn auto_deleverage<'info>(
+    ctx: Context<'_, '_, 'info, 'info, AutoDeleverage<'info>>,
+    _params: &AutoDeleverageParams,
+) -> Result<()> {
+    // check permissions
+    msg!("Check permissions");
+    let perpetuals = ctx.accounts.perpetuals.as_mut();
+    let custody = ctx.accounts.custody.as_mut();
+    let collateral_custody = ctx.accounts.collateral_custody.as_mut();
+    require!(
+        perpetuals.permissions.allow_close_position && custody.permissions.allow_close_position,
+        PerpetualsError::InstructionNotAllowed
+    );
+
+    // payouts go to the position holder
+    let holder = ctx
+        .accounts
+        .position
+        .get_holder(ctx.accounts.position_token_account.as_deref())?;
+    require_keys_eq!(
+        ctx.accounts.receiving_account.owner,
+        holder,
+        PerpetualsError::InvalidTokenAccountOwner
+    );
+
+    // check custody solvency
+    msg!("Check custody solvency");
+    let solvency = collateral_custody.get_solvency()?;
+    msg!("Solvency: {}", solvency);
+    require!(
+        custody.pricing.auto_deleverage_threshold > 0
+            && solvency < custody.pricing.auto_deleverage_threshold,
+        PerpetualsError::AutoDeleverageNotAllowed
+    );
+
+    let position = ctx.accounts.position.as_mut();
+    let pool = ctx.accounts.pool.as_mut();
+    let mut margin_account = MarginAccount::validate_position_account(
+        position,
+        ctx.accounts.margin_account.as_deref_mut(),
+    )?;
+
+    // compute position ranking
+    msg!("Check position rank");
+    let curtime = perpetuals.get_time()?;
+
+    let token_price = OraclePrice::new_from_oracle(
+        &ctx.accounts.custody_oracle_account.to_account_info(),
+        &custody.oracle,
+        curtime,
+    )?;
+
+    let collateral_token_price = OraclePrice::new_from_oracle(
+        &ctx.accounts
+            .collateral_custody_oracle_account
+            .to_account_info(),
+        &collateral_custody.oracle,
+        curtime,
+    )?;
+
+    // deleveraged positions are settled at the oracle price and without exit fees
+    let mut adl_custody = custody.clone();
+    adl_custody.pricing.trade_spread_long = 0;
+    adl_custody.pricing.trade_spread_short = 0;
+    adl_custody.pricing.virtual_depth_usd = 0;
+    adl_custody.fees.close_position = 0;
+    adl_custody.fees.close_position_profit_share = 0;
+
+    let adl_score = pool.get_adl_score(
+        position,
+        &token_price,
+        &adl_custody,
+        &collateral_token_price,
+        collateral_custody,
+        curtime,
+    )?;
+    msg!("ADL score: {}", adl_score);
+    require!(adl_score > 0, PerpetualsError::InvalidPositionState);
+
+    // the ranking must include every open position on the custody and side, positions
+    // that lock funds in other custodies are not deleveraged to restore this custody
+    require_eq!(
+        ctx.remaining_accounts.len() as u64,
+        custody
+            .get_position_stats(position.side)
+            .open_positions
+            .saturating_sub(1),
+        PerpetualsError::InvalidAutoDeleverageRank
+    );
+    let mut ranked_positions = vec![position.key()];
+    for account in ctx.remaining_accounts {
+        require!(
+            !ranked_positions.contains(account.key),
+            PerpetualsError::InvalidAutoDeleverageRank
+        );
+        ranked_positions.push(account.key());
+
+        let other_position = Account::<Position>::try_from(account)?;
+        require!(
+            other_position.custody == position.custody && other_position.side == position.side,
+            PerpetualsError::InvalidAutoDeleverageRank
+        );
+        if other_position.collateral_custody != position.collateral_custody {
+            continue;
+        }
+        let other_adl_score = pool.get_adl_score(
+            &other_position,
+            &token_price,
+            &adl_custody,
+            &collateral_token_price,
+            collateral_custody,
+            curtime,
+        )?;
+        require_gte!(
+            adl_score,
+            other_adl_score,
+            PerpetualsError::InvalidAutoDeleverageRank
+        );
+    }
+
+    // only the size needed to restore the custody solvency is closed
+    msg!("Settle position");
+    let (transfer_amount, _, _, _) = pool.get_close_amount(
+        position,
+        &token_price,
+        &adl_custody,
+        &collateral_token_price,
+        collateral_custody,
+        curtime,
+        false,
+    )?;
+    let close_size_usd = Pool::get_auto_deleverage_size_usd(
+        position,
+        transfer_amount,
+        collateral_custody,
+        custody.pricing.auto_deleverage_threshold,
+    )?;
+    let mut closed_position = position.get_partial_position(close_size_usd)?;
+    if closed_position.locked_amount == position.locked_amount
+        || closed_position.collateral_amount == position.collateral_amount
+    {
+        closed_position = position.get_partial_position(position.size_usd)?;
+    }
+    let full_close = closed_position.size_usd == position.size_usd;
+
+    let exit_price = pool.get_exit_price(
+        &token_price,
+        &adl_custody,
+        position.side,
+        closed_position.size_usd,
+    )?;
+    msg!("Exit price: {}", exit_price);
+
+    let (transfer_amount, _, profit_usd, loss_usd) = pool.get_close_amount(
+        &closed_position,
+        &token_price,
+        &adl_custody,
+        &collateral_token_price,
+        collateral_custody,
+        curtime,
+        false,
+    )?;
+
+    msg!("Net profit: {}, loss: {}", profit_usd, loss_usd);
+    msg!("Amount out: {}", transfer_amount);
+
+    // update existing position
+    let prev_position = position.clone();
+    if !full_close {
+        msg!("Update existing position");
+        position.update_time = curtime;
+        position.size_usd = math::checked_sub(position.size_usd, closed_position.size_usd)?;
+        position.borrow_size_usd =
+            math::checked_sub(position.borrow_size_usd, closed_position.borrow_size_usd)?;
+        position.collateral_usd =
+            math::checked_sub(position.collateral_usd, closed_position.collateral_usd)?;
+        position.unrealized_profit_usd = math::checked_sub(
+            position.unrealized_profit_usd,
+            closed_position.unrealized_profit_usd,
+        )?;
+        position.unrealized_loss_usd = math::checked_sub(
+            position.unrealized_loss_usd,
+            closed_position.unrealized_loss_usd,
+        )?;
+        position.locked_amount =
+            math::checked_sub(position.locked_amount, closed_position.locked_amount)?;
+        position.collateral_amount = math::checked_sub(
+            position.collateral_amount,
+            closed_position.collateral_amount,
+        )?;
+    }
+
+    pool.settle_position(
+        position,
+        &prev_position,
+        &closed_position,
+        (transfer_amount, 0, profit_usd, loss_usd),
+        if margin_account.is_some() {
+            transfer_amount
+        } else {
+            0
+        },
+        false,
+        custody,
+        &token_price,
+        collateral_custody,
+        &collateral_token_price,
+        ctx.accounts.insurance_fund.as_mut(),
+        margin_account
+            .as_deref_mut()
+            .map(|margin_account| &mut **margin_account),
+        &[],
+        curtime,
+    )?;
+
+    // transfer tokens, cross margin payouts stay in custody as margin account collateral
+    msg!("Transfer tokens");
+    if margin_account.is_none() {
+        perpetuals.transfer_tokens(
+            ctx.accounts
+                .collateral_custody_token_account
+                .to_account_info(),
+            ctx.accounts.receiving_account.to_account_info(),
+            ctx.accounts.transfer_authority.to_account_info(),
+            ctx.accounts.token_program.to_account_info(),
+            transfer_amount,
+        )?;
+    }
+
+    emit!(events::AutoDeleverage {
+        collateral_amount: position.collateral_amount,
+        collateral_custody: position.collateral_custody,
+        custody: position.custody,
+        owner: position.owner,
+        pool: position.pool,
+        price: exit_price,
+        side: position.side,
+        size_usd: position.size_usd,
+        time: curtime,
+        loss_usd,
+        profit_usd,
+        transfer_amount,
+        adl_score,
+        signer: ctx.accounts.signer.key(),
+        size_delta_usd: closed_position.size_usd,
+        solvency,
+    });
+
+    // close position account if the whole position has been settled
+    if full_close {
+        ctx.accounts
+            .position
+            .close(ctx.accounts.owner.to_account_info())?;
+    }
+
+    Ok(())
+}
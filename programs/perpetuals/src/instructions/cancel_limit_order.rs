@@ -0,0 +1,116 @@
+//! CancelLimitOrder instruction handler
+
+use {
+    crate::state::{
+        delegate_authority::{DelegateAction, DelegateAuthority},
+        limit_order::LimitOrder,
+        perpetuals::Perpetuals,
+    },
+    anchor_lang::prelude::*,
+    anchor_spl::token::{Token, TokenAccount},
+};
+
+#[derive(Accounts)]
+pub struct CancelLimitOrder<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(mut)]
+    pub owner: SystemAccount<'info>,
+
+    #[account(
+        seeds = [b"delegate_authority", owner.key().as_ref()],
+        bump = delegate_authority.bump
+    )]
+    pub delegate_authority: Option<Box<Account<'info, DelegateAuthority>>>,
+
+    #[account(
+        mut,
+        constraint = receiving_account.mint == escrow_token_account.mint,
+        has_one = owner
+    )]
+    pub receiving_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: empty PDA, authority for token accounts
+    #[account(
+        seeds = [b"transfer_authority"],
+        bump = perpetuals.transfer_authority_bump
+    )]
+    pub transfer_authority: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        mut,
+        has_one = owner,
+        seeds = [
+            b"limit_order",
+            owner.key().as_ref(),
+            limit_order.pool.as_ref(),
+            limit_order.custody.as_ref(),
+            &limit_order.position_index.to_le_bytes()
+        ],
+        bump = limit_order.bump,
+        close = owner
+    )]
+    pub limit_order: Box<Account<'info, LimitOrder>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"limit_order_escrow",
+            limit_order.key().as_ref()
+        ],
+        bump = limit_order.escrow_bump
+    )]
+    pub escrow_token_account: Box<Account<'info, TokenAccount>>,
+
+    token_program: Program<'info, Token>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct CancelLimitOrderParams {}
+
+pub fn cancel_limit_order<'info>(
+    ctx: Context<'_, '_, '_, 'info, CancelLimitOrder<'info>>,
+    _params: &CancelLimitOrderParams,
+) -> Result<()> {
+    // check permissions
+    msg!("Check permissions");
+    DelegateAuthority::validate_authority(
+        &ctx.accounts.authority.key(),
+        &ctx.accounts.owner.key(),
+        ctx.accounts.delegate_authority.as_deref(),
+        DelegateAction::Orders,
+    )?;
+
+    // return escrow in full
+    let escrow_amount = ctx.accounts.escrow_token_account.amount;
+    msg!("Amount out: {}", escrow_amount);
+
+    msg!("Transfer tokens");
+    ctx.accounts.perpetuals.transfer_tokens(
+        ctx.accounts.escrow_token_account.to_account_info(),
+        ctx.accounts.receiving_account.to_account_info(),
+        ctx.accounts.transfer_authority.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        escrow_amount,
+    )?;
+
+    Perpetuals::close_token_account(
+        ctx.accounts.owner.to_account_info(),
+        ctx.accounts.escrow_token_account.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        ctx.accounts.transfer_authority.to_account_info(),
+        &[&[
+            b"transfer_authority",
+            &[ctx.accounts.perpetuals.transfer_authority_bump],
+        ]],
+    )?;
+
+    Ok(())
+}
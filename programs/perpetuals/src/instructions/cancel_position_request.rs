@@ -0,0 +1,149 @@
+//! CancelPositionRequest instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError,
+        state::{
+            delegate_authority::{DelegateAction, DelegateAuthority},
+            perpetuals::Perpetuals,
+            position_request::{PositionRequest, RequestType},
+        },
+    },
+    anchor_lang::prelude::*,
+    anchor_spl::token::{Token, TokenAccount},
+};
+
+#[derive(Accounts)]
+pub struct CancelPositionRequest<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(mut)]
+    pub owner: SystemAccount<'info>,
+
+    #[account(
+        seeds = [b"delegate_authority", owner.key().as_ref()],
+        bump = delegate_authority.bump
+    )]
+    pub delegate_authority: Option<Box<Account<'info, DelegateAuthority>>>,
+
+    // only required by open requests, along with the escrow account
+    #[account(
+        mut,
+        has_one = owner
+    )]
+    pub receiving_account: Option<Box<Account<'info, TokenAccount>>>,
+
+    /// CHECK: empty PDA, authority for token accounts
+    #[account(
+        seeds = [b"transfer_authority"],
+        bump = perpetuals.transfer_authority_bump
+    )]
+    pub transfer_authority: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        mut,
+        has_one = owner,
+        seeds = [
+            b"position_request",
+            owner.key().as_ref(),
+            position_request.pool.as_ref(),
+            position_request.custody.as_ref(),
+            &[position_request.side as u8],
+            &position_request.position_index.to_le_bytes()
+        ],
+        bump = position_request.bump,
+        close = owner
+    )]
+    pub position_request: Box<Account<'info, PositionRequest>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"position_request_escrow",
+            position_request.key().as_ref()
+        ],
+        bump = position_request.escrow_bump
+    )]
+    pub escrow_token_account: Option<Box<Account<'info, TokenAccount>>>,
+
+    token_program: Program<'info, Token>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct CancelPositionRequestParams {}
+
+pub fn cancel_position_request<'info>(
+    ctx: Context<'_, '_, '_, 'info, CancelPositionRequest<'info>>,
+    _params: &CancelPositionRequestParams,
+) -> Result<()> {
+    // check permissions
+    msg!("Check permissions");
+    DelegateAuthority::validate_authority(
+        &ctx.accounts.authority.key(),
+        &ctx.accounts.owner.key(),
+        ctx.accounts.delegate_authority.as_deref(),
+        if ctx.accounts.position_request.request_type == RequestType::Open {
+            DelegateAction::OpenPosition
+        } else {
+            DelegateAction::ClosePosition
+        },
+    )?;
+
+    // check request expiry
+    msg!("Check position request");
+    let curtime = ctx.accounts.perpetuals.get_time()?;
+    require_gt!(
+        curtime,
+        ctx.accounts.position_request.expiry_time,
+        PerpetualsError::PositionRequestNotExpired
+    );
+
+    if ctx.accounts.position_request.request_type == RequestType::Close {
+        return Ok(());
+    }
+
+    // return escrow in full
+    let (Some(escrow_token_account), Some(receiving_account)) = (
+        ctx.accounts.escrow_token_account.as_ref(),
+        ctx.accounts.receiving_account.as_ref(),
+    ) else {
+        return Err(ProgramError::NotEnoughAccountKeys.into());
+    };
+    require_keys_eq!(
+        receiving_account.mint,
+        escrow_token_account.mint,
+        PerpetualsError::InvalidPositionRequest
+    );
+
+    let escrow_amount = escrow_token_account.amount;
+    msg!("Amount out: {}", escrow_amount);
+
+    msg!("Transfer tokens");
+    ctx.accounts.perpetuals.transfer_tokens(
+        escrow_token_account.to_account_info(),
+        receiving_account.to_account_info(),
+        ctx.accounts.transfer_authority.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        escrow_amount,
+    )?;
+
+    Perpetuals::close_token_account(
+        ctx.accounts.owner.to_account_info(),
+        escrow_token_account.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        ctx.accounts.transfer_authority.to_account_info(),
+        &[&[
+            b"transfer_authority",
+            &[ctx.accounts.perpetuals.transfer_authority_bump],
+        ]],
+    )?;
+
+    Ok(())
+}
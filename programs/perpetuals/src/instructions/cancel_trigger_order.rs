@@ -0,0 +1,76 @@
+//! CancelTriggerOrder instruction handler
+
+use {
+    crate::state::{
+        delegate_authority::{DelegateAction, DelegateAuthority},
+        position::Position,
+        trigger_order::TriggerOrder,
+    },
+    anchor_lang::prelude::*,
+};
+
+#[derive(Accounts)]
+pub struct CancelTriggerOrder<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(mut)]
+    pub owner: SystemAccount<'info>,
+
+    #[account(
+        seeds = [b"delegate_authority", owner.key().as_ref()],
+        bump = delegate_authority.bump
+    )]
+    pub delegate_authority: Option<Box<Account<'info, DelegateAuthority>>>,
+
+    #[account(
+        mut,
+        has_one = owner,
+        seeds = [
+            b"trigger_order",
+            trigger_order.position.as_ref(),
+            &[trigger_order.index]
+        ],
+        bump = trigger_order.bump,
+        close = owner
+    )]
+    pub trigger_order: Box<Account<'info, TriggerOrder>>,
+
+    /// CHECK: position the order was placed on, may have been closed since
+    #[account(
+        mut,
+        address = trigger_order.position
+    )]
+    pub position: UncheckedAccount<'info>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct CancelTriggerOrderParams {}
+
+pub fn cancel_trigger_order<'info>(
+    ctx: Context<'_, '_, '_, 'info, CancelTriggerOrder<'info>>,
+    _params: &CancelTriggerOrderParams,
+) -> Result<()> {
+    // check permissions
+    msg!("Check permissions");
+    DelegateAuthority::validate_authority(
+        &ctx.accounts.authority.key(),
+        &ctx.accounts.owner.key(),
+        ctx.accounts.delegate_authority.as_deref(),
+        DelegateAction::Orders,
+    )?;
+
+    // release the order from its position, unless the position has been closed or reopened
+    let position_info = ctx.accounts.position.to_account_info();
+    if position_info.owner == &crate::ID && !position_info.data_is_empty() {
+        let mut data = position_info.try_borrow_mut_data()?;
+        let mut position = Position::try_deserialize(&mut &data[..])?;
+        if ctx.accounts.trigger_order.create_time > position.open_time {
+            position.trigger_orders = position.trigger_orders.saturating_sub(1);
+            position.try_serialize(&mut &mut data[..])?;
+        }
+    }
+
+    // trigger order account is closed by the close constraint
+    Ok(())
+}
@@ -0,0 +1,137 @@
+//! CancelWithdrawalRequest instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError,
+        math,
+        state::{perpetuals::Perpetuals, pool::Pool, withdrawal_request::WithdrawalRequest},
+    },
+    anchor_lang::prelude::*,
+    anchor_spl::token::{Mint, Token, TokenAccount},
+};
+
+#[derive(Accounts)]
+pub struct CancelWithdrawalRequest<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint = lp_token_account.mint == escrow_token_account.mint,
+        has_one = owner
+    )]
+    pub lp_token_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: empty PDA, authority for token accounts
+    #[account(
+        seeds = [b"transfer_authority"],
+        bump = perpetuals.transfer_authority_bump
+    )]
+    pub transfer_authority: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        seeds = [b"pool", pool.name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        has_one = owner,
+        has_one = pool,
+        seeds = [
+            b"withdrawal_request",
+            owner.key().as_ref(),
+            pool.key().as_ref()
+        ],
+        bump = withdrawal_request.bump,
+        close = owner
+    )]
+    pub withdrawal_request: Box<Account<'info, WithdrawalRequest>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"withdrawal_request_escrow",
+            withdrawal_request.key().as_ref()
+        ],
+        bump = withdrawal_request.escrow_bump
+    )]
+    pub escrow_token_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"lp_token_mint", pool.key().as_ref()],
+        bump = pool.lp_token_bump
+    )]
+    pub lp_token_mint: Box<Account<'info, Mint>>,
+
+    token_program: Program<'info, Token>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct CancelWithdrawalRequestParams {}
+
+pub fn cancel_withdrawal_request<'info>(
+    ctx: Context<'_, '_, '_, 'info, CancelWithdrawalRequest<'info>>,
+    _params: &CancelWithdrawalRequestParams,
+) -> Result<()> {
+    // check request execution window
+    msg!("Check withdrawal request");
+    let perpetuals = ctx.accounts.perpetuals.as_ref();
+    let curtime = perpetuals.get_time()?;
+    let withdrawal_request = ctx.accounts.withdrawal_request.as_ref();
+    require!(
+        withdrawal_request.is_cancellable(curtime),
+        PerpetualsError::WithdrawalRequestNotCancellable
+    );
+
+    // expired requests pay a fee in lp tokens, burning them accrues the value
+    // to the remaining LPs
+    let escrow_amount = ctx.accounts.escrow_token_account.amount;
+    let fee_amount = if withdrawal_request.is_expired(curtime) {
+        Pool::get_fee_amount(ctx.accounts.pool.lp_expired_cancel_fee, escrow_amount)?
+    } else {
+        0
+    };
+    msg!("Collected fee: {}", fee_amount);
+
+    let transfer_amount = math::checked_sub(escrow_amount, fee_amount)?;
+    msg!("Amount out: {}", transfer_amount);
+
+    msg!("Transfer tokens");
+    perpetuals.transfer_tokens(
+        ctx.accounts.escrow_token_account.to_account_info(),
+        ctx.accounts.lp_token_account.to_account_info(),
+        ctx.accounts.transfer_authority.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        transfer_amount,
+    )?;
+
+    if fee_amount > 0 {
+        msg!("Burn LP tokens");
+        perpetuals.burn_escrowed_tokens(
+            ctx.accounts.lp_token_mint.to_account_info(),
+            ctx.accounts.escrow_token_account.to_account_info(),
+            ctx.accounts.transfer_authority.to_account_info(),
+            ctx.accounts.token_program.to_account_info(),
+            fee_amount,
+        )?;
+    }
+
+    Perpetuals::close_token_account(
+        ctx.accounts.owner.to_account_info(),
+        ctx.accounts.escrow_token_account.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        ctx.accounts.transfer_authority.to_account_info(),
+        &[&[b"transfer_authority", &[perpetuals.transfer_authority_bump]]],
+    )?;
+
+    Ok(())
+}
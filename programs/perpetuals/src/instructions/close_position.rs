@@ -3,9 +3,12 @@
 use {
     crate::{
         error::PerpetualsError,
-        events, math,
+        events,
         state::{
             custody::Custody,
+            delegate_authority::{DelegateAction, DelegateAuthority},
+            insurance_fund::InsuranceFund,
+            margin_account::MarginAccount,
             oracle::OraclePrice,
             perpetuals::Perpetuals,
             pool::Pool,
@@ -19,15 +22,34 @@ use {
 #[derive(Accounts)]
 pub struct ClosePosition<'info> {
     #[account(mut)]
-    pub owner: Signer<'info>,
+    pub authority: Signer<'info>,
+
+    #[account(mut)]
+    pub owner: SystemAccount<'info>,
+
+    #[account(
+        seeds = [b"delegate_authority", owner.key().as_ref()],
+        bump = delegate_authority.bump
+    )]
+    pub delegate_authority: Option<Box<Account<'info, DelegateAuthority>>>,
+
+    // holder's position token account, required for tokenized positions
+    pub position_token_account: Option<Box<Account<'info, TokenAccount>>>,
 
     #[account(
         mut,
-        constraint = receiving_account.mint == custody.mint,
-        has_one = owner
+        constraint = receiving_account.mint == collateral_custody.mint
     )]
     pub receiving_account: Box<Account<'info, TokenAccount>>,
 
+    // required for cross margin positions
+    #[account(
+        mut,
+        seeds = [b"margin_account", position.owner.as_ref(), pool.key().as_ref()],
+        bump = margin_account.bump
+    )]
+    pub margin_account: Option<Box<Account<'info, MarginAccount>>>,
+
     /// CHECK: empty PDA, authority for token accounts
     #[account(
         seeds = [b"transfer_authority"],
@@ -48,6 +70,13 @@ pub struct ClosePosition<'info> {
     )]
     pub pool: Box<Account<'info, Pool>>,
 
+    #[account(
+        mut,
+        seeds = [b"insurance_fund", pool.key().as_ref()],
+        bump = insurance_fund.bump
+    )]
+    pub insurance_fund: Box<Account<'info, InsuranceFund>>,
+
     #[account(
         mut,
         has_one = owner,
@@ -56,7 +85,8 @@ pub struct ClosePosition<'info> {
             owner.key().as_ref(),
             pool.key().as_ref(),
             custody.key().as_ref(),
-            &[Side::Long as u8]
+            &[position.side as u8],
+            &position.index.to_le_bytes()
         ],
         bump = position.bump,
         close = owner
@@ -75,18 +105,32 @@ pub struct ClosePosition<'info> {
     )]
     pub custody_oracle_account: UncheckedAccount<'info>,
 
+    #[account(
+        mut,
+        constraint = position.collateral_custody == collateral_custody.key()
+    )]
+    pub collateral_custody: Box<Account<'info, Custody>>,
+
+    /// CHECK: oracle account for the collateral token
+    #[account(
+        constraint = collateral_custody_oracle_account.key() == collateral_custody.oracle.oracle_account
+    )]
+    pub collateral_custody_oracle_account: UncheckedAccount<'info>,
+
     #[account(
         mut,
         seeds = [
             b"custody_token_account",
             pool.key().as_ref(),
-            custody.mint.as_ref()
+            collateral_custody.mint.as_ref()
         ],
-        bump = custody.token_account_bump
+        bump = collateral_custody.token_account_bump
     )]
-    pub custody_token_account: Box<Account<'info, TokenAccount>>,
+    pub collateral_custody_token_account: Box<Account<'info, TokenAccount>>,
 
     token_program: Program<'info, Token>,
+    // remaining accounts (cross margin positions only):
+    //   2 accounts per margin account collateral (writable, unsigned)
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
@@ -95,15 +139,38 @@ pub struct ClosePositionParams {
 }
 
 pub fn close_position<'info>(
-    ctx: Context<'_, '_, '_, 'info, ClosePosition<'info>>,
+    ctx: Context<'_, '_, 'info, 'info, ClosePosition<'info>>,
     params: &ClosePositionParams,
 ) -> Result<()> {
     // check permissions
     msg!("Check permissions");
+    let holder = ctx
+        .accounts
+        .position
+        .get_holder(ctx.accounts.position_token_account.as_deref())?;
+    DelegateAuthority::validate_authority(
+        &ctx.accounts.authority.key(),
+        &holder,
+        // delegates are granted by the position account owner, not the token holder
+        ctx.accounts
+            .delegate_authority
+            .as_deref()
+            .filter(|_| !ctx.accounts.position.is_tokenized()),
+        DelegateAction::ClosePosition,
+    )?;
+    require_keys_eq!(
+        ctx.accounts.receiving_account.owner,
+        holder,
+        PerpetualsError::InvalidTokenAccountOwner
+    );
     let perpetuals = ctx.accounts.perpetuals.as_mut();
     let custody = ctx.accounts.custody.as_mut();
+    let collateral_custody = ctx.accounts.collateral_custody.as_mut();
     require!(
-        perpetuals.permissions.allow_close_position && custody.permissions.allow_close_position,
+        perpetuals.permissions.allow_close_position
+            && custody.permissions.allow_close_position
+            && perpetuals.permissions.allow_direct_position
+            && custody.permissions.allow_direct_position,
         PerpetualsError::InstructionNotAllowed
     );
 
@@ -114,6 +181,10 @@ pub fn close_position<'info>(
     }
     let position = ctx.accounts.position.as_mut();
     let pool = ctx.accounts.pool.as_mut();
+    let mut margin_account = MarginAccount::validate_position_account(
+        position,
+        ctx.accounts.margin_account.as_deref_mut(),
+    )?;
 
     // compute exit price
     let curtime = perpetuals.get_time()?;
@@ -124,84 +195,76 @@ pub fn close_position<'info>(
         curtime,
     )?;
 
-    let exit_price = pool.get_exit_price(&token_price, custody)?;
+    let collateral_token_price = OraclePrice::new_from_oracle(
+        &ctx.accounts
+            .collateral_custody_oracle_account
+            .to_account_info(),
+        &collateral_custody.oracle,
+        curtime,
+    )?;
+
+    let exit_price =
+        pool.get_exit_price(&token_price, custody, position.side, position.size_usd)?;
     msg!("Exit price: {}", exit_price);
 
-    require_gte!(exit_price, params.price, PerpetualsError::MaxPriceSlippage);
+    if position.side == Side::Long {
+        require_gte!(exit_price, params.price, PerpetualsError::MaxPriceSlippage);
+    } else {
+        require_gte!(params.price, exit_price, PerpetualsError::MaxPriceSlippage);
+    }
 
     msg!("Settle position");
-    let (transfer_amount, fee_amount, profit_usd, loss_usd) =
-        pool.get_close_amount(position, &token_price, custody, curtime, false)?;
-
-    let fee_amount_usd = token_price.get_asset_amount_usd(fee_amount, custody.decimals)?;
+    let (transfer_amount, fee_amount, profit_usd, loss_usd) = pool.get_close_amount(
+        position,
+        &token_price,
+        custody,
+        &collateral_token_price,
+        collateral_custody,
+        curtime,
+        false,
+    )?;
 
     msg!("Net profit: {}, loss: {}", profit_usd, loss_usd);
     msg!("Collected fee: {}", fee_amount);
     msg!("Amount out: {}", transfer_amount);
 
-    // unlock pool funds
-    custody.unlock_funds(position.locked_amount)?;
-
-    // check pool constraints
-    msg!("Check pool constraints");
-    require!(
-        pool.check_available_amount(transfer_amount, custody)?,
-        PerpetualsError::CustodyAmountLimit
-    );
-
-    // transfer tokens
-    msg!("Transfer tokens");
-    perpetuals.transfer_tokens(
-        ctx.accounts.custody_token_account.to_account_info(),
-        ctx.accounts.receiving_account.to_account_info(),
-        ctx.accounts.transfer_authority.to_account_info(),
-        ctx.accounts.token_program.to_account_info(),
-        transfer_amount,
+    let protocol_fee = pool.settle_position(
+        position,
+        position,
+        position,
+        (transfer_amount, fee_amount, profit_usd, loss_usd),
+        if margin_account.is_some() {
+            transfer_amount
+        } else {
+            0
+        },
+        false,
+        custody,
+        &token_price,
+        collateral_custody,
+        &collateral_token_price,
+        ctx.accounts.insurance_fund.as_mut(),
+        margin_account
+            .as_deref_mut()
+            .map(|margin_account| &mut **margin_account),
+        ctx.remaining_accounts,
+        curtime,
     )?;
 
-    // update custody stats
-    msg!("Update custody stats");
-    custody.collected_fees.close_position_usd = custody
-        .collected_fees
-        .close_position_usd
-        .wrapping_add(fee_amount_usd);
-
-    if transfer_amount > position.collateral_amount {
-        let amount_lost = transfer_amount.saturating_sub(position.collateral_amount);
-        custody.assets.owned = math::checked_sub(custody.assets.owned, amount_lost)?;
-    } else {
-        let amount_gained = position.collateral_amount.saturating_sub(transfer_amount);
-        custody.assets.owned = math::checked_add(custody.assets.owned, amount_gained)?;
-    }
-    custody.assets.collateral =
-        math::checked_sub(custody.assets.collateral, position.collateral_amount)?;
-
-    let protocol_fee = Pool::get_fee_amount(custody.fees.protocol_share, fee_amount)?;
-
-    // Pay protocol_fee from custody if possible, otherwise no protocol_fee
-    if pool.check_available_amount(protocol_fee, custody)? {
-        custody.assets.protocol_fees =
-            math::checked_add(custody.assets.protocol_fees, protocol_fee)?;
-
-        custody.assets.owned = math::checked_sub(custody.assets.owned, protocol_fee)?;
+    // transfer tokens, cross margin payouts stay in custody as margin account collateral
+    msg!("Transfer tokens");
+    if margin_account.is_none() {
+        perpetuals.transfer_tokens(
+            ctx.accounts
+                .collateral_custody_token_account
+                .to_account_info(),
+            ctx.accounts.receiving_account.to_account_info(),
+            ctx.accounts.transfer_authority.to_account_info(),
+            ctx.accounts.token_program.to_account_info(),
+            transfer_amount,
+        )?;
     }
 
-    custody.volume_stats.close_position_usd = custody
-        .volume_stats
-        .close_position_usd
-        .wrapping_add(position.size_usd);
-
-    custody.trade_stats.oi_long_usd = custody
-        .trade_stats
-        .oi_long_usd
-        .saturating_sub(position.size_usd);
-
-    custody.trade_stats.profit_usd = custody.trade_stats.profit_usd.wrapping_add(profit_usd);
-    custody.trade_stats.loss_usd = custody.trade_stats.loss_usd.wrapping_add(loss_usd);
-
-    custody.remove_position(position, curtime)?;
-    custody.update_borrow_rate(curtime)?;
-
     emit!(events::ClosePosition {
         profit_usd,
         loss_usd,
@@ -209,11 +272,13 @@ pub fn close_position<'info>(
         transfer_amount,
         protocol_fee,
         collateral_amount: position.collateral_amount,
+        collateral_custody: position.collateral_custody,
         custody: position.custody,
         time: position.open_time,
         owner: position.owner,
         pool: position.pool,
         price: exit_price,
+        side: position.side,
         size_usd: position.size_usd,
     });
     Ok(())
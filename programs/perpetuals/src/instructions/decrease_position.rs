@@ -0,0 +1,331 @@
+//! DecreasePosition instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError,
+        events, math,
+        state::{
+            custody::Custody,
+            delegate_authority::{DelegateAction, DelegateAuthority},
+            insurance_fund::InsuranceFund,
+            margin_account::MarginAccount,
+            oracle::OraclePrice,
+            perpetuals::Perpetuals,
+            pool::Pool,
+            position::{Position, Side},
+        },
+    },
+    anchor_lang::prelude::*,
+    anchor_spl::token::{Token, TokenAccount},
+    solana_program::program_error::ProgramError,
+};
+
+#[derive(Accounts)]
+pub struct DecreasePosition<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(mut)]
+    pub owner: SystemAccount<'info>,
+
+    #[account(
+        seeds = [b"delegate_authority", owner.key().as_ref()],
+        bump = delegate_authority.bump
+    )]
+    pub delegate_authority: Option<Box<Account<'info, DelegateAuthority>>>,
+
+    // holder's position token account, required for tokenized positions
+    pub position_token_account: Option<Box<Account<'info, TokenAccount>>>,
+
+    #[account(
+        mut,
+        constraint = receiving_account.mint == collateral_custody.mint
+    )]
+    pub receiving_account: Box<Account<'info, TokenAccount>>,
+
+    // required for cross margin positions
+    #[account(
+        mut,
+        seeds = [b"margin_account", position.owner.as_ref(), pool.key().as_ref()],
+        bump = margin_account.bump
+    )]
+    pub margin_account: Option<Box<Account<'info, MarginAccount>>>,
+
+    /// CHECK: empty PDA, authority for token accounts
+    #[account(
+        seeds = [b"transfer_authority"],
+        bump = perpetuals.transfer_authority_bump
+    )]
+    pub transfer_authority: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        mut,
+        seeds = [b"pool", pool.name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        seeds = [b"insurance_fund", pool.key().as_ref()],
+        bump = insurance_fund.bump
+    )]
+    pub insurance_fund: Box<Account<'info, InsuranceFund>>,
+
+    #[account(
+        mut,
+        has_one = owner,
+        seeds = [
+            b"position",
+            owner.key().as_ref(),
+            pool.key().as_ref(),
+            custody.key().as_ref(),
+            &[position.side as u8],
+            &position.index.to_le_bytes()
+        ],
+        bump = position.bump
+    )]
+    pub position: Box<Account<'info, Position>>,
+
+    #[account(
+        mut,
+        constraint = position.custody == custody.key()
+    )]
+    pub custody: Box<Account<'info, Custody>>,
+
+    /// CHECK: oracle account for the position token
+    #[account(
+        constraint = custody_oracle_account.key() == custody.oracle.oracle_account
+    )]
+    pub custody_oracle_account: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        constraint = position.collateral_custody == collateral_custody.key()
+    )]
+    pub collateral_custody: Box<Account<'info, Custody>>,
+
+    /// CHECK: oracle account for the collateral token
+    #[account(
+        constraint = collateral_custody_oracle_account.key() == collateral_custody.oracle.oracle_account
+    )]
+    pub collateral_custody_oracle_account: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"custody_token_account",
+            pool.key().as_ref(),
+            collateral_custody.mint.as_ref()
+        ],
+        bump = collateral_custody.token_account_bump
+    )]
+    pub collateral_custody_token_account: Box<Account<'info, TokenAccount>>,
+
+    token_program: Program<'info, Token>,
+    // remaining accounts (cross margin positions only):
+    //   2 accounts per margin account collateral (writable, unsigned)
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct DecreasePositionParams {
+    pub price: u64,
+    pub size_usd: u64,
+}
+
+pub fn decrease_position<'info>(
+    ctx: Context<'_, '_, 'info, 'info, DecreasePosition<'info>>,
+    params: &DecreasePositionParams,
+) -> Result<()> {
+    // check permissions
+    msg!("Check permissions");
+    let holder = ctx
+        .accounts
+        .position
+        .get_holder(ctx.accounts.position_token_account.as_deref())?;
+    DelegateAuthority::validate_authority(
+        &ctx.accounts.authority.key(),
+        &holder,
+        // delegates are granted by the position account owner, not the token holder
+        ctx.accounts
+            .delegate_authority
+            .as_deref()
+            .filter(|_| !ctx.accounts.position.is_tokenized()),
+        DelegateAction::ClosePosition,
+    )?;
+    require_keys_eq!(
+        ctx.accounts.receiving_account.owner,
+        holder,
+        PerpetualsError::InvalidTokenAccountOwner
+    );
+    let perpetuals = ctx.accounts.perpetuals.as_mut();
+    let custody = ctx.accounts.custody.as_mut();
+    let collateral_custody = ctx.accounts.collateral_custody.as_mut();
+    require!(
+        perpetuals.permissions.allow_close_position
+            && custody.permissions.allow_close_position
+            && perpetuals.permissions.allow_direct_position
+            && custody.permissions.allow_direct_position,
+        PerpetualsError::InstructionNotAllowed
+    );
+
+    // validate inputs
+    msg!("Validate inputs");
+    let position = ctx.accounts.position.as_mut();
+    if params.price == 0 || params.size_usd == 0 || params.size_usd >= position.size_usd {
+        return Err(ProgramError::InvalidArgument.into());
+    }
+    let pool = ctx.accounts.pool.as_mut();
+    let mut margin_account = MarginAccount::validate_position_account(
+        position,
+        ctx.accounts.margin_account.as_deref_mut(),
+    )?;
+
+    // compute exit price
+    let curtime = perpetuals.get_time()?;
+
+    let token_price = OraclePrice::new_from_oracle(
+        &ctx.accounts.custody_oracle_account.to_account_info(),
+        &custody.oracle,
+        curtime,
+    )?;
+
+    let collateral_token_price = OraclePrice::new_from_oracle(
+        &ctx.accounts
+            .collateral_custody_oracle_account
+            .to_account_info(),
+        &collateral_custody.oracle,
+        curtime,
+    )?;
+
+    let exit_price = pool.get_exit_price(&token_price, custody, position.side, params.size_usd)?;
+    msg!("Exit price: {}", exit_price);
+
+    if position.side == Side::Long {
+        require_gte!(exit_price, params.price, PerpetualsError::MaxPriceSlippage);
+    } else {
+        require_gte!(params.price, exit_price, PerpetualsError::MaxPriceSlippage);
+    }
+
+    msg!("Settle partial position");
+    let closed_position = position.get_partial_position(params.size_usd)?;
+
+    let (transfer_amount, fee_amount, profit_usd, loss_usd) = pool.get_close_amount(
+        &closed_position,
+        &token_price,
+        custody,
+        &collateral_token_price,
+        collateral_custody,
+        curtime,
+        false,
+    )?;
+
+    msg!("Net profit: {}, loss: {}", profit_usd, loss_usd);
+    msg!("Collected fee: {}", fee_amount);
+    msg!("Amount out: {}", transfer_amount);
+
+    // update existing position
+    msg!("Update existing position");
+    let prev_position = position.clone();
+    position.update_time = curtime;
+    position.size_usd = math::checked_sub(position.size_usd, closed_position.size_usd)?;
+    position.borrow_size_usd =
+        math::checked_sub(position.borrow_size_usd, closed_position.borrow_size_usd)?;
+    position.collateral_usd =
+        math::checked_sub(position.collateral_usd, closed_position.collateral_usd)?;
+    position.unrealized_profit_usd = math::checked_sub(
+        position.unrealized_profit_usd,
+        closed_position.unrealized_profit_usd,
+    )?;
+    position.unrealized_loss_usd = math::checked_sub(
+        position.unrealized_loss_usd,
+        closed_position.unrealized_loss_usd,
+    )?;
+    position.locked_amount =
+        math::checked_sub(position.locked_amount, closed_position.locked_amount)?;
+    position.collateral_amount = math::checked_sub(
+        position.collateral_amount,
+        closed_position.collateral_amount,
+    )?;
+
+    // check position risk
+    msg!("Check position risks");
+    require!(
+        position.locked_amount > 0 && position.collateral_amount > 0,
+        PerpetualsError::InvalidPositionState
+    );
+    require!(
+        pool.check_leverage(
+            position,
+            &token_price,
+            custody,
+            &collateral_token_price,
+            collateral_custody,
+            curtime,
+            false
+        )?,
+        PerpetualsError::MaxLeverage
+    );
+
+    let protocol_fee = pool.settle_position(
+        position,
+        &prev_position,
+        &closed_position,
+        (transfer_amount, fee_amount, profit_usd, loss_usd),
+        if margin_account.is_some() {
+            transfer_amount
+        } else {
+            0
+        },
+        false,
+        custody,
+        &token_price,
+        collateral_custody,
+        &collateral_token_price,
+        ctx.accounts.insurance_fund.as_mut(),
+        margin_account
+            .as_deref_mut()
+            .map(|margin_account| &mut **margin_account),
+        ctx.remaining_accounts,
+        curtime,
+    )?;
+
+    // transfer tokens, cross margin payouts stay in custody as margin account collateral
+    msg!("Transfer tokens");
+    if margin_account.is_none() {
+        perpetuals.transfer_tokens(
+            ctx.accounts
+                .collateral_custody_token_account
+                .to_account_info(),
+            ctx.accounts.receiving_account.to_account_info(),
+            ctx.accounts.transfer_authority.to_account_info(),
+            ctx.accounts.token_program.to_account_info(),
+            transfer_amount,
+        )?;
+    }
+
+    emit!(events::DecreasePosition {
+        collateral_amount: position.collateral_amount,
+        collateral_custody: position.collateral_custody,
+        custody: position.custody,
+        owner: position.owner,
+        pool: position.pool,
+        price: exit_price,
+        side: position.side,
+        size_usd: position.size_usd,
+        time: curtime,
+        fee_amount,
+        loss_usd,
+        profit_usd,
+        protocol_fee,
+        transfer_amount,
+        size_delta_usd: closed_position.size_usd,
+    });
+    Ok(())
+}
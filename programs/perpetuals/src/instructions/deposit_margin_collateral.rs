@@ -0,0 +1,113 @@
+//! DepositMarginCollateral instruction handler
+
+use {
+    crate::{
+        math,
+        state::{
+            custody::Custody, margin_account::MarginAccount, perpetuals::Perpetuals, pool::Pool,
+        },
+    },
+    anchor_lang::prelude::*,
+    anchor_spl::token::{Token, TokenAccount},
+    solana_program::program_error::ProgramError,
+};
+
+#[derive(Accounts)]
+pub struct DepositMarginCollateral<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint = funding_account.mint == custody.mint,
+        has_one = owner
+    )]
+    pub funding_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        seeds = [b"pool", pool.name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        init_if_needed,
+        payer = owner,
+        space = MarginAccount::LEN,
+        seeds = [b"margin_account", owner.key().as_ref(), pool.key().as_ref()],
+        bump
+    )]
+    pub margin_account: Box<Account<'info, MarginAccount>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"custody",
+            pool.key().as_ref(),
+            custody.mint.as_ref()
+        ],
+        bump = custody.bump
+    )]
+    pub custody: Box<Account<'info, Custody>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"custody_token_account",
+            pool.key().as_ref(),
+            custody.mint.as_ref()
+        ],
+        bump = custody.token_account_bump
+    )]
+    pub custody_token_account: Box<Account<'info, TokenAccount>>,
+
+    system_program: Program<'info, System>,
+    token_program: Program<'info, Token>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct DepositMarginCollateralParams {
+    pub amount: u64,
+}
+
+pub fn deposit_margin_collateral<'info>(
+    ctx: Context<'_, '_, '_, 'info, DepositMarginCollateral<'info>>,
+    params: &DepositMarginCollateralParams,
+) -> Result<()> {
+    // validate inputs
+    msg!("Validate inputs");
+    if params.amount == 0 {
+        return Err(ProgramError::InvalidArgument.into());
+    }
+
+    // record collateral
+    msg!("Update margin account");
+    let custody = ctx.accounts.custody.as_mut();
+    let margin_account = ctx.accounts.margin_account.as_mut();
+    margin_account.owner = ctx.accounts.owner.key();
+    margin_account.pool = ctx.accounts.pool.key();
+    margin_account.bump = ctx.bumps.margin_account;
+    margin_account.add_collateral(&custody.key(), params.amount)?;
+
+    // transfer tokens
+    msg!("Transfer tokens");
+    ctx.accounts.perpetuals.transfer_tokens_from_user(
+        ctx.accounts.funding_account.to_account_info(),
+        ctx.accounts.custody_token_account.to_account_info(),
+        ctx.accounts.owner.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        params.amount,
+    )?;
+
+    // update custody stats
+    msg!("Update custody stats");
+    custody.assets.collateral = math::checked_add(custody.assets.collateral, params.amount)?;
+
+    Ok(())
+}
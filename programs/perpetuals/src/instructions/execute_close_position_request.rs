@@ -0,0 +1,288 @@
+//! ExecuteClosePositionRequest instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError,
+        events,
+        state::{
+            custody::Custody,
+            insurance_fund::InsuranceFund,
+            margin_account::MarginAccount,
+            oracle::OraclePrice,
+            perpetuals::Perpetuals,
+            pool::Pool,
+            position::{Position, Side},
+            position_request::{PositionRequest, RequestType},
+        },
+    },
+    anchor_lang::prelude::*,
+    anchor_spl::token::{Token, TokenAccount},
+};
+
+#[derive(Accounts)]
+pub struct ExecuteClosePositionRequest<'info> {
+    #[account(mut)]
+    pub keeper: Signer<'info>,
+
+    #[account(mut)]
+    pub owner: SystemAccount<'info>,
+
+    #[account(
+        mut,
+        constraint = receiving_account.mint == collateral_custody.mint,
+        constraint = receiving_account.owner == owner.key()
+    )]
+    pub receiving_account: Box<Account<'info, TokenAccount>>,
+
+    // required for cross margin positions
+    #[account(
+        mut,
+        seeds = [b"margin_account", position.owner.as_ref(), pool.key().as_ref()],
+        bump = margin_account.bump
+    )]
+    pub margin_account: Option<Box<Account<'info, MarginAccount>>>,
+
+    /// CHECK: empty PDA, authority for token accounts
+    #[account(
+        seeds = [b"transfer_authority"],
+        bump = perpetuals.transfer_authority_bump
+    )]
+    pub transfer_authority: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        mut,
+        seeds = [b"pool", pool.name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        seeds = [b"insurance_fund", pool.key().as_ref()],
+        bump = insurance_fund.bump
+    )]
+    pub insurance_fund: Box<Account<'info, InsuranceFund>>,
+
+    #[account(
+        mut,
+        has_one = owner,
+        constraint = !position.is_tokenized() @ PerpetualsError::TokenizedPosition,
+        seeds = [
+            b"position",
+            owner.key().as_ref(),
+            pool.key().as_ref(),
+            custody.key().as_ref(),
+            &[position.side as u8],
+            &position.index.to_le_bytes()
+        ],
+        bump = position.bump,
+        close = owner
+    )]
+    pub position: Box<Account<'info, Position>>,
+
+    #[account(
+        mut,
+        has_one = owner,
+        seeds = [
+            b"position_request",
+            owner.key().as_ref(),
+            pool.key().as_ref(),
+            custody.key().as_ref(),
+            &[position.side as u8],
+            &position.index.to_le_bytes()
+        ],
+        bump = position_request.bump,
+        constraint = position_request.request_type == RequestType::Close,
+        constraint = position_request.create_time > position.open_time @ PerpetualsError::StalePositionOrder,
+        close = owner
+    )]
+    pub position_request: Box<Account<'info, PositionRequest>>,
+
+    #[account(
+        mut,
+        constraint = position.custody == custody.key()
+    )]
+    pub custody: Box<Account<'info, Custody>>,
+
+    /// CHECK: oracle account for the position token
+    #[account(
+        constraint = custody_oracle_account.key() == custody.oracle.oracle_account
+    )]
+    pub custody_oracle_account: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        constraint = position.collateral_custody == collateral_custody.key()
+    )]
+    pub collateral_custody: Box<Account<'info, Custody>>,
+
+    /// CHECK: oracle account for the collateral token
+    #[account(
+        constraint = collateral_custody_oracle_account.key() == collateral_custody.oracle.oracle_account
+    )]
+    pub collateral_custody_oracle_account: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"custody_token_account",
+            pool.key().as_ref(),
+            collateral_custody.mint.as_ref()
+        ],
+        bump = collateral_custody.token_account_bump
+    )]
+    pub collateral_custody_token_account: Box<Account<'info, TokenAccount>>,
+
+    token_program: Program<'info, Token>,
+    // remaining accounts (cross margin positions only):
+    //   2 accounts per margin account collateral (writable, unsigned)
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct ExecuteClosePositionRequestParams {}
+
+pub fn execute_close_position_request<'info>(
+    ctx: Context<'_, '_, 'info, 'info, ExecuteClosePositionRequest<'info>>,
+    _params: &ExecuteClosePositionRequestParams,
+) -> Result<()> {
+    // check permissions
+    msg!("Check permissions");
+    let perpetuals = ctx.accounts.perpetuals.as_mut();
+    let custody = ctx.accounts.custody.as_mut();
+    let collateral_custody = ctx.accounts.collateral_custody.as_mut();
+    require!(
+        perpetuals.permissions.allow_close_position && custody.permissions.allow_close_position,
+        PerpetualsError::InstructionNotAllowed
+    );
+
+    let position_request = ctx.accounts.position_request.as_mut();
+    let position = ctx.accounts.position.as_mut();
+    let pool = ctx.accounts.pool.as_mut();
+    let mut margin_account = MarginAccount::validate_position_account(
+        position,
+        ctx.accounts.margin_account.as_deref_mut(),
+    )?;
+
+    // check if request can be executed
+    msg!("Check position request");
+    let curtime = perpetuals.get_time()?;
+
+    let publish_time = OraclePrice::get_publish_time(
+        &ctx.accounts.custody_oracle_account.to_account_info(),
+        &custody.oracle,
+    )?;
+    require!(
+        position_request.is_executable(publish_time, curtime),
+        PerpetualsError::PositionRequestNotExecutable
+    );
+
+    // compute exit price
+
+    let token_price = OraclePrice::new_from_oracle(
+        &ctx.accounts.custody_oracle_account.to_account_info(),
+        &custody.oracle,
+        curtime,
+    )?;
+
+    let collateral_token_price = OraclePrice::new_from_oracle(
+        &ctx.accounts
+            .collateral_custody_oracle_account
+            .to_account_info(),
+        &collateral_custody.oracle,
+        curtime,
+    )?;
+
+    let exit_price =
+        pool.get_exit_price(&token_price, custody, position.side, position.size_usd)?;
+    msg!("Exit price: {}", exit_price);
+
+    if position.side == Side::Long {
+        require_gte!(
+            exit_price,
+            position_request.price,
+            PerpetualsError::MaxPriceSlippage
+        );
+    } else {
+        require_gte!(
+            position_request.price,
+            exit_price,
+            PerpetualsError::MaxPriceSlippage
+        );
+    }
+
+    msg!("Settle position");
+    let (transfer_amount, fee_amount, profit_usd, loss_usd) = pool.get_close_amount(
+        position,
+        &token_price,
+        custody,
+        &collateral_token_price,
+        collateral_custody,
+        curtime,
+        false,
+    )?;
+
+    msg!("Net profit: {}, loss: {}", profit_usd, loss_usd);
+    msg!("Collected fee: {}", fee_amount);
+    msg!("Amount out: {}", transfer_amount);
+
+    let protocol_fee = pool.settle_position(
+        position,
+        position,
+        position,
+        (transfer_amount, fee_amount, profit_usd, loss_usd),
+        if margin_account.is_some() {
+            transfer_amount
+        } else {
+            0
+        },
+        false,
+        custody,
+        &token_price,
+        collateral_custody,
+        &collateral_token_price,
+        ctx.accounts.insurance_fund.as_mut(),
+        margin_account
+            .as_deref_mut()
+            .map(|margin_account| &mut **margin_account),
+        ctx.remaining_accounts,
+        curtime,
+    )?;
+
+    // transfer tokens, cross margin payouts stay in custody as margin account collateral
+    msg!("Transfer tokens");
+    if margin_account.is_none() {
+        perpetuals.transfer_tokens(
+            ctx.accounts
+                .collateral_custody_token_account
+                .to_account_info(),
+            ctx.accounts.receiving_account.to_account_info(),
+            ctx.accounts.transfer_authority.to_account_info(),
+            ctx.accounts.token_program.to_account_info(),
+            transfer_amount,
+        )?;
+    }
+
+    emit!(events::ClosePosition {
+        profit_usd,
+        loss_usd,
+        fee_amount,
+        transfer_amount,
+        protocol_fee,
+        collateral_amount: position.collateral_amount,
+        collateral_custody: position.collateral_custody,
+        custody: position.custody,
+        time: position.open_time,
+        owner: position.owner,
+        pool: position.pool,
+        price: exit_price,
+        side: position.side,
+        size_usd: position.size_usd,
+    });
+    Ok(())
+}
@@ -0,0 +1,421 @@
+//! ExecuteOpenPositionRequest instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError,
+        events, math,
+        state::{
+            custody::Custody,
+            insurance_fund::InsuranceFund,
+            oracle::OraclePrice,
+            perpetuals::Perpetuals,
+            pool::Pool,
+            position::{Position, Side},
+            position_request::{PositionRequest, RequestType},
+        },
+    },
+    anchor_lang::prelude::*,
+    anchor_spl::token::{Token, TokenAccount},
+};
+
+#[derive(Accounts)]
+pub struct ExecuteOpenPositionRequest<'info> {
+    #[account(mut)]
+    pub keeper: Signer<'info>,
+
+    #[account(mut)]
+    pub owner: SystemAccount<'info>,
+
+    #[account(
+        mut,
+        constraint = receiving_account.mint == collateral_custody.mint,
+        constraint = receiving_account.owner == owner.key()
+    )]
+    pub receiving_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: empty PDA, authority for token accounts
+    #[account(
+        seeds = [b"transfer_authority"],
+        bump = perpetuals.transfer_authority_bump
+    )]
+    pub transfer_authority: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        mut,
+        seeds = [b"pool", pool.name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        seeds = [b"insurance_fund", pool.key().as_ref()],
+        bump = insurance_fund.bump
+    )]
+    pub insurance_fund: Box<Account<'info, InsuranceFund>>,
+
+    #[account(
+        mut,
+        has_one = owner,
+        seeds = [
+            b"position_request",
+            owner.key().as_ref(),
+            pool.key().as_ref(),
+            custody.key().as_ref(),
+            &[position_request.side as u8],
+            &position_request.position_index.to_le_bytes()
+        ],
+        bump = position_request.bump,
+        constraint = position_request.request_type == RequestType::Open,
+        close = keeper
+    )]
+    pub position_request: Box<Account<'info, PositionRequest>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"position_request_escrow",
+            position_request.key().as_ref()
+        ],
+        bump = position_request.escrow_bump
+    )]
+    pub escrow_token_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        init,
+        payer = keeper,
+        space = Position::LEN,
+        seeds = [
+            b"position",
+            owner.key().as_ref(),
+            pool.key().as_ref(),
+            custody.key().as_ref(),
+            &[position_request.side as u8],
+            &position_request.position_index.to_le_bytes()
+        ],
+        bump
+    )]
+    pub position: Box<Account<'info, Position>>,
+
+    #[account(
+        mut,
+        constraint = position_request.custody == custody.key()
+    )]
+    pub custody: Box<Account<'info, Custody>>,
+
+    /// CHECK: oracle account for the position token
+    #[account(
+        constraint = custody_oracle_account.key() == custody.oracle.oracle_account
+    )]
+    pub custody_oracle_account: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        constraint = position_request.collateral_custody == collateral_custody.key()
+    )]
+    pub collateral_custody: Box<Account<'info, Custody>>,
+
+    /// CHECK: oracle account for the collateral token
+    #[account(
+        constraint = collateral_custody_oracle_account.key() == collateral_custody.oracle.oracle_account
+    )]
+    pub collateral_custody_oracle_account: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"custody_token_account",
+            pool.key().as_ref(),
+            collateral_custody.mint.as_ref()
+        ],
+        bump = collateral_custody.token_account_bump
+    )]
+    pub collateral_custody_token_account: Box<Account<'info, TokenAccount>>,
+
+    system_program: Program<'info, System>,
+    token_program: Program<'info, Token>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct ExecuteOpenPositionRequestParams {}
+
+pub fn execute_open_position_request<'info>(
+    ctx: Context<'_, '_, '_, 'info, ExecuteOpenPositionRequest<'info>>,
+    _params: &ExecuteOpenPositionRequestParams,
+) -> Result<()> {
+    // check permissions
+    msg!("Check permissions");
+    let perpetuals = ctx.accounts.perpetuals.as_mut();
+    let custody = ctx.accounts.custody.as_mut();
+    let collateral_custody = ctx.accounts.collateral_custody.as_mut();
+    require!(
+        perpetuals.permissions.allow_open_position
+            && custody.permissions.allow_open_position
+            && !custody.is_stable,
+        PerpetualsError::InstructionNotAllowed
+    );
+
+    let position_request = ctx.accounts.position_request.as_mut();
+    let position = ctx.accounts.position.as_mut();
+    let pool = ctx.accounts.pool.as_mut();
+
+    // check if request can be executed
+    msg!("Check position request");
+    let curtime = perpetuals.get_time()?;
+
+    let publish_time = OraclePrice::get_publish_time(
+        &ctx.accounts.custody_oracle_account.to_account_info(),
+        &custody.oracle,
+    )?;
+    require!(
+        position_request.is_executable(publish_time, curtime),
+        PerpetualsError::PositionRequestNotExecutable
+    );
+
+    // compute position price
+
+    let token_price = OraclePrice::new_from_oracle(
+        &ctx.accounts.custody_oracle_account.to_account_info(),
+        &custody.oracle,
+        curtime,
+    )?;
+
+    let collateral_token_price = OraclePrice::new_from_oracle(
+        &ctx.accounts
+            .collateral_custody_oracle_account
+            .to_account_info(),
+        &collateral_custody.oracle,
+        curtime,
+    )?;
+
+    let side = position_request.side;
+    let position_price = pool.get_entry_price(
+        &token_price,
+        custody,
+        side,
+        token_price.get_asset_amount_usd(position_request.size, custody.decimals)?,
+    )?;
+    msg!("Entry price: {}", position_price);
+
+    if side == Side::Long {
+        require_gte!(
+            position_request.price,
+            position_price,
+            PerpetualsError::MaxPriceSlippage
+        );
+    } else {
+        require_gte!(
+            position_price,
+            position_request.price,
+            PerpetualsError::MaxPriceSlippage
+        );
+    }
+
+    // compute position parameters
+    let position_oracle_price = OraclePrice {
+        price: position_price,
+        exponent: -(Perpetuals::PRICE_DECIMALS as i32),
+    };
+    let size_usd =
+        position_oracle_price.get_asset_amount_usd(position_request.size, custody.decimals)?;
+    let collateral_usd = collateral_token_price.get_asset_amount_usd(
+        position_request.collateral_amount,
+        collateral_custody.decimals,
+    )?;
+
+    // position size expressed in collateral tokens, used to compute fees
+    let (size, locked_amount, borrow_size_usd) = if custody.key() == collateral_custody.key() {
+        let locked_amount = custody.get_locked_amount(position_request.size, side)?;
+
+        // A better name would be "locked_amount_usd" (its the same)
+        let borrow_size_usd = if custody.pricing.max_payoff_mult as u128 != Perpetuals::BPS_POWER {
+            position_oracle_price.get_asset_amount_usd(locked_amount, custody.decimals)?
+        } else {
+            size_usd
+        };
+
+        (position_request.size, locked_amount, borrow_size_usd)
+    } else {
+        // stablecoin collateral is locked in the collateral custody
+        let locked_amount_usd = custody.get_locked_amount(size_usd, side)?;
+        (
+            collateral_token_price.get_token_amount(size_usd, collateral_custody.decimals)?,
+            collateral_token_price
+                .get_token_amount(locked_amount_usd, collateral_custody.decimals)?,
+            locked_amount_usd,
+        )
+    };
+
+    // compute fee
+    let fee_amount = pool.get_entry_fee(
+        custody.fees.open_position,
+        size,
+        locked_amount,
+        collateral_custody,
+    )?;
+    let fee_amount_usd =
+        collateral_token_price.get_asset_amount_usd(fee_amount, collateral_custody.decimals)?;
+    msg!("Collected fee: {}", fee_amount);
+
+    require!(
+        fee_amount <= position_request.max_fee,
+        PerpetualsError::InvalidPositionRequest
+    );
+
+    // compute amount to transfer
+    let transfer_amount = math::checked_add(position_request.collateral_amount, fee_amount)?;
+    let refund_amount =
+        math::checked_sub(ctx.accounts.escrow_token_account.amount, transfer_amount)?;
+    msg!("Amount in: {}", transfer_amount);
+    msg!("Amount refunded: {}", refund_amount);
+
+    // init new position
+    msg!("Initialize new position");
+    position.owner = position_request.owner;
+    position.pool = pool.key();
+    position.custody = custody.key();
+    position.collateral_custody = collateral_custody.key();
+    position.open_time = curtime;
+    position.update_time = 0;
+    position.vesting_start_time = position.open_time;
+    position.side = side;
+    position.index = position_request.position_index;
+    position.price = position_price;
+    position.size_usd = size_usd;
+    position.borrow_size_usd = borrow_size_usd;
+    position.collateral_usd = collateral_usd;
+    position.unrealized_profit_usd = 0;
+    position.unrealized_loss_usd = 0;
+    position.cumulative_interest_snapshot = collateral_custody.get_cumulative_interest(curtime)?;
+    position.cumulative_funding_snapshot = custody.get_cumulative_funding(side, curtime)?;
+    position.locked_amount = locked_amount;
+    position.collateral_amount = position_request.collateral_amount;
+    position.bump = ctx.bumps.position;
+
+    // check position risk
+    msg!("Check position risks");
+    require!(
+        position.locked_amount > 0,
+        PerpetualsError::InsufficientAmountReturned
+    );
+    require!(
+        pool.check_leverage(
+            position,
+            &token_price,
+            custody,
+            &collateral_token_price,
+            collateral_custody,
+            curtime,
+            true
+        )?,
+        PerpetualsError::MaxLeverage
+    );
+
+    // lock funds for potential profit payoff
+    collateral_custody.lock_funds(position.locked_amount)?;
+
+    // transfer tokens
+    msg!("Transfer tokens");
+    perpetuals.transfer_tokens(
+        ctx.accounts.escrow_token_account.to_account_info(),
+        ctx.accounts
+            .collateral_custody_token_account
+            .to_account_info(),
+        ctx.accounts.transfer_authority.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        transfer_amount,
+    )?;
+
+    if refund_amount > 0 {
+        perpetuals.transfer_tokens(
+            ctx.accounts.escrow_token_account.to_account_info(),
+            ctx.accounts.receiving_account.to_account_info(),
+            ctx.accounts.transfer_authority.to_account_info(),
+            ctx.accounts.token_program.to_account_info(),
+            refund_amount,
+        )?;
+    }
+
+    Perpetuals::close_token_account(
+        ctx.accounts.owner.to_account_info(),
+        ctx.accounts.escrow_token_account.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        ctx.accounts.transfer_authority.to_account_info(),
+        &[&[b"transfer_authority", &[perpetuals.transfer_authority_bump]]],
+    )?;
+
+    // update custody stats
+    msg!("Update custody stats");
+    collateral_custody.assets.collateral = math::checked_add(
+        collateral_custody.assets.collateral,
+        position_request.collateral_amount,
+    )?;
+
+    let protocol_fee = Pool::get_fee_amount(custody.fees.protocol_share, fee_amount)?;
+    collateral_custody.assets.protocol_fees =
+        math::checked_add(collateral_custody.assets.protocol_fees, protocol_fee)?;
+
+    let insurance_fee = Pool::get_fee_amount(custody.fees.insurance_share, fee_amount)?;
+    let insurance_fund = ctx.accounts.insurance_fund.as_mut();
+    insurance_fund.add_funds(pool.get_token_id(&collateral_custody.key())?, insurance_fee)?;
+
+    // update borrow stats of the custody lending the locked funds
+    collateral_custody.add_borrow(position, curtime)?;
+
+    // if custody and collateral_custody accounts are the same, ensure that data is in sync
+    if custody.key() == collateral_custody.key() {
+        *custody = collateral_custody.clone();
+    }
+
+    custody.collected_fees.open_position_usd = custody
+        .collected_fees
+        .open_position_usd
+        .wrapping_add(fee_amount_usd);
+
+    custody.volume_stats.open_position_usd = custody
+        .volume_stats
+        .open_position_usd
+        .wrapping_add(size_usd);
+
+    if side == Side::Long {
+        custody.trade_stats.oi_long_usd =
+            math::checked_add(custody.trade_stats.oi_long_usd, size_usd)?;
+    } else {
+        custody.trade_stats.oi_short_usd =
+            math::checked_add(custody.trade_stats.oi_short_usd, size_usd)?;
+    }
+
+    custody.add_position(position, &token_price, curtime)?;
+    custody.update_borrow_rate(curtime)?;
+    custody.update_funding_rate(curtime)?;
+
+    if custody.key() == collateral_custody.key() {
+        *collateral_custody = custody.clone();
+    } else {
+        collateral_custody.update_borrow_rate(curtime)?;
+    }
+
+    emit!(events::OpenPosition {
+        borrow_size_usd: position.borrow_size_usd,
+        collateral_amount: position.collateral_amount,
+        collateral_custody: position.collateral_custody,
+        collateral_usd: position.collateral_usd,
+        custody: position.custody,
+        locked_amount: position.locked_amount,
+        owner: position.owner,
+        pool: position.pool,
+        price: position.price,
+        side: position.side,
+        size_usd: position.size_usd,
+        time: position.open_time,
+        transfer_amount,
+    });
+
+    Ok(())
+}
@@ -0,0 +1,353 @@
+//! ExecuteTriggerOrder instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError,
+        events, math,
+        state::{
+            custody::Custody, insurance_fund::InsuranceFund, margin_account::MarginAccount,
+            oracle::OraclePrice, perpetuals::Perpetuals, pool::Pool, position::Position,
+            trigger_order::TriggerOrder,
+        },
+    },
+    anchor_lang::prelude::*,
+    anchor_spl::token::{Token, TokenAccount},
+};
+
+#[derive(Accounts)]
+pub struct ExecuteTriggerOrder<'info> {
+    #[account(mut)]
+    pub keeper: Signer<'info>,
+
+    #[account(mut)]
+    pub owner: SystemAccount<'info>,
+
+    #[account(
+        mut,
+        constraint = receiving_account.mint == collateral_custody.mint,
+        constraint = receiving_account.owner == position.owner
+    )]
+    pub receiving_account: Box<Account<'info, TokenAccount>>,
+
+    // required for cross margin positions
+    #[account(
+        mut,
+        seeds = [b"margin_account", position.owner.as_ref(), pool.key().as_ref()],
+        bump = margin_account.bump
+    )]
+    pub margin_account: Option<Box<Account<'info, MarginAccount>>>,
+
+    #[account(
+        mut,
+        constraint = keeper_receiving_account.mint == collateral_custody.mint,
+        constraint = keeper_receiving_account.owner == keeper.key()
+    )]
+    pub keeper_receiving_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: empty PDA, authority for token accounts
+    #[account(
+        seeds = [b"transfer_authority"],
+        bump = perpetuals.transfer_authority_bump
+    )]
+    pub transfer_authority: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        mut,
+        seeds = [b"pool", pool.name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        seeds = [b"insurance_fund", pool.key().as_ref()],
+        bump = insurance_fund.bump
+    )]
+    pub insurance_fund: Box<Account<'info, InsuranceFund>>,
+
+    #[account(
+        mut,
+        has_one = owner,
+        constraint = !position.is_tokenized() @ PerpetualsError::TokenizedPosition,
+        seeds = [
+            b"position",
+            position.owner.as_ref(),
+            pool.key().as_ref(),
+            custody.key().as_ref(),
+            &[position.side as u8],
+            &position.index.to_le_bytes()
+        ],
+        bump = position.bump
+    )]
+    pub position: Box<Account<'info, Position>>,
+
+    #[account(
+        mut,
+        has_one = owner,
+        has_one = position,
+        seeds = [
+            b"trigger_order",
+            position.key().as_ref(),
+            &[trigger_order.index]
+        ],
+        bump = trigger_order.bump,
+        constraint = trigger_order.create_time > position.open_time @ PerpetualsError::StalePositionOrder,
+        close = owner
+    )]
+    pub trigger_order: Box<Account<'info, TriggerOrder>>,
+
+    #[account(
+        mut,
+        constraint = position.custody == custody.key()
+    )]
+    pub custody: Box<Account<'info, Custody>>,
+
+    /// CHECK: oracle account for the position token
+    #[account(
+        constraint = custody_oracle_account.key() == custody.oracle.oracle_account
+    )]
+    pub custody_oracle_account: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        constraint = position.collateral_custody == collateral_custody.key()
+    )]
+    pub collateral_custody: Box<Account<'info, Custody>>,
+
+    /// CHECK: oracle account for the collateral token
+    #[account(
+        constraint = collateral_custody_oracle_account.key() == collateral_custody.oracle.oracle_account
+    )]
+    pub collateral_custody_oracle_account: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"custody_token_account",
+            pool.key().as_ref(),
+            collateral_custody.mint.as_ref()
+        ],
+        bump = collateral_custody.token_account_bump
+    )]
+    pub collateral_custody_token_account: Box<Account<'info, TokenAccount>>,
+
+    token_program: Program<'info, Token>,
+    // remaining accounts (cross margin positions only):
+    //   2 accounts per margin account collateral (writable, unsigned)
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct ExecuteTriggerOrderParams {}
+
+pub fn execute_trigger_order<'info>(
+    ctx: Context<'_, '_, 'info, 'info, ExecuteTriggerOrder<'info>>,
+    _params: &ExecuteTriggerOrderParams,
+) -> Result<()> {
+    // check permissions
+    msg!("Check permissions");
+    let perpetuals = ctx.accounts.perpetuals.as_mut();
+    let custody = ctx.accounts.custody.as_mut();
+    let collateral_custody = ctx.accounts.collateral_custody.as_mut();
+    require!(
+        perpetuals.permissions.allow_close_position && custody.permissions.allow_close_position,
+        PerpetualsError::InstructionNotAllowed
+    );
+
+    let position = ctx.accounts.position.as_mut();
+    let trigger_order = ctx.accounts.trigger_order.as_mut();
+    let pool = ctx.accounts.pool.as_mut();
+    let mut margin_account = MarginAccount::validate_position_account(
+        position,
+        ctx.accounts.margin_account.as_deref_mut(),
+    )?;
+
+    // check if order can be executed
+    msg!("Check trigger price");
+    let curtime = perpetuals.get_time()?;
+
+    let token_price = OraclePrice::new_from_oracle(
+        &ctx.accounts.custody_oracle_account.to_account_info(),
+        &custody.oracle,
+        curtime,
+    )?;
+
+    let collateral_token_price = OraclePrice::new_from_oracle(
+        &ctx.accounts
+            .collateral_custody_oracle_account
+            .to_account_info(),
+        &collateral_custody.oracle,
+        curtime,
+    )?;
+
+    let oracle_price = token_price
+        .scale_to_exponent(-(Perpetuals::PRICE_DECIMALS as i32))?
+        .price;
+    require!(
+        trigger_order.is_triggered(position, oracle_price),
+        PerpetualsError::TriggerPriceNotReached
+    );
+
+    let close_size_usd = trigger_order.get_close_size_usd(position);
+    let exit_price = pool.get_exit_price(&token_price, custody, position.side, close_size_usd)?;
+    msg!("Exit price: {}", exit_price);
+
+    msg!("Settle position");
+    let full_close = close_size_usd == position.size_usd;
+    let closed_position = position.get_partial_position(close_size_usd)?;
+
+    let (total_amount_out, fee_amount, profit_usd, loss_usd) = pool.get_close_amount(
+        &closed_position,
+        &token_price,
+        custody,
+        &collateral_token_price,
+        collateral_custody,
+        curtime,
+        false,
+    )?;
+
+    msg!("Net profit: {}, loss: {}", profit_usd, loss_usd);
+    msg!("Collected fee: {}", fee_amount);
+
+    // the keeper reward is charged on the closed size, the pool pays the part of it
+    // the position payout can't cover
+    let reward = Pool::get_fee_amount(
+        custody.fees.trigger_order,
+        collateral_token_price
+            .get_token_amount(closed_position.size_usd, collateral_custody.decimals)?,
+    )?;
+    let user_amount = total_amount_out.saturating_sub(reward);
+    let total_amount_out = math::checked_add(user_amount, reward)?;
+
+    msg!("Amount out: {}", user_amount);
+    msg!("Reward: {}", reward);
+
+    // update existing position
+    let prev_position = position.clone();
+    if !full_close {
+        msg!("Update existing position");
+        position.update_time = curtime;
+        position.trigger_orders = position.trigger_orders.saturating_sub(1);
+        position.size_usd = math::checked_sub(position.size_usd, closed_position.size_usd)?;
+        position.borrow_size_usd =
+            math::checked_sub(position.borrow_size_usd, closed_position.borrow_size_usd)?;
+        position.collateral_usd =
+            math::checked_sub(position.collateral_usd, closed_position.collateral_usd)?;
+        position.unrealized_profit_usd = math::checked_sub(
+            position.unrealized_profit_usd,
+            closed_position.unrealized_profit_usd,
+        )?;
+        position.unrealized_loss_usd = math::checked_sub(
+            position.unrealized_loss_usd,
+            closed_position.unrealized_loss_usd,
+        )?;
+        position.locked_amount =
+            math::checked_sub(position.locked_amount, closed_position.locked_amount)?;
+        position.collateral_amount = math::checked_sub(
+            position.collateral_amount,
+            closed_position.collateral_amount,
+        )?;
+
+        // check position risk
+        msg!("Check position risks");
+        require!(
+            position.locked_amount > 0 && position.collateral_amount > 0,
+            PerpetualsError::InvalidPositionState
+        );
+        require!(
+            pool.check_leverage(
+                position,
+                &token_price,
+                custody,
+                &collateral_token_price,
+                collateral_custody,
+                curtime,
+                false
+            )?,
+            PerpetualsError::MaxLeverage
+        );
+    }
+
+    let protocol_fee = pool.settle_position(
+        position,
+        &prev_position,
+        &closed_position,
+        (total_amount_out, fee_amount, profit_usd, loss_usd),
+        if margin_account.is_some() {
+            user_amount
+        } else {
+            0
+        },
+        false,
+        custody,
+        &token_price,
+        collateral_custody,
+        &collateral_token_price,
+        ctx.accounts.insurance_fund.as_mut(),
+        margin_account
+            .as_deref_mut()
+            .map(|margin_account| &mut **margin_account),
+        ctx.remaining_accounts,
+        curtime,
+    )?;
+
+    // transfer tokens, cross margin payouts stay in custody as margin account collateral
+    msg!("Transfer tokens");
+    if margin_account.is_none() {
+        perpetuals.transfer_tokens(
+            ctx.accounts
+                .collateral_custody_token_account
+                .to_account_info(),
+            ctx.accounts.receiving_account.to_account_info(),
+            ctx.accounts.transfer_authority.to_account_info(),
+            ctx.accounts.token_program.to_account_info(),
+            user_amount,
+        )?;
+    }
+
+    perpetuals.transfer_tokens(
+        ctx.accounts
+            .collateral_custody_token_account
+            .to_account_info(),
+        ctx.accounts.keeper_receiving_account.to_account_info(),
+        ctx.accounts.transfer_authority.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        reward,
+    )?;
+
+    emit!(events::ExecuteTriggerOrder {
+        collateral_amount: position.collateral_amount,
+        collateral_custody: position.collateral_custody,
+        custody: position.custody,
+        owner: position.owner,
+        pool: position.pool,
+        price: exit_price,
+        side: position.side,
+        size_usd: position.size_usd,
+        time: curtime,
+        fee_amount,
+        loss_usd,
+        profit_usd,
+        protocol_fee,
+        transfer_amount: user_amount,
+        keeper: ctx.accounts.keeper.key(),
+        order_type: trigger_order.order_type,
+        reward_amount: reward,
+        size_delta_usd: closed_position.size_usd,
+        trigger_price: trigger_order.trigger_price,
+    });
+
+    // close position account if the whole position has been settled
+    if full_close {
+        ctx.accounts
+            .position
+            .close(ctx.accounts.owner.to_account_info())?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,231 @@
+//! ExecuteWithdrawalRequest instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError,
+        math,
+        state::{
+            custody::Custody, oracle::OraclePrice, perpetuals::Perpetuals, pool::Pool,
+            withdrawal_request::WithdrawalRequest,
+        },
+    },
+    anchor_lang::prelude::*,
+    anchor_spl::token::{Mint, Token, TokenAccount},
+};
+
+#[derive(Accounts)]
+pub struct ExecuteWithdrawalRequest<'info> {
+    pub keeper: Signer<'info>,
+
+    #[account(mut)]
+    pub owner: SystemAccount<'info>,
+
+    #[account(
+        mut,
+        constraint = receiving_account.mint == custody.mint,
+        constraint = receiving_account.owner == owner.key()
+    )]
+    pub receiving_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: empty PDA, authority for token accounts
+    #[account(
+        seeds = [b"transfer_authority"],
+        bump = perpetuals.transfer_authority_bump
+    )]
+    pub transfer_authority: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        mut,
+        seeds = [b"pool", pool.name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        has_one = owner,
+        seeds = [
+            b"withdrawal_request",
+            owner.key().as_ref(),
+            pool.key().as_ref()
+        ],
+        bump = withdrawal_request.bump,
+        close = owner
+    )]
+    pub withdrawal_request: Box<Account<'info, WithdrawalRequest>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"withdrawal_request_escrow",
+            withdrawal_request.key().as_ref()
+        ],
+        bump = withdrawal_request.escrow_bump
+    )]
+    pub escrow_token_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = withdrawal_request.custody == custody.key()
+    )]
+    pub custody: Box<Account<'info, Custody>>,
+
+    /// CHECK: oracle account for the returned token
+    #[account(
+        constraint = custody_oracle_account.key() == custody.oracle.oracle_account
+    )]
+    pub custody_oracle_account: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"custody_token_account",
+            pool.key().as_ref(),
+            custody.mint.as_ref()
+        ],
+        bump = custody.token_account_bump
+    )]
+    pub custody_token_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"lp_token_mint", pool.key().as_ref()],
+        bump = pool.lp_token_bump
+    )]
+    pub lp_token_mint: Box<Account<'info, Mint>>,
+
+    token_program: Program<'info, Token>,
+    // remaining accounts:
+    //   pool.tokens.len() custody accounts (read-only, unsigned)
+    //   pool.tokens.len() custody oracles (read-only, unsigned)
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct ExecuteWithdrawalRequestParams {}
+
+pub fn execute_withdrawal_request<'info>(
+    ctx: Context<'_, '_, 'info, 'info, ExecuteWithdrawalRequest<'info>>,
+    _params: &ExecuteWithdrawalRequestParams,
+) -> Result<()> {
+    // check permissions
+    msg!("Check permissions");
+    let perpetuals = ctx.accounts.perpetuals.as_ref();
+    let custody = ctx.accounts.custody.as_mut();
+    require!(
+        perpetuals.permissions.allow_remove_liquidity && custody.permissions.allow_remove_liquidity,
+        PerpetualsError::InstructionNotAllowed
+    );
+
+    // check request execution window
+    msg!("Check withdrawal request");
+    let curtime = perpetuals.get_time()?;
+    let withdrawal_request = ctx.accounts.withdrawal_request.as_ref();
+    require!(
+        withdrawal_request.is_executable(curtime),
+        PerpetualsError::WithdrawalRequestNotExecutable
+    );
+    let lp_amount_in = withdrawal_request.lp_amount;
+
+    let pool = ctx.accounts.pool.as_mut();
+    let token_id = pool.get_token_id(&custody.key())?;
+
+    // compute assets under management
+    msg!("Compute assets under management");
+    pool.aum_usd = pool.get_assets_under_management_usd(ctx.remaining_accounts, curtime)?;
+
+    let token_price = OraclePrice::new_from_oracle(
+        &ctx.accounts.custody_oracle_account.to_account_info(),
+        &custody.oracle,
+        curtime,
+    )?;
+
+    // compute amount of tokens to return
+    let remove_amount_usd = math::checked_as_u64(math::checked_div(
+        math::checked_mul(pool.aum_usd, lp_amount_in as u128)?,
+        ctx.accounts.lp_token_mint.supply as u128,
+    )?)?;
+
+    let remove_amount = token_price.get_token_amount(remove_amount_usd, custody.decimals)?;
+
+    // calculate fee
+    let fee_amount =
+        pool.get_remove_liquidity_fee(token_id, remove_amount, custody, &token_price)?;
+    msg!("Collected fee: {}", fee_amount);
+
+    let transfer_amount = math::checked_sub(remove_amount, fee_amount)?;
+    msg!("Amount out: {}", transfer_amount);
+
+    require!(
+        transfer_amount >= withdrawal_request.min_amount_out,
+        PerpetualsError::MaxPriceSlippage
+    );
+
+    // check pool constraints
+    msg!("Check pool constraints");
+    let protocol_fee = Pool::get_fee_amount(custody.fees.protocol_share, fee_amount)?;
+    let withdrawal_amount = math::checked_add(transfer_amount, protocol_fee)?;
+
+    require!(
+        math::checked_sub(custody.assets.owned, custody.assets.locked)? >= withdrawal_amount,
+        PerpetualsError::CustodyAmountLimit
+    );
+
+    // transfer tokens
+    msg!("Transfer tokens");
+    perpetuals.transfer_tokens(
+        ctx.accounts.custody_token_account.to_account_info(),
+        ctx.accounts.receiving_account.to_account_info(),
+        ctx.accounts.transfer_authority.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        transfer_amount,
+    )?;
+
+    // burn escrowed lp tokens
+    msg!("Burn LP tokens");
+    perpetuals.burn_escrowed_tokens(
+        ctx.accounts.lp_token_mint.to_account_info(),
+        ctx.accounts.escrow_token_account.to_account_info(),
+        ctx.accounts.transfer_authority.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        lp_amount_in,
+    )?;
+
+    Perpetuals::close_token_account(
+        ctx.accounts.owner.to_account_info(),
+        ctx.accounts.escrow_token_account.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        ctx.accounts.transfer_authority.to_account_info(),
+        &[&[b"transfer_authority", &[perpetuals.transfer_authority_bump]]],
+    )?;
+
+    // update custody stats
+    msg!("Update custody stats");
+    custody.collected_fees.remove_liquidity_usd = custody
+        .collected_fees
+        .remove_liquidity_usd
+        .wrapping_add(token_price.get_asset_amount_usd(fee_amount, custody.decimals)?);
+
+    custody.volume_stats.remove_liquidity_usd = custody
+        .volume_stats
+        .remove_liquidity_usd
+        .wrapping_add(remove_amount_usd);
+
+    custody.assets.protocol_fees = math::checked_add(custody.assets.protocol_fees, protocol_fee)?;
+
+    custody.assets.owned = math::checked_sub(custody.assets.owned, withdrawal_amount)?;
+
+    custody.update_borrow_rate(curtime)?;
+
+    // update pool stats
+    msg!("Update pool stats");
+    custody.exit(&crate::ID)?;
+    pool.aum_usd = pool.get_assets_under_management_usd(ctx.remaining_accounts, curtime)?;
+
+    Ok(())
+}
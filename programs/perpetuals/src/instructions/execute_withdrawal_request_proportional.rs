@@ -0,0 +1,162 @@
+//! ExecuteWithdrawalRequestProportional instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError,
+        instructions::remove_liquidity_proportional::transfer_proportional_amounts,
+        math,
+        state::{perpetuals::Perpetuals, pool::Pool, withdrawal_request::WithdrawalRequest},
+    },
+    anchor_lang::prelude::*,
+    anchor_spl::token::{Mint, Token, TokenAccount},
+};
+
+#[derive(Accounts)]
+pub struct ExecuteWithdrawalRequestProportional<'info> {
+    pub keeper: Signer<'info>,
+
+    #[account(mut)]
+    pub owner: SystemAccount<'info>,
+
+    /// CHECK: empty PDA, authority for token accounts
+    #[account(
+        seeds = [b"transfer_authority"],
+        bump = perpetuals.transfer_authority_bump
+    )]
+    pub transfer_authority: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        mut,
+        seeds = [b"pool", pool.name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        has_one = owner,
+        constraint = withdrawal_request.is_proportional() @ PerpetualsError::InvalidWithdrawalRequest,
+        seeds = [
+            b"withdrawal_request",
+            owner.key().as_ref(),
+            pool.key().as_ref()
+        ],
+        bump = withdrawal_request.bump,
+        close = owner
+    )]
+    pub withdrawal_request: Box<Account<'info, WithdrawalRequest>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"withdrawal_request_escrow",
+            withdrawal_request.key().as_ref()
+        ],
+        bump = withdrawal_request.escrow_bump
+    )]
+    pub escrow_token_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"lp_token_mint", pool.key().as_ref()],
+        bump = pool.lp_token_bump
+    )]
+    pub lp_token_mint: Box<Account<'info, Mint>>,
+
+    token_program: Program<'info, Token>,
+    // remaining accounts:
+    //   pool.tokens.len() custody accounts (writable, unsigned)
+    //   pool.tokens.len() custody oracles (read-only, unsigned)
+    //   pool.tokens.len() custody token accounts (writable, unsigned)
+    //   pool.tokens.len() receiving token accounts (writable, unsigned)
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct ExecuteWithdrawalRequestProportionalParams {}
+
+pub fn execute_withdrawal_request_proportional<'info>(
+    ctx: Context<'_, '_, 'info, 'info, ExecuteWithdrawalRequestProportional<'info>>,
+    _params: &ExecuteWithdrawalRequestProportionalParams,
+) -> Result<()> {
+    // check permissions
+    msg!("Check permissions");
+    let perpetuals = ctx.accounts.perpetuals.as_ref();
+    require!(
+        perpetuals.permissions.allow_remove_liquidity,
+        PerpetualsError::InstructionNotAllowed
+    );
+
+    // check request execution window
+    msg!("Check withdrawal request");
+    let curtime = perpetuals.get_time()?;
+    let withdrawal_request = ctx.accounts.withdrawal_request.as_ref();
+    require!(
+        withdrawal_request.is_executable(curtime),
+        PerpetualsError::WithdrawalRequestNotExecutable
+    );
+    let lp_amount_in = withdrawal_request.lp_amount;
+
+    let pool = ctx.accounts.pool.as_mut();
+    let custodies_len = pool.custodies.len();
+    if ctx.remaining_accounts.len() < custodies_len * 4 {
+        return Err(ProgramError::NotEnoughAccountKeys.into());
+    }
+
+    // compute the value of the lp tokens, the same as for remove_liquidity_proportional
+    msg!("Compute assets under management");
+    let pool_amount_usd = pool
+        .get_assets_under_management_usd(&ctx.remaining_accounts[..custodies_len * 2], curtime)?;
+    let remove_amount_usd = math::checked_div(
+        math::checked_mul(pool_amount_usd, lp_amount_in as u128)?,
+        ctx.accounts.lp_token_mint.supply as u128,
+    )?;
+    msg!("Amount out usd: {}", remove_amount_usd);
+
+    require!(
+        remove_amount_usd >= withdrawal_request.min_amount_out as u128,
+        PerpetualsError::MaxPriceSlippage
+    );
+
+    transfer_proportional_amounts(
+        perpetuals,
+        pool,
+        remove_amount_usd,
+        &vec![0; custodies_len],
+        &ctx.accounts.owner.key(),
+        ctx.accounts.transfer_authority.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        ctx.remaining_accounts,
+        curtime,
+    )?;
+
+    // burn escrowed lp tokens
+    msg!("Burn LP tokens");
+    perpetuals.burn_escrowed_tokens(
+        ctx.accounts.lp_token_mint.to_account_info(),
+        ctx.accounts.escrow_token_account.to_account_info(),
+        ctx.accounts.transfer_authority.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        lp_amount_in,
+    )?;
+
+    Perpetuals::close_token_account(
+        ctx.accounts.owner.to_account_info(),
+        ctx.accounts.escrow_token_account.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        ctx.accounts.transfer_authority.to_account_info(),
+        &[&[b"transfer_authority", &[perpetuals.transfer_authority_bump]]],
+    )?;
+
+    // update pool stats
+    msg!("Update pool stats");
+    pool.aum_usd = pool
+        .get_assets_under_management_usd(&ctx.remaining_accounts[..custodies_len * 2], curtime)?;
+
+    Ok(())
+}
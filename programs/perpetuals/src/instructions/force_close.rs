@@ -3,14 +3,16 @@
 use {
     crate::{
         error::PerpetualsError,
-        events, math,
+        events,
         state::{
             custody::Custody,
+            insurance_fund::InsuranceFund,
+            margin_account::MarginAccount,
             multisig::{AdminInstruction, Multisig},
             oracle::OraclePrice,
             perpetuals::Perpetuals,
             pool::Pool,
-            position::{Position, Side},
+            position::Position,
         },
     },
     anchor_lang::prelude::*,
@@ -34,12 +36,20 @@ pub struct ForceClose<'info> {
 
     #[account(
         mut,
-        constraint = receiving_account.mint == custody.mint,
+        constraint = receiving_account.mint == collateral_custody.mint,
         // Allow the tokens for force close to go anywhere
         // constraint = receiving_account.owner == position.owner
     )]
     pub receiving_account: Box<Account<'info, TokenAccount>>,
 
+    // required for cross margin positions
+    #[account(
+        mut,
+        seeds = [b"margin_account", position.owner.as_ref(), pool.key().as_ref()],
+        bump = margin_account.bump
+    )]
+    pub margin_account: Option<Box<Account<'info, MarginAccount>>>,
+
     /// CHECK: empty PDA, authority for token accounts
     #[account(
         seeds = [b"transfer_authority"],
@@ -60,6 +70,13 @@ pub struct ForceClose<'info> {
     )]
     pub pool: Box<Account<'info, Pool>>,
 
+    #[account(
+        mut,
+        seeds = [b"insurance_fund", pool.key().as_ref()],
+        bump = insurance_fund.bump
+    )]
+    pub insurance_fund: Box<Account<'info, InsuranceFund>>,
+
     #[account(
         mut,
         seeds = [
@@ -67,7 +84,8 @@ pub struct ForceClose<'info> {
             position.owner.as_ref(),
             pool.key().as_ref(),
             custody.key().as_ref(),
-            &[Side::Long as u8]
+            &[position.side as u8],
+            &position.index.to_le_bytes()
         ],
         bump = position.bump,
         close = owner
@@ -86,25 +104,39 @@ pub struct ForceClose<'info> {
     )]
     pub custody_oracle_account: UncheckedAccount<'info>,
 
+    #[account(
+        mut,
+        constraint = position.collateral_custody == collateral_custody.key()
+    )]
+    pub collateral_custody: Box<Account<'info, Custody>>,
+
+    /// CHECK: oracle account for the collateral token
+    #[account(
+        constraint = collateral_custody_oracle_account.key() == collateral_custody.oracle.oracle_account
+    )]
+    pub collateral_custody_oracle_account: UncheckedAccount<'info>,
+
     #[account(
         mut,
         seeds = [
             b"custody_token_account",
             pool.key().as_ref(),
-            custody.mint.as_ref()
+            collateral_custody.mint.as_ref()
         ],
-        bump = custody.token_account_bump
+        bump = collateral_custody.token_account_bump
     )]
-    pub custody_token_account: Box<Account<'info, TokenAccount>>,
+    pub collateral_custody_token_account: Box<Account<'info, TokenAccount>>,
 
     token_program: Program<'info, Token>,
+    // remaining accounts (cross margin positions only):
+    //   2 accounts per margin account collateral (writable, unsigned)
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize)]
 pub struct ForceCloseParams {}
 
 pub fn force_close<'info>(
-    ctx: Context<'_, '_, '_, 'info, ForceClose<'info>>,
+    ctx: Context<'_, '_, 'info, 'info, ForceClose<'info>>,
     params: &ForceCloseParams,
 ) -> Result<u8> {
     // validate signatures
@@ -127,6 +159,7 @@ pub fn force_close<'info>(
     msg!("Check permissions");
     let perpetuals = ctx.accounts.perpetuals.as_mut();
     let custody = ctx.accounts.custody.as_mut();
+    let collateral_custody = ctx.accounts.collateral_custody.as_mut();
     require!(
         perpetuals.permissions.allow_close_position && custody.permissions.allow_close_position,
         PerpetualsError::InstructionNotAllowed
@@ -134,6 +167,10 @@ pub fn force_close<'info>(
 
     let position = ctx.accounts.position.as_mut();
     let pool = ctx.accounts.pool.as_mut();
+    let mut margin_account = MarginAccount::validate_position_account(
+        position,
+        ctx.accounts.margin_account.as_deref_mut(),
+    )?;
 
     // compute exit price
     let curtime = perpetuals.get_time()?;
@@ -144,82 +181,70 @@ pub fn force_close<'info>(
         curtime,
     )?;
 
-    let exit_price = pool.get_exit_price(&token_price, custody)?;
+    let collateral_token_price = OraclePrice::new_from_oracle(
+        &ctx.accounts
+            .collateral_custody_oracle_account
+            .to_account_info(),
+        &collateral_custody.oracle,
+        curtime,
+    )?;
+
+    let exit_price =
+        pool.get_exit_price(&token_price, custody, position.side, position.size_usd)?;
     msg!("Exit price: {}", exit_price);
 
     msg!("Settle position");
-    let (transfer_amount, fee_amount, profit_usd, loss_usd) =
-        pool.get_close_amount(position, &token_price, custody, curtime, false)?;
-
-    let fee_amount_usd = token_price.get_asset_amount_usd(fee_amount, custody.decimals)?;
+    let (transfer_amount, fee_amount, profit_usd, loss_usd) = pool.get_close_amount(
+        position,
+        &token_price,
+        custody,
+        &collateral_token_price,
+        collateral_custody,
+        curtime,
+        false,
+    )?;
 
     msg!("Net profit: {}, loss: {}", profit_usd, loss_usd);
     msg!("Collected fee: {}", fee_amount);
     msg!("Amount out: {}", transfer_amount);
 
-    // unlock pool funds
-    custody.unlock_funds(position.locked_amount)?;
-
-    // check pool constraints
-    msg!("Check pool constraints");
-    require!(
-        pool.check_available_amount(transfer_amount, custody)?,
-        PerpetualsError::CustodyAmountLimit
-    );
-
-    // transfer tokens
-    msg!("Transfer tokens");
-    perpetuals.transfer_tokens(
-        ctx.accounts.custody_token_account.to_account_info(),
-        ctx.accounts.receiving_account.to_account_info(),
-        ctx.accounts.transfer_authority.to_account_info(),
-        ctx.accounts.token_program.to_account_info(),
-        transfer_amount,
+    let protocol_fee = pool.settle_position(
+        position,
+        position,
+        position,
+        (transfer_amount, fee_amount, profit_usd, loss_usd),
+        if margin_account.is_some() {
+            transfer_amount
+        } else {
+            0
+        },
+        false,
+        custody,
+        &token_price,
+        collateral_custody,
+        &collateral_token_price,
+        ctx.accounts.insurance_fund.as_mut(),
+        margin_account
+            .as_deref_mut()
+            .map(|margin_account| &mut **margin_account),
+        ctx.remaining_accounts,
+        curtime,
     )?;
 
-    // update custody stats
-    msg!("Update custody stats");
-    custody.collected_fees.close_position_usd = custody
-        .collected_fees
-        .close_position_usd
-        .wrapping_add(fee_amount_usd);
-
-    if transfer_amount > position.collateral_amount {
-        let amount_lost = transfer_amount.saturating_sub(position.collateral_amount);
-        custody.assets.owned = math::checked_sub(custody.assets.owned, amount_lost)?;
-    } else {
-        let amount_gained = position.collateral_amount.saturating_sub(transfer_amount);
-        custody.assets.owned = math::checked_add(custody.assets.owned, amount_gained)?;
-    }
-    custody.assets.collateral =
-        math::checked_sub(custody.assets.collateral, position.collateral_amount)?;
-
-    let protocol_fee = Pool::get_fee_amount(custody.fees.protocol_share, fee_amount)?;
-
-    // Pay protocol_fee from custody if possible, otherwise no protocol_fee
-    if pool.check_available_amount(protocol_fee, custody)? {
-        custody.assets.protocol_fees =
-            math::checked_add(custody.assets.protocol_fees, protocol_fee)?;
-
-        custody.assets.owned = math::checked_sub(custody.assets.owned, protocol_fee)?;
+    // transfer tokens, cross margin payouts stay in custody as margin account collateral
+    msg!("Transfer tokens");
+    if margin_account.is_none() {
+        perpetuals.transfer_tokens(
+            ctx.accounts
+                .collateral_custody_token_account
+                .to_account_info(),
+            ctx.accounts.receiving_account.to_account_info(),
+            ctx.accounts.transfer_authority.to_account_info(),
+            ctx.accounts.token_program.to_account_info(),
+            transfer_amount,
+        )?;
     }
 
-    custody.volume_stats.close_position_usd = custody
-        .volume_stats
-        .close_position_usd
-        .wrapping_add(position.size_usd);
-
-    custody.trade_stats.oi_long_usd = custody
-        .trade_stats
-        .oi_long_usd
-        .saturating_sub(position.size_usd);
-
-    custody.trade_stats.profit_usd = custody.trade_stats.profit_usd.wrapping_add(profit_usd);
-    custody.trade_stats.loss_usd = custody.trade_stats.loss_usd.wrapping_add(loss_usd);
-
-    custody.remove_position(position, curtime)?;
-    custody.update_borrow_rate(curtime)?;
-
     emit!(events::ClosePosition {
         profit_usd,
         loss_usd,
@@ -227,11 +252,13 @@ pub fn force_close<'info>(
         transfer_amount,
         protocol_fee,
         collateral_amount: position.collateral_amount,
+        collateral_custody: position.collateral_custody,
         custody: position.custody,
         time: position.open_time,
         owner: position.owner,
         pool: position.pool,
         price: exit_price,
+        side: position.side,
         size_usd: position.size_usd,
     });
     Ok(0)
@@ -65,7 +65,7 @@ pub fn get_add_liquidity_amount_and_fee<'info>(
     if params.amount_in == 0 {
         return Err(ProgramError::InvalidArgument.into());
     }
-    let pool = &ctx.accounts.pool;
+    let mut pool = Pool::clone(&ctx.accounts.pool);
     let custody = &ctx.accounts.custody;
     let token_id = pool.get_token_id(&custody.key())?;
 
@@ -78,12 +78,14 @@ pub fn get_add_liquidity_amount_and_fee<'info>(
         curtime,
     )?;
 
+    // the fee depends on the custody share of the current pool value
+    let pool_amount_usd = pool.get_assets_under_management_usd(ctx.remaining_accounts, curtime)?;
+    pool.aum_usd = pool_amount_usd;
+
     let fee_amount =
         pool.get_add_liquidity_fee(token_id, params.amount_in, custody, &token_price)?;
     let no_fee_amount = math::checked_sub(params.amount_in, fee_amount)?;
 
-    let pool_amount_usd = pool.get_assets_under_management_usd(ctx.remaining_accounts, curtime)?;
-
     let token_amount_usd = token_price.get_asset_amount_usd(no_fee_amount, custody.decimals)?;
 
     let lp_amount = if pool_amount_usd == 0 {
@@ -1,12 +1,15 @@
 //! GetEntryPriceAndFee instruction handler
 
 use {
-    crate::state::{
-        custody::Custody,
-        oracle::OraclePrice,
-        perpetuals::{NewPositionPricesAndFee, Perpetuals},
-        pool::Pool,
-        position::Position,
+    crate::{
+        error::PerpetualsError,
+        state::{
+            custody::Custody,
+            oracle::OraclePrice,
+            perpetuals::{NewPositionPricesAndFee, Perpetuals},
+            pool::Pool,
+            position::{Position, Side},
+        },
     },
     anchor_lang::prelude::*,
     solana_program::program_error::ProgramError,
@@ -36,17 +39,34 @@ pub struct GetEntryPriceAndFee<'info> {
     )]
     pub custody: Box<Account<'info, Custody>>,
 
-    /// CHECK: oracle account for the collateral token
+    /// CHECK: oracle account for the position token
     #[account(
         constraint = custody_oracle_account.key() == custody.oracle.oracle_account
     )]
     pub custody_oracle_account: UncheckedAccount<'info>,
+
+    #[account(
+        seeds = [
+            b"custody",
+            pool.key().as_ref(),
+            collateral_custody.mint.as_ref()
+        ],
+        bump = collateral_custody.bump
+    )]
+    pub collateral_custody: Box<Account<'info, Custody>>,
+
+    /// CHECK: oracle account for the collateral token
+    #[account(
+        constraint = collateral_custody_oracle_account.key() == collateral_custody.oracle.oracle_account
+    )]
+    pub collateral_custody_oracle_account: UncheckedAccount<'info>,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize)]
 pub struct GetEntryPriceAndFeeParams {
     collateral: u64,
     size: u64,
+    side: Side,
 }
 
 pub fn get_entry_price_and_fee<'info>(
@@ -54,11 +74,18 @@ pub fn get_entry_price_and_fee<'info>(
     params: &GetEntryPriceAndFeeParams,
 ) -> Result<NewPositionPricesAndFee> {
     // validate inputs
-    if params.collateral == 0 || params.size == 0 {
+    if params.collateral == 0 || params.size == 0 || params.side == Side::None {
         return Err(ProgramError::InvalidArgument.into());
     }
     let pool = &ctx.accounts.pool;
     let custody = &ctx.accounts.custody;
+    let collateral_custody = &ctx.accounts.collateral_custody;
+
+    require!(
+        collateral_custody.is_stable
+            || (params.side == Side::Long && custody.key() == collateral_custody.key()),
+        PerpetualsError::InvalidCollateralCustody
+    );
 
     // compute position price
     let curtime = ctx.accounts.perpetuals.get_time()?;
@@ -69,32 +96,63 @@ pub fn get_entry_price_and_fee<'info>(
         curtime,
     )?;
 
-    let entry_price = pool.get_entry_price(&token_price, custody)?;
+    let collateral_token_price = OraclePrice::new_from_oracle(
+        &ctx.accounts
+            .collateral_custody_oracle_account
+            .to_account_info(),
+        &collateral_custody.oracle,
+        curtime,
+    )?;
+
+    let entry_price = pool.get_entry_price(
+        &token_price,
+        custody,
+        params.side,
+        token_price.get_asset_amount_usd(params.size, custody.decimals)?,
+    )?;
 
     let position_oracle_price = OraclePrice {
         price: entry_price,
         exponent: -(Perpetuals::PRICE_DECIMALS as i32),
     };
     let size_usd = position_oracle_price.get_asset_amount_usd(params.size, custody.decimals)?;
-    let collateral_usd = token_price.get_asset_amount_usd(params.collateral, custody.decimals)?;
-
-    let locked_amount = custody.get_locked_amount(params.size)?;
+    let collateral_usd = collateral_token_price
+        .get_asset_amount_usd(params.collateral, collateral_custody.decimals)?;
+
+    let (size, locked_amount) = if custody.key() == collateral_custody.key() {
+        (
+            params.size,
+            custody.get_locked_amount(params.size, params.side)?,
+        )
+    } else {
+        let locked_amount_usd = custody.get_locked_amount(size_usd, params.side)?;
+        (
+            collateral_token_price.get_token_amount(size_usd, collateral_custody.decimals)?,
+            collateral_token_price
+                .get_token_amount(locked_amount_usd, collateral_custody.decimals)?,
+        )
+    };
 
     let position = Position {
+        custody: custody.key(),
+        collateral_custody: collateral_custody.key(),
+        side: params.side,
         price: entry_price,
         size_usd,
         collateral_usd,
-        cumulative_interest_snapshot: custody.get_cumulative_interest(curtime)?,
+        cumulative_interest_snapshot: collateral_custody.get_cumulative_interest(curtime)?,
+        cumulative_funding_snapshot: custody.get_cumulative_funding(params.side, curtime)?,
         ..Position::default()
     };
 
-    let liquidation_price = pool.get_liquidation_price(&position, custody, curtime)?;
+    let liquidation_price =
+        pool.get_liquidation_price(&position, custody, collateral_custody, curtime)?;
 
     let fee = pool.get_entry_fee(
         custody.fees.open_position,
-        params.size,
+        size,
         locked_amount,
-        custody,
+        collateral_custody,
     )?;
 
     Ok(NewPositionPricesAndFee {
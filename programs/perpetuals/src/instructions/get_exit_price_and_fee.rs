@@ -6,7 +6,7 @@ use {
         oracle::OraclePrice,
         perpetuals::{Perpetuals, PriceAndFee},
         pool::Pool,
-        position::{Position, Side},
+        position::Position,
     },
     anchor_lang::prelude::*,
 };
@@ -31,7 +31,8 @@ pub struct GetExitPriceAndFee<'info> {
             position.owner.as_ref(),
             pool.key().as_ref(),
             custody.key().as_ref(),
-            &[Side::Long as u8]
+            &[position.side as u8],
+            &position.index.to_le_bytes()
         ],
         bump = position.bump
     )]
@@ -47,11 +48,22 @@ pub struct GetExitPriceAndFee<'info> {
     )]
     pub custody: Box<Account<'info, Custody>>,
 
-    /// CHECK: oracle account for the collateral token
+    /// CHECK: oracle account for the position token
     #[account(
         constraint = custody_oracle_account.key() == custody.oracle.oracle_account
     )]
     pub custody_oracle_account: UncheckedAccount<'info>,
+
+    #[account(
+        constraint = position.collateral_custody == collateral_custody.key()
+    )]
+    pub collateral_custody: Box<Account<'info, Custody>>,
+
+    /// CHECK: oracle account for the collateral token
+    #[account(
+        constraint = collateral_custody_oracle_account.key() == collateral_custody.oracle.oracle_account
+    )]
+    pub collateral_custody_oracle_account: UncheckedAccount<'info>,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize)]
@@ -66,6 +78,7 @@ pub fn get_exit_price_and_fee<'info>(
     let pool = &ctx.accounts.pool;
     let curtime = ctx.accounts.perpetuals.get_time()?;
     let custody = &ctx.accounts.custody;
+    let collateral_custody = &ctx.accounts.collateral_custody;
 
     let token_price = OraclePrice::new_from_oracle(
         &ctx.accounts.custody_oracle_account.to_account_info(),
@@ -73,9 +86,25 @@ pub fn get_exit_price_and_fee<'info>(
         curtime,
     )?;
 
-    let price = pool.get_exit_price(&token_price, custody)?;
+    let collateral_token_price = OraclePrice::new_from_oracle(
+        &ctx.accounts
+            .collateral_custody_oracle_account
+            .to_account_info(),
+        &collateral_custody.oracle,
+        curtime,
+    )?;
+
+    let price = pool.get_exit_price(&token_price, custody, position.side, position.size_usd)?;
 
-    let (_, _, fee) = pool.get_pnl_usd(position, &token_price, custody, curtime, false)?;
+    let (_, _, fee) = pool.get_pnl_usd(
+        position,
+        &token_price,
+        custody,
+        &collateral_token_price,
+        collateral_custody,
+        curtime,
+        false,
+    )?;
 
     Ok(PriceAndFee { price, fee })
 }
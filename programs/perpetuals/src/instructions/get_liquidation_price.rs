@@ -4,11 +4,8 @@ use {
     crate::{
         math,
         state::{
-            custody::Custody,
-            oracle::OraclePrice,
-            perpetuals::Perpetuals,
-            pool::Pool,
-            position::{Position, Side},
+            custody::Custody, oracle::OraclePrice, perpetuals::Perpetuals, pool::Pool,
+            position::Position,
         },
     },
     anchor_lang::prelude::*,
@@ -34,7 +31,8 @@ pub struct GetLiquidationPrice<'info> {
             position.owner.as_ref(),
             pool.key().as_ref(),
             custody.key().as_ref(),
-            &[Side::Long as u8]
+            &[position.side as u8],
+            &position.index.to_le_bytes()
         ],
         bump = position.bump
     )]
@@ -50,11 +48,22 @@ pub struct GetLiquidationPrice<'info> {
     )]
     pub custody: Box<Account<'info, Custody>>,
 
-    /// CHECK: oracle account for the collateral token
+    /// CHECK: oracle account for the position token
     #[account(
         constraint = custody_oracle_account.key() == custody.oracle.oracle_account
     )]
     pub custody_oracle_account: UncheckedAccount<'info>,
+
+    #[account(
+        constraint = position.collateral_custody == collateral_custody.key()
+    )]
+    pub collateral_custody: Box<Account<'info, Custody>>,
+
+    /// CHECK: oracle account for the collateral token
+    #[account(
+        constraint = collateral_custody_oracle_account.key() == collateral_custody.oracle.oracle_account
+    )]
+    pub collateral_custody_oracle_account: UncheckedAccount<'info>,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize)]
@@ -68,11 +77,14 @@ pub fn get_liquidation_price<'info>(
     params: &GetLiquidationPriceParams,
 ) -> Result<u64> {
     let custody = &ctx.accounts.custody;
+    let collateral_custody = &ctx.accounts.collateral_custody;
     let curtime = ctx.accounts.perpetuals.get_time()?;
 
-    let token_price = OraclePrice::new_from_oracle(
-        &ctx.accounts.custody_oracle_account.to_account_info(),
-        &custody.oracle,
+    let collateral_token_price = OraclePrice::new_from_oracle(
+        &ctx.accounts
+            .collateral_custody_oracle_account
+            .to_account_info(),
+        &collateral_custody.oracle,
         curtime,
     )?;
 
@@ -80,15 +92,15 @@ pub fn get_liquidation_price<'info>(
     position.update_time = ctx.accounts.perpetuals.get_time()?;
 
     if params.add_collateral > 0 {
-        let collateral_usd =
-            token_price.get_asset_amount_usd(params.add_collateral, custody.decimals)?;
+        let collateral_usd = collateral_token_price
+            .get_asset_amount_usd(params.add_collateral, collateral_custody.decimals)?;
         position.collateral_usd = math::checked_add(position.collateral_usd, collateral_usd)?;
         position.collateral_amount =
             math::checked_add(position.collateral_amount, params.add_collateral)?;
     }
     if params.remove_collateral > 0 {
-        let collateral_usd =
-            token_price.get_asset_amount_usd(params.remove_collateral, custody.decimals)?;
+        let collateral_usd = collateral_token_price
+            .get_asset_amount_usd(params.remove_collateral, collateral_custody.decimals)?;
         if collateral_usd >= position.collateral_usd
             || params.remove_collateral >= position.collateral_amount
         {
@@ -101,5 +113,5 @@ pub fn get_liquidation_price<'info>(
 
     ctx.accounts
         .pool
-        .get_liquidation_price(&position, custody, curtime)
+        .get_liquidation_price(&position, custody, collateral_custody, curtime)
 }
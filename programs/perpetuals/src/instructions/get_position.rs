@@ -2,11 +2,8 @@ use {
     crate::{
         math,
         state::{
-            custody::Custody,
-            oracle::OraclePrice,
-            perpetuals::Perpetuals,
-            pool::Pool,
-            position::{Position, Side},
+            custody::Custody, oracle::OraclePrice, perpetuals::Perpetuals, pool::Pool,
+            position::Position,
         },
     },
     anchor_lang::prelude::*,
@@ -32,7 +29,8 @@ pub struct GetPosition<'info> {
             position.owner.as_ref(),
             pool.key().as_ref(),
             custody.key().as_ref(),
-            &[Side::Long as u8]
+            &[position.side as u8],
+            &position.index.to_le_bytes()
         ],
         bump = position.bump
     )]
@@ -48,11 +46,22 @@ pub struct GetPosition<'info> {
     )]
     pub custody: Box<Account<'info, Custody>>,
 
-    /// CHECK: oracle account for the collateral token
+    /// CHECK: oracle account for the position token
     #[account(
         constraint = custody_oracle_account.key() == custody.oracle.oracle_account
     )]
     pub custody_oracle_account: UncheckedAccount<'info>,
+
+    #[account(
+        constraint = position.collateral_custody == collateral_custody.key()
+    )]
+    pub collateral_custody: Box<Account<'info, Custody>>,
+
+    /// CHECK: oracle account for the collateral token
+    #[account(
+        constraint = collateral_custody_oracle_account.key() == collateral_custody.oracle.oracle_account
+    )]
+    pub collateral_custody_oracle_account: UncheckedAccount<'info>,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize)]
@@ -78,6 +87,7 @@ pub fn get_position<'info>(
     let pool = &ctx.accounts.pool;
     let curtime = ctx.accounts.perpetuals.get_time()?;
     let custody = &ctx.accounts.custody;
+    let collateral_custody = &ctx.accounts.collateral_custody;
 
     let token_price = OraclePrice::new_from_oracle(
         &ctx.accounts.custody_oracle_account.to_account_info(),
@@ -85,30 +95,55 @@ pub fn get_position<'info>(
         curtime,
     )?;
 
+    let collateral_token_price = OraclePrice::new_from_oracle(
+        &ctx.accounts
+            .collateral_custody_oracle_account
+            .to_account_info(),
+        &collateral_custody.oracle,
+        curtime,
+    )?;
+
     // compute pnl
-    let (profit, loss, _) = pool.get_pnl_usd(position, &token_price, custody, curtime, false)?;
+    let (profit, loss, _) = pool.get_pnl_usd(
+        position,
+        &token_price,
+        custody,
+        &collateral_token_price,
+        collateral_custody,
+        curtime,
+        false,
+    )?;
 
-    let liquidation_price = ctx
-        .accounts
-        .pool
-        .get_liquidation_price(position, custody, curtime)?;
+    let liquidation_price =
+        ctx.accounts
+            .pool
+            .get_liquidation_price(position, custody, collateral_custody, curtime)?;
 
-    let leverage = ctx
-        .accounts
-        .pool
-        .get_leverage(position, &token_price, custody, curtime)?;
+    let leverage = ctx.accounts.pool.get_leverage(
+        position,
+        &token_price,
+        custody,
+        &collateral_token_price,
+        collateral_custody,
+        curtime,
+    )?;
 
     let leverage_check = ctx.accounts.pool.check_leverage(
         &ctx.accounts.position,
         &token_price,
         custody,
+        &collateral_token_price,
+        collateral_custody,
         curtime,
         false,
     )?;
 
     let margin = math::checked_as_u64(math::checked_div(
         math::checked_mul(leverage as u128, Perpetuals::BPS_POWER)?,
-        ctx.accounts.custody.pricing.max_leverage as u128,
+        ctx.accounts
+            .custody
+            .pricing
+            .get_max_leverage(ctx.accounts.position.size_usd) as u128,
     )?)?;
 
     // Convert to price decimals
@@ -65,7 +65,7 @@ pub fn get_remove_liquidity_amount_and_fee<'info>(
     if params.lp_amount_in == 0 {
         return Err(ProgramError::InvalidArgument.into());
     }
-    let pool = &ctx.accounts.pool;
+    let mut pool = Pool::clone(&ctx.accounts.pool);
     let custody = &ctx.accounts.custody;
     let token_id = pool.get_token_id(&custody.key())?;
 
@@ -78,7 +78,9 @@ pub fn get_remove_liquidity_amount_and_fee<'info>(
         curtime,
     )?;
 
+    // the fee depends on the custody share of the current pool value
     let pool_amount_usd = pool.get_assets_under_management_usd(ctx.remaining_accounts, curtime)?;
+    pool.aum_usd = pool_amount_usd;
 
     let remove_amount_usd = math::checked_as_u64(math::checked_div(
         math::checked_mul(pool_amount_usd, params.lp_amount_in as u128)?,
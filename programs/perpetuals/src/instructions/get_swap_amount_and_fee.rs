@@ -0,0 +1,148 @@
+//! GetSwapAmountAndFee instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError,
+        math,
+        state::{
+            custody::Custody,
+            oracle::OraclePrice,
+            perpetuals::{Perpetuals, SwapAmountAndFees},
+            pool::Pool,
+        },
+    },
+    anchor_lang::prelude::*,
+    solana_program::program_error::ProgramError,
+};
+
+#[derive(Accounts)]
+pub struct GetSwapAmountAndFee<'info> {
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        seeds = [b"pool", pool.name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        seeds = [
+            b"custody",
+            pool.key().as_ref(),
+            receiving_custody.mint.as_ref()
+        ],
+        bump = receiving_custody.bump
+    )]
+    pub receiving_custody: Box<Account<'info, Custody>>,
+
+    /// CHECK: oracle account for the received token
+    #[account(
+        constraint = receiving_custody_oracle_account.key() == receiving_custody.oracle.oracle_account
+    )]
+    pub receiving_custody_oracle_account: UncheckedAccount<'info>,
+
+    #[account(
+        seeds = [
+            b"custody",
+            pool.key().as_ref(),
+            dispensing_custody.mint.as_ref()
+        ],
+        bump = dispensing_custody.bump
+    )]
+    pub dispensing_custody: Box<Account<'info, Custody>>,
+
+    /// CHECK: oracle account for the dispensed token
+    #[account(
+        constraint = dispensing_custody_oracle_account.key() == dispensing_custody.oracle.oracle_account
+    )]
+    pub dispensing_custody_oracle_account: UncheckedAccount<'info>,
+    // remaining accounts:
+    //   pool.tokens.len() custody accounts (read-only, unsigned)
+    //   pool.tokens.len() custody oracles (read-only, unsigned)
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct GetSwapAmountAndFeeParams {
+    amount_in: u64,
+}
+
+pub fn get_swap_amount_and_fee<'info>(
+    ctx: Context<'_, '_, 'info, 'info, GetSwapAmountAndFee<'info>>,
+    params: &GetSwapAmountAndFeeParams,
+) -> Result<SwapAmountAndFees> {
+    // validate inputs
+    if params.amount_in == 0 {
+        return Err(ProgramError::InvalidArgument.into());
+    }
+    let receiving_custody = &ctx.accounts.receiving_custody;
+    let dispensing_custody = &ctx.accounts.dispensing_custody;
+    require_keys_neq!(
+        receiving_custody.key(),
+        dispensing_custody.key(),
+        PerpetualsError::InvalidCustodyConfig
+    );
+
+    let mut pool = Pool::clone(&ctx.accounts.pool);
+    let token_id_in = pool.get_token_id(&receiving_custody.key())?;
+    let token_id_out = pool.get_token_id(&dispensing_custody.key())?;
+
+    // compute amount returned to the user
+    let curtime = ctx.accounts.perpetuals.get_time()?;
+
+    // the fees depend on the custody shares of the current pool value
+    pool.aum_usd = pool.get_assets_under_management_usd(ctx.remaining_accounts, curtime)?;
+
+    let received_token_price = OraclePrice::new_from_oracle(
+        &ctx.accounts
+            .receiving_custody_oracle_account
+            .to_account_info(),
+        &receiving_custody.oracle,
+        curtime,
+    )?;
+
+    let dispensed_token_price = OraclePrice::new_from_oracle(
+        &ctx.accounts
+            .dispensing_custody_oracle_account
+            .to_account_info(),
+        &dispensing_custody.oracle,
+        curtime,
+    )?;
+
+    let (fee_in, _) = pool.get_swap_fees(
+        token_id_in,
+        token_id_out,
+        params.amount_in,
+        0,
+        receiving_custody,
+        &received_token_price,
+        dispensing_custody,
+        &dispensed_token_price,
+    )?;
+    let amount_out = pool.get_swap_amount(
+        &received_token_price,
+        &dispensed_token_price,
+        receiving_custody,
+        dispensing_custody,
+        math::checked_sub(params.amount_in, fee_in)?,
+    )?;
+    let (_, fee_out) = pool.get_swap_fees(
+        token_id_in,
+        token_id_out,
+        0,
+        amount_out,
+        receiving_custody,
+        &received_token_price,
+        dispensing_custody,
+        &dispensed_token_price,
+    )?;
+
+    Ok(SwapAmountAndFees {
+        amount_out: math::checked_sub(amount_out, fee_out)?,
+        fee_in,
+        fee_out,
+    })
+}
@@ -0,0 +1,405 @@
+//! IncreasePosition instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError,
+        events, math,
+        state::{
+            custody::Custody,
+            delegate_authority::{DelegateAction, DelegateAuthority},
+            insurance_fund::InsuranceFund,
+            oracle::OraclePrice,
+            perpetuals::Perpetuals,
+            pool::Pool,
+            position::{Position, Side},
+        },
+    },
+    anchor_lang::prelude::*,
+    anchor_spl::token::{Token, TokenAccount},
+    solana_program::program_error::ProgramError,
+};
+
+#[derive(Accounts)]
+#[instruction(params: IncreasePositionParams)]
+pub struct IncreasePosition<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(mut)]
+    pub owner: SystemAccount<'info>,
+
+    #[account(
+        seeds = [b"delegate_authority", owner.key().as_ref()],
+        bump = delegate_authority.bump
+    )]
+    pub delegate_authority: Option<Box<Account<'info, DelegateAuthority>>>,
+
+    #[account(
+        mut,
+        constraint = funding_account.mint == collateral_custody.mint,
+        has_one = owner
+    )]
+    pub funding_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: empty PDA, authority for token accounts
+    #[account(
+        seeds = [b"transfer_authority"],
+        bump = perpetuals.transfer_authority_bump
+    )]
+    pub transfer_authority: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        mut,
+        seeds = [b"pool", pool.name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        seeds = [b"insurance_fund", pool.key().as_ref()],
+        bump = insurance_fund.bump
+    )]
+    pub insurance_fund: Box<Account<'info, InsuranceFund>>,
+
+    #[account(
+        mut,
+        has_one = owner,
+        constraint = !position.is_tokenized() @ PerpetualsError::TokenizedPosition,
+        constraint = !position.is_cross_margin() @ PerpetualsError::CrossMarginPosition,
+        seeds = [
+            b"position",
+            owner.key().as_ref(),
+            pool.key().as_ref(),
+            custody.key().as_ref(),
+            &[position.side as u8],
+            &position.index.to_le_bytes()
+        ],
+        bump = position.bump
+    )]
+    pub position: Box<Account<'info, Position>>,
+
+    #[account(
+        mut,
+        constraint = position.custody == custody.key()
+    )]
+    pub custody: Box<Account<'info, Custody>>,
+
+    /// CHECK: oracle account for the position token
+    #[account(
+        constraint = custody_oracle_account.key() == custody.oracle.oracle_account
+    )]
+    pub custody_oracle_account: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        constraint = position.collateral_custody == collateral_custody.key()
+    )]
+    pub collateral_custody: Box<Account<'info, Custody>>,
+
+    /// CHECK: oracle account for the collateral token
+    #[account(
+        constraint = collateral_custody_oracle_account.key() == collateral_custody.oracle.oracle_account
+    )]
+    pub collateral_custody_oracle_account: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"custody_token_account",
+            pool.key().as_ref(),
+            collateral_custody.mint.as_ref()
+        ],
+        bump = collateral_custody.token_account_bump
+    )]
+    pub collateral_custody_token_account: Box<Account<'info, TokenAccount>>,
+
+    token_program: Program<'info, Token>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct IncreasePositionParams {
+    pub price: u64,
+    // optional, can be zero
+    pub collateral: u64,
+    pub size: u64,
+}
+
+pub fn increase_position<'info>(
+    ctx: Context<'_, '_, '_, 'info, IncreasePosition<'info>>,
+    params: &IncreasePositionParams,
+) -> Result<()> {
+    // check permissions
+    msg!("Check permissions");
+    DelegateAuthority::validate_authority(
+        &ctx.accounts.authority.key(),
+        &ctx.accounts.owner.key(),
+        ctx.accounts.delegate_authority.as_deref(),
+        DelegateAction::OpenPosition,
+    )?;
+    let perpetuals = ctx.accounts.perpetuals.as_mut();
+    let custody = ctx.accounts.custody.as_mut();
+    let collateral_custody = ctx.accounts.collateral_custody.as_mut();
+    require!(
+        perpetuals.permissions.allow_size_change
+            && custody.permissions.allow_size_change
+            && perpetuals.permissions.allow_direct_position
+            && custody.permissions.allow_direct_position,
+        PerpetualsError::InstructionNotAllowed
+    );
+
+    // validate inputs
+    msg!("Validate inputs");
+    if params.price == 0 || params.size == 0 {
+        return Err(ProgramError::InvalidArgument.into());
+    }
+    let position = ctx.accounts.position.as_mut();
+    let pool = ctx.accounts.pool.as_mut();
+
+    // compute position price
+    let curtime = perpetuals.get_time()?;
+
+    let token_price = OraclePrice::new_from_oracle(
+        &ctx.accounts.custody_oracle_account.to_account_info(),
+        &custody.oracle,
+        curtime,
+    )?;
+
+    let collateral_token_price = OraclePrice::new_from_oracle(
+        &ctx.accounts
+            .collateral_custody_oracle_account
+            .to_account_info(),
+        &collateral_custody.oracle,
+        curtime,
+    )?;
+
+    let entry_price = pool.get_entry_price(
+        &token_price,
+        custody,
+        position.side,
+        token_price.get_asset_amount_usd(params.size, custody.decimals)?,
+    )?;
+    msg!("Entry price: {}", entry_price);
+
+    if position.side == Side::Long {
+        require_gte!(params.price, entry_price, PerpetualsError::MaxPriceSlippage);
+    } else {
+        require_gte!(entry_price, params.price, PerpetualsError::MaxPriceSlippage);
+    }
+
+    // compute size increase parameters
+    let entry_oracle_price = OraclePrice {
+        price: entry_price,
+        exponent: -(Perpetuals::PRICE_DECIMALS as i32),
+    };
+    let size_usd = entry_oracle_price.get_asset_amount_usd(params.size, custody.decimals)?;
+    let collateral_usd = collateral_token_price
+        .get_asset_amount_usd(params.collateral, collateral_custody.decimals)?;
+
+    // size increase expressed in collateral tokens, used to compute fees
+    let (size, locked_amount, borrow_size_usd) = if custody.key() == collateral_custody.key() {
+        let locked_amount = custody.get_locked_amount(params.size, position.side)?;
+
+        let borrow_size_usd = if custody.pricing.max_payoff_mult as u128 != Perpetuals::BPS_POWER {
+            entry_oracle_price.get_asset_amount_usd(locked_amount, custody.decimals)?
+        } else {
+            size_usd
+        };
+
+        (params.size, locked_amount, borrow_size_usd)
+    } else {
+        let locked_amount_usd = custody.get_locked_amount(size_usd, position.side)?;
+        (
+            collateral_token_price.get_token_amount(size_usd, collateral_custody.decimals)?,
+            collateral_token_price
+                .get_token_amount(locked_amount_usd, collateral_custody.decimals)?,
+            locked_amount_usd,
+        )
+    };
+
+    // compute fee
+    let fee_amount = pool.get_entry_fee(
+        custody.fees.open_position,
+        size,
+        locked_amount,
+        collateral_custody,
+    )?;
+    let fee_amount_usd =
+        collateral_token_price.get_asset_amount_usd(fee_amount, collateral_custody.decimals)?;
+    msg!("Collected fee: {}", fee_amount);
+
+    // compute amount to transfer
+    let transfer_amount = math::checked_add(params.collateral, fee_amount)?;
+    msg!("Amount in: {}", transfer_amount);
+
+    // compute blended entry price, weighted by position size in tokens
+    let position_oracle_price = OraclePrice {
+        price: position.price,
+        exponent: -(Perpetuals::PRICE_DECIMALS as i32),
+    };
+    let current_size =
+        position_oracle_price.get_token_amount(position.size_usd, custody.decimals)?;
+    let total_size = math::checked_add(current_size, params.size)?;
+    let blended_price = math::checked_as_u64(math::checked_div(
+        math::checked_add(
+            math::checked_mul(position.price as u128, current_size as u128)?,
+            math::checked_mul(entry_price as u128, params.size as u128)?,
+        )?,
+        total_size as u128,
+    )?)?;
+    msg!("Blended entry price: {}", blended_price);
+
+    // settle accrued interest and funding into the position
+    let interest_usd = collateral_custody.get_interest_amount_usd(position, curtime)?;
+    msg!("Settled interest: {}", interest_usd);
+
+    let (funding_received_usd, funding_paid_usd) =
+        custody.get_funding_amount_usd(position, curtime)?;
+    msg!(
+        "Settled funding received: {}, paid: {}",
+        funding_received_usd,
+        funding_paid_usd
+    );
+
+    // update existing position
+    msg!("Update existing position");
+    let prev_position = position.clone();
+    position.update_time = curtime;
+    position.price = blended_price;
+    position.size_usd = math::checked_add(position.size_usd, size_usd)?;
+    position.borrow_size_usd = math::checked_add(position.borrow_size_usd, borrow_size_usd)?;
+    position.collateral_usd = math::checked_add(position.collateral_usd, collateral_usd)?;
+    position.unrealized_profit_usd =
+        math::checked_add(position.unrealized_profit_usd, funding_received_usd)?;
+    position.unrealized_loss_usd = math::checked_add(
+        position.unrealized_loss_usd,
+        math::checked_add(interest_usd, funding_paid_usd)?,
+    )?;
+    position.cumulative_interest_snapshot = collateral_custody.get_cumulative_interest(curtime)?;
+    position.cumulative_funding_snapshot =
+        custody.get_cumulative_funding(position.side, curtime)?;
+    position.locked_amount = math::checked_add(position.locked_amount, locked_amount)?;
+    position.collateral_amount = math::checked_add(position.collateral_amount, params.collateral)?;
+
+    // restart profit vesting for the added size
+    position.vesting_start_time = math::checked_as_u64(math::checked_div(
+        math::checked_add(
+            math::checked_mul(
+                prev_position.vesting_start_time as u128,
+                prev_position.size_usd as u128,
+            )?,
+            math::checked_mul(curtime as u128, size_usd as u128)?,
+        )?,
+        position.size_usd as u128,
+    )?)? as i64;
+
+    // check position risk
+    msg!("Check position risks");
+    require!(
+        locked_amount > 0,
+        PerpetualsError::InsufficientAmountReturned
+    );
+    require!(
+        pool.check_leverage(
+            position,
+            &token_price,
+            custody,
+            &collateral_token_price,
+            collateral_custody,
+            curtime,
+            true
+        )?,
+        PerpetualsError::MaxLeverage
+    );
+
+    // lock funds for potential profit payoff
+    collateral_custody.lock_funds(locked_amount)?;
+
+    // transfer tokens
+    msg!("Transfer tokens");
+    perpetuals.transfer_tokens_from_user(
+        ctx.accounts.funding_account.to_account_info(),
+        ctx.accounts
+            .collateral_custody_token_account
+            .to_account_info(),
+        ctx.accounts.authority.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        transfer_amount,
+    )?;
+
+    // update custody stats
+    msg!("Update custody stats");
+    collateral_custody.assets.collateral =
+        math::checked_add(collateral_custody.assets.collateral, params.collateral)?;
+
+    let protocol_fee = Pool::get_fee_amount(custody.fees.protocol_share, fee_amount)?;
+    collateral_custody.assets.protocol_fees =
+        math::checked_add(collateral_custody.assets.protocol_fees, protocol_fee)?;
+
+    let insurance_fee = Pool::get_fee_amount(custody.fees.insurance_share, fee_amount)?;
+    let insurance_fund = ctx.accounts.insurance_fund.as_mut();
+    insurance_fund.add_funds(pool.get_token_id(&collateral_custody.key())?, insurance_fee)?;
+
+    // update borrow stats of the custody lending the locked funds
+    collateral_custody.remove_borrow(&prev_position, curtime)?;
+    collateral_custody.add_borrow(position, curtime)?;
+
+    // if custody and collateral_custody accounts are the same, ensure that data is in sync
+    if custody.key() == collateral_custody.key() {
+        *custody = collateral_custody.clone();
+    }
+
+    custody.collected_fees.open_position_usd = custody
+        .collected_fees
+        .open_position_usd
+        .wrapping_add(fee_amount_usd);
+
+    custody.volume_stats.open_position_usd = custody
+        .volume_stats
+        .open_position_usd
+        .wrapping_add(size_usd);
+
+    if position.side == Side::Long {
+        custody.trade_stats.oi_long_usd =
+            math::checked_add(custody.trade_stats.oi_long_usd, size_usd)?;
+    } else {
+        custody.trade_stats.oi_short_usd =
+            math::checked_add(custody.trade_stats.oi_short_usd, size_usd)?;
+    }
+
+    custody.remove_position(&prev_position, curtime)?;
+    custody.add_position(position, &token_price, curtime)?;
+    custody.update_borrow_rate(curtime)?;
+    custody.update_funding_rate(curtime)?;
+
+    if custody.key() == collateral_custody.key() {
+        *collateral_custody = custody.clone();
+    } else {
+        collateral_custody.update_borrow_rate(curtime)?;
+    }
+
+    emit!(events::IncreasePosition {
+        collateral_amount: position.collateral_amount,
+        collateral_custody: position.collateral_custody,
+        custody: position.custody,
+        owner: position.owner,
+        pool: position.pool,
+        price: position.price,
+        side: position.side,
+        size_usd: position.size_usd,
+        time: curtime,
+        transfer_amount,
+        entry_price,
+        fee_amount,
+        interest_usd,
+        size_delta_usd: size_usd,
+    });
+
+    Ok(())
+}
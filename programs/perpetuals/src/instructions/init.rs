@@ -53,6 +53,7 @@ pub struct Init<'info> {
 #[derive(AnchorSerialize, AnchorDeserialize, Copy, Clone)]
 pub struct InitParams {
     pub min_signatures: u8,
+    pub allow_swap: bool,
     pub allow_add_liquidity: bool,
     pub allow_remove_liquidity: bool,
     pub allow_open_position: bool,
@@ -60,6 +61,7 @@ pub struct InitParams {
     pub allow_pnl_withdrawal: bool,
     pub allow_collateral_withdrawal: bool,
     pub allow_size_change: bool,
+    pub allow_direct_position: bool,
 }
 
 pub fn init<'info>(
@@ -83,6 +85,7 @@ pub fn init<'info>(
     // record perpetuals
     let perpetuals = ctx.accounts.perpetuals.as_mut();
 
+    perpetuals.permissions.allow_swap = params.allow_swap;
     perpetuals.permissions.allow_add_liquidity = params.allow_add_liquidity;
     perpetuals.permissions.allow_remove_liquidity = params.allow_remove_liquidity;
     perpetuals.permissions.allow_open_position = params.allow_open_position;
@@ -90,6 +93,7 @@ pub fn init<'info>(
     perpetuals.permissions.allow_pnl_withdrawal = params.allow_pnl_withdrawal;
     perpetuals.permissions.allow_collateral_withdrawal = params.allow_collateral_withdrawal;
     perpetuals.permissions.allow_size_change = params.allow_size_change;
+    perpetuals.permissions.allow_direct_position = params.allow_direct_position;
     perpetuals.transfer_authority_bump = ctx.bumps.transfer_authority;
     perpetuals.perpetuals_bump = ctx.bumps.perpetuals;
     perpetuals.inception_time = perpetuals.get_time()?;
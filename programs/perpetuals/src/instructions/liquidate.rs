@@ -5,11 +5,8 @@ use {
         error::PerpetualsError,
         events, math,
         state::{
-            custody::Custody,
-            oracle::OraclePrice,
-            perpetuals::Perpetuals,
-            pool::Pool,
-            position::{Position, Side},
+            custody::Custody, insurance_fund::InsuranceFund, margin_account::MarginAccount,
+            oracle::OraclePrice, perpetuals::Perpetuals, pool::Pool, position::Position,
         },
     },
     anchor_lang::prelude::*,
@@ -23,14 +20,24 @@ pub struct Liquidate<'info> {
 
     #[account(
         mut,
-        constraint = receiving_account.mint == custody.mint,
-        constraint = receiving_account.owner == position.owner
+        constraint = receiving_account.mint == collateral_custody.mint
     )]
     pub receiving_account: Box<Account<'info, TokenAccount>>,
 
+    // holder's position token account, required for tokenized positions
+    pub position_token_account: Option<Box<Account<'info, TokenAccount>>>,
+
+    // required for cross margin positions
+    #[account(
+        mut,
+        seeds = [b"margin_account", position.owner.as_ref(), pool.key().as_ref()],
+        bump = margin_account.bump
+    )]
+    pub margin_account: Option<Box<Account<'info, MarginAccount>>>,
+
     #[account(
         mut,
-        constraint = rewards_receiving_account.mint == custody.mint,
+        constraint = rewards_receiving_account.mint == collateral_custody.mint,
         constraint = rewards_receiving_account.owner == signer.key()
     )]
     pub rewards_receiving_account: Box<Account<'info, TokenAccount>>,
@@ -55,6 +62,13 @@ pub struct Liquidate<'info> {
     )]
     pub pool: Box<Account<'info, Pool>>,
 
+    #[account(
+        mut,
+        seeds = [b"insurance_fund", pool.key().as_ref()],
+        bump = insurance_fund.bump
+    )]
+    pub insurance_fund: Box<Account<'info, InsuranceFund>>,
+
     #[account(
         mut,
         seeds = [
@@ -62,10 +76,10 @@ pub struct Liquidate<'info> {
             position.owner.as_ref(),
             pool.key().as_ref(),
             custody.key().as_ref(),
-            &[Side::Long as u8]
+            &[position.side as u8],
+            &position.index.to_le_bytes()
         ],
-        bump = position.bump,
-        close = signer
+        bump = position.bump
     )]
     pub position: Box<Account<'info, Position>>,
 
@@ -81,36 +95,63 @@ pub struct Liquidate<'info> {
     )]
     pub custody_oracle_account: UncheckedAccount<'info>,
 
+    #[account(
+        mut,
+        constraint = position.collateral_custody == collateral_custody.key()
+    )]
+    pub collateral_custody: Box<Account<'info, Custody>>,
+
+    /// CHECK: oracle account for the collateral token
+    #[account(
+        constraint = collateral_custody_oracle_account.key() == collateral_custody.oracle.oracle_account
+    )]
+    pub collateral_custody_oracle_account: UncheckedAccount<'info>,
+
     #[account(
         mut,
         seeds = [
             b"custody_token_account",
             pool.key().as_ref(),
-            custody.mint.as_ref()
-            ],
-        bump = custody.token_account_bump
+            collateral_custody.mint.as_ref()
+        ],
+        bump = collateral_custody.token_account_bump
     )]
-    pub custody_token_account: Box<Account<'info, TokenAccount>>,
+    pub collateral_custody_token_account: Box<Account<'info, TokenAccount>>,
 
     token_program: Program<'info, Token>,
+    // remaining accounts (cross margin positions only):
+    //   5 accounts per margin account position (read-only, unsigned)
+    //   2 accounts per margin account collateral (writable, unsigned)
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize)]
 pub struct LiquidateParams {}
 
 pub fn liquidate<'info>(
-    ctx: Context<'_, '_, '_, 'info, Liquidate<'info>>,
+    ctx: Context<'_, '_, 'info, 'info, Liquidate<'info>>,
     _params: &LiquidateParams,
 ) -> Result<()> {
     // check permissions
     msg!("Check permissions");
     let perpetuals = ctx.accounts.perpetuals.as_mut();
     let custody = ctx.accounts.custody.as_mut();
+    let collateral_custody = ctx.accounts.collateral_custody.as_mut();
     require!(
         perpetuals.permissions.allow_close_position && custody.permissions.allow_close_position,
         PerpetualsError::InstructionNotAllowed
     );
 
+    // payouts go to the position holder
+    let holder = ctx
+        .accounts
+        .position
+        .get_holder(ctx.accounts.position_token_account.as_deref())?;
+    require_keys_eq!(
+        ctx.accounts.receiving_account.owner,
+        holder,
+        PerpetualsError::InvalidTokenAccountOwner
+    );
+
     let position = ctx.accounts.position.as_mut();
     let pool = ctx.accounts.pool.as_mut();
 
@@ -124,16 +165,81 @@ pub fn liquidate<'info>(
         curtime,
     )?;
 
-    require!(
-        !pool.check_leverage(position, &token_price, custody, curtime, false)?,
-        PerpetualsError::InvalidPositionState
-    );
+    let collateral_token_price = OraclePrice::new_from_oracle(
+        &ctx.accounts
+            .collateral_custody_oracle_account
+            .to_account_info(),
+        &collateral_custody.oracle,
+        curtime,
+    )?;
 
-    msg!("Settle position");
-    let (total_amount_out, fee_amount, profit_usd, loss_usd) =
-        pool.get_close_amount(position, &token_price, custody, curtime, true)?;
+    // cross margin positions are liquidated in full once the whole account equity
+    // falls below its maintenance requirement
+    let mut margin_account = MarginAccount::validate_position_account(
+        position,
+        ctx.accounts.margin_account.as_deref_mut(),
+    )?;
+    let mut margin_collateral_accounts: &[AccountInfo] = &[];
+    if let Some(margin_account) = margin_account.as_deref_mut() {
+        let margin_account_key = margin_account.key();
+        let collaterals_idx = margin_account.get_positions().len() * 5;
+        let (equity_usd, requirement_usd) = pool.get_margin_account_health(
+            margin_account,
+            &margin_account_key,
+            ctx.remaining_accounts,
+            curtime,
+            false,
+        )?;
+        msg!("Equity: {}, requirement: {}", equity_usd, requirement_usd);
+        require!(
+            equity_usd < requirement_usd,
+            PerpetualsError::InvalidPositionState
+        );
+        margin_collateral_accounts = &ctx.remaining_accounts[collaterals_idx..];
+    } else {
+        require!(
+            !pool.check_leverage(
+                position,
+                &token_price,
+                custody,
+                &collateral_token_price,
+                collateral_custody,
+                curtime,
+                false
+            )?,
+            PerpetualsError::InvalidPositionState
+        );
+    }
 
-    let fee_amount_usd = token_price.get_asset_amount_usd(fee_amount, custody.decimals)?;
+    msg!("Settle position");
+    let liquidation_size_usd = if margin_account.is_some() {
+        position.size_usd
+    } else {
+        pool.get_liquidation_size_usd(
+            position,
+            &token_price,
+            custody,
+            &collateral_token_price,
+            collateral_custody,
+            curtime,
+        )?
+    };
+    let full_liquidation = liquidation_size_usd == position.size_usd;
+    let liquidated_position = position.get_partial_position(liquidation_size_usd)?;
+
+    let exit_price =
+        pool.get_exit_price(&token_price, custody, position.side, liquidation_size_usd)?;
+    msg!("Exit price: {}", exit_price);
+
+    let (total_amount_out, fee_amount, profit_usd, loss_usd) = pool.get_close_amount(
+        &liquidated_position,
+        &token_price,
+        custody,
+        &collateral_token_price,
+        collateral_custody,
+        curtime,
+        true,
+    )?;
 
     msg!("Net profit: {}, loss: {}", profit_usd, loss_usd);
     msg!("Collected fee: {}", fee_amount);
@@ -144,82 +250,116 @@ pub fn liquidate<'info>(
     msg!("Amount out: {}", user_amount);
     msg!("Reward: {}", reward);
 
-    // unlock pool funds
-    custody.unlock_funds(position.locked_amount)?;
+    // update existing position, the remaining amount out stays in the position as collateral
+    let prev_position = position.clone();
+    if !full_liquidation {
+        msg!("Update existing position");
+        let user_amount_usd = collateral_token_price
+            .get_asset_amount_usd(user_amount, collateral_custody.decimals)?;
+        position.update_time = curtime;
+        position.size_usd = math::checked_sub(position.size_usd, liquidated_position.size_usd)?;
+        position.borrow_size_usd = math::checked_sub(
+            position.borrow_size_usd,
+            liquidated_position.borrow_size_usd,
+        )?;
+        position.collateral_usd = math::checked_add(
+            math::checked_sub(position.collateral_usd, liquidated_position.collateral_usd)?,
+            user_amount_usd,
+        )?;
+        position.unrealized_profit_usd = math::checked_sub(
+            position.unrealized_profit_usd,
+            liquidated_position.unrealized_profit_usd,
+        )?;
+        position.unrealized_loss_usd = math::checked_sub(
+            position.unrealized_loss_usd,
+            liquidated_position.unrealized_loss_usd,
+        )?;
+        position.locked_amount =
+            math::checked_sub(position.locked_amount, liquidated_position.locked_amount)?;
+        position.collateral_amount = math::checked_add(
+            math::checked_sub(
+                position.collateral_amount,
+                liquidated_position.collateral_amount,
+            )?,
+            user_amount,
+        )?;
+
+        // check position risk
+        msg!("Check position risks");
+        require!(
+            position.locked_amount > 0 && position.collateral_amount > 0,
+            PerpetualsError::InvalidPositionState
+        );
+        require!(
+            pool.check_leverage(
+                position,
+                &token_price,
+                custody,
+                &collateral_token_price,
+                collateral_custody,
+                curtime,
+                false
+            )?,
+            PerpetualsError::MaxLeverage
+        );
+    }
 
-    // check pool constraints
-    msg!("Check pool constraints");
-    require!(
-        pool.check_available_amount(total_amount_out, custody)?,
-        PerpetualsError::CustodyAmountLimit
-    );
+    let protocol_fee = pool.settle_position(
+        position,
+        &prev_position,
+        &liquidated_position,
+        (total_amount_out, fee_amount, profit_usd, loss_usd),
+        if !full_liquidation || margin_account.is_some() {
+            user_amount
+        } else {
+            0
+        },
+        true,
+        custody,
+        &token_price,
+        collateral_custody,
+        &collateral_token_price,
+        ctx.accounts.insurance_fund.as_mut(),
+        margin_account
+            .as_deref_mut()
+            .map(|margin_account| &mut **margin_account),
+        margin_collateral_accounts,
+        curtime,
+    )?;
 
-    // transfer tokens
+    // transfer tokens, cross margin payouts stay in custody as margin account collateral
     msg!("Transfer tokens");
-    perpetuals.transfer_tokens(
-        ctx.accounts.custody_token_account.to_account_info(),
-        ctx.accounts.receiving_account.to_account_info(),
-        ctx.accounts.transfer_authority.to_account_info(),
-        ctx.accounts.token_program.to_account_info(),
-        user_amount,
-    )?;
+    if margin_account.is_none() && full_liquidation {
+        perpetuals.transfer_tokens(
+            ctx.accounts
+                .collateral_custody_token_account
+                .to_account_info(),
+            ctx.accounts.receiving_account.to_account_info(),
+            ctx.accounts.transfer_authority.to_account_info(),
+            ctx.accounts.token_program.to_account_info(),
+            user_amount,
+        )?;
+    }
 
     perpetuals.transfer_tokens(
-        ctx.accounts.custody_token_account.to_account_info(),
+        ctx.accounts
+            .collateral_custody_token_account
+            .to_account_info(),
         ctx.accounts.rewards_receiving_account.to_account_info(),
         ctx.accounts.transfer_authority.to_account_info(),
         ctx.accounts.token_program.to_account_info(),
         reward,
     )?;
 
-    // update custody stats
-    msg!("Update custody stats");
-    custody.collected_fees.liquidation_usd = custody
-        .collected_fees
-        .liquidation_usd
-        .wrapping_add(fee_amount_usd);
-
-    if total_amount_out > position.collateral_amount {
-        let amount_lost = total_amount_out.saturating_sub(position.collateral_amount);
-        custody.assets.owned = math::checked_sub(custody.assets.owned, amount_lost)?;
-    } else {
-        let amount_gained = position.collateral_amount.saturating_sub(total_amount_out);
-        custody.assets.owned = math::checked_add(custody.assets.owned, amount_gained)?;
-    }
-    custody.assets.collateral =
-        math::checked_sub(custody.assets.collateral, position.collateral_amount)?;
-
-    let protocol_fee = Pool::get_fee_amount(custody.fees.protocol_share, fee_amount)?;
-
-    // Pay protocol_fee from custody if possible, otherwise no protocol_fee
-    if pool.check_available_amount(protocol_fee, custody)? {
-        custody.assets.protocol_fees =
-            math::checked_add(custody.assets.protocol_fees, protocol_fee)?;
-
-        custody.assets.owned = math::checked_sub(custody.assets.owned, protocol_fee)?;
-    }
-
-    custody.volume_stats.liquidation_usd =
-        math::checked_add(custody.volume_stats.liquidation_usd, position.size_usd)?;
-
-    custody.trade_stats.oi_long_usd = custody
-        .trade_stats
-        .oi_long_usd
-        .saturating_sub(position.size_usd);
-
-    custody.trade_stats.profit_usd = custody.trade_stats.profit_usd.wrapping_add(profit_usd);
-    custody.trade_stats.loss_usd = custody.trade_stats.loss_usd.wrapping_add(loss_usd);
-
-    custody.remove_position(position, curtime)?;
-    custody.update_borrow_rate(curtime)?;
-
     emit!(events::LiquidatePosition {
         // Common position fields
         collateral_amount: position.collateral_amount,
+        collateral_custody: position.collateral_custody,
         custody: position.custody,
         owner: position.owner,
         pool: position.pool,
-        price: pool.get_exit_price(&token_price, &custody)?,
+        price: exit_price,
+        side: position.side,
         size_usd: position.size_usd,
         time: curtime,
 
@@ -227,12 +367,21 @@ pub fn liquidate<'info>(
         fee_amount,
         loss_usd,
         profit_usd,
-        transfer_amount: user_amount,
+        transfer_amount: if full_liquidation { user_amount } else { 0 },
         protocol_fee,
 
         // Unique with Liquidate Position
         reward_amount: reward,
         signer: ctx.accounts.signer.key(),
+        size_delta_usd: liquidated_position.size_usd,
     });
+
+    // close position account if the whole position has been liquidated
+    if full_liquidation {
+        ctx.accounts
+            .position
+            .close(ctx.accounts.signer.to_account_info())?;
+    }
+
     Ok(())
 }
@@ -6,6 +6,9 @@ use {
         events, math,
         state::{
             custody::Custody,
+            delegate_authority::{DelegateAction, DelegateAuthority},
+            insurance_fund::InsuranceFund,
+            margin_account::MarginAccount,
             oracle::OraclePrice,
             perpetuals::Perpetuals,
             pool::Pool,
@@ -21,11 +24,29 @@ use {
 #[instruction(params: OpenPositionParams)]
 pub struct OpenPosition<'info> {
     #[account(mut)]
-    pub owner: Signer<'info>,
+    pub authority: Signer<'info>,
+
+    #[account(mut)]
+    pub owner: SystemAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"delegate_authority", owner.key().as_ref()],
+        bump = delegate_authority.bump
+    )]
+    pub delegate_authority: Option<Box<Account<'info, DelegateAuthority>>>,
+
+    // opens the position in cross margin mode, funded from the margin account
+    #[account(
+        mut,
+        seeds = [b"margin_account", owner.key().as_ref(), pool.key().as_ref()],
+        bump = margin_account.bump
+    )]
+    pub margin_account: Option<Box<Account<'info, MarginAccount>>>,
 
     #[account(
         mut,
-        constraint = funding_account.mint == custody.mint,
+        constraint = funding_account.mint == collateral_custody.mint,
         has_one = owner
     )]
     pub funding_account: Box<Account<'info, TokenAccount>>,
@@ -50,16 +71,24 @@ pub struct OpenPosition<'info> {
     )]
     pub pool: Box<Account<'info, Pool>>,
 
+    #[account(
+        mut,
+        seeds = [b"insurance_fund", pool.key().as_ref()],
+        bump = insurance_fund.bump
+    )]
+    pub insurance_fund: Box<Account<'info, InsuranceFund>>,
+
     #[account(
         init,
-        payer = owner,
+        payer = authority,
         space = Position::LEN,
         seeds = [
             b"position",
             owner.key().as_ref(),
             pool.key().as_ref(),
             custody.key().as_ref(),
-            &[Side::Long as u8]
+            &[params.side as u8],
+            &params.index.to_le_bytes()
         ],
         bump
     )]
@@ -82,19 +111,39 @@ pub struct OpenPosition<'info> {
     )]
     pub custody_oracle_account: UncheckedAccount<'info>,
 
+    #[account(
+        mut,
+        seeds = [
+            b"custody",
+            pool.key().as_ref(),
+            collateral_custody.mint.as_ref()
+        ],
+        bump = collateral_custody.bump
+    )]
+    pub collateral_custody: Box<Account<'info, Custody>>,
+
+    /// CHECK: oracle account for the collateral token
+    #[account(
+        constraint = collateral_custody_oracle_account.key() == collateral_custody.oracle.oracle_account
+    )]
+    pub collateral_custody_oracle_account: UncheckedAccount<'info>,
+
     #[account(
         mut,
         seeds = [
             b"custody_token_account",
             pool.key().as_ref(),
-            custody.mint.as_ref()
+            collateral_custody.mint.as_ref()
         ],
-        bump = custody.token_account_bump
+        bump = collateral_custody.token_account_bump
     )]
-    pub custody_token_account: Box<Account<'info, TokenAccount>>,
+    pub collateral_custody_token_account: Box<Account<'info, TokenAccount>>,
 
     system_program: Program<'info, System>,
     token_program: Program<'info, Token>,
+    // remaining accounts (cross margin positions only):
+    //   5 accounts per margin account position (read-only, unsigned)
+    //   2 accounts per margin account collateral (read-only, unsigned)
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
@@ -102,26 +151,47 @@ pub struct OpenPositionParams {
     pub price: u64,
     pub collateral: u64,
     pub size: u64,
+    pub side: Side,
+    // caller-chosen index, allows several positions on the same market and side
+    pub index: u64,
 }
 
 pub fn open_position<'info>(
-    ctx: Context<'_, '_, '_, 'info, OpenPosition<'info>>,
+    ctx: Context<'_, '_, 'info, 'info, OpenPosition<'info>>,
     params: &OpenPositionParams,
 ) -> Result<()> {
     // check permissions
     msg!("Check permissions");
+    DelegateAuthority::validate_authority(
+        &ctx.accounts.authority.key(),
+        &ctx.accounts.owner.key(),
+        ctx.accounts.delegate_authority.as_deref(),
+        DelegateAction::OpenPosition,
+    )?;
     let perpetuals = ctx.accounts.perpetuals.as_mut();
     let custody = ctx.accounts.custody.as_mut();
+    let collateral_custody = ctx.accounts.collateral_custody.as_mut();
     require!(
-        perpetuals.permissions.allow_open_position && custody.permissions.allow_open_position,
+        perpetuals.permissions.allow_open_position
+            && custody.permissions.allow_open_position
+            && perpetuals.permissions.allow_direct_position
+            && custody.permissions.allow_direct_position
+            && !custody.is_stable,
         PerpetualsError::InstructionNotAllowed
     );
 
     // validate inputs
     msg!("Validate inputs");
-    if params.price == 0 || params.collateral == 0 || params.size == 0 {
+    if params.price == 0 || params.collateral == 0 || params.size == 0 || params.side == Side::None
+    {
         return Err(ProgramError::InvalidArgument.into());
     }
+    // longs are collateralized with the position token or a stablecoin, shorts with a stablecoin
+    require!(
+        collateral_custody.is_stable
+            || (params.side == Side::Long && custody.key() == collateral_custody.key()),
+        PerpetualsError::InvalidCollateralCustody
+    );
 
     let position = ctx.accounts.position.as_mut();
     let pool = ctx.accounts.pool.as_mut();
@@ -135,14 +205,35 @@ pub fn open_position<'info>(
         curtime,
     )?;
 
-    let position_price = pool.get_entry_price(&token_price, custody)?;
+    let collateral_token_price = OraclePrice::new_from_oracle(
+        &ctx.accounts
+            .collateral_custody_oracle_account
+            .to_account_info(),
+        &collateral_custody.oracle,
+        curtime,
+    )?;
+
+    let position_price = pool.get_entry_price(
+        &token_price,
+        custody,
+        params.side,
+        token_price.get_asset_amount_usd(params.size, custody.decimals)?,
+    )?;
     msg!("Entry price: {}", position_price);
 
-    require_gte!(
-        params.price,
-        position_price,
-        PerpetualsError::MaxPriceSlippage
-    );
+    if params.side == Side::Long {
+        require_gte!(
+            params.price,
+            position_price,
+            PerpetualsError::MaxPriceSlippage
+        );
+    } else {
+        require_gte!(
+            position_price,
+            params.price,
+            PerpetualsError::MaxPriceSlippage
+        );
+    }
 
     // compute position parameters
     let position_oracle_price = OraclePrice {
@@ -150,25 +241,41 @@ pub fn open_position<'info>(
         exponent: -(Perpetuals::PRICE_DECIMALS as i32),
     };
     let size_usd = position_oracle_price.get_asset_amount_usd(params.size, custody.decimals)?;
-    let collateral_usd = token_price.get_asset_amount_usd(params.collateral, custody.decimals)?;
+    let collateral_usd = collateral_token_price
+        .get_asset_amount_usd(params.collateral, collateral_custody.decimals)?;
+
+    // position size expressed in collateral tokens, used to compute fees
+    let (size, locked_amount, borrow_size_usd) = if custody.key() == collateral_custody.key() {
+        let locked_amount = custody.get_locked_amount(params.size, params.side)?;
 
-    let locked_amount = custody.get_locked_amount(params.size)?;
+        // A better name would be "locked_amount_usd" (its the same)
+        let borrow_size_usd = if custody.pricing.max_payoff_mult as u128 != Perpetuals::BPS_POWER {
+            position_oracle_price.get_asset_amount_usd(locked_amount, custody.decimals)?
+        } else {
+            size_usd
+        };
 
-    // A better name would be "locked_amount_usd" (its the same)
-    let borrow_size_usd = if custody.pricing.max_payoff_mult as u128 != Perpetuals::BPS_POWER {
-        position_oracle_price.get_asset_amount_usd(locked_amount, custody.decimals)?
+        (params.size, locked_amount, borrow_size_usd)
     } else {
-        size_usd
+        // stablecoin collateral is locked in the collateral custody
+        let locked_amount_usd = custody.get_locked_amount(size_usd, params.side)?;
+        (
+            collateral_token_price.get_token_amount(size_usd, collateral_custody.decimals)?,
+            collateral_token_price
+                .get_token_amount(locked_amount_usd, collateral_custody.decimals)?,
+            locked_amount_usd,
+        )
     };
 
     // compute fee
     let fee_amount = pool.get_entry_fee(
         custody.fees.open_position,
-        params.size,
+        size,
         locked_amount,
-        custody,
+        collateral_custody,
     )?;
-    let fee_amount_usd = token_price.get_asset_amount_usd(fee_amount, custody.decimals)?;
+    let fee_amount_usd =
+        collateral_token_price.get_asset_amount_usd(fee_amount, collateral_custody.decimals)?;
     msg!("Collected fee: {}", fee_amount);
 
     // compute amount to transfer
@@ -180,78 +287,181 @@ pub fn open_position<'info>(
     position.owner = ctx.accounts.owner.key();
     position.pool = pool.key();
     position.custody = custody.key();
+    position.collateral_custody = collateral_custody.key();
     position.open_time = perpetuals.get_time()?;
     position.update_time = 0;
+    position.vesting_start_time = position.open_time;
+    position.side = params.side;
+    position.index = params.index;
     position.price = position_price;
     position.size_usd = size_usd;
     position.borrow_size_usd = borrow_size_usd;
     position.collateral_usd = collateral_usd;
     position.unrealized_profit_usd = 0;
     position.unrealized_loss_usd = 0;
-    position.cumulative_interest_snapshot = custody.get_cumulative_interest(curtime)?;
+    position.cumulative_interest_snapshot = collateral_custody.get_cumulative_interest(curtime)?;
+    position.cumulative_funding_snapshot = custody.get_cumulative_funding(params.side, curtime)?;
     position.locked_amount = locked_amount;
     position.collateral_amount = params.collateral;
     position.bump = ctx.bumps.position;
 
-    // check position risk
+    // check position risk, cross margin positions are checked against the account equity
     msg!("Check position risks");
     require!(
         position.locked_amount > 0,
         PerpetualsError::InsufficientAmountReturned
     );
-    require!(
-        pool.check_leverage(position, &token_price, custody, curtime, true)?,
-        PerpetualsError::MaxLeverage
-    );
+    if let Some(margin_account) = ctx.accounts.margin_account.as_deref_mut() {
+        let margin_account_key = margin_account.key();
+        let (equity_usd, requirement_usd) = pool.get_margin_account_health(
+            margin_account,
+            &margin_account_key,
+            ctx.remaining_accounts,
+            curtime,
+            true,
+        )?;
+
+        // the position collateral and fee are drawn from the account collateral
+        let (profit_usd, loss_usd, _) = pool.get_pnl_usd(
+            position,
+            &token_price,
+            custody,
+            &collateral_token_price,
+            collateral_custody,
+            curtime,
+            false,
+        )?;
+        let transfer_amount_usd = collateral_token_price
+            .get_asset_amount_usd(transfer_amount, collateral_custody.decimals)?;
+        let equity_usd = math::checked_add(
+            math::checked_add(equity_usd, position.collateral_usd)?,
+            profit_usd,
+        )?
+        .saturating_sub(math::checked_add(transfer_amount_usd, loss_usd)?);
+        let requirement_usd = math::checked_add(
+            requirement_usd,
+            math::checked_as_u64(math::checked_ceil_div(
+                math::checked_mul(position.size_usd as u128, Perpetuals::BPS_POWER)?,
+                custody.pricing.max_initial_leverage as u128,
+            )?)?,
+        )?;
+        msg!("Equity: {}, requirement: {}", equity_usd, requirement_usd);
+        require_gte!(
+            equity_usd,
+            requirement_usd,
+            PerpetualsError::InsufficientMargin
+        );
+
+        position.margin_account = margin_account_key;
+        margin_account.add_position(&position.key())?;
+    } else {
+        require!(
+            pool.check_leverage(
+                position,
+                &token_price,
+                custody,
+                &collateral_token_price,
+                collateral_custody,
+                curtime,
+                true
+            )?,
+            PerpetualsError::MaxLeverage
+        );
+    }
 
     // lock funds for potential profit payoff
-    custody.lock_funds(position.locked_amount)?;
+    collateral_custody.lock_funds(position.locked_amount)?;
 
-    // transfer tokens
+    // transfer tokens, cross margin positions draw on collateral already held in custody
     msg!("Transfer tokens");
-    perpetuals.transfer_tokens_from_user(
-        ctx.accounts.funding_account.to_account_info(),
-        ctx.accounts.custody_token_account.to_account_info(),
-        ctx.accounts.owner.to_account_info(),
-        ctx.accounts.token_program.to_account_info(),
-        transfer_amount,
-    )?;
+    if let Some(margin_account) = ctx.accounts.margin_account.as_deref_mut() {
+        margin_account.remove_collateral(&collateral_custody.key(), transfer_amount)?;
+        collateral_custody.assets.collateral =
+            math::checked_sub(collateral_custody.assets.collateral, transfer_amount)?;
+    } else {
+        perpetuals.transfer_tokens_from_user(
+            ctx.accounts.funding_account.to_account_info(),
+            ctx.accounts
+                .collateral_custody_token_account
+                .to_account_info(),
+            ctx.accounts.authority.to_account_info(),
+            ctx.accounts.token_program.to_account_info(),
+            transfer_amount,
+        )?;
+    }
 
     // update custody stats
     msg!("Update custody stats");
+    collateral_custody.assets.collateral =
+        math::checked_add(collateral_custody.assets.collateral, params.collateral)?;
+
+    let protocol_fee = Pool::get_fee_amount(custody.fees.protocol_share, fee_amount)?;
+    collateral_custody.assets.protocol_fees =
+        math::checked_add(collateral_custody.assets.protocol_fees, protocol_fee)?;
+
+    let insurance_fee = Pool::get_fee_amount(custody.fees.insurance_share, fee_amount)?;
+    let insurance_fund = ctx.accounts.insurance_fund.as_mut();
+    insurance_fund.add_funds(pool.get_token_id(&collateral_custody.key())?, insurance_fee)?;
+
+    // update borrow stats of the custody lending the locked funds
+    collateral_custody.add_borrow(position, curtime)?;
+
+    // if custody and collateral_custody accounts are the same, ensure that data is in sync
+    if custody.key() == collateral_custody.key() {
+        *custody = collateral_custody.clone();
+    }
+
     custody.collected_fees.open_position_usd = custody
         .collected_fees
         .open_position_usd
         .wrapping_add(fee_amount_usd);
 
-    custody.assets.collateral = math::checked_add(custody.assets.collateral, params.collateral)?;
-
-    let protocol_fee = Pool::get_fee_amount(custody.fees.protocol_share, fee_amount)?;
-    custody.assets.protocol_fees = math::checked_add(custody.assets.protocol_fees, protocol_fee)?;
-
     custody.volume_stats.open_position_usd = custody
         .volume_stats
         .open_position_usd
         .wrapping_add(size_usd);
 
-    custody.trade_stats.oi_long_usd = math::checked_add(custody.trade_stats.oi_long_usd, size_usd)?;
+    if position.side == Side::Long {
+        custody.trade_stats.oi_long_usd =
+            math::checked_add(custody.trade_stats.oi_long_usd, size_usd)?;
+    } else {
+        custody.trade_stats.oi_short_usd =
+            math::checked_add(custody.trade_stats.oi_short_usd, size_usd)?;
+    }
 
     custody.add_position(position, &token_price, curtime)?;
     custody.update_borrow_rate(curtime)?;
+    custody.update_funding_rate(curtime)?;
+
+    if custody.key() == collateral_custody.key() {
+        *collateral_custody = custody.clone();
+    } else {
+        collateral_custody.update_borrow_rate(curtime)?;
+    }
 
     emit!(events::OpenPosition {
         borrow_size_usd: position.borrow_size_usd,
         collateral_amount: position.collateral_amount,
+        collateral_custody: position.collateral_custody,
         collateral_usd: position.collateral_usd,
         custody: position.custody,
         locked_amount: position.locked_amount,
         owner: position.owner,
         pool: position.pool,
         price: position.price,
+        side: position.side,
         size_usd: position.size_usd,
         time: position.open_time,
         transfer_amount: position.collateral_amount,
     });
 
+    // refund the rent paid by operators from the owner deposit
+    DelegateAuthority::refund_rent(
+        &ctx.accounts.authority.to_account_info(),
+        &ctx.accounts.owner.key(),
+        ctx.accounts.delegate_authority.as_deref(),
+        &[ctx.accounts.position.to_account_info()],
+    )?;
+
     Ok(())
 }
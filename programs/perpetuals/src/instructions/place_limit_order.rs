@@ -0,0 +1,235 @@
+//! PlaceLimitOrder instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError,
+        state::{
+            custody::Custody,
+            delegate_authority::{DelegateAction, DelegateAuthority},
+            limit_order::LimitOrder,
+            oracle::OraclePrice,
+            perpetuals::Perpetuals,
+            pool::Pool,
+            position::Position,
+        },
+    },
+    anchor_lang::prelude::*,
+    anchor_spl::token::{Mint, Token, TokenAccount},
+};
+
+#[derive(Accounts)]
+#[instruction(params: PlaceLimitOrderParams)]
+pub struct PlaceLimitOrder<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(mut)]
+    pub owner: SystemAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"delegate_authority", owner.key().as_ref()],
+        bump = delegate_authority.bump
+    )]
+    pub delegate_authority: Option<Box<Account<'info, DelegateAuthority>>>,
+
+    #[account(
+        mut,
+        constraint = funding_account.mint == collateral_custody.mint,
+        has_one = owner
+    )]
+    pub funding_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: empty PDA, authority for token accounts
+    #[account(
+        seeds = [b"transfer_authority"],
+        bump = perpetuals.transfer_authority_bump
+    )]
+    pub transfer_authority: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        seeds = [b"pool", pool.name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        seeds = [
+            b"custody",
+            pool.key().as_ref(),
+            custody.mint.as_ref()
+        ],
+        bump = custody.bump
+    )]
+    pub custody: Box<Account<'info, Custody>>,
+
+    #[account(
+        seeds = [
+            b"custody",
+            pool.key().as_ref(),
+            collateral_custody.mint.as_ref()
+        ],
+        bump = collateral_custody.bump
+    )]
+    pub collateral_custody: Box<Account<'info, Custody>>,
+
+    /// CHECK: oracle account for the collateral token
+    #[account(
+        constraint = collateral_custody_oracle_account.key() == collateral_custody.oracle.oracle_account
+    )]
+    pub collateral_custody_oracle_account: UncheckedAccount<'info>,
+
+    #[account(
+        constraint = collateral_custody_mint.key() == collateral_custody.mint
+    )]
+    pub collateral_custody_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = LimitOrder::LEN,
+        seeds = [
+            b"limit_order",
+            owner.key().as_ref(),
+            pool.key().as_ref(),
+            custody.key().as_ref(),
+            &params.position_index.to_le_bytes()
+        ],
+        bump
+    )]
+    pub limit_order: Box<Account<'info, LimitOrder>>,
+
+    #[account(
+        init,
+        payer = authority,
+        token::mint = collateral_custody_mint,
+        token::authority = transfer_authority,
+        seeds = [
+            b"limit_order_escrow",
+            limit_order.key().as_ref()
+        ],
+        bump
+    )]
+    pub escrow_token_account: Box<Account<'info, TokenAccount>>,
+
+    system_program: Program<'info, System>,
+    token_program: Program<'info, Token>,
+    rent: Sysvar<'info, Rent>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct PlaceLimitOrderParams {
+    pub limit_price: u64,
+    pub collateral: u64,
+    pub size: u64,
+    pub position_index: u64,
+}
+
+pub fn place_limit_order<'info>(
+    ctx: Context<'_, '_, '_, 'info, PlaceLimitOrder<'info>>,
+    params: &PlaceLimitOrderParams,
+) -> Result<()> {
+    // check permissions
+    msg!("Check permissions");
+    DelegateAuthority::validate_authority(
+        &ctx.accounts.authority.key(),
+        &ctx.accounts.owner.key(),
+        ctx.accounts.delegate_authority.as_deref(),
+        DelegateAction::Orders,
+    )?;
+    let perpetuals = ctx.accounts.perpetuals.as_mut();
+    let custody = ctx.accounts.custody.as_mut();
+    let collateral_custody = ctx.accounts.collateral_custody.as_mut();
+    require!(
+        perpetuals.permissions.allow_open_position
+            && custody.permissions.allow_open_position
+            && !custody.is_stable,
+        PerpetualsError::InstructionNotAllowed
+    );
+
+    // limit orders open long positions, collateralized with the position token or a stablecoin
+    require!(
+        collateral_custody.is_stable || custody.key() == collateral_custody.key(),
+        PerpetualsError::InvalidCollateralCustody
+    );
+
+    // position size expressed in collateral tokens at the limit price, used to compute fees
+    let curtime = perpetuals.get_time()?;
+    let size = if custody.key() == collateral_custody.key() {
+        params.size
+    } else {
+        let collateral_token_price = OraclePrice::new_from_oracle(
+            &ctx.accounts
+                .collateral_custody_oracle_account
+                .to_account_info(),
+            &collateral_custody.oracle,
+            curtime,
+        )?;
+        let size_usd = OraclePrice::new(params.limit_price, -(Perpetuals::PRICE_DECIMALS as i32))
+            .get_asset_amount_usd(params.size, custody.decimals)?;
+        collateral_token_price.get_token_amount(size_usd, collateral_custody.decimals)?
+    };
+
+    // record order data
+    msg!("Initialize new limit order");
+    let pool = ctx.accounts.pool.as_mut();
+    let limit_order = ctx.accounts.limit_order.as_mut();
+    limit_order.owner = ctx.accounts.owner.key();
+    limit_order.pool = pool.key();
+    limit_order.custody = custody.key();
+    limit_order.collateral_custody = collateral_custody.key();
+    limit_order.position_index = params.position_index;
+    limit_order.limit_price = params.limit_price;
+    limit_order.size = params.size;
+    limit_order.collateral_amount = params.collateral;
+    limit_order.max_fee =
+        pool.get_max_entry_fee(custody.fees.open_position, size, collateral_custody)?;
+    limit_order.create_time = curtime;
+    limit_order.bump = ctx.bumps.limit_order;
+    limit_order.escrow_bump = ctx.bumps.escrow_token_account;
+
+    if !limit_order.validate() {
+        return err!(PerpetualsError::InvalidLimitOrder);
+    }
+
+    let escrow_amount = limit_order.get_escrow_amount()?;
+    msg!("Amount in: {}", escrow_amount);
+
+    // transfer tokens
+    msg!("Transfer tokens");
+    perpetuals.transfer_tokens_from_user(
+        ctx.accounts.funding_account.to_account_info(),
+        ctx.accounts.escrow_token_account.to_account_info(),
+        ctx.accounts.authority.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        escrow_amount,
+    )?;
+
+    // deposit rent for the position account, the keeper pays for it on execution
+    let position_rent = Rent::get()?.minimum_balance(Position::LEN);
+    Perpetuals::transfer_sol(
+        ctx.accounts.authority.to_account_info(),
+        ctx.accounts.limit_order.to_account_info(),
+        ctx.accounts.system_program.to_account_info(),
+        position_rent,
+    )?;
+
+    // refund the rent paid by operators from the owner deposit
+    DelegateAuthority::refund_rent(
+        &ctx.accounts.authority.to_account_info(),
+        &ctx.accounts.owner.key(),
+        ctx.accounts.delegate_authority.as_deref(),
+        &[
+            ctx.accounts.limit_order.to_account_info(),
+            ctx.accounts.escrow_token_account.to_account_info(),
+        ],
+    )?;
+
+    Ok(())
+}
@@ -0,0 +1,131 @@
+//! PlaceTriggerOrder instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError,
+        math,
+        state::{
+            delegate_authority::{DelegateAction, DelegateAuthority},
+            perpetuals::Perpetuals,
+            position::Position,
+            trigger_order::{TriggerOrder, TriggerOrderType},
+        },
+    },
+    anchor_lang::prelude::*,
+};
+
+#[derive(Accounts)]
+#[instruction(params: PlaceTriggerOrderParams)]
+pub struct PlaceTriggerOrder<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(mut)]
+    pub owner: SystemAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"delegate_authority", owner.key().as_ref()],
+        bump = delegate_authority.bump
+    )]
+    pub delegate_authority: Option<Box<Account<'info, DelegateAuthority>>>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        mut,
+        has_one = owner,
+        constraint = !position.is_tokenized() @ PerpetualsError::TokenizedPosition,
+        seeds = [
+            b"position",
+            owner.key().as_ref(),
+            position.pool.as_ref(),
+            position.custody.as_ref(),
+            &[position.side as u8],
+            &position.index.to_le_bytes()
+        ],
+        bump = position.bump
+    )]
+    pub position: Box<Account<'info, Position>>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = TriggerOrder::LEN,
+        seeds = [
+            b"trigger_order",
+            position.key().as_ref(),
+            &[params.index]
+        ],
+        bump
+    )]
+    pub trigger_order: Box<Account<'info, TriggerOrder>>,
+
+    system_program: Program<'info, System>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct PlaceTriggerOrderParams {
+    pub order_type: TriggerOrderType,
+    pub index: u8,
+    pub trigger_price: u64,
+    // zero closes the whole position
+    pub size_usd: u64,
+}
+
+pub fn place_trigger_order<'info>(
+    ctx: Context<'_, '_, '_, 'info, PlaceTriggerOrder<'info>>,
+    params: &PlaceTriggerOrderParams,
+) -> Result<()> {
+    // check permissions
+    msg!("Check permissions");
+    DelegateAuthority::validate_authority(
+        &ctx.accounts.authority.key(),
+        &ctx.accounts.owner.key(),
+        ctx.accounts.delegate_authority.as_deref(),
+        DelegateAction::Orders,
+    )?;
+    require!(
+        ctx.accounts.perpetuals.permissions.allow_close_position,
+        PerpetualsError::InstructionNotAllowed
+    );
+
+    // record order data
+    msg!("Initialize new trigger order");
+    let trigger_order = ctx.accounts.trigger_order.as_mut();
+    trigger_order.owner = ctx.accounts.owner.key();
+    trigger_order.position = ctx.accounts.position.key();
+    trigger_order.order_type = params.order_type;
+    trigger_order.index = params.index;
+    trigger_order.trigger_price = params.trigger_price;
+    trigger_order.size_usd = params.size_usd;
+    trigger_order.create_time = ctx.accounts.perpetuals.get_time()?;
+    trigger_order.bump = ctx.bumps.trigger_order;
+
+    if !trigger_order.validate() {
+        return err!(PerpetualsError::InvalidTriggerOrder);
+    }
+
+    // orders placed in the second the position was opened can't be told apart from
+    // orders of a previous position with the same address
+    let position = ctx.accounts.position.as_mut();
+    require!(
+        trigger_order.create_time > position.open_time,
+        PerpetualsError::StalePositionOrder
+    );
+    position.trigger_orders = math::checked_add(position.trigger_orders, 1)?;
+
+    // refund the rent paid by operators from the owner deposit
+    DelegateAuthority::refund_rent(
+        &ctx.accounts.authority.to_account_info(),
+        &ctx.accounts.owner.key(),
+        ctx.accounts.delegate_authority.as_deref(),
+        &[ctx.accounts.trigger_order.to_account_info()],
+    )?;
+
+    Ok(())
+}
@@ -0,0 +1,141 @@
+//! RedeemPosition instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError,
+        events,
+        state::{perpetuals::Perpetuals, pool::Pool, position::Position},
+    },
+    anchor_lang::prelude::*,
+    anchor_spl::token::{Mint, Token, TokenAccount},
+};
+
+#[derive(Accounts)]
+#[instruction(params: RedeemPositionParams)]
+pub struct RedeemPosition<'info> {
+    #[account(mut)]
+    pub holder: Signer<'info>,
+
+    // owner of the tokenized position account, receives its rent back
+    #[account(mut)]
+    pub owner: SystemAccount<'info>,
+
+    #[account(
+        mut,
+        constraint = position_token_account.owner == holder.key()
+    )]
+    pub position_token_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = position_mint.key() == position.token_mint
+    )]
+    pub position_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        seeds = [b"pool", pool.name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        has_one = owner,
+        seeds = [
+            b"position",
+            owner.key().as_ref(),
+            pool.key().as_ref(),
+            position.custody.as_ref(),
+            &[position.side as u8],
+            &position.index.to_le_bytes()
+        ],
+        bump = position.bump,
+        close = owner
+    )]
+    pub position: Box<Account<'info, Position>>,
+
+    #[account(
+        init,
+        payer = holder,
+        space = Position::LEN,
+        seeds = [
+            b"position",
+            holder.key().as_ref(),
+            pool.key().as_ref(),
+            position.custody.as_ref(),
+            &[position.side as u8],
+            &params.new_index.to_le_bytes()
+        ],
+        bump
+    )]
+    pub new_position: Box<Account<'info, Position>>,
+
+    system_program: Program<'info, System>,
+    token_program: Program<'info, Token>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct RedeemPositionParams {
+    // index of the position under the holder
+    pub new_index: u64,
+}
+
+pub fn redeem_position<'info>(
+    ctx: Context<'_, '_, '_, 'info, RedeemPosition<'info>>,
+    params: &RedeemPositionParams,
+) -> Result<()> {
+    // validate inputs
+    msg!("Validate inputs");
+    let position = ctx.accounts.position.as_ref();
+    require!(
+        position.is_tokenized(),
+        PerpetualsError::InvalidPositionState
+    );
+    position.get_holder(Some(ctx.accounts.position_token_account.as_ref()))?;
+
+    // burn the position token
+    msg!("Burn position token");
+    let perpetuals = ctx.accounts.perpetuals.as_ref();
+    perpetuals.burn_tokens(
+        ctx.accounts.position_mint.to_account_info(),
+        ctx.accounts.position_token_account.to_account_info(),
+        ctx.accounts.holder.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        1,
+    )?;
+
+    // migrate position data to an account owned by the holder
+    msg!("Migrate position");
+    let new_position = ctx.accounts.new_position.as_mut();
+    new_position.set_inner(Position {
+        owner: ctx.accounts.holder.key(),
+        index: params.new_index,
+        token_mint: Pubkey::default(),
+        bump: ctx.bumps.new_position,
+        ..(**position).clone()
+    });
+
+    emit!(events::RedeemPosition {
+        collateral_amount: position.collateral_amount,
+        collateral_custody: position.collateral_custody,
+        custody: position.custody,
+        owner: position.owner,
+        pool: position.pool,
+        price: position.price,
+        side: position.side,
+        size_usd: position.size_usd,
+        time: perpetuals.get_time()?,
+        index: position.index,
+        new_index: params.new_index,
+        new_owner: new_position.owner,
+        token_mint: position.token_mint,
+    });
+
+    Ok(())
+}
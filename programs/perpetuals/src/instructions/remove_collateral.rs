@@ -6,10 +6,12 @@ use {
         events, math,
         state::{
             custody::Custody,
+            delegate_authority::{DelegateAction, DelegateAuthority},
+            margin_account::MarginAccount,
             oracle::OraclePrice,
             perpetuals::Perpetuals,
             pool::Pool,
-            position::{Position, Side},
+            position::Position,
         },
     },
     anchor_lang::prelude::*,
@@ -21,15 +23,34 @@ use {
 #[instruction(params: RemoveCollateralParams)]
 pub struct RemoveCollateral<'info> {
     #[account(mut)]
-    pub owner: Signer<'info>,
+    pub authority: Signer<'info>,
+
+    #[account(mut)]
+    pub owner: SystemAccount<'info>,
+
+    #[account(
+        seeds = [b"delegate_authority", owner.key().as_ref()],
+        bump = delegate_authority.bump
+    )]
+    pub delegate_authority: Option<Box<Account<'info, DelegateAuthority>>>,
+
+    // holder's position token account, required for tokenized positions
+    pub position_token_account: Option<Box<Account<'info, TokenAccount>>>,
 
     #[account(
         mut,
-        constraint = receiving_account.mint == custody.mint,
-        has_one = owner
+        constraint = receiving_account.mint == collateral_custody.mint
     )]
     pub receiving_account: Box<Account<'info, TokenAccount>>,
 
+    // required for cross margin positions
+    #[account(
+        mut,
+        seeds = [b"margin_account", position.owner.as_ref(), pool.key().as_ref()],
+        bump = margin_account.bump
+    )]
+    pub margin_account: Option<Box<Account<'info, MarginAccount>>>,
+
     /// CHECK: empty PDA, authority for token accounts
     #[account(
         seeds = [b"transfer_authority"],
@@ -58,7 +79,8 @@ pub struct RemoveCollateral<'info> {
             owner.key().as_ref(),
             pool.key().as_ref(),
             custody.key().as_ref(),
-            &[Side::Long as u8]
+            &[position.side as u8],
+            &position.index.to_le_bytes()
         ],
         bump = position.bump
     )]
@@ -70,22 +92,34 @@ pub struct RemoveCollateral<'info> {
     )]
     pub custody: Box<Account<'info, Custody>>,
 
-    /// CHECK: oracle account for the collateral token
+    /// CHECK: oracle account for the position token
     #[account(
         constraint = custody_oracle_account.key() == custody.oracle.oracle_account
     )]
     pub custody_oracle_account: UncheckedAccount<'info>,
 
+    #[account(
+        mut,
+        constraint = position.collateral_custody == collateral_custody.key()
+    )]
+    pub collateral_custody: Box<Account<'info, Custody>>,
+
+    /// CHECK: oracle account for the collateral token
+    #[account(
+        constraint = collateral_custody_oracle_account.key() == collateral_custody.oracle.oracle_account
+    )]
+    pub collateral_custody_oracle_account: UncheckedAccount<'info>,
+
     #[account(
         mut,
         seeds = [
             b"custody_token_account",
             pool.key().as_ref(),
-            custody.mint.as_ref()
+            collateral_custody.mint.as_ref()
         ],
-        bump = custody.token_account_bump
+        bump = collateral_custody.token_account_bump
     )]
-    pub custody_token_account: Box<Account<'info, TokenAccount>>,
+    pub collateral_custody_token_account: Box<Account<'info, TokenAccount>>,
 
     token_program: Program<'info, Token>,
 }
@@ -101,8 +135,28 @@ pub fn remove_collateral<'info>(
 ) -> Result<()> {
     // check permissions
     msg!("Check permissions");
+    let holder = ctx
+        .accounts
+        .position
+        .get_holder(ctx.accounts.position_token_account.as_deref())?;
+    DelegateAuthority::validate_authority(
+        &ctx.accounts.authority.key(),
+        &holder,
+        // delegates are granted by the position account owner, not the token holder
+        ctx.accounts
+            .delegate_authority
+            .as_deref()
+            .filter(|_| !ctx.accounts.position.is_tokenized()),
+        DelegateAction::CollateralChange,
+    )?;
+    require_keys_eq!(
+        ctx.accounts.receiving_account.owner,
+        holder,
+        PerpetualsError::InvalidTokenAccountOwner
+    );
     let perpetuals = ctx.accounts.perpetuals.as_mut();
     let custody = ctx.accounts.custody.as_mut();
+    let collateral_custody = ctx.accounts.collateral_custody.as_mut();
     require!(
         perpetuals.permissions.allow_collateral_withdrawal
             && custody.permissions.allow_collateral_withdrawal,
@@ -116,6 +170,10 @@ pub fn remove_collateral<'info>(
         return Err(ProgramError::InvalidArgument.into());
     }
     let pool = ctx.accounts.pool.as_mut();
+    let mut margin_account = MarginAccount::validate_position_account(
+        position,
+        ctx.accounts.margin_account.as_deref_mut(),
+    )?;
 
     // compute position price
     let curtime = perpetuals.get_time()?;
@@ -126,8 +184,17 @@ pub fn remove_collateral<'info>(
         curtime,
     )?;
 
+    let collateral_token_price = OraclePrice::new_from_oracle(
+        &ctx.accounts
+            .collateral_custody_oracle_account
+            .to_account_info(),
+        &collateral_custody.oracle,
+        curtime,
+    )?;
+
     // compute amount to transfer
-    let collateral = token_price.get_token_amount(params.collateral_usd, custody.decimals)?;
+    let collateral = collateral_token_price
+        .get_token_amount(params.collateral_usd, collateral_custody.decimals)?;
     if collateral > position.collateral_amount {
         return Err(ProgramError::InsufficientFunds.into());
     }
@@ -142,32 +209,51 @@ pub fn remove_collateral<'info>(
     // check position risk
     msg!("Check position risks");
     require!(
-        pool.check_leverage(position, &token_price, custody, curtime, true)?,
+        pool.check_leverage(
+            position,
+            &token_price,
+            custody,
+            &collateral_token_price,
+            collateral_custody,
+            curtime,
+            true
+        )?,
         PerpetualsError::MaxLeverage
     );
 
-    // transfer tokens
+    // transfer tokens, collateral removed from cross positions goes back to the margin account
     msg!("Transfer tokens");
-    perpetuals.transfer_tokens(
-        ctx.accounts.custody_token_account.to_account_info(),
-        ctx.accounts.receiving_account.to_account_info(),
-        ctx.accounts.transfer_authority.to_account_info(),
-        ctx.accounts.token_program.to_account_info(),
-        collateral,
-    )?;
+    if let Some(margin_account) = margin_account.as_deref_mut() {
+        margin_account.add_collateral(&collateral_custody.key(), collateral)?;
+    } else {
+        perpetuals.transfer_tokens(
+            ctx.accounts
+                .collateral_custody_token_account
+                .to_account_info(),
+            ctx.accounts.receiving_account.to_account_info(),
+            ctx.accounts.transfer_authority.to_account_info(),
+            ctx.accounts.token_program.to_account_info(),
+            collateral,
+        )?;
+    }
 
     // update custody stats
     msg!("Update custody stats");
-    custody.assets.collateral = math::checked_sub(custody.assets.collateral, collateral)?;
+    if margin_account.is_none() {
+        collateral_custody.assets.collateral =
+            math::checked_sub(collateral_custody.assets.collateral, collateral)?;
+    }
 
     emit!(events::RemoveCollateral {
         collateral_amount: position.collateral_amount,
+        collateral_custody: position.collateral_custody,
         custody: position.custody,
         owner: position.owner,
         pool: position.pool,
         price: token_price
             .scale_to_exponent(-(Perpetuals::PRICE_DECIMALS as i32))?
             .price,
+        side: position.side,
         size_usd: position.size_usd,
         time: curtime,
         transfer_amount: collateral,
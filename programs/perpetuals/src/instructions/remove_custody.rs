@@ -5,6 +5,7 @@ use {
         error::PerpetualsError,
         state::{
             custody::Custody,
+            insurance_fund::InsuranceFund,
             multisig::{AdminInstruction, Multisig},
             perpetuals::Perpetuals,
             pool::Pool,
@@ -42,7 +43,7 @@ pub struct RemoveCustody<'info> {
 
     #[account(
         mut,
-        realloc = Pool::LEN + (pool.custodies.len() - 1) * std::mem::size_of::<Pubkey>(),
+        realloc = Pool::LEN + (pool.custodies.len() - 1) * (std::mem::size_of::<Pubkey>() + std::mem::size_of::<u64>()),
         realloc::payer = admin,
         realloc::zero = false,
         seeds = [b"pool", pool.name.as_bytes()],
@@ -50,6 +51,16 @@ pub struct RemoveCustody<'info> {
     )]
     pub pool: Box<Account<'info, Pool>>,
 
+    #[account(
+        mut,
+        realloc = InsuranceFund::LEN + (pool.custodies.len() - 1) * std::mem::size_of::<u64>(),
+        realloc::payer = admin,
+        realloc::zero = false,
+        seeds = [b"insurance_fund", pool.key().as_ref()],
+        bump = insurance_fund.bump
+    )]
+    pub insurance_fund: Box<Account<'info, InsuranceFund>>,
+
     #[account(
         mut,
         seeds = [
@@ -108,7 +119,19 @@ pub fn remove_custody<'info>(
     // remove token from the list
     let pool = ctx.accounts.pool.as_mut();
     let token_id = pool.get_token_id(&ctx.accounts.custody.key())?;
+    // the target weight is moved to the other custodies with set_pool_config first
+    require!(
+        pool.target_weights[token_id] == 0,
+        PerpetualsError::InvalidPoolConfig
+    );
+    // insurance funds are withdrawn with withdraw_insurance_fund first
+    require!(
+        ctx.accounts.insurance_fund.get_balance(token_id)? == 0,
+        PerpetualsError::InvalidCustodyState
+    );
     pool.custodies.remove(token_id);
+    ctx.accounts.insurance_fund.balances.remove(token_id);
+    pool.target_weights.remove(token_id);
     if !pool.validate() {
         return err!(PerpetualsError::InvalidPoolConfig);
     }
@@ -4,7 +4,10 @@ use {
     crate::{
         error::PerpetualsError,
         math,
-        state::{custody::Custody, oracle::OraclePrice, perpetuals::Perpetuals, pool::Pool},
+        state::{
+            custody::Custody, lp_account::LpAccount, oracle::OraclePrice, perpetuals::Perpetuals,
+            pool::Pool,
+        },
     },
     anchor_lang::prelude::*,
     anchor_spl::token::{Mint, Token, TokenAccount},
@@ -86,6 +89,13 @@ pub struct RemoveLiquidity<'info> {
     )]
     pub lp_token_mint: Box<Account<'info, Mint>>,
 
+    // deposit record of the owner, required to remove liquidity during the cooldown
+    #[account(
+        seeds = [b"lp_account", owner.key().as_ref(), pool.key().as_ref()],
+        bump = lp_account.bump
+    )]
+    pub lp_account: Option<Box<Account<'info, LpAccount>>>,
+
     token_program: Program<'info, Token>,
     // remaining accounts:
     //   pool.tokens.len() custody accounts (read-only, unsigned)
@@ -123,6 +133,15 @@ pub fn remove_liquidity<'info>(
     msg!("Compute assets under management");
     let curtime = perpetuals.get_time()?;
 
+    // the cooldown runs from the owner's last deposit, owners without a deposit
+    // record remove liquidity through withdrawal requests
+    msg!("Check liquidity cooldown");
+    let cooled_down = match ctx.accounts.lp_account.as_deref() {
+        Some(lp_account) => lp_account.is_cooled_down(pool.lp_cooldown_sec, curtime)?,
+        None => pool.lp_cooldown_sec == 0,
+    };
+    require!(cooled_down, PerpetualsError::LiquidityCooldown);
+
     // Refresh pool.aum_usm to adapt to token price change
     pool.aum_usd = pool.get_assets_under_management_usd(ctx.remaining_accounts, curtime)?;
 
@@ -0,0 +1,251 @@
+//! RemoveLiquidityProportional instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError,
+        math,
+        state::{
+            custody::Custody, lp_account::LpAccount, oracle::OraclePrice, perpetuals::Perpetuals,
+            pool::Pool,
+        },
+    },
+    anchor_lang::prelude::*,
+    anchor_spl::token::{Mint, Token, TokenAccount},
+    solana_program::program_error::ProgramError,
+};
+
+#[derive(Accounts)]
+#[instruction(params: RemoveLiquidityProportionalParams)]
+pub struct RemoveLiquidityProportional<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint = lp_token_account.mint == lp_token_mint.key(),
+        has_one = owner
+    )]
+    pub lp_token_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: empty PDA, authority for token accounts
+    #[account(
+        seeds = [b"transfer_authority"],
+        bump = perpetuals.transfer_authority_bump
+    )]
+    pub transfer_authority: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        mut,
+        seeds = [b"pool", pool.name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        seeds = [b"lp_token_mint", pool.key().as_ref()],
+        bump = pool.lp_token_bump
+    )]
+    pub lp_token_mint: Box<Account<'info, Mint>>,
+
+    // deposit record of the owner, required to remove liquidity during the cooldown
+    #[account(
+        seeds = [b"lp_account", owner.key().as_ref(), pool.key().as_ref()],
+        bump = lp_account.bump
+    )]
+    pub lp_account: Option<Box<Account<'info, LpAccount>>>,
+
+    token_program: Program<'info, Token>,
+    // remaining accounts:
+    //   pool.tokens.len() custody accounts (writable, unsigned)
+    //   pool.tokens.len() custody oracles (read-only, unsigned)
+    //   pool.tokens.len() custody token accounts (writable, unsigned)
+    //   pool.tokens.len() receiving token accounts (writable, unsigned)
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct RemoveLiquidityProportionalParams {
+    pub lp_amount_in: u64,
+    // one bound per pool custody, in pool order
+    pub min_amounts_out: Vec<u64>,
+}
+
+pub fn remove_liquidity_proportional<'info>(
+    ctx: Context<'_, '_, 'info, 'info, RemoveLiquidityProportional<'info>>,
+    params: &RemoveLiquidityProportionalParams,
+) -> Result<()> {
+    // check permissions
+    msg!("Check permissions");
+    let perpetuals = ctx.accounts.perpetuals.as_ref();
+    require!(
+        perpetuals.permissions.allow_remove_liquidity,
+        PerpetualsError::InstructionNotAllowed
+    );
+
+    // validate inputs
+    msg!("Validate inputs");
+    let pool = ctx.accounts.pool.as_mut();
+    let custodies_len = pool.custodies.len();
+    let lp_supply = ctx.accounts.lp_token_mint.supply;
+    if params.lp_amount_in == 0
+        || params.lp_amount_in > lp_supply
+        || params.min_amounts_out.len() != custodies_len
+    {
+        return Err(ProgramError::InvalidArgument.into());
+    }
+    if ctx.remaining_accounts.len() < custodies_len * 4 {
+        return Err(ProgramError::NotEnoughAccountKeys.into());
+    }
+
+    let curtime = perpetuals.get_time()?;
+
+    // the cooldown runs from the owner's last deposit, owners without a deposit
+    // record remove liquidity through withdrawal requests
+    msg!("Check liquidity cooldown");
+    let cooled_down = match ctx.accounts.lp_account.as_deref() {
+        Some(lp_account) => lp_account.is_cooled_down(pool.lp_cooldown_sec, curtime)?,
+        None => pool.lp_cooldown_sec == 0,
+    };
+    require!(cooled_down, PerpetualsError::LiquidityCooldown);
+
+    // compute the value of the lp tokens, the same as for remove_liquidity
+    let pool_amount_usd = pool
+        .get_assets_under_management_usd(&ctx.remaining_accounts[..custodies_len * 2], curtime)?;
+    let remove_amount_usd = math::checked_div(
+        math::checked_mul(pool_amount_usd, params.lp_amount_in as u128)?,
+        lp_supply as u128,
+    )?;
+    msg!("Amount out usd: {}", remove_amount_usd);
+
+    transfer_proportional_amounts(
+        perpetuals,
+        pool,
+        remove_amount_usd,
+        &params.min_amounts_out,
+        &ctx.accounts.owner.key(),
+        ctx.accounts.transfer_authority.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        ctx.remaining_accounts,
+        curtime,
+    )?;
+
+    // burn lp tokens
+    msg!("Burn LP tokens");
+    perpetuals.burn_tokens(
+        ctx.accounts.lp_token_mint.to_account_info(),
+        ctx.accounts.lp_token_account.to_account_info(),
+        ctx.accounts.owner.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        params.lp_amount_in,
+    )?;
+
+    // update pool stats
+    msg!("Update pool stats");
+    pool.aum_usd = pool
+        .get_assets_under_management_usd(&ctx.remaining_accounts[..custodies_len * 2], curtime)?;
+
+    Ok(())
+}
+
+/// Pays out the value in kind, split across custodies by their share of the owned value.
+/// The pool composition does not change so no liquidity fees are charged.
+#[allow(clippy::too_many_arguments)]
+pub fn transfer_proportional_amounts<'info>(
+    perpetuals: &Perpetuals,
+    pool: &Pool,
+    remove_amount_usd: u128,
+    min_amounts_out: &[u64],
+    receiver: &Pubkey,
+    transfer_authority: AccountInfo<'info>,
+    token_program: AccountInfo<'info>,
+    accounts: &'info [AccountInfo<'info>],
+    curtime: i64,
+) -> Result<()> {
+    let custodies_len = pool.custodies.len();
+    if accounts.len() < custodies_len * 4 {
+        return Err(ProgramError::NotEnoughAccountKeys.into());
+    }
+
+    // load pool custodies
+    let mut custodies = Vec::with_capacity(custodies_len);
+    let mut token_prices = Vec::with_capacity(custodies_len);
+    for (idx, &custody_key) in pool.custodies.iter().enumerate() {
+        let custody_info = &accounts[idx];
+        let oracle_info = &accounts[custodies_len + idx];
+        require_keys_eq!(custody_info.key(), custody_key);
+        let custody = Account::<Custody>::try_from(custody_info)?;
+        require!(
+            custody.permissions.allow_remove_liquidity,
+            PerpetualsError::InstructionNotAllowed
+        );
+        require_keys_eq!(oracle_info.key(), custody.oracle.oracle_account);
+        token_prices.push(OraclePrice::new_from_oracle(
+            oracle_info,
+            &custody.oracle,
+            curtime,
+        )?);
+        custodies.push(custody);
+    }
+
+    let transfer_amounts = Pool::get_remove_liquidity_proportional_amounts(
+        remove_amount_usd,
+        &custodies
+            .iter()
+            .map(|custody| (**custody).clone())
+            .collect::<Vec<_>>(),
+        &token_prices,
+    )?;
+
+    for (idx, custody) in custodies.iter_mut().enumerate() {
+        let custody_token_account_info = &accounts[custodies_len * 2 + idx];
+        let receiving_account_info = &accounts[custodies_len * 3 + idx];
+        require_keys_eq!(custody_token_account_info.key(), custody.token_account);
+
+        let receiving_account = Account::<TokenAccount>::try_from(receiving_account_info)?;
+        require_keys_eq!(receiving_account.mint, custody.mint);
+        require_keys_eq!(
+            receiving_account.owner,
+            *receiver,
+            PerpetualsError::InvalidTokenAccountOwner
+        );
+
+        let transfer_amount = transfer_amounts[idx];
+        msg!("Amount out: {}", transfer_amount);
+
+        require!(
+            transfer_amount >= min_amounts_out[idx],
+            PerpetualsError::MaxPriceSlippage
+        );
+
+        // transfer tokens
+        if transfer_amount > 0 {
+            perpetuals.transfer_tokens(
+                custody_token_account_info.clone(),
+                receiving_account_info.clone(),
+                transfer_authority.clone(),
+                token_program.clone(),
+                transfer_amount,
+            )?;
+        }
+
+        // update custody stats
+        custody.volume_stats.remove_liquidity_usd =
+            custody.volume_stats.remove_liquidity_usd.wrapping_add(
+                token_prices[idx].get_asset_amount_usd(transfer_amount, custody.decimals)?,
+            );
+
+        custody.assets.owned = math::checked_sub(custody.assets.owned, transfer_amount)?;
+
+        custody.update_borrow_rate(curtime)?;
+
+        custody.exit(&crate::ID)?;
+    }
+
+    Ok(())
+}
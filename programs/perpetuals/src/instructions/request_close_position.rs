@@ -0,0 +1,144 @@
+//! RequestClosePosition instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError,
+        state::{
+            custody::Custody,
+            delegate_authority::{DelegateAction, DelegateAuthority},
+            perpetuals::Perpetuals,
+            pool::Pool,
+            position::Position,
+            position_request::{PositionRequest, RequestType},
+        },
+    },
+    anchor_lang::prelude::*,
+};
+
+#[derive(Accounts)]
+pub struct RequestClosePosition<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(mut)]
+    pub owner: SystemAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"delegate_authority", owner.key().as_ref()],
+        bump = delegate_authority.bump
+    )]
+    pub delegate_authority: Option<Box<Account<'info, DelegateAuthority>>>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        seeds = [b"pool", pool.name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        has_one = owner,
+        constraint = !position.is_tokenized() @ PerpetualsError::TokenizedPosition,
+        seeds = [
+            b"position",
+            owner.key().as_ref(),
+            pool.key().as_ref(),
+            position.custody.as_ref(),
+            &[position.side as u8],
+            &position.index.to_le_bytes()
+        ],
+        bump = position.bump
+    )]
+    pub position: Box<Account<'info, Position>>,
+
+    #[account(
+        constraint = position.custody == custody.key()
+    )]
+    pub custody: Box<Account<'info, Custody>>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = PositionRequest::LEN,
+        seeds = [
+            b"position_request",
+            owner.key().as_ref(),
+            pool.key().as_ref(),
+            position.custody.as_ref(),
+            &[position.side as u8],
+            &position.index.to_le_bytes()
+        ],
+        bump
+    )]
+    pub position_request: Box<Account<'info, PositionRequest>>,
+
+    system_program: Program<'info, System>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct RequestClosePositionParams {
+    pub price: u64,
+    pub expiry_time: i64,
+}
+
+pub fn request_close_position<'info>(
+    ctx: Context<'_, '_, '_, 'info, RequestClosePosition<'info>>,
+    params: &RequestClosePositionParams,
+) -> Result<()> {
+    // check permissions
+    msg!("Check permissions");
+    DelegateAuthority::validate_authority(
+        &ctx.accounts.authority.key(),
+        &ctx.accounts.owner.key(),
+        ctx.accounts.delegate_authority.as_deref(),
+        DelegateAction::ClosePosition,
+    )?;
+    let perpetuals = ctx.accounts.perpetuals.as_mut();
+    require!(
+        perpetuals.permissions.allow_close_position,
+        PerpetualsError::InstructionNotAllowed
+    );
+
+    // record request data
+    msg!("Initialize new position request");
+    let position = ctx.accounts.position.as_mut();
+    let position_request = ctx.accounts.position_request.as_mut();
+    position_request.owner = position.owner;
+    position_request.pool = position.pool;
+    position_request.custody = position.custody;
+    position_request.collateral_custody = position.collateral_custody;
+    position_request.request_type = RequestType::Close;
+    position_request.side = position.side;
+    position_request.position_index = position.index;
+    position_request.price = params.price;
+    position_request.create_time = perpetuals.get_time()?;
+    position_request.expiry_time = params.expiry_time;
+    position_request.bump = ctx.bumps.position_request;
+
+    if !position_request.validate(ctx.accounts.custody.pricing.max_request_age_sec) {
+        return err!(PerpetualsError::InvalidPositionRequest);
+    }
+
+    // requests made in the second the position was opened can't be told apart from
+    // requests for a previous position with the same address
+    require!(
+        position_request.create_time > position.open_time,
+        PerpetualsError::StalePositionOrder
+    );
+
+    // refund the rent paid by operators from the owner deposit
+    DelegateAuthority::refund_rent(
+        &ctx.accounts.authority.to_account_info(),
+        &ctx.accounts.owner.key(),
+        ctx.accounts.delegate_authority.as_deref(),
+        &[ctx.accounts.position_request.to_account_info()],
+    )?;
+
+    Ok(())
+}
@@ -0,0 +1,260 @@
+//! RequestOpenPosition instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError,
+        state::{
+            custody::Custody,
+            delegate_authority::{DelegateAction, DelegateAuthority},
+            oracle::OraclePrice,
+            perpetuals::Perpetuals,
+            pool::Pool,
+            position::{Position, Side},
+            position_request::{PositionRequest, RequestType},
+        },
+    },
+    anchor_lang::prelude::*,
+    anchor_spl::token::{Mint, Token, TokenAccount},
+};
+
+#[derive(Accounts)]
+#[instruction(params: RequestOpenPositionParams)]
+pub struct RequestOpenPosition<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(mut)]
+    pub owner: SystemAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"delegate_authority", owner.key().as_ref()],
+        bump = delegate_authority.bump
+    )]
+    pub delegate_authority: Option<Box<Account<'info, DelegateAuthority>>>,
+
+    #[account(
+        mut,
+        constraint = funding_account.mint == collateral_custody.mint,
+        has_one = owner
+    )]
+    pub funding_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: empty PDA, authority for token accounts
+    #[account(
+        seeds = [b"transfer_authority"],
+        bump = perpetuals.transfer_authority_bump
+    )]
+    pub transfer_authority: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        seeds = [b"pool", pool.name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        seeds = [
+            b"custody",
+            pool.key().as_ref(),
+            custody.mint.as_ref()
+        ],
+        bump = custody.bump
+    )]
+    pub custody: Box<Account<'info, Custody>>,
+
+    /// CHECK: oracle account for the position token
+    #[account(
+        constraint = custody_oracle_account.key() == custody.oracle.oracle_account
+    )]
+    pub custody_oracle_account: UncheckedAccount<'info>,
+
+    #[account(
+        seeds = [
+            b"custody",
+            pool.key().as_ref(),
+            collateral_custody.mint.as_ref()
+        ],
+        bump = collateral_custody.bump
+    )]
+    pub collateral_custody: Box<Account<'info, Custody>>,
+
+    /// CHECK: oracle account for the collateral token
+    #[account(
+        constraint = collateral_custody_oracle_account.key() == collateral_custody.oracle.oracle_account
+    )]
+    pub collateral_custody_oracle_account: UncheckedAccount<'info>,
+
+    #[account(
+        constraint = collateral_custody_mint.key() == collateral_custody.mint
+    )]
+    pub collateral_custody_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = PositionRequest::LEN,
+        seeds = [
+            b"position_request",
+            owner.key().as_ref(),
+            pool.key().as_ref(),
+            custody.key().as_ref(),
+            &[params.side as u8],
+            &params.index.to_le_bytes()
+        ],
+        bump
+    )]
+    pub position_request: Box<Account<'info, PositionRequest>>,
+
+    #[account(
+        init,
+        payer = authority,
+        token::mint = collateral_custody_mint,
+        token::authority = transfer_authority,
+        seeds = [
+            b"position_request_escrow",
+            position_request.key().as_ref()
+        ],
+        bump
+    )]
+    pub escrow_token_account: Box<Account<'info, TokenAccount>>,
+
+    system_program: Program<'info, System>,
+    token_program: Program<'info, Token>,
+    rent: Sysvar<'info, Rent>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct RequestOpenPositionParams {
+    pub price: u64,
+    pub collateral: u64,
+    pub size: u64,
+    pub side: Side,
+    pub index: u64,
+    pub expiry_time: i64,
+}
+
+pub fn request_open_position<'info>(
+    ctx: Context<'_, '_, '_, 'info, RequestOpenPosition<'info>>,
+    params: &RequestOpenPositionParams,
+) -> Result<()> {
+    // check permissions
+    msg!("Check permissions");
+    DelegateAuthority::validate_authority(
+        &ctx.accounts.authority.key(),
+        &ctx.accounts.owner.key(),
+        ctx.accounts.delegate_authority.as_deref(),
+        DelegateAction::OpenPosition,
+    )?;
+    let perpetuals = ctx.accounts.perpetuals.as_mut();
+    let custody = ctx.accounts.custody.as_mut();
+    let collateral_custody = ctx.accounts.collateral_custody.as_mut();
+    require!(
+        perpetuals.permissions.allow_open_position
+            && custody.permissions.allow_open_position
+            && !custody.is_stable,
+        PerpetualsError::InstructionNotAllowed
+    );
+
+    // longs are collateralized with the position token or a stablecoin, shorts with a stablecoin
+    require!(
+        collateral_custody.is_stable
+            || (params.side == Side::Long && custody.key() == collateral_custody.key()),
+        PerpetualsError::InvalidCollateralCustody
+    );
+
+    // position size expressed in collateral tokens, used to compute fees. The size is valued at
+    // the higher of the current and the requested price, which bounds the entry price of longs
+    let curtime = perpetuals.get_time()?;
+    let size = if custody.key() == collateral_custody.key() {
+        params.size
+    } else {
+        let token_price = OraclePrice::new_from_oracle(
+            &ctx.accounts.custody_oracle_account.to_account_info(),
+            &custody.oracle,
+            curtime,
+        )?
+        .scale_to_exponent(-(Perpetuals::PRICE_DECIMALS as i32))?;
+
+        let collateral_token_price = OraclePrice::new_from_oracle(
+            &ctx.accounts
+                .collateral_custody_oracle_account
+                .to_account_info(),
+            &collateral_custody.oracle,
+            curtime,
+        )?;
+
+        let size_usd = OraclePrice::new(
+            std::cmp::max(params.price, token_price.price),
+            -(Perpetuals::PRICE_DECIMALS as i32),
+        )
+        .get_asset_amount_usd(params.size, custody.decimals)?;
+        collateral_token_price.get_token_amount(size_usd, collateral_custody.decimals)?
+    };
+
+    // record request data
+    msg!("Initialize new position request");
+    let pool = ctx.accounts.pool.as_mut();
+    let position_request = ctx.accounts.position_request.as_mut();
+    position_request.owner = ctx.accounts.owner.key();
+    position_request.pool = pool.key();
+    position_request.custody = custody.key();
+    position_request.collateral_custody = collateral_custody.key();
+    position_request.request_type = RequestType::Open;
+    position_request.side = params.side;
+    position_request.position_index = params.index;
+    position_request.price = params.price;
+    position_request.size = params.size;
+    position_request.collateral_amount = params.collateral;
+    position_request.max_fee =
+        pool.get_max_entry_fee(custody.fees.open_position, size, collateral_custody)?;
+    position_request.create_time = curtime;
+    position_request.expiry_time = params.expiry_time;
+    position_request.bump = ctx.bumps.position_request;
+    position_request.escrow_bump = ctx.bumps.escrow_token_account;
+
+    if !position_request.validate(custody.pricing.max_request_age_sec) {
+        return err!(PerpetualsError::InvalidPositionRequest);
+    }
+
+    let escrow_amount = position_request.get_escrow_amount()?;
+    msg!("Amount in: {}", escrow_amount);
+
+    // transfer tokens
+    msg!("Transfer tokens");
+    perpetuals.transfer_tokens_from_user(
+        ctx.accounts.funding_account.to_account_info(),
+        ctx.accounts.escrow_token_account.to_account_info(),
+        ctx.accounts.authority.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        escrow_amount,
+    )?;
+
+    // deposit rent for the position account, the keeper pays for it on execution
+    let position_rent = Rent::get()?.minimum_balance(Position::LEN);
+    Perpetuals::transfer_sol(
+        ctx.accounts.authority.to_account_info(),
+        ctx.accounts.position_request.to_account_info(),
+        ctx.accounts.system_program.to_account_info(),
+        position_rent,
+    )?;
+
+    // refund the rent paid by operators from the owner deposit
+    DelegateAuthority::refund_rent(
+        &ctx.accounts.authority.to_account_info(),
+        &ctx.accounts.owner.key(),
+        ctx.accounts.delegate_authority.as_deref(),
+        &[
+            ctx.accounts.position_request.to_account_info(),
+            ctx.accounts.escrow_token_account.to_account_info(),
+        ],
+    )?;
+
+    Ok(())
+}
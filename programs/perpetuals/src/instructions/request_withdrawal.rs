@@ -0,0 +1,166 @@
+//! RequestWithdrawal instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError,
+        math,
+        state::{
+            custody::Custody, lp_account::LpAccount, perpetuals::Perpetuals, pool::Pool,
+            withdrawal_request::WithdrawalRequest,
+        },
+    },
+    anchor_lang::prelude::*,
+    anchor_spl::token::{Mint, Token, TokenAccount},
+};
+
+#[derive(Accounts)]
+pub struct RequestWithdrawal<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint = lp_token_account.mint == lp_token_mint.key(),
+        has_one = owner
+    )]
+    pub lp_token_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: empty PDA, authority for token accounts
+    #[account(
+        seeds = [b"transfer_authority"],
+        bump = perpetuals.transfer_authority_bump
+    )]
+    pub transfer_authority: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        seeds = [b"pool", pool.name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    // custody of the token paid out, proportional withdrawals are paid out in every
+    // pool custody
+    #[account(
+        seeds = [
+            b"custody",
+            pool.key().as_ref(),
+            custody.mint.as_ref()
+        ],
+        bump = custody.bump
+    )]
+    pub custody: Option<Box<Account<'info, Custody>>>,
+
+    #[account(
+        seeds = [b"lp_token_mint", pool.key().as_ref()],
+        bump = pool.lp_token_bump
+    )]
+    pub lp_token_mint: Box<Account<'info, Mint>>,
+
+    // deposit record of the owner, the request waits for the rest of its cooldown
+    #[account(
+        seeds = [b"lp_account", owner.key().as_ref(), pool.key().as_ref()],
+        bump = lp_account.bump
+    )]
+    pub lp_account: Option<Box<Account<'info, LpAccount>>>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = WithdrawalRequest::LEN,
+        seeds = [
+            b"withdrawal_request",
+            owner.key().as_ref(),
+            pool.key().as_ref()
+        ],
+        bump
+    )]
+    pub withdrawal_request: Box<Account<'info, WithdrawalRequest>>,
+
+    #[account(
+        init,
+        payer = owner,
+        token::mint = lp_token_mint,
+        token::authority = transfer_authority,
+        seeds = [
+            b"withdrawal_request_escrow",
+            withdrawal_request.key().as_ref()
+        ],
+        bump
+    )]
+    pub escrow_token_account: Box<Account<'info, TokenAccount>>,
+
+    system_program: Program<'info, System>,
+    token_program: Program<'info, Token>,
+    rent: Sysvar<'info, Rent>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct RequestWithdrawalParams {
+    pub lp_amount_in: u64,
+    // minimum value in USD for proportional withdrawals
+    pub min_amount_out: u64,
+}
+
+pub fn request_withdrawal<'info>(
+    ctx: Context<'_, '_, '_, 'info, RequestWithdrawal<'info>>,
+    params: &RequestWithdrawalParams,
+) -> Result<()> {
+    // check permissions
+    msg!("Check permissions");
+    let perpetuals = ctx.accounts.perpetuals.as_ref();
+    let custody = ctx.accounts.custody.as_deref();
+    require!(
+        perpetuals.permissions.allow_remove_liquidity
+            && custody.map_or(true, |custody| custody.permissions.allow_remove_liquidity),
+        PerpetualsError::InstructionNotAllowed
+    );
+
+    // record request data, it is processed at the pool value after the cooldown of the
+    // owner's last deposit, or after a full cooldown for owners without a deposit record
+    msg!("Initialize new withdrawal request");
+    let curtime = perpetuals.get_time()?;
+    let cooldown_start_time = ctx
+        .accounts
+        .lp_account
+        .as_deref()
+        .map_or(curtime, |lp_account| lp_account.last_deposit_time);
+    let withdrawal_request = ctx.accounts.withdrawal_request.as_mut();
+    withdrawal_request.owner = ctx.accounts.owner.key();
+    withdrawal_request.pool = ctx.accounts.pool.key();
+    withdrawal_request.custody = custody.map_or(Pubkey::default(), |custody| custody.key());
+    withdrawal_request.lp_amount = params.lp_amount_in;
+    withdrawal_request.min_amount_out = params.min_amount_out;
+    withdrawal_request.create_time = curtime;
+    withdrawal_request.execute_time = std::cmp::max(
+        curtime,
+        math::checked_add(cooldown_start_time, ctx.accounts.pool.lp_cooldown_sec)?,
+    );
+    withdrawal_request.expire_time = math::checked_add(
+        withdrawal_request.execute_time,
+        ctx.accounts.pool.lp_withdrawal_window_sec,
+    )?;
+    withdrawal_request.bump = ctx.bumps.withdrawal_request;
+    withdrawal_request.escrow_bump = ctx.bumps.escrow_token_account;
+
+    if !withdrawal_request.validate() {
+        return err!(PerpetualsError::InvalidWithdrawalRequest);
+    }
+
+    // escrow lp tokens
+    msg!("Transfer tokens");
+    perpetuals.transfer_tokens_from_user(
+        ctx.accounts.lp_token_account.to_account_info(),
+        ctx.accounts.escrow_token_account.to_account_info(),
+        ctx.accounts.owner.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        params.lp_amount_in,
+    )?;
+
+    Ok(())
+}
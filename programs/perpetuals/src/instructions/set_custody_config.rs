@@ -47,6 +47,7 @@ pub struct SetCustodyConfig<'info> {
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone)]
 pub struct SetCustodyConfigParams {
+    pub is_stable: bool,
     pub oracle: OracleParams,
     pub pricing: PricingParams,
     pub permissions: Permissions,
@@ -74,14 +75,9 @@ pub fn set_custody_config<'info>(
         return Ok(signatures_left);
     }
 
-    // update pool data
-    let pool = ctx.accounts.pool.as_mut();
-    if !pool.validate() {
-        return err!(PerpetualsError::InvalidPoolConfig);
-    }
-
     // update custody data
     let custody = ctx.accounts.custody.as_mut();
+    custody.is_stable = params.is_stable;
     custody.oracle = params.oracle;
     custody.pricing = params.pricing;
     custody.permissions = params.permissions;
@@ -0,0 +1,68 @@
+//! SetDelegateAuthority instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError,
+        math,
+        state::{
+            delegate_authority::{DelegateAuthority, Operator},
+            perpetuals::Perpetuals,
+        },
+    },
+    anchor_lang::prelude::*,
+};
+
+#[derive(Accounts)]
+pub struct SetDelegateAuthority<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = owner,
+        space = DelegateAuthority::LEN,
+        seeds = [b"delegate_authority", owner.key().as_ref()],
+        bump
+    )]
+    pub delegate_authority: Box<Account<'info, DelegateAuthority>>,
+
+    system_program: Program<'info, System>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct SetDelegateAuthorityParams {
+    // replaces the current operators, an empty list revokes all delegations
+    pub operators: Vec<Operator>,
+    // lamports to return to the owner from the deposit that covers the rent of accounts
+    // created by operators, deposits are plain transfers to the delegate authority account
+    pub withdraw_lamports: u64,
+}
+
+pub fn set_delegate_authority<'info>(
+    ctx: Context<'_, '_, '_, 'info, SetDelegateAuthority<'info>>,
+    params: &SetDelegateAuthorityParams,
+) -> Result<()> {
+    msg!("Set operators");
+    let delegate_authority = ctx.accounts.delegate_authority.as_mut();
+    delegate_authority.owner = ctx.accounts.owner.key();
+    delegate_authority.bump = ctx.bumps.delegate_authority;
+    delegate_authority.set_operators(&params.operators)?;
+
+    if params.withdraw_lamports > 0 {
+        msg!("Withdraw rent deposit");
+        let delegate_authority = ctx.accounts.delegate_authority.to_account_info();
+        let min_balance = Rent::get()?.minimum_balance(delegate_authority.data_len());
+        require_gte!(
+            delegate_authority.try_lamports()?,
+            math::checked_add(min_balance, params.withdraw_lamports)?,
+            PerpetualsError::InsufficientRentDeposit
+        );
+        Perpetuals::transfer_sol_from_owned(
+            delegate_authority,
+            ctx.accounts.owner.to_account_info(),
+            params.withdraw_lamports,
+        )?;
+    }
+
+    Ok(())
+}
@@ -33,6 +33,7 @@ pub struct SetPermissions<'info> {
 
 #[derive(AnchorSerialize, AnchorDeserialize)]
 pub struct SetPermissionsParams {
+    pub allow_swap: bool,
     pub allow_add_liquidity: bool,
     pub allow_remove_liquidity: bool,
     pub allow_open_position: bool,
@@ -40,6 +41,7 @@ pub struct SetPermissionsParams {
     pub allow_pnl_withdrawal: bool,
     pub allow_collateral_withdrawal: bool,
     pub allow_size_change: bool,
+    pub allow_direct_position: bool,
 }
 
 pub fn set_permissions<'info>(
@@ -64,6 +66,7 @@ pub fn set_permissions<'info>(
 
     // update permissions
     let perpetuals = ctx.accounts.perpetuals.as_mut();
+    perpetuals.permissions.allow_swap = params.allow_swap;
     perpetuals.permissions.allow_add_liquidity = params.allow_add_liquidity;
     perpetuals.permissions.allow_remove_liquidity = params.allow_remove_liquidity;
     perpetuals.permissions.allow_open_position = params.allow_open_position;
@@ -71,6 +74,7 @@ pub fn set_permissions<'info>(
     perpetuals.permissions.allow_pnl_withdrawal = params.allow_pnl_withdrawal;
     perpetuals.permissions.allow_collateral_withdrawal = params.allow_collateral_withdrawal;
     perpetuals.permissions.allow_size_change = params.allow_size_change;
+    perpetuals.permissions.allow_direct_position = params.allow_direct_position;
 
     if !perpetuals.validate() {
         err!(PerpetualsError::InvalidPerpetualsConfig)
@@ -0,0 +1,289 @@
+//! Swap instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError,
+        math,
+        state::{custody::Custody, oracle::OraclePrice, perpetuals::Perpetuals, pool::Pool},
+    },
+    anchor_lang::prelude::*,
+    anchor_spl::token::{Token, TokenAccount},
+    solana_program::program_error::ProgramError,
+};
+
+#[derive(Accounts)]
+#[instruction(params: SwapParams)]
+pub struct Swap<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint = funding_account.mint == receiving_custody.mint,
+        has_one = owner
+    )]
+    pub funding_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = receiving_account.mint == dispensing_custody.mint,
+        has_one = owner
+    )]
+    pub receiving_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: empty PDA, authority for token accounts
+    #[account(
+        seeds = [b"transfer_authority"],
+        bump = perpetuals.transfer_authority_bump
+    )]
+    pub transfer_authority: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        mut,
+        seeds = [b"pool", pool.name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"custody",
+            pool.key().as_ref(),
+            receiving_custody.mint.as_ref()
+        ],
+        bump = receiving_custody.bump
+    )]
+    pub receiving_custody: Box<Account<'info, Custody>>,
+
+    /// CHECK: oracle account for the received token
+    #[account(
+        constraint = receiving_custody_oracle_account.key() == receiving_custody.oracle.oracle_account
+    )]
+    pub receiving_custody_oracle_account: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"custody_token_account",
+            pool.key().as_ref(),
+            receiving_custody.mint.as_ref()
+        ],
+        bump = receiving_custody.token_account_bump
+    )]
+    pub receiving_custody_token_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"custody",
+            pool.key().as_ref(),
+            dispensing_custody.mint.as_ref()
+        ],
+        bump = dispensing_custody.bump
+    )]
+    pub dispensing_custody: Box<Account<'info, Custody>>,
+
+    /// CHECK: oracle account for the dispensed token
+    #[account(
+        constraint = dispensing_custody_oracle_account.key() == dispensing_custody.oracle.oracle_account
+    )]
+    pub dispensing_custody_oracle_account: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"custody_token_account",
+            pool.key().as_ref(),
+            dispensing_custody.mint.as_ref()
+        ],
+        bump = dispensing_custody.token_account_bump
+    )]
+    pub dispensing_custody_token_account: Box<Account<'info, TokenAccount>>,
+
+    token_program: Program<'info, Token>,
+    // remaining accounts:
+    //   pool.tokens.len() custody accounts (read-only, unsigned)
+    //   pool.tokens.len() custody oracles (read-only, unsigned)
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct SwapParams {
+    pub amount_in: u64,
+    pub min_amount_out: u64,
+}
+
+pub fn swap<'info>(
+    ctx: Context<'_, '_, 'info, 'info, Swap<'info>>,
+    params: &SwapParams,
+) -> Result<()> {
+    // check permissions
+    msg!("Check permissions");
+    let perpetuals = ctx.accounts.perpetuals.as_ref();
+    let receiving_custody = ctx.accounts.receiving_custody.as_mut();
+    let dispensing_custody = ctx.accounts.dispensing_custody.as_mut();
+    require!(
+        perpetuals.permissions.allow_swap
+            && receiving_custody.permissions.allow_swap
+            && dispensing_custody.permissions.allow_swap,
+        PerpetualsError::InstructionNotAllowed
+    );
+
+    // validate inputs
+    msg!("Validate inputs");
+    if params.amount_in == 0 {
+        return Err(ProgramError::InvalidArgument.into());
+    }
+    require_keys_neq!(
+        receiving_custody.key(),
+        dispensing_custody.key(),
+        PerpetualsError::InvalidCustodyConfig
+    );
+
+    let pool = ctx.accounts.pool.as_mut();
+    let token_id_in = pool.get_token_id(&receiving_custody.key())?;
+    let token_id_out = pool.get_token_id(&dispensing_custody.key())?;
+
+    // compute token amount returned to the user
+    let curtime = perpetuals.get_time()?;
+
+    // Refresh pool.aum_usd, fees depend on the custody shares of the pool value
+    pool.aum_usd = pool.get_assets_under_management_usd(ctx.remaining_accounts, curtime)?;
+
+    let received_token_price = OraclePrice::new_from_oracle(
+        &ctx.accounts
+            .receiving_custody_oracle_account
+            .to_account_info(),
+        &receiving_custody.oracle,
+        curtime,
+    )?;
+
+    let dispensed_token_price = OraclePrice::new_from_oracle(
+        &ctx.accounts
+            .dispensing_custody_oracle_account
+            .to_account_info(),
+        &dispensing_custody.oracle,
+        curtime,
+    )?;
+
+    let (fee_in, _) = pool.get_swap_fees(
+        token_id_in,
+        token_id_out,
+        params.amount_in,
+        0,
+        receiving_custody,
+        &received_token_price,
+        dispensing_custody,
+        &dispensed_token_price,
+    )?;
+    let amount_out = pool.get_swap_amount(
+        &received_token_price,
+        &dispensed_token_price,
+        receiving_custody,
+        dispensing_custody,
+        math::checked_sub(params.amount_in, fee_in)?,
+    )?;
+    let (_, fee_out) = pool.get_swap_fees(
+        token_id_in,
+        token_id_out,
+        0,
+        amount_out,
+        receiving_custody,
+        &received_token_price,
+        dispensing_custody,
+        &dispensed_token_price,
+    )?;
+    msg!("Collected fees: {} {}", fee_in, fee_out);
+
+    let no_fee_amount = math::checked_sub(amount_out, fee_out)?;
+    msg!("Amount out: {}", no_fee_amount);
+    require!(
+        no_fee_amount >= params.min_amount_out,
+        PerpetualsError::MaxPriceSlippage
+    );
+
+    // check pool constraints
+    msg!("Check pool constraints");
+    let protocol_fee_in = Pool::get_fee_amount(receiving_custody.fees.protocol_share, fee_in)?;
+    let deposit_amount = math::checked_sub(params.amount_in, protocol_fee_in)?;
+
+    let protocol_fee_out = Pool::get_fee_amount(dispensing_custody.fees.protocol_share, fee_out)?;
+    let withdrawal_amount = math::checked_add(no_fee_amount, protocol_fee_out)?;
+
+    require!(
+        pool.check_available_amount(withdrawal_amount, dispensing_custody)?,
+        PerpetualsError::CustodyAmountLimit
+    );
+
+    // transfer tokens
+    msg!("Transfer tokens");
+    perpetuals.transfer_tokens_from_user(
+        ctx.accounts.funding_account.to_account_info(),
+        ctx.accounts
+            .receiving_custody_token_account
+            .to_account_info(),
+        ctx.accounts.owner.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        params.amount_in,
+    )?;
+
+    perpetuals.transfer_tokens(
+        ctx.accounts
+            .dispensing_custody_token_account
+            .to_account_info(),
+        ctx.accounts.receiving_account.to_account_info(),
+        ctx.accounts.transfer_authority.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        no_fee_amount,
+    )?;
+
+    // update custody stats
+    msg!("Update custody stats");
+    receiving_custody.collected_fees.swap_usd =
+        receiving_custody.collected_fees.swap_usd.wrapping_add(
+            received_token_price.get_asset_amount_usd(fee_in, receiving_custody.decimals)?,
+        );
+
+    receiving_custody.volume_stats.swap_usd = receiving_custody.volume_stats.swap_usd.wrapping_add(
+        received_token_price.get_asset_amount_usd(params.amount_in, receiving_custody.decimals)?,
+    );
+
+    receiving_custody.assets.protocol_fees =
+        math::checked_add(receiving_custody.assets.protocol_fees, protocol_fee_in)?;
+
+    receiving_custody.assets.owned =
+        math::checked_add(receiving_custody.assets.owned, deposit_amount)?;
+
+    dispensing_custody.collected_fees.swap_usd =
+        dispensing_custody.collected_fees.swap_usd.wrapping_add(
+            dispensed_token_price.get_asset_amount_usd(fee_out, dispensing_custody.decimals)?,
+        );
+
+    dispensing_custody.volume_stats.swap_usd =
+        dispensing_custody.volume_stats.swap_usd.wrapping_add(
+            dispensed_token_price.get_asset_amount_usd(amount_out, dispensing_custody.decimals)?,
+        );
+
+    dispensing_custody.assets.protocol_fees =
+        math::checked_add(dispensing_custody.assets.protocol_fees, protocol_fee_out)?;
+
+    dispensing_custody.assets.owned =
+        math::checked_sub(dispensing_custody.assets.owned, withdrawal_amount)?;
+
+    receiving_custody.update_borrow_rate(curtime)?;
+    dispensing_custody.update_borrow_rate(curtime)?;
+
+    // update pool stats
+    msg!("Update pool stats");
+    receiving_custody.exit(&crate::ID)?;
+    dispensing_custody.exit(&crate::ID)?;
+    pool.aum_usd = pool.get_assets_under_management_usd(ctx.remaining_accounts, curtime)?;
+
+    Ok(())
+}
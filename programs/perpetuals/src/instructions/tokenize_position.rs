@@ -0,0 +1,122 @@
+//! TokenizePosition instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError,
+        events,
+        state::{perpetuals::Perpetuals, pool::Pool, position::Position},
+    },
+    anchor_lang::prelude::*,
+    anchor_spl::{
+        associated_token::AssociatedToken,
+        token::{Mint, Token, TokenAccount},
+    },
+};
+
+#[derive(Accounts)]
+pub struct TokenizePosition<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    /// CHECK: empty PDA, authority for token accounts
+    #[account(
+        seeds = [b"transfer_authority"],
+        bump = perpetuals.transfer_authority_bump
+    )]
+    pub transfer_authority: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        seeds = [b"pool", pool.name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        has_one = owner,
+        constraint = !position.is_cross_margin() @ PerpetualsError::CrossMarginPosition,
+        constraint = !position.is_tokenized() @ PerpetualsError::TokenizedPosition,
+        seeds = [
+            b"position",
+            owner.key().as_ref(),
+            pool.key().as_ref(),
+            position.custody.as_ref(),
+            &[position.side as u8],
+            &position.index.to_le_bytes()
+        ],
+        bump = position.bump
+    )]
+    pub position: Box<Account<'info, Position>>,
+
+    // a fresh keypair, so that a token of a closed position can never match
+    // a new position re-created under the same address
+    #[account(
+        init,
+        payer = owner,
+        mint::authority = transfer_authority,
+        mint::decimals = 0
+    )]
+    pub position_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        init,
+        payer = owner,
+        associated_token::mint = position_mint,
+        associated_token::authority = owner
+    )]
+    pub position_token_account: Box<Account<'info, TokenAccount>>,
+
+    system_program: Program<'info, System>,
+    token_program: Program<'info, Token>,
+    associated_token_program: Program<'info, AssociatedToken>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct TokenizePositionParams {}
+
+pub fn tokenize_position<'info>(
+    ctx: Context<'_, '_, '_, 'info, TokenizePosition<'info>>,
+    _params: &TokenizePositionParams,
+) -> Result<()> {
+    // mint the position token and drop the mint authority so the supply stays at one
+    msg!("Mint position token");
+    let perpetuals = ctx.accounts.perpetuals.as_ref();
+    perpetuals.mint_tokens(
+        ctx.accounts.position_mint.to_account_info(),
+        ctx.accounts.position_token_account.to_account_info(),
+        ctx.accounts.transfer_authority.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        1,
+    )?;
+    perpetuals.revoke_mint_authority(
+        ctx.accounts.position_mint.to_account_info(),
+        ctx.accounts.transfer_authority.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+    )?;
+
+    // record the mint, from now on the token holder is the effective owner
+    let position = ctx.accounts.position.as_mut();
+    position.token_mint = ctx.accounts.position_mint.key();
+
+    emit!(events::TokenizePosition {
+        collateral_amount: position.collateral_amount,
+        collateral_custody: position.collateral_custody,
+        custody: position.custody,
+        owner: position.owner,
+        pool: position.pool,
+        price: position.price,
+        side: position.side,
+        size_usd: position.size_usd,
+        time: perpetuals.get_time()?,
+        index: position.index,
+        token_mint: position.token_mint,
+    });
+
+    Ok(())
+}
@@ -0,0 +1,130 @@
+//! TransferPosition instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError,
+        events,
+        state::{perpetuals::Perpetuals, pool::Pool, position::Position},
+    },
+    anchor_lang::prelude::*,
+};
+
+#[derive(Accounts)]
+#[instruction(params: TransferPositionParams)]
+pub struct TransferPosition<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub new_owner: SystemAccount<'info>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        seeds = [b"pool", pool.name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        has_one = owner,
+        constraint = !position.is_cross_margin() @ PerpetualsError::CrossMarginPosition,
+        constraint = !position.is_tokenized() @ PerpetualsError::TokenizedPosition,
+        constraint = position.trigger_orders == 0 @ PerpetualsError::PendingPositionOrders,
+        seeds = [
+            b"position",
+            owner.key().as_ref(),
+            pool.key().as_ref(),
+            position.custody.as_ref(),
+            &[position.side as u8],
+            &position.index.to_le_bytes()
+        ],
+        bump = position.bump,
+        close = owner
+    )]
+    pub position: Box<Account<'info, Position>>,
+
+    /// CHECK: request PDA of the position, must not exist
+    #[account(
+        seeds = [
+            b"position_request",
+            owner.key().as_ref(),
+            pool.key().as_ref(),
+            position.custody.as_ref(),
+            &[position.side as u8],
+            &position.index.to_le_bytes()
+        ],
+        bump,
+        constraint = position_request.data_is_empty() @ PerpetualsError::PendingPositionOrders
+    )]
+    pub position_request: UncheckedAccount<'info>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = Position::LEN,
+        seeds = [
+            b"position",
+            new_owner.key().as_ref(),
+            pool.key().as_ref(),
+            position.custody.as_ref(),
+            &[position.side as u8],
+            &params.new_index.to_le_bytes()
+        ],
+        bump
+    )]
+    pub new_position: Box<Account<'info, Position>>,
+
+    system_program: Program<'info, System>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct TransferPositionParams {
+    // index of the position under the new owner, which may already use the current one
+    pub new_index: u64,
+}
+
+pub fn transfer_position<'info>(
+    ctx: Context<'_, '_, '_, 'info, TransferPosition<'info>>,
+    params: &TransferPositionParams,
+) -> Result<()> {
+    // validate inputs
+    msg!("Validate inputs");
+    require_keys_neq!(
+        ctx.accounts.owner.key(),
+        ctx.accounts.new_owner.key(),
+        PerpetualsError::InvalidPositionState
+    );
+
+    // migrate position data, only the owner, index and bump are changed
+    msg!("Migrate position");
+    let position = ctx.accounts.position.as_ref();
+    let new_position = ctx.accounts.new_position.as_mut();
+    new_position.set_inner(Position {
+        owner: ctx.accounts.new_owner.key(),
+        index: params.new_index,
+        bump: ctx.bumps.new_position,
+        ..(**position).clone()
+    });
+
+    emit!(events::TransferPosition {
+        collateral_amount: position.collateral_amount,
+        collateral_custody: position.collateral_custody,
+        custody: position.custody,
+        owner: position.owner,
+        pool: position.pool,
+        price: position.price,
+        side: position.side,
+        size_usd: position.size_usd,
+        time: ctx.accounts.perpetuals.get_time()?,
+        index: position.index,
+        new_index: params.new_index,
+        new_owner: new_position.owner,
+    });
+
+    Ok(())
+}
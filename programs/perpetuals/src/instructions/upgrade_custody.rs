@@ -0,0 +1,109 @@
+//! UpgradeCustody instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError,
+        state::{
+            custody::{Custody, DeprecatedCustody},
+            multisig::{AdminInstruction, Multisig},
+            perpetuals::Perpetuals,
+            pool::Pool,
+        },
+    },
+    anchor_lang::{prelude::*, Discriminator},
+    solana_program::program_error::ProgramError,
+};
+
+#[derive(Accounts)]
+pub struct UpgradeCustody<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"multisig"],
+        bump = multisig.load()?.bump
+    )]
+    pub multisig: AccountLoader<'info, Multisig>,
+
+    // must be upgraded first
+    #[account(
+        seeds = [b"pool", pool.name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    /// CHECK: custody account in the initial release layout, validated in the handler
+    #[account(
+        mut,
+        owner = crate::ID,
+        constraint = pool.custodies.contains(&custody.key()) @ PerpetualsError::InvalidCustodyState
+    )]
+    pub custody: UncheckedAccount<'info>,
+
+    system_program: Program<'info, System>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct UpgradeCustodyParams {
+    pub is_stable: bool,
+}
+
+pub fn upgrade_custody<'info>(
+    ctx: Context<'_, '_, '_, 'info, UpgradeCustody<'info>>,
+    params: &UpgradeCustodyParams,
+) -> Result<u8> {
+    // validate signatures
+    let mut multisig = ctx.accounts.multisig.load_mut()?;
+
+    let signatures_left = multisig.sign_multisig(
+        &ctx.accounts.admin,
+        &Multisig::get_account_infos(&ctx)[1..],
+        &Multisig::get_instruction_data(AdminInstruction::UpgradeCustody, params)?,
+    )?;
+    if signatures_left > 0 {
+        msg!(
+            "Instruction has been signed but more signatures are required: {}",
+            signatures_left
+        );
+        return Ok(signatures_left);
+    }
+
+    // load deprecated custody data
+    msg!("Load deprecated custody");
+    let custody_account = ctx.accounts.custody.to_account_info();
+    if custody_account.try_data_len()? != DeprecatedCustody::LEN {
+        return Err(ProgramError::InvalidAccountData.into());
+    }
+    let deprecated_custody = {
+        let data = custody_account.try_borrow_data()?;
+        if data[..8] != Custody::DISCRIMINATOR {
+            return Err(ProgramError::InvalidAccountData.into());
+        }
+        DeprecatedCustody::try_deserialize_unchecked(&mut &data[..])?
+    };
+
+    // update custody data
+    let mut custody = Custody::from(deprecated_custody);
+    custody.is_stable = params.is_stable;
+
+    if !custody.validate() {
+        return err!(PerpetualsError::InvalidCustodyConfig);
+    }
+
+    // resize custody account
+    msg!("Resize custody account");
+    Perpetuals::realloc(
+        ctx.accounts.admin.to_account_info(),
+        custody_account.clone(),
+        ctx.accounts.system_program.to_account_info(),
+        Custody::LEN,
+        true,
+    )?;
+
+    // save upgraded custody
+    msg!("Save upgraded custody");
+    custody.try_serialize(&mut &mut custody_account.try_borrow_mut_data()?[..])?;
+
+    Ok(0)
+}
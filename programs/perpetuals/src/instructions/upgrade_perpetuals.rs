@@ -0,0 +1,95 @@
+//! UpgradePerpetuals instruction handler
+
+use {
+    crate::state::{
+        multisig::{AdminInstruction, Multisig},
+        perpetuals::{DeprecatedPerpetuals, Perpetuals},
+    },
+    anchor_lang::{prelude::*, Discriminator},
+    solana_program::program_error::ProgramError,
+};
+
+#[derive(Accounts)]
+pub struct UpgradePerpetuals<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"multisig"],
+        bump = multisig.load()?.bump
+    )]
+    pub multisig: AccountLoader<'info, Multisig>,
+
+    /// CHECK: perpetuals account in the initial release layout, validated in the handler
+    #[account(
+        mut,
+        seeds = [b"perpetuals"],
+        bump
+    )]
+    pub perpetuals: UncheckedAccount<'info>,
+
+    system_program: Program<'info, System>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct UpgradePerpetualsParams {}
+
+pub fn upgrade_perpetuals<'info>(
+    ctx: Context<'_, '_, '_, 'info, UpgradePerpetuals<'info>>,
+    params: &UpgradePerpetualsParams,
+) -> Result<u8> {
+    // validate signatures
+    let mut multisig = ctx.accounts.multisig.load_mut()?;
+
+    let signatures_left = multisig.sign_multisig(
+        &ctx.accounts.admin,
+        &Multisig::get_account_infos(&ctx)[1..],
+        &Multisig::get_instruction_data(AdminInstruction::UpgradePerpetuals, params)?,
+    )?;
+    if signatures_left > 0 {
+        msg!(
+            "Instruction has been signed but more signatures are required: {}",
+            signatures_left
+        );
+        return Ok(signatures_left);
+    }
+
+    // load deprecated perpetuals data
+    msg!("Load deprecated perpetuals");
+    let perpetuals_account = ctx.accounts.perpetuals.to_account_info();
+    let deprecated_perpetuals = {
+        let data = perpetuals_account.try_borrow_data()?;
+        if data.len() < 8 || data[..8] != Perpetuals::DISCRIMINATOR {
+            return Err(ProgramError::InvalidAccountData.into());
+        }
+        DeprecatedPerpetuals::try_deserialize_unchecked(&mut &data[..])?
+    };
+
+    // upgraded accounts don't deserialize into the deprecated layout consistently
+    if deprecated_perpetuals.perpetuals_bump != ctx.bumps.perpetuals
+        || perpetuals_account.try_data_len()?
+            != DeprecatedPerpetuals::LEN
+                + deprecated_perpetuals.pools.len() * std::mem::size_of::<Pubkey>()
+    {
+        return Err(ProgramError::InvalidAccountData.into());
+    }
+
+    let perpetuals = Perpetuals::from(deprecated_perpetuals);
+
+    // resize perpetuals account
+    msg!("Resize perpetuals account");
+    Perpetuals::realloc(
+        ctx.accounts.admin.to_account_info(),
+        perpetuals_account.clone(),
+        ctx.accounts.system_program.to_account_info(),
+        Perpetuals::LEN + perpetuals.pools.len() * std::mem::size_of::<Pubkey>(),
+        true,
+    )?;
+
+    // save upgraded perpetuals
+    msg!("Save upgraded perpetuals");
+    perpetuals.try_serialize(&mut &mut perpetuals_account.try_borrow_mut_data()?[..])?;
+
+    Ok(0)
+}
@@ -0,0 +1,153 @@
+//! UpgradePool instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError,
+        state::{
+            insurance_fund::InsuranceFund,
+            multisig::{AdminInstruction, Multisig},
+            perpetuals::Perpetuals,
+            pool::{DeprecatedPool, Pool},
+        },
+    },
+    anchor_lang::{prelude::*, Discriminator},
+    solana_program::program_error::ProgramError,
+};
+
+#[derive(Accounts)]
+pub struct UpgradePool<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"multisig"],
+        bump = multisig.load()?.bump
+    )]
+    pub multisig: AccountLoader<'info, Multisig>,
+
+    // must be upgraded first
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    /// CHECK: pool account in the initial release layout, validated in the handler
+    #[account(
+        mut,
+        owner = crate::ID,
+        constraint = perpetuals.pools.contains(&pool.key()) @ PerpetualsError::InvalidPoolState
+    )]
+    pub pool: UncheckedAccount<'info>,
+
+    // pools of the initial release have no insurance fund. Instruction can be called
+    // multiple times due to multisig use, hence init_if_needed instead of init.
+    #[account(
+        init_if_needed,
+        payer = admin,
+        space = InsuranceFund::LEN,
+        seeds = [b"insurance_fund", pool.key().as_ref()],
+        bump
+    )]
+    pub insurance_fund: Box<Account<'info, InsuranceFund>>,
+
+    system_program: Program<'info, System>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct UpgradePoolParams {
+    pub lp_cooldown_sec: i64,
+    pub lp_withdrawal_window_sec: i64,
+    pub lp_expired_cancel_fee: u64,
+}
+
+pub fn upgrade_pool<'info>(
+    ctx: Context<'_, '_, '_, 'info, UpgradePool<'info>>,
+    params: &UpgradePoolParams,
+) -> Result<u8> {
+    // validate signatures
+    let mut multisig = ctx.accounts.multisig.load_mut()?;
+
+    let signatures_left = multisig.sign_multisig(
+        &ctx.accounts.admin,
+        &Multisig::get_account_infos(&ctx)[1..],
+        &Multisig::get_instruction_data(AdminInstruction::UpgradePool, params)?,
+    )?;
+    if signatures_left > 0 {
+        msg!(
+            "Instruction has been signed but more signatures are required: {}",
+            signatures_left
+        );
+        return Ok(signatures_left);
+    }
+
+    // load deprecated pool data
+    msg!("Load deprecated pool");
+    let pool_account = ctx.accounts.pool.to_account_info();
+    let deprecated_pool = {
+        let data = pool_account.try_borrow_data()?;
+        if data.len() < 8 || data[..8] != Pool::DISCRIMINATOR {
+            return Err(ProgramError::InvalidAccountData.into());
+        }
+        DeprecatedPool::try_deserialize_unchecked(&mut &data[..])?
+    };
+
+    // the new layout starts with the deprecated one, upgraded accounts are told
+    // apart by their size
+    let custodies_len = deprecated_pool.custodies.len();
+    if pool_account.try_data_len()?
+        != DeprecatedPool::LEN + custodies_len * std::mem::size_of::<Pubkey>()
+    {
+        return Err(ProgramError::InvalidAccountData.into());
+    }
+
+    // update pool data
+    let pool = Pool {
+        name: deprecated_pool.name,
+        custodies: deprecated_pool.custodies,
+        aum_usd: deprecated_pool.aum_usd,
+        bump: deprecated_pool.bump,
+        lp_token_bump: deprecated_pool.lp_token_bump,
+        inception_time: deprecated_pool.inception_time,
+        target_weights: vec![0; custodies_len],
+        lp_cooldown_sec: params.lp_cooldown_sec,
+        lp_withdrawal_window_sec: params.lp_withdrawal_window_sec,
+        lp_expired_cancel_fee: params.lp_expired_cancel_fee,
+    };
+
+    if !pool.validate() {
+        return err!(PerpetualsError::InvalidPoolConfig);
+    }
+
+    // resize pool account
+    msg!("Resize pool account");
+    Perpetuals::realloc(
+        ctx.accounts.admin.to_account_info(),
+        pool_account.clone(),
+        ctx.accounts.system_program.to_account_info(),
+        Pool::LEN + custodies_len * (std::mem::size_of::<Pubkey>() + std::mem::size_of::<u64>()),
+        true,
+    )?;
+
+    // save upgraded pool
+    msg!("Save upgraded pool");
+    pool.try_serialize(&mut &mut pool_account.try_borrow_mut_data()?[..])?;
+
+    // record insurance fund
+    msg!("Record insurance fund");
+    Perpetuals::realloc(
+        ctx.accounts.admin.to_account_info(),
+        ctx.accounts.insurance_fund.to_account_info(),
+        ctx.accounts.system_program.to_account_info(),
+        InsuranceFund::LEN + custodies_len * std::mem::size_of::<u64>(),
+        false,
+    )?;
+
+    let insurance_fund = ctx.accounts.insurance_fund.as_mut();
+    insurance_fund.pool = pool_account.key();
+    insurance_fund.balances = vec![0; custodies_len];
+    insurance_fund.bump = ctx.bumps.insurance_fund;
+
+    Ok(0)
+}
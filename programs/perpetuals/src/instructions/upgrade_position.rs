@@ -0,0 +1,111 @@
+//! UpgradePosition instruction handler
+
+use {
+    crate::state::{
+        custody::Custody,
+        perpetuals::Perpetuals,
+        pool::Pool,
+        position::{DeprecatedPosition, Position, Side},
+    },
+    anchor_lang::{prelude::*, Discriminator},
+    solana_program::program_error::ProgramError,
+};
+
+#[derive(Accounts)]
+pub struct UpgradePosition<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        seeds = [b"pool", pool.name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    // must be upgraded first
+    #[account(
+        seeds = [
+            b"custody",
+            pool.key().as_ref(),
+            custody.mint.as_ref()
+        ],
+        bump = custody.bump
+    )]
+    pub custody: Box<Account<'info, Custody>>,
+
+    /// CHECK: position account in the initial release layout, validated in the handler
+    #[account(
+        mut,
+        owner = crate::ID,
+        seeds = [
+            b"position",
+            owner.key().as_ref(),
+            pool.key().as_ref(),
+            custody.key().as_ref(),
+            &[Side::Long as u8]
+        ],
+        bump
+    )]
+    pub deprecated_position: UncheckedAccount<'info>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = Position::LEN,
+        seeds = [
+            b"position",
+            owner.key().as_ref(),
+            pool.key().as_ref(),
+            custody.key().as_ref(),
+            &[Side::Long as u8],
+            &0u64.to_le_bytes()
+        ],
+        bump
+    )]
+    pub position: Box<Account<'info, Position>>,
+
+    system_program: Program<'info, System>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct UpgradePositionParams {}
+
+pub fn upgrade_position<'info>(
+    ctx: Context<'_, '_, '_, 'info, UpgradePosition<'info>>,
+    _params: &UpgradePositionParams,
+) -> Result<()> {
+    // load deprecated position data
+    msg!("Load deprecated position");
+    let deprecated_position_account = ctx.accounts.deprecated_position.to_account_info();
+    if deprecated_position_account.try_data_len()? != DeprecatedPosition::LEN {
+        return Err(ProgramError::InvalidAccountData.into());
+    }
+    let deprecated_position = {
+        let data = deprecated_position_account.try_borrow_data()?;
+        if data[..8] != Position::DISCRIMINATOR {
+            return Err(ProgramError::InvalidAccountData.into());
+        }
+        DeprecatedPosition::try_deserialize_unchecked(&mut &data[..])?
+    };
+    require_keys_eq!(deprecated_position.owner, ctx.accounts.owner.key());
+    require_keys_eq!(deprecated_position.pool, ctx.accounts.pool.key());
+    require_keys_eq!(deprecated_position.custody, ctx.accounts.custody.key());
+
+    // move position data to the address with the position index
+    msg!("Save upgraded position");
+    let position = ctx.accounts.position.as_mut();
+    position.set_inner(Position::from(deprecated_position));
+    position.bump = ctx.bumps.position;
+
+    // close deprecated position account
+    msg!("Close deprecated position");
+    Perpetuals::transfer_sol_from_owned(
+        deprecated_position_account.clone(),
+        ctx.accounts.owner.to_account_info(),
+        deprecated_position_account.try_lamports()?,
+    )?;
+    deprecated_position_account.assign(&System::id());
+    deprecated_position_account
+        .realloc(0, false)
+        .map_err(|_| ProgramError::InvalidRealloc.into())
+}
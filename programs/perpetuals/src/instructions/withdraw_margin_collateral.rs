@@ -0,0 +1,145 @@
+//! WithdrawMarginCollateral instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError,
+        math,
+        state::{
+            custody::Custody, margin_account::MarginAccount, perpetuals::Perpetuals, pool::Pool,
+        },
+    },
+    anchor_lang::prelude::*,
+    anchor_spl::token::{Token, TokenAccount},
+    solana_program::program_error::ProgramError,
+};
+
+#[derive(Accounts)]
+pub struct WithdrawMarginCollateral<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint = receiving_account.mint == custody.mint,
+        has_one = owner
+    )]
+    pub receiving_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: empty PDA, authority for token accounts
+    #[account(
+        seeds = [b"transfer_authority"],
+        bump = perpetuals.transfer_authority_bump
+    )]
+    pub transfer_authority: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        seeds = [b"pool", pool.name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        has_one = owner,
+        seeds = [b"margin_account", owner.key().as_ref(), pool.key().as_ref()],
+        bump = margin_account.bump
+    )]
+    pub margin_account: Box<Account<'info, MarginAccount>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"custody",
+            pool.key().as_ref(),
+            custody.mint.as_ref()
+        ],
+        bump = custody.bump
+    )]
+    pub custody: Box<Account<'info, Custody>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"custody_token_account",
+            pool.key().as_ref(),
+            custody.mint.as_ref()
+        ],
+        bump = custody.token_account_bump
+    )]
+    pub custody_token_account: Box<Account<'info, TokenAccount>>,
+
+    token_program: Program<'info, Token>,
+    // remaining accounts:
+    //   5 accounts per margin account position (read-only, unsigned)
+    //   2 accounts per margin account collateral (read-only, unsigned)
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct WithdrawMarginCollateralParams {
+    pub amount: u64,
+}
+
+pub fn withdraw_margin_collateral<'info>(
+    ctx: Context<'_, '_, 'info, 'info, WithdrawMarginCollateral<'info>>,
+    params: &WithdrawMarginCollateralParams,
+) -> Result<()> {
+    // check permissions
+    msg!("Check permissions");
+    let perpetuals = ctx.accounts.perpetuals.as_ref();
+    let custody = ctx.accounts.custody.as_mut();
+    require!(
+        perpetuals.permissions.allow_collateral_withdrawal
+            && custody.permissions.allow_collateral_withdrawal,
+        PerpetualsError::InstructionNotAllowed
+    );
+
+    // validate inputs
+    msg!("Validate inputs");
+    if params.amount == 0 {
+        return Err(ProgramError::InvalidArgument.into());
+    }
+
+    // update margin account
+    msg!("Update margin account");
+    let margin_account_key = ctx.accounts.margin_account.key();
+    let margin_account = ctx.accounts.margin_account.as_mut();
+    margin_account.remove_collateral(&custody.key(), params.amount)?;
+
+    // check account risk, the remaining equity must cover the initial margin of all positions
+    msg!("Check margin account risks");
+    let (equity_usd, requirement_usd) = ctx.accounts.pool.get_margin_account_health(
+        margin_account,
+        &margin_account_key,
+        ctx.remaining_accounts,
+        perpetuals.get_time()?,
+        true,
+    )?;
+    msg!("Equity: {}, requirement: {}", equity_usd, requirement_usd);
+    require_gte!(
+        equity_usd,
+        requirement_usd,
+        PerpetualsError::InsufficientMargin
+    );
+
+    // transfer tokens
+    msg!("Transfer tokens");
+    perpetuals.transfer_tokens(
+        ctx.accounts.custody_token_account.to_account_info(),
+        ctx.accounts.receiving_account.to_account_info(),
+        ctx.accounts.transfer_authority.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        params.amount,
+    )?;
+
+    // update custody stats
+    msg!("Update custody stats");
+    custody.assets.collateral = math::checked_sub(custody.assets.collateral, params.amount)?;
+
+    Ok(())
+}
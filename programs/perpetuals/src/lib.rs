@@ -11,7 +11,7 @@ pub mod state;
 use {
     anchor_lang::prelude::*,
     instructions::*,
-    state::perpetuals::{AmountAndFee, NewPositionPricesAndFee, PriceAndFee},
+    state::perpetuals::{AmountAndFee, NewPositionPricesAndFee, PriceAndFee, SwapAmountAndFees},
 };
 
 solana_security_txt::security_txt! {
@@ -53,7 +53,7 @@ pub mod perpetuals {
     }
 
     pub fn force_close<'info>(
-        ctx: Context<'_, '_, '_, 'info, ForceClose<'info>>,
+        ctx: Context<'_, '_, 'info, 'info, ForceClose<'info>>,
         params: ForceCloseParams,
     ) -> Result<u8> {
         instructions::force_close(ctx, &params)
@@ -107,6 +107,36 @@ pub mod perpetuals {
         instructions::set_permissions(ctx, &params)
     }
 
+    pub fn set_pool_config<'info>(
+        ctx: Context<'_, '_, '_, 'info, SetPoolConfig<'info>>,
+        params: SetPoolConfigParams,
+    ) -> Result<u8> {
+        instructions::set_pool_config(ctx, &params)
+    }
+
+    // accounts created by the initial release are upgraded in order:
+    // perpetuals, then each pool, then each custody of the pool
+    pub fn upgrade_perpetuals<'info>(
+        ctx: Context<'_, '_, '_, 'info, UpgradePerpetuals<'info>>,
+        params: UpgradePerpetualsParams,
+    ) -> Result<u8> {
+        instructions::upgrade_perpetuals(ctx, &params)
+    }
+
+    pub fn upgrade_pool<'info>(
+        ctx: Context<'_, '_, '_, 'info, UpgradePool<'info>>,
+        params: UpgradePoolParams,
+    ) -> Result<u8> {
+        instructions::upgrade_pool(ctx, &params)
+    }
+
+    pub fn upgrade_custody<'info>(
+        ctx: Context<'_, '_, '_, 'info, UpgradeCustody<'info>>,
+        params: UpgradeCustodyParams,
+    ) -> Result<u8> {
+        instructions::upgrade_custody(ctx, &params)
+    }
+
     pub fn withdraw_fees<'info>(
         ctx: Context<'_, '_, '_, 'info, WithdrawFees<'info>>,
         params: WithdrawFeesParams,
@@ -114,6 +144,13 @@ pub mod perpetuals {
         instructions::withdraw_fees(ctx, &params)
     }
 
+    pub fn withdraw_insurance_fund<'info>(
+        ctx: Context<'_, '_, '_, 'info, WithdrawInsuranceFund<'info>>,
+        params: WithdrawInsuranceFundParams,
+    ) -> Result<u8> {
+        instructions::withdraw_insurance_fund(ctx, &params)
+    }
+
     pub fn withdraw_sol_fees<'info>(
         ctx: Context<'_, '_, '_, 'info, WithdrawSolFees<'info>>,
         params: WithdrawSolFeesParams,
@@ -137,6 +174,13 @@ pub mod perpetuals {
         instructions::add_liquidity(ctx, &params)
     }
 
+    pub fn add_liquidity_basket<'info>(
+        ctx: Context<'_, '_, 'info, 'info, AddLiquidityBasket<'info>>,
+        params: AddLiquidityBasketParams,
+    ) -> Result<()> {
+        instructions::add_liquidity_basket(ctx, &params)
+    }
+
     pub fn remove_liquidity<'info>(
         ctx: Context<'_, '_, 'info, 'info, RemoveLiquidity<'info>>,
         params: RemoveLiquidityParams,
@@ -144,13 +188,55 @@ pub mod perpetuals {
         instructions::remove_liquidity(ctx, &params)
     }
 
+    pub fn remove_liquidity_proportional<'info>(
+        ctx: Context<'_, '_, 'info, 'info, RemoveLiquidityProportional<'info>>,
+        params: RemoveLiquidityProportionalParams,
+    ) -> Result<()> {
+        instructions::remove_liquidity_proportional(ctx, &params)
+    }
+
+    pub fn request_withdrawal<'info>(
+        ctx: Context<'_, '_, '_, 'info, RequestWithdrawal<'info>>,
+        params: RequestWithdrawalParams,
+    ) -> Result<()> {
+        instructions::request_withdrawal(ctx, &params)
+    }
+
+    pub fn execute_withdrawal_request<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ExecuteWithdrawalRequest<'info>>,
+        params: ExecuteWithdrawalRequestParams,
+    ) -> Result<()> {
+        instructions::execute_withdrawal_request(ctx, &params)
+    }
+
+    pub fn execute_withdrawal_request_proportional<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ExecuteWithdrawalRequestProportional<'info>>,
+        params: ExecuteWithdrawalRequestProportionalParams,
+    ) -> Result<()> {
+        instructions::execute_withdrawal_request_proportional(ctx, &params)
+    }
+
+    pub fn cancel_withdrawal_request<'info>(
+        ctx: Context<'_, '_, '_, 'info, CancelWithdrawalRequest<'info>>,
+        params: CancelWithdrawalRequestParams,
+    ) -> Result<()> {
+        instructions::cancel_withdrawal_request(ctx, &params)
+    }
+
     pub fn open_position<'info>(
-        ctx: Context<'_, '_, '_, 'info, OpenPosition<'info>>,
+        ctx: Context<'_, '_, 'info, 'info, OpenPosition<'info>>,
         params: OpenPositionParams,
     ) -> Result<()> {
         instructions::open_position(ctx, &params)
     }
 
+    pub fn increase_position<'info>(
+        ctx: Context<'_, '_, '_, 'info, IncreasePosition<'info>>,
+        params: IncreasePositionParams,
+    ) -> Result<()> {
+        instructions::increase_position(ctx, &params)
+    }
+
     pub fn add_collateral<'info>(
         ctx: Context<'_, '_, '_, 'info, AddCollateral<'info>>,
         params: AddCollateralParams,
@@ -166,25 +252,172 @@ pub mod perpetuals {
     }
 
     pub fn close_position<'info>(
-        ctx: Context<'_, '_, '_, 'info, ClosePosition<'info>>,
+        ctx: Context<'_, '_, 'info, 'info, ClosePosition<'info>>,
         params: ClosePositionParams,
     ) -> Result<()> {
         instructions::close_position(ctx, &params)
     }
 
+    pub fn decrease_position<'info>(
+        ctx: Context<'_, '_, 'info, 'info, DecreasePosition<'info>>,
+        params: DecreasePositionParams,
+    ) -> Result<()> {
+        instructions::decrease_position(ctx, &params)
+    }
+
     pub fn liquidate<'info>(
-        ctx: Context<'_, '_, '_, 'info, Liquidate<'info>>,
+        ctx: Context<'_, '_, 'info, 'info, Liquidate<'info>>,
         params: LiquidateParams,
     ) -> Result<()> {
         instructions::liquidate(ctx, &params)
     }
 
+    pub fn place_trigger_order<'info>(
+        ctx: Context<'_, '_, '_, 'info, PlaceTriggerOrder<'info>>,
+        params: PlaceTriggerOrderParams,
+    ) -> Result<()> {
+        instructions::place_trigger_order(ctx, &params)
+    }
+
+    pub fn cancel_trigger_order<'info>(
+        ctx: Context<'_, '_, '_, 'info, CancelTriggerOrder<'info>>,
+        params: CancelTriggerOrderParams,
+    ) -> Result<()> {
+        instructions::cancel_trigger_order(ctx, &params)
+    }
+
+    pub fn execute_trigger_order<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ExecuteTriggerOrder<'info>>,
+        params: ExecuteTriggerOrderParams,
+    ) -> Result<()> {
+        instructions::execute_trigger_order(ctx, &params)
+    }
+
+    pub fn place_limit_order<'info>(
+        ctx: Context<'_, '_, '_, 'info, PlaceLimitOrder<'info>>,
+        params: PlaceLimitOrderParams,
+    ) -> Result<()> {
+        instructions::place_limit_order(ctx, &params)
+    }
+
+    pub fn cancel_limit_order<'info>(
+        ctx: Context<'_, '_, '_, 'info, CancelLimitOrder<'info>>,
+        params: CancelLimitOrderParams,
+    ) -> Result<()> {
+        instructions::cancel_limit_order(ctx, &params)
+    }
+
+    pub fn execute_limit_order<'info>(
+        ctx: Context<'_, '_, '_, 'info, ExecuteLimitOrder<'info>>,
+        params: ExecuteLimitOrderParams,
+    ) -> Result<()> {
+        instructions::execute_limit_order(ctx, &params)
+    }
+
+    pub fn auto_deleverage<'info>(
+        ctx: Context<'_, '_, 'info, 'info, AutoDeleverage<'info>>,
+        params: AutoDeleverageParams,
+    ) -> Result<()> {
+        instructions::auto_deleverage(ctx, &params)
+    }
+
+    pub fn request_open_position<'info>(
+        ctx: Context<'_, '_, '_, 'info, RequestOpenPosition<'info>>,
+        params: RequestOpenPositionParams,
+    ) -> Result<()> {
+        instructions::request_open_position(ctx, &params)
+    }
+
+    pub fn request_close_position<'info>(
+        ctx: Context<'_, '_, '_, 'info, RequestClosePosition<'info>>,
+        params: RequestClosePositionParams,
+    ) -> Result<()> {
+        instructions::request_close_position(ctx, &params)
+    }
+
+    pub fn execute_open_position_request<'info>(
+        ctx: Context<'_, '_, '_, 'info, ExecuteOpenPositionRequest<'info>>,
+        params: ExecuteOpenPositionRequestParams,
+    ) -> Result<()> {
+        instructions::execute_open_position_request(ctx, &params)
+    }
+
+    pub fn execute_close_position_request<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ExecuteClosePositionRequest<'info>>,
+        params: ExecuteClosePositionRequestParams,
+    ) -> Result<()> {
+        instructions::execute_close_position_request(ctx, &params)
+    }
+
+    pub fn cancel_position_request<'info>(
+        ctx: Context<'_, '_, '_, 'info, CancelPositionRequest<'info>>,
+        params: CancelPositionRequestParams,
+    ) -> Result<()> {
+        instructions::cancel_position_request(ctx, &params)
+    }
+
+    pub fn set_delegate_authority<'info>(
+        ctx: Context<'_, '_, '_, 'info, SetDelegateAuthority<'info>>,
+        params: SetDelegateAuthorityParams,
+    ) -> Result<()> {
+        instructions::set_delegate_authority(ctx, &params)
+    }
+
+    pub fn transfer_position<'info>(
+        ctx: Context<'_, '_, '_, 'info, TransferPosition<'info>>,
+        params: TransferPositionParams,
+    ) -> Result<()> {
+        instructions::transfer_position(ctx, &params)
+    }
+
+    pub fn tokenize_position<'info>(
+        ctx: Context<'_, '_, '_, 'info, TokenizePosition<'info>>,
+        params: TokenizePositionParams,
+    ) -> Result<()> {
+        instructions::tokenize_position(ctx, &params)
+    }
+
+    pub fn redeem_position<'info>(
+        ctx: Context<'_, '_, '_, 'info, RedeemPosition<'info>>,
+        params: RedeemPositionParams,
+    ) -> Result<()> {
+        instructions::redeem_position(ctx, &params)
+    }
+
+    pub fn deposit_margin_collateral<'info>(
+        ctx: Context<'_, '_, '_, 'info, DepositMarginCollateral<'info>>,
+        params: DepositMarginCollateralParams,
+    ) -> Result<()> {
+        instructions::deposit_margin_collateral(ctx, &params)
+    }
+
+    pub fn withdraw_margin_collateral<'info>(
+        ctx: Context<'_, '_, 'info, 'info, WithdrawMarginCollateral<'info>>,
+        params: WithdrawMarginCollateralParams,
+    ) -> Result<()> {
+        instructions::withdraw_margin_collateral(ctx, &params)
+    }
+
+    pub fn swap<'info>(
+        ctx: Context<'_, '_, 'info, 'info, Swap<'info>>,
+        params: SwapParams,
+    ) -> Result<()> {
+        instructions::swap(ctx, &params)
+    }
+
     pub fn update_pool_aum<'info>(
         ctx: Context<'_, '_, 'info, 'info, UpdatePoolAum<'info>>,
     ) -> Result<u128> {
         instructions::update_pool_aum(ctx)
     }
 
+    pub fn upgrade_position<'info>(
+        ctx: Context<'_, '_, '_, 'info, UpgradePosition<'info>>,
+        params: UpgradePositionParams,
+    ) -> Result<()> {
+        instructions::upgrade_position(ctx, &params)
+    }
+
     pub fn get_add_liquidity_amount_and_fee<'info>(
         ctx: Context<'_, '_, 'info, 'info, GetAddLiquidityAmountAndFee<'info>>,
         params: GetAddLiquidityAmountAndFeeParams,
@@ -199,6 +432,13 @@ pub mod perpetuals {
         instructions::get_remove_liquidity_amount_and_fee(ctx, &params)
     }
 
+    pub fn get_swap_amount_and_fee<'info>(
+        ctx: Context<'_, '_, 'info, 'info, GetSwapAmountAndFee<'info>>,
+        params: GetSwapAmountAndFeeParams,
+    ) -> Result<SwapAmountAndFees> {
+        instructions::get_swap_amount_and_fee(ctx, &params)
+    }
+
     pub fn get_entry_price_and_fee<'info>(
         ctx: Context<'_, '_, '_, 'info, GetEntryPriceAndFee<'info>>,
         params: GetEntryPriceAndFeeParams,
@@ -1,8 +1,16 @@
 // Program state handling.
 
 pub mod custody;
+pub mod delegate_authority;
+pub mod insurance_fund;
+pub mod limit_order;
+pub mod lp_account;
+pub mod margin_account;
 pub mod multisig;
 pub mod oracle;
 pub mod perpetuals;
 pub mod pool;
 pub mod position;
+pub mod position_request;
+pub mod trigger_order;
+pub mod withdrawal_request;
@@ -4,8 +4,8 @@ use {
         math,
         state::{
             oracle::{OracleParams, OraclePrice, OracleType},
-            perpetuals::{Permissions, Perpetuals},
-            position::Position,
+            perpetuals::{DeprecatedPermissions, Permissions, Perpetuals},
+            position::{Position, Side},
         },
     },
     anchor_lang::prelude::*,
@@ -18,9 +18,17 @@ pub struct Fees {
     pub add_liquidity: u64,
     pub remove_liquidity: u64,
     pub open_position: u64,
+    // exit fee, charged on the position size and on the position profit
     pub close_position: u64,
     pub liquidation: u64,
     pub protocol_share: u64,
+    pub swap_in: u64,
+    pub swap_out: u64,
+    pub close_position_profit_share: u64,
+    // keeper reward for executing trigger orders, charged on the closed size
+    pub trigger_order: u64,
+    // part of the collected fees that funds the insurance fund
+    pub insurance_share: u64,
 }
 
 #[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
@@ -30,6 +38,7 @@ pub struct FeesStats {
     pub open_position_usd: u64,
     pub close_position_usd: u64,
     pub liquidation_usd: u64,
+    pub swap_usd: u64,
 }
 
 #[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
@@ -39,6 +48,7 @@ pub struct VolumeStats {
     pub open_position_usd: u64,
     pub close_position_usd: u64,
     pub liquidation_usd: u64,
+    pub swap_usd: u64,
 }
 
 #[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
@@ -47,6 +57,11 @@ pub struct TradeStats {
     pub loss_usd: u64,
     // open interest
     pub oi_long_usd: u64,
+    pub oi_short_usd: u64,
+    // losses exceeding the collateral of closed positions, and the part of
+    // them covered by the insurance fund
+    pub bad_debt_usd: u64,
+    pub bad_debt_covered_usd: u64,
 }
 
 #[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
@@ -55,12 +70,18 @@ pub struct Assets {
     pub collateral: u64,
     // protocol_fees are part of the collected fees that is reserved for the protocol
     pub protocol_fees: u64,
-    // owned = total_assets - collateral + collected_fees - protocol_fees
+    // owned = total_assets - collateral + collected_fees - protocol_fees - insurance fund balance
     pub owned: u64,
     // locked funds for pnl payoff
     pub locked: u64,
 }
 
+#[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
+pub struct LeverageTier {
+    pub max_size_usd: u64,
+    pub max_leverage: u64,
+}
+
 #[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
 pub struct PricingParams {
     // whether to account for unrealized pnl in assets under management calculations
@@ -77,6 +98,24 @@ pub struct PricingParams {
     // USD denominated values always have implied USD_DECIMALS decimals
     pub max_position_locked_usd: u64,
     pub max_total_locked_usd: u64,
+    pub swap_spread: u64,
+    // upper bound of the size dependent spread added on top of the trade spread
+    pub max_price_impact: u64,
+    // max leverage by position size, sorted by increasing max_size_usd, unused tiers are zeroed.
+    // positions above the last tier use its leverage, no tiers means max_leverage for all sizes
+    pub leverage_tiers: [LeverageTier; 4], // PricingParams::MAX_LEVERAGE_TIERS
+    // leverage restored by partial liquidations, zero liquidates whole positions
+    pub liquidation_target_leverage: u64,
+    // trades of this size move the price by 100%, zero disables price impact
+    pub virtual_depth_usd: u64,
+    // custody solvency below which profitable positions can be auto-deleveraged, zero disables it
+    pub auto_deleverage_threshold: u64,
+    // profit vests linearly during this time after a position is opened or increased,
+    // which neutralizes latency arbitrage against slow oracle updates
+    pub min_hold_time_sec: u64,
+    // longest lifetime of position requests, bounds the time the requester can
+    // choose to execute them at
+    pub max_request_age_sec: u64,
 }
 
 #[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
@@ -86,6 +125,8 @@ pub struct BorrowRateParams {
     pub slope1: u64,
     pub slope2: u64,
     pub optimal_utilization: u64,
+    // hourly funding rate paid by the heavier side of open interest when fully skewed
+    pub max_funding_rate: u64,
 }
 
 #[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
@@ -96,6 +137,17 @@ pub struct BorrowRateState {
     pub last_update: i64,
 }
 
+#[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
+pub struct FundingRateState {
+    // funding rates have implied RATE_DECIMALS decimals,
+    // positive rates are paid by the side and negative rates are received
+    pub long_rate: i64,
+    pub short_rate: i64,
+    pub cumulative_funding_long: i128,
+    pub cumulative_funding_short: i128,
+    pub last_update: i64,
+}
+
 #[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
 pub struct PositionStats {
     pub open_positions: u64,
@@ -105,6 +157,23 @@ pub struct PositionStats {
     pub locked_amount: u64,
     pub weighted_price: u128,
     pub total_quantity: u128,
+    pub cumulative_funding_received_usd: u64,
+    pub cumulative_funding_paid_usd: u64,
+    pub cumulative_funding_snapshot: i128,
+    // interest and funding settled into the open positions
+    pub unrealized_profit_usd: u64,
+    pub unrealized_loss_usd: u64,
+    // value of the stablecoins locked in collateral custodies, their amounts are
+    // tracked in collateral custody tokens by the borrow stats of those custodies
+    pub stable_locked_usd: u64,
+}
+
+#[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
+pub struct BorrowStats {
+    pub open_positions: u64,
+    pub borrow_size_usd: u64,
+    // locked amounts are in tokens of the lending custody
+    pub locked_amount: u64,
     pub cumulative_interest_usd: u64,
     pub cumulative_interest_snapshot: u128,
 }
@@ -134,16 +203,115 @@ pub struct Custody {
     // bumps for address validation
     pub bump: u8,
     pub token_account_bump: u8,
+
+    // fields added after the initial release, appended to keep the layout of
+    // upgraded accounts (see upgrade_custody)
+    pub is_stable: bool,
+    pub short_positions: PositionStats,
+    pub funding_rate_state: FundingRateState,
+    // positions that lock funds in and pay interest to this custody,
+    // i.e. the positions that use it as their collateral custody
+    pub borrow_stats: BorrowStats,
+}
+
+// layouts of the initial release, used to upgrade deployed custody accounts
+
+#[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
+pub struct DeprecatedFees {
+    pub utilization_mult: u64,
+    pub add_liquidity: u64,
+    pub remove_liquidity: u64,
+    pub open_position: u64,
+    pub close_position: u64,
+    pub liquidation: u64,
+    pub protocol_share: u64,
+}
+
+#[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
+pub struct DeprecatedFeesStats {
+    pub add_liquidity_usd: u64,
+    pub remove_liquidity_usd: u64,
+    pub open_position_usd: u64,
+    pub close_position_usd: u64,
+    pub liquidation_usd: u64,
+}
+
+#[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
+pub struct DeprecatedTradeStats {
+    pub profit_usd: u64,
+    pub loss_usd: u64,
+    pub oi_long_usd: u64,
+}
+
+#[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
+pub struct DeprecatedPricingParams {
+    pub use_unrealized_pnl_in_aum: bool,
+    pub trade_spread_long: u64,
+    pub trade_spread_short: u64,
+    pub min_initial_leverage: u64,
+    pub max_initial_leverage: u64,
+    pub max_leverage: u64,
+    pub max_payoff_mult: u64,
+    pub max_utilization: u64,
+    pub max_position_locked_usd: u64,
+    pub max_total_locked_usd: u64,
+}
+
+#[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
+pub struct DeprecatedBorrowRateParams {
+    pub base_rate: u64,
+    pub slope1: u64,
+    pub slope2: u64,
+    pub optimal_utilization: u64,
+}
+
+#[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
+pub struct DeprecatedPositionStats {
+    pub open_positions: u64,
+    pub collateral_usd: u64,
+    pub size_usd: u64,
+    pub borrow_size_usd: u64,
+    pub locked_amount: u64,
+    pub weighted_price: u128,
+    pub total_quantity: u128,
+    pub cumulative_interest_usd: u64,
+    pub cumulative_interest_snapshot: u128,
+}
+
+#[account]
+#[derive(Default, Debug)]
+pub struct DeprecatedCustody {
+    pub pool: Pubkey,
+    pub mint: Pubkey,
+    pub token_account: Pubkey,
+    pub decimals: u8,
+    pub oracle: OracleParams,
+    pub pricing: DeprecatedPricingParams,
+    pub permissions: DeprecatedPermissions,
+    pub fees: DeprecatedFees,
+    pub borrow_rate: DeprecatedBorrowRateParams,
+    pub assets: Assets,
+    pub collected_fees: DeprecatedFeesStats,
+    pub volume_stats: DeprecatedFeesStats,
+    pub trade_stats: DeprecatedTradeStats,
+    pub long_positions: DeprecatedPositionStats,
+    pub borrow_rate_state: BorrowRateState,
+    pub bump: u8,
+    pub token_account_bump: u8,
 }
 
 impl Fees {
     pub fn validate(&self) -> bool {
-        self.add_liquidity as u128 <= Perpetuals::BPS_POWER
+        self.swap_in as u128 <= Perpetuals::BPS_POWER
+            && self.swap_out as u128 <= Perpetuals::BPS_POWER
+            && self.add_liquidity as u128 <= Perpetuals::BPS_POWER
             && self.remove_liquidity as u128 <= Perpetuals::BPS_POWER
             && self.open_position as u128 <= Perpetuals::BPS_POWER
             && self.close_position as u128 <= Perpetuals::BPS_POWER
+            && self.close_position_profit_share as u128 <= Perpetuals::BPS_POWER
             && self.liquidation as u128 <= Perpetuals::BPS_POWER
-            && self.protocol_share as u128 <= Perpetuals::BPS_POWER
+            && self.trigger_order as u128 <= Perpetuals::BPS_POWER
+            && self.protocol_share as u128 + self.insurance_share as u128 <= Perpetuals::BPS_POWER
     }
 }
 
@@ -154,20 +322,72 @@ impl OracleParams {
 }
 
 impl PricingParams {
+    pub const MAX_LEVERAGE_TIERS: usize = 4;
+
     pub fn validate(&self) -> bool {
-        (self.min_initial_leverage as u128) >= Perpetuals::BPS_POWER
+        self.validate_leverage_tiers()
+            && (self.min_initial_leverage as u128) >= Perpetuals::BPS_POWER
             && self.min_initial_leverage <= self.max_initial_leverage
             && self.max_initial_leverage <= self.max_leverage
-            && (self.trade_spread_long as u128) < Perpetuals::BPS_POWER
-            && (self.trade_spread_short as u128) < Perpetuals::BPS_POWER
+            && (self.liquidation_target_leverage == 0
+                || ((self.liquidation_target_leverage as u128) >= Perpetuals::BPS_POWER
+                    && self.liquidation_target_leverage < self.max_leverage))
+            && (self.virtual_depth_usd == 0 || self.max_price_impact > 0)
+            && (self.trade_spread_long as u128 + self.max_price_impact as u128)
+                < Perpetuals::BPS_POWER
+            && (self.trade_spread_short as u128 + self.max_price_impact as u128)
+                < Perpetuals::BPS_POWER
+            && (self.swap_spread as u128) < Perpetuals::BPS_POWER
             && (self.max_utilization as u128) <= Perpetuals::BPS_POWER
             && self.max_position_locked_usd <= self.max_total_locked_usd
+            && self.max_request_age_sec > 0
+    }
+
+    // tiers must lower the max leverage as the position size grows and stay above the
+    // liquidation target, so that partially liquidated positions are healthy in any tier
+    fn validate_leverage_tiers(&self) -> bool {
+        let tiers_count = self
+            .leverage_tiers
+            .iter()
+            .take_while(|tier| tier.max_size_usd > 0)
+            .count();
+        let (tiers, unused_tiers) = self.leverage_tiers.split_at(tiers_count);
+
+        unused_tiers
+            .iter()
+            .all(|tier| *tier == LeverageTier::default())
+            && tiers.iter().all(|tier| {
+                (tier.max_leverage as u128) >= Perpetuals::BPS_POWER
+                    && tier.max_leverage <= self.max_leverage
+                    && tier.max_leverage > self.liquidation_target_leverage
+            })
+            && tiers.windows(2).all(|pair| {
+                pair[0].max_size_usd < pair[1].max_size_usd
+                    && pair[0].max_leverage >= pair[1].max_leverage
+            })
+    }
+
+    pub fn get_max_leverage(&self, size_usd: u64) -> u64 {
+        let mut max_leverage = self.max_leverage;
+        for tier in self
+            .leverage_tiers
+            .iter()
+            .take_while(|tier| tier.max_size_usd > 0)
+        {
+            max_leverage = tier.max_leverage;
+            if size_usd <= tier.max_size_usd {
+                break;
+            }
+        }
+        max_leverage
     }
 }
 
 impl BorrowRateParams {
     pub fn validate(&self) -> bool {
-        self.optimal_utilization > 0 && (self.optimal_utilization as u128) <= Perpetuals::RATE_POWER
+        self.optimal_utilization > 0
+            && (self.optimal_utilization as u128) <= Perpetuals::RATE_POWER
+            && (self.max_funding_rate as u128) <= Perpetuals::RATE_POWER
     }
 }
 
@@ -218,14 +438,52 @@ impl Custody {
         Ok(())
     }
 
-    pub fn get_locked_amount(&self, size: u64) -> Result<u64> {
-        let max_payoff_mult = self.pricing.max_payoff_mult as u128;
+    // ratio of owned funds to funds locked for trader profits, with implied BPS_DECIMALS decimals
+    pub fn get_solvency(&self) -> Result<u64> {
+        if self.assets.locked == 0 {
+            return Ok(u64::MAX);
+        }
+        math::checked_as_u64(math::checked_div(
+            math::checked_mul(self.assets.owned as u128, Perpetuals::BPS_POWER)?,
+            self.assets.locked as u128,
+        )?)
+    }
+
+    // For shorts the payoff can't exceed the position size, so the multiplier is capped at 1x
+    pub fn get_locked_amount(&self, size: u64, side: Side) -> Result<u64> {
+        let max_payoff_mult = if side == Side::Short {
+            std::cmp::min(self.pricing.max_payoff_mult as u128, Perpetuals::BPS_POWER)
+        } else {
+            self.pricing.max_payoff_mult as u128
+        };
         math::checked_as_u64(math::checked_div(
             math::checked_mul(size as u128, max_payoff_mult)?,
             Perpetuals::BPS_POWER,
         )?)
     }
 
+    // returns the part of the position profit vested since the position was opened or increased
+    pub fn get_vested_profit_usd(
+        &self,
+        position: &Position,
+        profit_usd: u64,
+        curtime: i64,
+    ) -> Result<u64> {
+        if curtime <= position.vesting_start_time {
+            return Ok(0);
+        }
+
+        let hold_time = math::checked_sub(curtime, position.vesting_start_time)? as u64;
+        if hold_time >= self.pricing.min_hold_time_sec {
+            return Ok(profit_usd);
+        }
+
+        math::checked_as_u64(math::checked_div(
+            math::checked_mul(profit_usd as u128, hold_time as u128)?,
+            self.pricing.min_hold_time_sec as u128,
+        )?)
+    }
+
     pub fn get_interest_amount_usd(&self, position: &Position, curtime: i64) -> Result<u64> {
         if position.borrow_size_usd == 0 {
             return Ok(0);
@@ -321,10 +579,120 @@ impl Custody {
         Ok(())
     }
 
-    pub fn get_collective_position(&self) -> Result<Position> {
-        let stats = &self.long_positions;
+    pub fn get_cumulative_funding(&self, side: Side, curtime: i64) -> Result<i128> {
+        let state = &self.funding_rate_state;
+        let (rate, cumulative_funding) = if side == Side::Short {
+            (state.short_rate, state.cumulative_funding_short)
+        } else {
+            (state.long_rate, state.cumulative_funding_long)
+        };
+
+        if curtime > state.last_update {
+            let funding = math::checked_div(
+                math::checked_mul(
+                    math::checked_sub(curtime, state.last_update)? as i128,
+                    rate as i128,
+                )?,
+                3600,
+            )?;
+            math::checked_add(cumulative_funding, funding)
+        } else {
+            Ok(cumulative_funding)
+        }
+    }
+
+    // returns the funding received and paid by the position since its snapshot
+    pub fn get_funding_amount_usd(&self, position: &Position, curtime: i64) -> Result<(u64, u64)> {
+        if position.size_usd == 0 {
+            return Ok((0, 0));
+        }
+
+        let position_funding = math::checked_sub(
+            self.get_cumulative_funding(position.side, curtime)?,
+            position.cumulative_funding_snapshot,
+        )?;
+
+        let funding_usd = math::checked_as_u64(math::checked_div(
+            math::checked_mul(position_funding.unsigned_abs(), position.size_usd as u128)?,
+            Perpetuals::RATE_POWER,
+        )?)?;
+
+        if position_funding < 0 {
+            Ok((funding_usd, 0))
+        } else {
+            Ok((0, funding_usd))
+        }
+    }
+
+    pub fn update_funding_rate(&mut self, curtime: i64) -> Result<()> {
+        // heavy_rate = max_funding_rate * (heavy_oi - light_oi) / (heavy_oi + light_oi)
+        // light_rate = -min(heavy_rate * heavy_oi / light_oi, max_funding_rate)
+        // so that the funding paid by the heavier side is received by the lighter side,
+        // the cap keeps dust light side rates bounded and the excess stays with the pool
+
+        if curtime > self.funding_rate_state.last_update {
+            // compute funding accumulated since previous update
+            self.funding_rate_state.cumulative_funding_long =
+                self.get_cumulative_funding(Side::Long, curtime)?;
+            self.funding_rate_state.cumulative_funding_short =
+                self.get_cumulative_funding(Side::Short, curtime)?;
+            self.funding_rate_state.last_update = curtime;
+        }
+
+        let oi_long = self.trade_stats.oi_long_usd as u128;
+        let oi_short = self.trade_stats.oi_short_usd as u128;
+
+        if self.borrow_rate.max_funding_rate == 0 || oi_long == 0 || oi_short == 0 {
+            self.funding_rate_state.long_rate = 0;
+            self.funding_rate_state.short_rate = 0;
+            return Ok(());
+        }
+
+        let (heavy_oi, light_oi) = if oi_long >= oi_short {
+            (oi_long, oi_short)
+        } else {
+            (oi_short, oi_long)
+        };
+
+        let heavy_rate = math::checked_div(
+            math::checked_mul(
+                self.borrow_rate.max_funding_rate as u128,
+                math::checked_sub(heavy_oi, light_oi)?,
+            )?,
+            math::checked_add(heavy_oi, light_oi)?,
+        )?;
+        let light_rate = std::cmp::min(
+            math::checked_div(math::checked_mul(heavy_rate, heavy_oi)?, light_oi)?,
+            self.borrow_rate.max_funding_rate as u128,
+        );
+
+        let heavy_rate = i64::try_from(heavy_rate).map_err(|_| PerpetualsError::MathOverflow)?;
+        let light_rate = -i64::try_from(light_rate).map_err(|_| PerpetualsError::MathOverflow)?;
+
+        if oi_long >= oi_short {
+            self.funding_rate_state.long_rate = heavy_rate;
+            self.funding_rate_state.short_rate = light_rate;
+        } else {
+            self.funding_rate_state.long_rate = light_rate;
+            self.funding_rate_state.short_rate = heavy_rate;
+        }
+
+        Ok(())
+    }
+
+    pub fn get_position_stats(&self, side: Side) -> &PositionStats {
+        if side == Side::Short {
+            &self.short_positions
+        } else {
+            &self.long_positions
+        }
+    }
+
+    pub fn get_collective_position(&self, side: Side) -> Result<Position> {
+        let stats = self.get_position_stats(side);
         if stats.open_positions > 0 {
             Ok(Position {
+                side,
                 price: if stats.total_quantity > 0 {
                     math::checked_as_u64(math::checked_div(
                         stats.weighted_price,
@@ -335,8 +703,15 @@ impl Custody {
                 },
                 size_usd: stats.size_usd,
                 borrow_size_usd: stats.borrow_size_usd,
-                unrealized_loss_usd: stats.cumulative_interest_usd,
-                cumulative_interest_snapshot: stats.cumulative_interest_snapshot,
+                unrealized_profit_usd: math::checked_add(
+                    stats.cumulative_funding_received_usd,
+                    stats.unrealized_profit_usd,
+                )?,
+                unrealized_loss_usd: math::checked_add(
+                    stats.cumulative_funding_paid_usd,
+                    stats.unrealized_loss_usd,
+                )?,
+                cumulative_funding_snapshot: stats.cumulative_funding_snapshot,
                 locked_amount: stats.locked_amount,
                 ..Position::default()
             })
@@ -345,29 +720,74 @@ impl Custody {
         }
     }
 
+    // returns the value of the funds locked for the positions on the given side
+    pub fn get_position_stats_locked_usd(
+        &self,
+        side: Side,
+        token_price: &OraclePrice,
+    ) -> Result<u64> {
+        let stats = self.get_position_stats(side);
+        math::checked_add(
+            token_price.get_asset_amount_usd(stats.locked_amount, self.decimals)?,
+            stats.stable_locked_usd,
+        )
+    }
+
+    // position stats track locked amounts in custody tokens, so positions that lock
+    // stablecoins in another custody only contribute their borrowed size, their
+    // locked amounts are tracked by the borrow stats of the collateral custody
+    fn get_position_stats_locked_amount(position: &Position) -> u64 {
+        if position.has_stable_collateral() {
+            0
+        } else {
+            position.locked_amount
+        }
+    }
+
     pub fn add_position(
         &mut self,
         position: &Position,
         token_price: &OraclePrice,
         curtime: i64,
     ) -> Result<()> {
-        // compute accumulated interest
-        let collective_position = self.get_collective_position()?;
-        let interest_usd = self.get_interest_amount_usd(&collective_position, curtime)?;
+        // compute accumulated funding
+        let collective_position = self.get_collective_position(position.side)?;
+        let (funding_received_usd, funding_paid_usd) =
+            self.get_funding_amount_usd(&collective_position, curtime)?;
 
         // update positions
-        let stats = &mut self.long_positions;
+        let stats = if position.side == Side::Short {
+            &mut self.short_positions
+        } else {
+            &mut self.long_positions
+        };
 
         stats.open_positions = math::checked_add(stats.open_positions, 1)?;
         stats.size_usd = math::checked_add(stats.size_usd, position.size_usd)?;
-        stats.locked_amount = math::checked_add(stats.locked_amount, position.locked_amount)?;
+        stats.locked_amount = math::checked_add(
+            stats.locked_amount,
+            Self::get_position_stats_locked_amount(position),
+        )?;
+        if position.has_stable_collateral() {
+            stats.stable_locked_usd =
+                math::checked_add(stats.stable_locked_usd, position.borrow_size_usd)?;
+        }
 
-        // update borrowed size and cumulative interest
-        stats.cumulative_interest_usd =
-            math::checked_add(stats.cumulative_interest_usd, interest_usd)?;
-        stats.cumulative_interest_snapshot = position.cumulative_interest_snapshot;
         stats.borrow_size_usd = math::checked_add(stats.borrow_size_usd, position.borrow_size_usd)?;
 
+        // update cumulative funding
+        stats.cumulative_funding_received_usd =
+            math::checked_add(stats.cumulative_funding_received_usd, funding_received_usd)?;
+        stats.cumulative_funding_paid_usd =
+            math::checked_add(stats.cumulative_funding_paid_usd, funding_paid_usd)?;
+        stats.cumulative_funding_snapshot = position.cumulative_funding_snapshot;
+
+        // settled interest and funding stay in the collective pnl until the position is closed
+        stats.unrealized_profit_usd =
+            math::checked_add(stats.unrealized_profit_usd, position.unrealized_profit_usd)?;
+        stats.unrealized_loss_usd =
+            math::checked_add(stats.unrealized_loss_usd, position.unrealized_loss_usd)?;
+
         let position_price = math::scale_to_exponent(
             position.price,
             -(Perpetuals::PRICE_DECIMALS as i32),
@@ -384,9 +804,14 @@ impl Custody {
         stats.total_quantity = math::checked_add(stats.total_quantity, quantity)?;
 
         // check limits
+        // positions with stablecoin collateral lock funds in the collateral custody, so their
+        // locked value is the borrowed size rather than the custody token amount
         if self.pricing.max_position_locked_usd > 0 {
-            let locked_amount_usd =
-                token_price.get_asset_amount_usd(position.locked_amount, self.decimals)?;
+            let locked_amount_usd = if position.has_stable_collateral() {
+                position.borrow_size_usd
+            } else {
+                token_price.get_asset_amount_usd(position.locked_amount, self.decimals)?
+            };
             require!(
                 locked_amount_usd <= self.pricing.max_position_locked_usd,
                 PerpetualsError::PositionAmountLimit
@@ -394,7 +819,7 @@ impl Custody {
         }
         if self.pricing.max_total_locked_usd > 0 {
             let locked_amount_usd =
-                token_price.get_asset_amount_usd(stats.locked_amount, self.decimals)?;
+                self.get_position_stats_locked_usd(position.side, token_price)?;
             require!(
                 locked_amount_usd <= self.pricing.max_total_locked_usd,
                 PerpetualsError::CustodyAmountLimit
@@ -405,32 +830,54 @@ impl Custody {
     }
 
     pub fn remove_position(&mut self, position: &Position, curtime: i64) -> Result<()> {
-        // compute accumulated interest
-        let collective_position = self.get_collective_position()?;
-        let interest_usd = self.get_interest_amount_usd(&collective_position, curtime)?;
-        let cumulative_interest_snapshot = self.get_cumulative_interest(curtime)?;
-        let position_interest_usd = self.get_interest_amount_usd(position, curtime)?;
+        // compute accumulated funding
+        let collective_position = self.get_collective_position(position.side)?;
+        let (funding_received_usd, funding_paid_usd) =
+            self.get_funding_amount_usd(&collective_position, curtime)?;
+        let cumulative_funding_snapshot = self.get_cumulative_funding(position.side, curtime)?;
+        let (position_funding_received_usd, position_funding_paid_usd) =
+            self.get_funding_amount_usd(position, curtime)?;
 
         // update stats
-        let stats = &mut self.long_positions;
+        let stats = if position.side == Side::Short {
+            &mut self.short_positions
+        } else {
+            &mut self.long_positions
+        };
 
         if stats.open_positions == 1 {
             *stats = PositionStats::default();
             return Ok(());
         }
 
-        // update borrowed size and cumulative interest only if trading token custody is the collateral custody
-        stats.cumulative_interest_usd =
-            math::checked_add(stats.cumulative_interest_usd, interest_usd)?;
-        stats.cumulative_interest_usd = stats
-            .cumulative_interest_usd
-            .saturating_sub(position_interest_usd);
-        stats.cumulative_interest_snapshot = cumulative_interest_snapshot;
         stats.borrow_size_usd = math::checked_sub(stats.borrow_size_usd, position.borrow_size_usd)?;
 
+        // update cumulative funding
+        stats.cumulative_funding_received_usd =
+            math::checked_add(stats.cumulative_funding_received_usd, funding_received_usd)?
+                .saturating_sub(position_funding_received_usd);
+        stats.cumulative_funding_paid_usd =
+            math::checked_add(stats.cumulative_funding_paid_usd, funding_paid_usd)?
+                .saturating_sub(position_funding_paid_usd);
+        stats.cumulative_funding_snapshot = cumulative_funding_snapshot;
+
+        stats.unrealized_profit_usd = stats
+            .unrealized_profit_usd
+            .saturating_sub(position.unrealized_profit_usd);
+        stats.unrealized_loss_usd = stats
+            .unrealized_loss_usd
+            .saturating_sub(position.unrealized_loss_usd);
+
         stats.open_positions = math::checked_sub(stats.open_positions, 1)?;
         stats.size_usd = math::checked_sub(stats.size_usd, position.size_usd)?;
-        stats.locked_amount = math::checked_sub(stats.locked_amount, position.locked_amount)?;
+        stats.locked_amount = math::checked_sub(
+            stats.locked_amount,
+            Self::get_position_stats_locked_amount(position),
+        )?;
+        if position.has_stable_collateral() {
+            stats.stable_locked_usd =
+                math::checked_sub(stats.stable_locked_usd, position.borrow_size_usd)?;
+        }
 
         let position_price = math::scale_to_exponent(
             position.price,
@@ -449,6 +896,167 @@ impl Custody {
 
         Ok(())
     }
+
+    // returns the interest accrued by the positions borrowing from the custody
+    pub fn get_borrow_interest_usd(&self, curtime: i64) -> Result<u64> {
+        let stats = &self.borrow_stats;
+        let collective_position = Position {
+            borrow_size_usd: stats.borrow_size_usd,
+            cumulative_interest_snapshot: stats.cumulative_interest_snapshot,
+            ..Position::default()
+        };
+        math::checked_add(
+            stats.cumulative_interest_usd,
+            self.get_interest_amount_usd(&collective_position, curtime)?,
+        )
+    }
+
+    // called on the collateral custody, which lends the locked funds to the position
+    pub fn add_borrow(&mut self, position: &Position, curtime: i64) -> Result<()> {
+        // compute accumulated interest, positions that are re-added after an update
+        // bring the interest accrued since their snapshot
+        let interest_usd = self.get_borrow_interest_usd(curtime)?;
+        let position_interest_usd = self.get_interest_amount_usd(position, curtime)?;
+        let cumulative_interest_snapshot = self.get_cumulative_interest(curtime)?;
+
+        let stats = &mut self.borrow_stats;
+        stats.open_positions = math::checked_add(stats.open_positions, 1)?;
+        stats.borrow_size_usd = math::checked_add(stats.borrow_size_usd, position.borrow_size_usd)?;
+        stats.locked_amount = math::checked_add(stats.locked_amount, position.locked_amount)?;
+        stats.cumulative_interest_usd = math::checked_add(interest_usd, position_interest_usd)?;
+        stats.cumulative_interest_snapshot = cumulative_interest_snapshot;
+
+        Ok(())
+    }
+
+    pub fn remove_borrow(&mut self, position: &Position, curtime: i64) -> Result<()> {
+        // compute accumulated interest
+        let interest_usd = self.get_borrow_interest_usd(curtime)?;
+        let position_interest_usd = self.get_interest_amount_usd(position, curtime)?;
+        let cumulative_interest_snapshot = self.get_cumulative_interest(curtime)?;
+
+        let stats = &mut self.borrow_stats;
+        if stats.open_positions == 1 {
+            *stats = BorrowStats::default();
+            return Ok(());
+        }
+
+        stats.open_positions = math::checked_sub(stats.open_positions, 1)?;
+        stats.borrow_size_usd = math::checked_sub(stats.borrow_size_usd, position.borrow_size_usd)?;
+        stats.locked_amount = math::checked_sub(stats.locked_amount, position.locked_amount)?;
+        stats.cumulative_interest_usd = interest_usd.saturating_sub(position_interest_usd);
+        stats.cumulative_interest_snapshot = cumulative_interest_snapshot;
+
+        Ok(())
+    }
+}
+
+impl DeprecatedCustody {
+    pub const LEN: usize = 8 + std::mem::size_of::<DeprecatedCustody>();
+}
+
+impl From<DeprecatedCustody> for Custody {
+    // new parameters start disabled, so the upgraded custody trades as before
+    // until they are set with set_custody_config
+    fn from(custody: DeprecatedCustody) -> Self {
+        let pricing = custody.pricing;
+        let fees = custody.fees;
+        let borrow_rate = custody.borrow_rate;
+        let long_positions = custody.long_positions;
+
+        Self {
+            pool: custody.pool,
+            mint: custody.mint,
+            token_account: custody.token_account,
+            decimals: custody.decimals,
+            oracle: custody.oracle,
+            pricing: PricingParams {
+                use_unrealized_pnl_in_aum: pricing.use_unrealized_pnl_in_aum,
+                trade_spread_long: pricing.trade_spread_long,
+                trade_spread_short: pricing.trade_spread_short,
+                min_initial_leverage: pricing.min_initial_leverage,
+                max_initial_leverage: pricing.max_initial_leverage,
+                max_leverage: pricing.max_leverage,
+                max_payoff_mult: pricing.max_payoff_mult,
+                max_utilization: pricing.max_utilization,
+                max_position_locked_usd: pricing.max_position_locked_usd,
+                max_total_locked_usd: pricing.max_total_locked_usd,
+                max_request_age_sec: custody.oracle.max_price_age_sec as u64,
+                ..PricingParams::default()
+            },
+            permissions: custody.permissions.into(),
+            fees: Fees {
+                utilization_mult: fees.utilization_mult,
+                add_liquidity: fees.add_liquidity,
+                remove_liquidity: fees.remove_liquidity,
+                open_position: fees.open_position,
+                // the initial release ignored close_position and took 1% of the profit
+                close_position: 0,
+                liquidation: fees.liquidation,
+                protocol_share: fees.protocol_share,
+                close_position_profit_share: 100,
+                ..Fees::default()
+            },
+            borrow_rate: BorrowRateParams {
+                base_rate: borrow_rate.base_rate,
+                slope1: borrow_rate.slope1,
+                slope2: borrow_rate.slope2,
+                optimal_utilization: borrow_rate.optimal_utilization,
+                ..BorrowRateParams::default()
+            },
+            assets: custody.assets,
+            collected_fees: FeesStats {
+                add_liquidity_usd: custody.collected_fees.add_liquidity_usd,
+                remove_liquidity_usd: custody.collected_fees.remove_liquidity_usd,
+                open_position_usd: custody.collected_fees.open_position_usd,
+                close_position_usd: custody.collected_fees.close_position_usd,
+                liquidation_usd: custody.collected_fees.liquidation_usd,
+                ..FeesStats::default()
+            },
+            volume_stats: VolumeStats {
+                add_liquidity_usd: custody.volume_stats.add_liquidity_usd,
+                remove_liquidity_usd: custody.volume_stats.remove_liquidity_usd,
+                open_position_usd: custody.volume_stats.open_position_usd,
+                close_position_usd: custody.volume_stats.close_position_usd,
+                liquidation_usd: custody.volume_stats.liquidation_usd,
+                ..VolumeStats::default()
+            },
+            trade_stats: TradeStats {
+                profit_usd: custody.trade_stats.profit_usd,
+                loss_usd: custody.trade_stats.loss_usd,
+                oi_long_usd: custody.trade_stats.oi_long_usd,
+                ..TradeStats::default()
+            },
+            long_positions: PositionStats {
+                open_positions: long_positions.open_positions,
+                collateral_usd: long_positions.collateral_usd,
+                size_usd: long_positions.size_usd,
+                borrow_size_usd: long_positions.borrow_size_usd,
+                locked_amount: long_positions.locked_amount,
+                weighted_price: long_positions.weighted_price,
+                total_quantity: long_positions.total_quantity,
+                ..PositionStats::default()
+            },
+            borrow_rate_state: custody.borrow_rate_state,
+            bump: custody.bump,
+            token_account_bump: custody.token_account_bump,
+            is_stable: false,
+            short_positions: PositionStats::default(),
+            funding_rate_state: FundingRateState {
+                last_update: custody.borrow_rate_state.last_update,
+                ..FundingRateState::default()
+            },
+            // initial release positions were long and collateralized by the traded custody,
+            // so its interest stats move to the borrow stats unchanged
+            borrow_stats: BorrowStats {
+                open_positions: long_positions.open_positions,
+                borrow_size_usd: long_positions.borrow_size_usd,
+                locked_amount: long_positions.locked_amount,
+                cumulative_interest_usd: long_positions.cumulative_interest_usd,
+                cumulative_interest_snapshot: long_positions.cumulative_interest_snapshot,
+            },
+        }
+    }
 }
 
 #[cfg(test)]
@@ -467,6 +1075,7 @@ mod test {
             slope1: 80000,
             slope2: 120000,
             optimal_utilization: 800000000,
+            max_funding_rate: 0,
         };
 
         Custody {
@@ -571,4 +1180,376 @@ mod test {
         custody.update_borrow_rate(3600).unwrap();
         assert_eq!(custody.borrow_rate_state.current_rate, 199400);
     }
+
+    #[test]
+    fn test_update_funding_rate() {
+        let mut custody = get_fixture();
+        custody.borrow_rate.max_funding_rate = 100_000;
+        custody.update_funding_rate(3600).unwrap();
+        assert_eq!(custody.funding_rate_state.long_rate, 0);
+        assert_eq!(custody.funding_rate_state.short_rate, 0);
+
+        // longs are heavier and pay the shorts
+        custody.trade_stats.oi_long_usd = 3000;
+        custody.trade_stats.oi_short_usd = 1000;
+        custody.update_funding_rate(3600).unwrap();
+        assert_eq!(
+            custody.funding_rate_state,
+            FundingRateState {
+                long_rate: 50_000,
+                short_rate: -100_000,
+                cumulative_funding_long: 0,
+                cumulative_funding_short: 0,
+                last_update: 3600
+            }
+        );
+
+        custody.update_funding_rate(7200).unwrap();
+        assert_eq!(custody.funding_rate_state.cumulative_funding_long, 50_000);
+        assert_eq!(
+            custody.funding_rate_state.cumulative_funding_short,
+            -100_000
+        );
+
+        let mut position = Position {
+            side: Side::Long,
+            size_usd: 1_000_000_000,
+            ..Position::default()
+        };
+        assert_eq!(
+            custody.get_funding_amount_usd(&position, 7200).unwrap(),
+            (0, 50_000)
+        );
+        position.side = Side::Short;
+        assert_eq!(
+            custody.get_funding_amount_usd(&position, 7200).unwrap(),
+            (100_000, 0)
+        );
+
+        // shorts are heavier and pay the longs
+        custody.trade_stats.oi_long_usd = 1000;
+        custody.trade_stats.oi_short_usd = 4000;
+        custody.update_funding_rate(7200).unwrap();
+        assert_eq!(custody.funding_rate_state.long_rate, -100_000);
+        assert_eq!(custody.funding_rate_state.short_rate, 60_000);
+
+        // a dust light side receives at most the max funding rate
+        custody.trade_stats.oi_long_usd = u64::MAX;
+        custody.trade_stats.oi_short_usd = 1;
+        custody.update_funding_rate(7200).unwrap();
+        assert_eq!(custody.funding_rate_state.long_rate, 99_999);
+        assert_eq!(custody.funding_rate_state.short_rate, -100_000);
+
+        // no funding without both sides open
+        custody.trade_stats.oi_long_usd = 0;
+        custody.update_funding_rate(7200).unwrap();
+        assert_eq!(custody.funding_rate_state.long_rate, 0);
+        assert_eq!(custody.funding_rate_state.short_rate, 0);
+    }
+
+    #[test]
+    fn test_get_locked_amount() {
+        let mut custody = get_fixture();
+        custody.pricing.max_payoff_mult = 20_000;
+        assert_eq!(custody.get_locked_amount(1000, Side::Long).unwrap(), 2000);
+        assert_eq!(custody.get_locked_amount(1000, Side::Short).unwrap(), 1000);
+
+        custody.pricing.max_payoff_mult = 5_000;
+        assert_eq!(custody.get_locked_amount(1000, Side::Long).unwrap(), 500);
+        assert_eq!(custody.get_locked_amount(1000, Side::Short).unwrap(), 500);
+    }
+
+    #[test]
+    fn test_leverage_tiers() {
+        let mut pricing = PricingParams {
+            min_initial_leverage: 10_000,
+            max_initial_leverage: 100_000,
+            max_leverage: 100_000,
+            max_request_age_sec: 60,
+            ..PricingParams::default()
+        };
+        assert!(pricing.validate());
+        assert_eq!(pricing.get_max_leverage(u64::MAX), 100_000);
+
+        pricing.leverage_tiers[0] = LeverageTier {
+            max_size_usd: 100_000,
+            max_leverage: 100_000,
+        };
+        pricing.leverage_tiers[1] = LeverageTier {
+            max_size_usd: 1_000_000,
+            max_leverage: 50_000,
+        };
+        assert!(pricing.validate());
+        assert_eq!(pricing.get_max_leverage(0), 100_000);
+        assert_eq!(pricing.get_max_leverage(100_000), 100_000);
+        assert_eq!(pricing.get_max_leverage(100_001), 50_000);
+        assert_eq!(pricing.get_max_leverage(u64::MAX), 50_000);
+
+        // liquidation target must be below every tier
+        pricing.liquidation_target_leverage = 50_000;
+        assert!(!pricing.validate());
+        pricing.liquidation_target_leverage = 0;
+
+        // leverage can't grow with size
+        pricing.leverage_tiers[1].max_leverage = 110_000;
+        assert!(!pricing.validate());
+        pricing.leverage_tiers[1].max_leverage = 50_000;
+
+        // sizes must be increasing
+        pricing.leverage_tiers[1].max_size_usd = 100_000;
+        assert!(!pricing.validate());
+        pricing.leverage_tiers[1].max_size_usd = 1_000_000;
+
+        // no gaps between tiers
+        pricing.leverage_tiers[3] = LeverageTier {
+            max_size_usd: 10_000_000,
+            max_leverage: 20_000,
+        };
+        assert!(!pricing.validate());
+    }
+
+    #[test]
+    fn test_get_vested_profit_usd() {
+        let mut custody = get_fixture();
+        let position = Position {
+            vesting_start_time: 100,
+            ..Position::default()
+        };
+
+        // no profit until the position is at least one second old
+        assert_eq!(
+            custody.get_vested_profit_usd(&position, 1000, 100).unwrap(),
+            0
+        );
+        assert_eq!(
+            custody.get_vested_profit_usd(&position, 1000, 101).unwrap(),
+            1000
+        );
+
+        custody.pricing.min_hold_time_sec = 60;
+        assert_eq!(
+            custody.get_vested_profit_usd(&position, 1000, 100).unwrap(),
+            0
+        );
+        assert_eq!(
+            custody.get_vested_profit_usd(&position, 1000, 115).unwrap(),
+            250
+        );
+        assert_eq!(
+            custody.get_vested_profit_usd(&position, 1000, 145).unwrap(),
+            750
+        );
+        assert_eq!(
+            custody.get_vested_profit_usd(&position, 1000, 160).unwrap(),
+            1000
+        );
+        assert_eq!(
+            custody.get_vested_profit_usd(&position, 1000, 500).unwrap(),
+            1000
+        );
+    }
+
+    #[test]
+    fn test_get_solvency() {
+        let mut custody = get_fixture();
+        custody.assets.locked = 0;
+        assert_eq!(custody.get_solvency().unwrap(), u64::MAX);
+
+        custody.assets.owned = 5000;
+        custody.assets.locked = 4000;
+        assert_eq!(custody.get_solvency().unwrap(), 12_500);
+
+        custody.assets.locked = 10_000;
+        assert_eq!(custody.get_solvency().unwrap(), 5_000);
+    }
+
+    #[test]
+    fn test_borrow_stats() {
+        let mut custody = get_fixture();
+        custody.borrow_rate_state.current_rate = 10_000;
+
+        // shorts lock and borrow from the stablecoin custody
+        let position = Position {
+            side: Side::Short,
+            borrow_size_usd: 1_000_000_000,
+            locked_amount: 1_000,
+            ..Position::default()
+        };
+        custody.add_borrow(&position, 0).unwrap();
+        assert_eq!(custody.borrow_stats.locked_amount, 1_000);
+        assert_eq!(custody.get_borrow_interest_usd(3_600).unwrap(), 10_000);
+
+        // a second borrower snapshotted later only pays from its snapshot
+        let position2 = Position {
+            cumulative_interest_snapshot: custody.get_cumulative_interest(3_600).unwrap(),
+            ..position
+        };
+        custody.add_borrow(&position2, 3_600).unwrap();
+        assert_eq!(custody.borrow_stats.borrow_size_usd, 2_000_000_000);
+        assert_eq!(custody.get_borrow_interest_usd(7_200).unwrap(), 30_000);
+
+        // closed positions take their interest with them
+        custody.remove_borrow(&position, 7_200).unwrap();
+        assert_eq!(custody.borrow_stats.locked_amount, 1_000);
+        assert_eq!(custody.get_borrow_interest_usd(7_200).unwrap(), 10_000);
+
+        custody.remove_borrow(&position2, 7_200).unwrap();
+        assert_eq!(custody.borrow_stats, BorrowStats::default());
+    }
+
+    #[test]
+    fn test_collective_settled_loss() {
+        let mut custody = get_fixture();
+        let token_price = OraclePrice {
+            price: 25_000,
+            exponent: -3,
+        };
+
+        // interest settled on increase stays in the collective loss
+        let position = Position {
+            side: Side::Long,
+            price: 25_000_000,
+            size_usd: 1_000_000_000,
+            unrealized_loss_usd: 10_000,
+            ..Position::default()
+        };
+        custody.add_position(&position, &token_price, 0).unwrap();
+        assert_eq!(
+            custody
+                .get_collective_position(Side::Long)
+                .unwrap()
+                .unrealized_loss_usd,
+            10_000
+        );
+
+        custody.add_position(&position, &token_price, 0).unwrap();
+        custody.remove_position(&position, 0).unwrap();
+        assert_eq!(
+            custody
+                .get_collective_position(Side::Long)
+                .unwrap()
+                .unrealized_loss_usd,
+            10_000
+        );
+    }
+
+    #[test]
+    fn test_get_position_stats_locked_usd() {
+        let mut custody = get_fixture();
+        let token_price = OraclePrice {
+            price: 25_000,
+            exponent: -3,
+        };
+
+        // position token collateral locks custody tokens
+        let position = Position {
+            side: Side::Long,
+            price: 25_000_000,
+            size_usd: 25_000_000,
+            borrow_size_usd: 25_000_000,
+            locked_amount: 100,
+            ..Position::default()
+        };
+        custody.add_position(&position, &token_price, 0).unwrap();
+        assert_eq!(
+            custody
+                .get_position_stats_locked_usd(Side::Long, &token_price)
+                .unwrap(),
+            25_000_000
+        );
+
+        // stablecoin collateral is locked in the collateral custody at its borrowed size
+        let stable_position = Position {
+            collateral_custody: Pubkey::new_unique(),
+            locked_amount: 25_000_000,
+            ..position
+        };
+        custody
+            .add_position(&stable_position, &token_price, 0)
+            .unwrap();
+        assert_eq!(custody.long_positions.locked_amount, 100);
+        assert_eq!(
+            custody
+                .get_position_stats_locked_usd(Side::Long, &token_price)
+                .unwrap(),
+            50_000_000
+        );
+
+        custody.remove_position(&stable_position, 0).unwrap();
+        assert_eq!(custody.long_positions.stable_locked_usd, 0);
+    }
+
+    #[test]
+    fn test_upgrade_custody() {
+        let deprecated_custody = DeprecatedCustody {
+            mint: Pubkey::new_unique(),
+            token_account: Pubkey::new_unique(),
+            decimals: 6,
+            oracle: OracleParams {
+                max_price_age_sec: 60,
+                ..OracleParams::default()
+            },
+            pricing: DeprecatedPricingParams {
+                min_initial_leverage: 10_000,
+                max_initial_leverage: 1_000_000,
+                max_leverage: 1_000_000,
+                max_utilization: 10_000,
+                ..DeprecatedPricingParams::default()
+            },
+            permissions: DeprecatedPermissions {
+                allow_open_position: true,
+                ..DeprecatedPermissions::default()
+            },
+            fees: DeprecatedFees {
+                open_position: 100,
+                protocol_share: 10,
+                ..DeprecatedFees::default()
+            },
+            borrow_rate: DeprecatedBorrowRateParams {
+                optimal_utilization: 800_000_000,
+                ..DeprecatedBorrowRateParams::default()
+            },
+            trade_stats: DeprecatedTradeStats {
+                oi_long_usd: 5_000,
+                ..DeprecatedTradeStats::default()
+            },
+            long_positions: DeprecatedPositionStats {
+                open_positions: 2,
+                size_usd: 5_000,
+                borrow_size_usd: 4_000,
+                locked_amount: 300,
+                cumulative_interest_usd: 20,
+                cumulative_interest_snapshot: 7,
+                ..DeprecatedPositionStats::default()
+            },
+            borrow_rate_state: BorrowRateState {
+                last_update: 100,
+                ..BorrowRateState::default()
+            },
+            ..DeprecatedCustody::default()
+        };
+
+        let custody = Custody::from(deprecated_custody);
+        assert!(custody.validate());
+        assert_eq!(custody.pricing.max_leverage, 1_000_000);
+        assert_eq!(custody.pricing.leverage_tiers, [LeverageTier::default(); 4]);
+        assert!(custody.permissions.allow_open_position);
+        assert!(!custody.permissions.allow_swap);
+        assert_eq!(custody.fees.open_position, 100);
+        assert_eq!(custody.fees.insurance_share, 0);
+        assert_eq!(custody.trade_stats.oi_long_usd, 5_000);
+        assert_eq!(custody.long_positions.size_usd, 5_000);
+        assert_eq!(custody.long_positions.locked_amount, 300);
+        assert_eq!(custody.funding_rate_state.last_update, 100);
+        assert_eq!(
+            custody.borrow_stats,
+            BorrowStats {
+                open_positions: 2,
+                borrow_size_usd: 4_000,
+                locked_amount: 300,
+                cumulative_interest_usd: 20,
+                cumulative_interest_snapshot: 7,
+            }
+        );
+    }
 }
@@ -0,0 +1,175 @@
+use {
+    crate::{error::PerpetualsError, math, state::perpetuals::Perpetuals},
+    anchor_lang::prelude::*,
+};
+
+#[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Debug)]
+pub enum DelegateAction {
+    OpenPosition,
+    ClosePosition,
+    CollateralChange,
+    Orders,
+}
+
+#[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
+pub struct Operator {
+    pub key: Pubkey,
+    pub allow_open_position: bool,
+    pub allow_close_position: bool,
+    pub allow_collateral_change: bool,
+    pub allow_orders: bool,
+}
+
+#[account]
+#[derive(Default, Debug)]
+pub struct DelegateAuthority {
+    pub owner: Pubkey,
+    pub operators: [Operator; 4], // DelegateAuthority::MAX_OPERATORS
+    pub bump: u8,
+}
+
+impl DelegateAuthority {
+    pub const LEN: usize = 8 + std::mem::size_of::<DelegateAuthority>();
+    pub const MAX_OPERATORS: usize = 4;
+
+    pub fn set_operators(&mut self, operators: &[Operator]) -> Result<()> {
+        if operators.len() > DelegateAuthority::MAX_OPERATORS {
+            msg!(
+                "Error: Number of operators exceeds the limit: {} > {}",
+                operators.len(),
+                DelegateAuthority::MAX_OPERATORS
+            );
+            return err!(PerpetualsError::InvalidDelegateAuthority);
+        }
+
+        for (idx, operator) in operators.iter().enumerate() {
+            if operator.key == Pubkey::default()
+                || operator.key == self.owner
+                || operators[..idx]
+                    .iter()
+                    .any(|other| other.key == operator.key)
+            {
+                return err!(PerpetualsError::InvalidDelegateAuthority);
+            }
+        }
+
+        self.operators = Default::default();
+        self.operators[..operators.len()].copy_from_slice(operators);
+
+        Ok(())
+    }
+
+    pub fn is_authorized(&self, operator: &Pubkey, action: DelegateAction) -> bool {
+        self.operators
+            .iter()
+            .find(|op| op.key != Pubkey::default() && op.key == *operator)
+            .map_or(false, |op| match action {
+                DelegateAction::OpenPosition => op.allow_open_position,
+                DelegateAction::ClosePosition => op.allow_close_position,
+                DelegateAction::CollateralChange => op.allow_collateral_change,
+                DelegateAction::Orders => op.allow_orders,
+            })
+    }
+
+    // the owner can always act on its positions, operators only within their delegated scope
+    pub fn validate_authority(
+        authority: &Pubkey,
+        owner: &Pubkey,
+        delegate_authority: Option<&Account<DelegateAuthority>>,
+        action: DelegateAction,
+    ) -> Result<()> {
+        if authority == owner {
+            return Ok(());
+        }
+        require!(
+            delegate_authority.map_or(false, |delegate_authority| {
+                delegate_authority.is_authorized(authority, action)
+            }),
+            PerpetualsError::UnauthorizedDelegate
+        );
+        Ok(())
+    }
+
+    // accounts created by operators are closed to the owner, so the owner pays their rent:
+    // it is refunded to the operator from the lamports deposited in the delegate authority
+    pub fn refund_rent<'info>(
+        authority: &AccountInfo<'info>,
+        owner: &Pubkey,
+        delegate_authority: Option<&Account<'info, DelegateAuthority>>,
+        created_accounts: &[AccountInfo<'info>],
+    ) -> Result<()> {
+        if authority.key == owner {
+            return Ok(());
+        }
+        let Some(delegate_authority) = delegate_authority else {
+            return err!(PerpetualsError::UnauthorizedDelegate);
+        };
+        let delegate_authority = delegate_authority.to_account_info();
+
+        let mut rent = 0u64;
+        for account in created_accounts {
+            rent = math::checked_add(rent, account.try_lamports()?)?;
+        }
+        let min_balance = Rent::get()?.minimum_balance(delegate_authority.data_len());
+        require_gte!(
+            delegate_authority.try_lamports()?,
+            math::checked_add(min_balance, rent)?,
+            PerpetualsError::InsufficientRentDeposit
+        );
+
+        Perpetuals::transfer_sol_from_owned(delegate_authority, authority.clone(), rent)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_set_operators() {
+        let mut delegate_authority = DelegateAuthority {
+            owner: Pubkey::new_unique(),
+            ..DelegateAuthority::default()
+        };
+        let trader = Operator {
+            key: Pubkey::new_unique(),
+            allow_open_position: true,
+            allow_close_position: true,
+            ..Operator::default()
+        };
+        let keeper = Operator {
+            key: Pubkey::new_unique(),
+            allow_orders: true,
+            ..Operator::default()
+        };
+
+        delegate_authority.set_operators(&[trader, keeper]).unwrap();
+        assert!(delegate_authority.is_authorized(&trader.key, DelegateAction::OpenPosition));
+        assert!(delegate_authority.is_authorized(&trader.key, DelegateAction::ClosePosition));
+        assert!(!delegate_authority.is_authorized(&trader.key, DelegateAction::CollateralChange));
+        assert!(!delegate_authority.is_authorized(&trader.key, DelegateAction::Orders));
+        assert!(delegate_authority.is_authorized(&keeper.key, DelegateAction::Orders));
+        assert!(!delegate_authority.is_authorized(&keeper.key, DelegateAction::OpenPosition));
+        assert!(!delegate_authority.is_authorized(&Pubkey::default(), DelegateAction::Orders));
+
+        // operators are replaced
+        delegate_authority.set_operators(&[keeper]).unwrap();
+        assert!(!delegate_authority.is_authorized(&trader.key, DelegateAction::OpenPosition));
+
+        // duplicates, the owner and too many operators are rejected
+        assert!(delegate_authority.set_operators(&[keeper, keeper]).is_err());
+        assert!(delegate_authority
+            .set_operators(&[Operator {
+                key: delegate_authority.owner,
+                ..keeper
+            }])
+            .is_err());
+        let operators: Vec<Operator> = (0..=DelegateAuthority::MAX_OPERATORS)
+            .map(|_| Operator {
+                key: Pubkey::new_unique(),
+                ..keeper
+            })
+            .collect();
+        assert!(delegate_authority.set_operators(&operators).is_err());
+    }
+}
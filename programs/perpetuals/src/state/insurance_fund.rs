@@ -0,0 +1,112 @@
+use {
+    crate::{error::PerpetualsError, math, state::custody::Custody},
+    anchor_lang::prelude::*,
+};
+
+#[account]
+#[derive(Default, Debug)]
+pub struct InsuranceFund {
+    pub pool: Pubkey,
+    // fund balance of each pool custody in custody tokens, in pool order. The tokens are
+    // held by the custody token accounts but are excluded from the custody owned amounts
+    pub balances: Vec<u64>,
+    pub bump: u8,
+}
+
+impl InsuranceFund {
+    pub const LEN: usize = 8 + std::mem::size_of::<InsuranceFund>();
+
+    pub fn get_balance(&self, token_id: usize) -> Result<u64> {
+        self.balances
+            .get(token_id)
+            .copied()
+            .ok_or_else(|| PerpetualsError::UnsupportedToken.into())
+    }
+
+    pub fn add_funds(&mut self, token_id: usize, amount: u64) -> Result<()> {
+        let balance = self.get_balance(token_id)?;
+        self.balances[token_id] = math::checked_add(balance, amount)?;
+        Ok(())
+    }
+
+    pub fn withdraw_funds(&mut self, token_id: usize, amount: u64) -> Result<()> {
+        let balance = self.get_balance(token_id)?;
+        if balance < amount {
+            return Err(ProgramError::InsufficientFunds.into());
+        }
+        self.balances[token_id] = math::checked_sub(balance, amount)?;
+        Ok(())
+    }
+
+    // moves insurance funds to the custody to cover bad debt, returns the amount covered
+    pub fn cover_bad_debt(
+        &mut self,
+        token_id: usize,
+        amount: u64,
+        custody: &mut Custody,
+    ) -> Result<u64> {
+        let balance = self.get_balance(token_id)?;
+        let covered_amount = std::cmp::min(amount, balance);
+        self.balances[token_id] = math::checked_sub(balance, covered_amount)?;
+        custody.assets.owned = math::checked_add(custody.assets.owned, covered_amount)?;
+        Ok(covered_amount)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_withdraw_funds() {
+        let mut insurance_fund = InsuranceFund {
+            balances: vec![0, 1000],
+            ..InsuranceFund::default()
+        };
+
+        insurance_fund.withdraw_funds(1, 400).unwrap();
+        assert_eq!(insurance_fund.balances[1], 600);
+        assert!(insurance_fund.withdraw_funds(1, 601).is_err());
+        assert!(insurance_fund.withdraw_funds(0, 1).is_err());
+        insurance_fund.withdraw_funds(1, 600).unwrap();
+        assert_eq!(insurance_fund.balances, vec![0, 0]);
+    }
+
+    #[test]
+    fn test_cover_bad_debt() {
+        let mut insurance_fund = InsuranceFund {
+            balances: vec![0, 1000],
+            ..InsuranceFund::default()
+        };
+        let mut custody = Custody::default();
+        custody.assets.owned = 5000;
+
+        assert_eq!(
+            insurance_fund.cover_bad_debt(1, 400, &mut custody).unwrap(),
+            400
+        );
+        assert_eq!(insurance_fund.balances[1], 600);
+        assert_eq!(custody.assets.owned, 5400);
+
+        // only part of the bad debt is covered once the fund is depleted
+        assert_eq!(
+            insurance_fund
+                .cover_bad_debt(1, 1000, &mut custody)
+                .unwrap(),
+            600
+        );
+        assert_eq!(insurance_fund.balances[1], 0);
+        assert_eq!(custody.assets.owned, 6000);
+
+        // balances of other custodies can't be used
+        assert_eq!(
+            insurance_fund
+                .cover_bad_debt(0, 1000, &mut custody)
+                .unwrap(),
+            0
+        );
+        assert!(insurance_fund
+            .cover_bad_debt(2, 1000, &mut custody)
+            .is_err());
+    }
+}
@@ -0,0 +1,34 @@
+use {crate::math, anchor_lang::prelude::*};
+
+#[account]
+#[derive(Default, Debug)]
+pub struct LimitOrder {
+    pub owner: Pubkey,
+    pub pool: Pubkey,
+    pub custody: Pubkey,
+    pub collateral_custody: Pubkey,
+
+    // index of the position to be opened
+    pub position_index: u64,
+    pub limit_price: u64,
+    pub size: u64,
+    pub collateral_amount: u64,
+    // escrowed on top of the collateral, unused part is refunded on execution
+    pub max_fee: u64,
+    pub create_time: i64,
+
+    pub bump: u8,
+    pub escrow_bump: u8,
+}
+
+impl LimitOrder {
+    pub const LEN: usize = 8 + std::mem::size_of::<LimitOrder>();
+
+    pub fn validate(&self) -> bool {
+        self.limit_price > 0 && self.size > 0 && self.collateral_amount > 0
+    }
+
+    pub fn get_escrow_amount(&self) -> Result<u64> {
+        math::checked_add(self.collateral_amount, self.max_fee)
+    }
+}
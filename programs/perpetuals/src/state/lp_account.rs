@@ -0,0 +1,37 @@
+use {crate::math, anchor_lang::prelude::*};
+
+#[account]
+#[derive(Default, Debug)]
+pub struct LpAccount {
+    pub owner: Pubkey,
+    pub pool: Pubkey,
+    // liquidity can only be removed once the pool cooldown has passed since this time
+    pub last_deposit_time: i64,
+    pub bump: u8,
+}
+
+impl LpAccount {
+    pub const LEN: usize = 8 + std::mem::size_of::<LpAccount>();
+
+    pub fn is_cooled_down(&self, cooldown_sec: i64, curtime: i64) -> Result<bool> {
+        Ok(curtime >= math::checked_add(self.last_deposit_time, cooldown_sec)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_is_cooled_down() {
+        let lp_account = LpAccount {
+            last_deposit_time: 100,
+            ..LpAccount::default()
+        };
+
+        assert!(!lp_account.is_cooled_down(60, 100).unwrap());
+        assert!(!lp_account.is_cooled_down(60, 159).unwrap());
+        assert!(lp_account.is_cooled_down(60, 160).unwrap());
+        assert!(lp_account.is_cooled_down(0, 100).unwrap());
+    }
+}
@@ -0,0 +1,219 @@
+use {
+    crate::{error::PerpetualsError, math, state::position::Position},
+    anchor_lang::prelude::*,
+};
+
+#[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
+pub struct MarginCollateral {
+    pub custody: Pubkey,
+    pub amount: u64,
+}
+
+#[account]
+#[derive(Default, Debug)]
+pub struct MarginAccount {
+    pub owner: Pubkey,
+    pub pool: Pubkey,
+    // collateral entries are kept once created, so cross positions can always be
+    // settled back into the account
+    pub collaterals: [MarginCollateral; 4], // MarginAccount::MAX_COLLATERALS
+    pub positions: [Pubkey; 8],             // MarginAccount::MAX_POSITIONS
+    pub bump: u8,
+}
+
+impl MarginAccount {
+    pub const LEN: usize = 8 + std::mem::size_of::<MarginAccount>();
+    pub const MAX_COLLATERALS: usize = 4;
+    pub const MAX_POSITIONS: usize = 8;
+
+    pub fn get_collaterals(&self) -> &[MarginCollateral] {
+        let len = self
+            .collaterals
+            .iter()
+            .take_while(|collateral| collateral.custody != Pubkey::default())
+            .count();
+        &self.collaterals[..len]
+    }
+
+    pub fn get_positions(&self) -> &[Pubkey] {
+        let len = self
+            .positions
+            .iter()
+            .take_while(|position| **position != Pubkey::default())
+            .count();
+        &self.positions[..len]
+    }
+
+    pub fn get_collateral_amount(&self, custody: &Pubkey) -> u64 {
+        self.get_collaterals()
+            .iter()
+            .find(|collateral| collateral.custody == *custody)
+            .map_or(0, |collateral| collateral.amount)
+    }
+
+    pub fn add_collateral(&mut self, custody: &Pubkey, amount: u64) -> Result<()> {
+        let len = self.get_collaterals().len();
+        if let Some(collateral) = self.collaterals[..len]
+            .iter_mut()
+            .find(|collateral| collateral.custody == *custody)
+        {
+            collateral.amount = math::checked_add(collateral.amount, amount)?;
+            return Ok(());
+        }
+        if len == MarginAccount::MAX_COLLATERALS {
+            return err!(PerpetualsError::MarginAccountFull);
+        }
+        self.collaterals[len] = MarginCollateral {
+            custody: *custody,
+            amount,
+        };
+        Ok(())
+    }
+
+    pub fn remove_collateral(&mut self, custody: &Pubkey, amount: u64) -> Result<()> {
+        let len = self.get_collaterals().len();
+        let Some(collateral) = self.collaterals[..len]
+            .iter_mut()
+            .find(|collateral| collateral.custody == *custody)
+        else {
+            return err!(PerpetualsError::InsufficientMargin);
+        };
+        require_gte!(
+            collateral.amount,
+            amount,
+            PerpetualsError::InsufficientMargin
+        );
+        collateral.amount -= amount;
+        Ok(())
+    }
+
+    // removes up to the given amount of collateral, returns the amount removed
+    pub fn take_collateral(&mut self, custody: &Pubkey, amount: u64) -> Result<u64> {
+        let amount = std::cmp::min(amount, self.get_collateral_amount(custody));
+        if amount > 0 {
+            self.remove_collateral(custody, amount)?;
+        }
+        Ok(amount)
+    }
+
+    // returns the margin account of a cross position, which is required to settle it,
+    // or none for isolated positions
+    pub fn validate_position_account<'a, 'info>(
+        position: &Position,
+        margin_account: Option<&'a mut Account<'info, MarginAccount>>,
+    ) -> Result<Option<&'a mut Account<'info, MarginAccount>>> {
+        if !position.is_cross_margin() {
+            return Ok(None);
+        }
+        let Some(margin_account) = margin_account else {
+            return err!(PerpetualsError::InvalidMarginAccount);
+        };
+        require_keys_eq!(
+            margin_account.key(),
+            position.margin_account,
+            PerpetualsError::InvalidMarginAccount
+        );
+        Ok(Some(margin_account))
+    }
+
+    pub fn add_position(&mut self, position: &Pubkey) -> Result<()> {
+        let len = self.get_positions().len();
+        if self.positions[..len].contains(position) {
+            return Ok(());
+        }
+        if len == MarginAccount::MAX_POSITIONS {
+            return err!(PerpetualsError::MarginAccountFull);
+        }
+        self.positions[len] = *position;
+        Ok(())
+    }
+
+    pub fn remove_position(&mut self, position: &Pubkey) {
+        let len = self.get_positions().len();
+        if let Some(idx) = self.positions[..len].iter().position(|key| key == position) {
+            self.positions.copy_within(idx + 1..len, idx);
+            self.positions[len - 1] = Pubkey::default();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_collaterals() {
+        let mut margin_account = MarginAccount::default();
+        let custodies: Vec<Pubkey> = (0..=MarginAccount::MAX_COLLATERALS)
+            .map(|_| Pubkey::new_unique())
+            .collect();
+
+        margin_account.add_collateral(&custodies[0], 100).unwrap();
+        margin_account.add_collateral(&custodies[1], 50).unwrap();
+        margin_account.add_collateral(&custodies[0], 25).unwrap();
+        assert_eq!(margin_account.get_collaterals().len(), 2);
+        assert_eq!(margin_account.get_collateral_amount(&custodies[0]), 125);
+        assert_eq!(margin_account.get_collateral_amount(&custodies[2]), 0);
+
+        // entries are kept at zero balance
+        margin_account.remove_collateral(&custodies[1], 50).unwrap();
+        assert_eq!(margin_account.get_collaterals().len(), 2);
+        assert_eq!(margin_account.get_collateral_amount(&custodies[1]), 0);
+        assert!(margin_account.remove_collateral(&custodies[1], 1).is_err());
+        assert!(margin_account.remove_collateral(&custodies[2], 1).is_err());
+
+        // taking collateral is capped by the balance
+        assert_eq!(
+            margin_account.take_collateral(&custodies[0], 25).unwrap(),
+            25
+        );
+        assert_eq!(
+            margin_account.take_collateral(&custodies[0], 500).unwrap(),
+            100
+        );
+        assert_eq!(
+            margin_account.take_collateral(&custodies[2], 10).unwrap(),
+            0
+        );
+        assert_eq!(margin_account.get_collateral_amount(&custodies[0]), 0);
+        margin_account.add_collateral(&custodies[0], 125).unwrap();
+
+        for custody in &custodies[2..MarginAccount::MAX_COLLATERALS] {
+            margin_account.add_collateral(custody, 1).unwrap();
+        }
+        assert!(margin_account
+            .add_collateral(&custodies[MarginAccount::MAX_COLLATERALS], 1)
+            .is_err());
+    }
+
+    #[test]
+    fn test_positions() {
+        let mut margin_account = MarginAccount::default();
+        let positions: Vec<Pubkey> = (0..=MarginAccount::MAX_POSITIONS)
+            .map(|_| Pubkey::new_unique())
+            .collect();
+
+        for position in &positions[..MarginAccount::MAX_POSITIONS] {
+            margin_account.add_position(position).unwrap();
+        }
+        margin_account.add_position(&positions[0]).unwrap();
+        assert!(margin_account
+            .add_position(&positions[MarginAccount::MAX_POSITIONS])
+            .is_err());
+
+        margin_account.remove_position(&positions[2]);
+        assert_eq!(
+            margin_account.get_positions().len(),
+            MarginAccount::MAX_POSITIONS - 1
+        );
+        assert_eq!(margin_account.get_positions()[2], positions[3]);
+
+        margin_account
+            .add_position(&positions[MarginAccount::MAX_POSITIONS])
+            .unwrap();
+        assert_eq!(
+            margin_account.get_positions()[MarginAccount::MAX_POSITIONS - 1],
+            positions[MarginAccount::MAX_POSITIONS]
+        );
+    }
+}
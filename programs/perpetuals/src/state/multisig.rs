@@ -35,6 +35,10 @@ pub enum AdminInstruction {
     WithdrawSolFees,
     SetCustomOraclePrice,
     UpgradeCustody,
+    SetPoolConfig,
+    UpgradePerpetuals,
+    UpgradePool,
+    WithdrawInsuranceFund,
 }
 
 impl Multisig {
@@ -113,6 +113,32 @@ impl OraclePrice {
         }
     }
 
+    // Returns the time the current oracle price was published at
+    pub fn get_publish_time(
+        oracle_account: &AccountInfo,
+        oracle_params: &OracleParams,
+    ) -> Result<i64> {
+        require!(
+            !Perpetuals::is_empty_account(oracle_account)?,
+            PerpetualsError::InvalidOracleAccount
+        );
+        match oracle_params.oracle_type {
+            OracleType::Custom => {
+                let oracle_acc = try_from!(Account::<CustomOracle>, oracle_account)?;
+                Ok(oracle_acc.publish_time)
+            }
+            OracleType::Pyth => {
+                let data = &oracle_account.try_borrow_data()?[8..];
+                Ok(
+                    pyth_min::price_update::PriceUpdateV2::get_price_update_v2_from_bytes(data)
+                        .price_message
+                        .publish_time,
+                )
+            }
+            _ => err!(PerpetualsError::UnsupportedOracle),
+        }
+    }
+
     // Converts token amount to USD with implied USD_DECIMALS decimals using oracle price
     pub fn get_asset_amount_usd(&self, token_amount: u64, token_decimals: u8) -> Result<u64> {
         if token_amount == 0 || self.price == 0 {
@@ -1,7 +1,9 @@
 use {
     crate::try_from,
     anchor_lang::prelude::*,
-    anchor_spl::token::{Burn, MintTo, Transfer},
+    anchor_spl::token::{
+        spl_token::instruction::AuthorityType, Burn, MintTo, SetAuthority, Transfer,
+    },
 };
 
 #[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
@@ -16,6 +18,13 @@ pub struct AmountAndFee {
     pub fee: u64,
 }
 
+#[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
+pub struct SwapAmountAndFees {
+    pub amount_out: u64,
+    pub fee_in: u64,
+    pub fee_out: u64,
+}
+
 #[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
 pub struct NewPositionPricesAndFee {
     pub entry_price: u64,
@@ -32,6 +41,9 @@ pub struct Permissions {
     pub allow_pnl_withdrawal: bool,
     pub allow_collateral_withdrawal: bool,
     pub allow_size_change: bool,
+    pub allow_swap: bool,
+    // direct open_position and close_position, bypassing the request flow
+    pub allow_direct_position: bool,
 }
 
 #[account]
@@ -46,6 +58,64 @@ pub struct Perpetuals {
     pub inception_time: i64,
 }
 
+// layouts of the initial release, used to upgrade deployed accounts
+
+#[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
+pub struct DeprecatedPermissions {
+    pub allow_add_liquidity: bool,
+    pub allow_remove_liquidity: bool,
+    pub allow_open_position: bool,
+    pub allow_close_position: bool,
+    pub allow_pnl_withdrawal: bool,
+    pub allow_collateral_withdrawal: bool,
+    pub allow_size_change: bool,
+}
+
+#[account]
+#[derive(Default, Debug)]
+pub struct DeprecatedPerpetuals {
+    pub permissions: DeprecatedPermissions,
+    pub pools: Vec<Pubkey>,
+
+    pub transfer_authority_bump: u8,
+    pub perpetuals_bump: u8,
+    pub inception_time: i64,
+}
+
+impl DeprecatedPerpetuals {
+    pub const LEN: usize = 8 + std::mem::size_of::<DeprecatedPerpetuals>();
+}
+
+impl From<DeprecatedPermissions> for Permissions {
+    // swaps and direct positions were not available before, they stay disabled
+    // until enabled with set_permissions
+    fn from(permissions: DeprecatedPermissions) -> Self {
+        Self {
+            allow_add_liquidity: permissions.allow_add_liquidity,
+            allow_remove_liquidity: permissions.allow_remove_liquidity,
+            allow_open_position: permissions.allow_open_position,
+            allow_close_position: permissions.allow_close_position,
+            allow_pnl_withdrawal: permissions.allow_pnl_withdrawal,
+            allow_collateral_withdrawal: permissions.allow_collateral_withdrawal,
+            allow_size_change: permissions.allow_size_change,
+            allow_swap: false,
+            allow_direct_position: false,
+        }
+    }
+}
+
+impl From<DeprecatedPerpetuals> for Perpetuals {
+    fn from(perpetuals: DeprecatedPerpetuals) -> Self {
+        Self {
+            permissions: perpetuals.permissions.into(),
+            pools: perpetuals.pools,
+            transfer_authority_bump: perpetuals.transfer_authority_bump,
+            perpetuals_bump: perpetuals.perpetuals_bump,
+            inception_time: perpetuals.inception_time,
+        }
+    }
+}
+
 impl anchor_lang::Id for Perpetuals {
     fn id() -> Pubkey {
         crate::ID
@@ -169,6 +239,27 @@ impl Perpetuals {
         anchor_spl::token::mint_to(context, amount)
     }
 
+    pub fn revoke_mint_authority<'info>(
+        &self,
+        mint: AccountInfo<'info>,
+        authority: AccountInfo<'info>,
+        token_program: AccountInfo<'info>,
+    ) -> Result<()> {
+        let authority_seeds: &[&[&[u8]]] =
+            &[&[b"transfer_authority", &[self.transfer_authority_bump]]];
+
+        let context = CpiContext::new(
+            token_program,
+            SetAuthority {
+                current_authority: authority,
+                account_or_mint: mint,
+            },
+        )
+        .with_signer(authority_seeds);
+
+        anchor_spl::token::set_authority(context, AuthorityType::MintTokens, None)
+    }
+
     pub fn burn_tokens<'info>(
         &self,
         mint: AccountInfo<'info>,
@@ -189,6 +280,31 @@ impl Perpetuals {
         anchor_spl::token::burn(context, amount)
     }
 
+    // burns tokens held by the transfer authority, e.g. escrowed LP tokens
+    pub fn burn_escrowed_tokens<'info>(
+        &self,
+        mint: AccountInfo<'info>,
+        from: AccountInfo<'info>,
+        authority: AccountInfo<'info>,
+        token_program: AccountInfo<'info>,
+        amount: u64,
+    ) -> Result<()> {
+        let authority_seeds: &[&[&[u8]]] =
+            &[&[b"transfer_authority", &[self.transfer_authority_bump]]];
+
+        let context = CpiContext::new(
+            token_program,
+            Burn {
+                mint,
+                from,
+                authority,
+            },
+        )
+        .with_signer(authority_seeds);
+
+        anchor_spl::token::burn(context, amount)
+    }
+
     pub fn is_empty_account(account_info: &AccountInfo) -> Result<bool> {
         Ok(account_info.try_data_is_empty()? || account_info.try_lamports()? == 0)
     }
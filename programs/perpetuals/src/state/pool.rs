@@ -3,7 +3,9 @@ use {
         error::PerpetualsError,
         math::{self},
         state::{
-            custody::Custody,
+            custody::{Custody, Fees},
+            insurance_fund::InsuranceFund,
+            margin_account::MarginAccount,
             oracle::OraclePrice,
             perpetuals::Perpetuals,
             position::{Position, Side},
@@ -22,6 +24,35 @@ pub struct Pool {
     pub bump: u8,
     pub lp_token_bump: u8,
     pub inception_time: i64,
+
+    // fields added after the initial release, appended to keep the layout of
+    // upgraded accounts (see upgrade_pool)
+
+    // target share of the pool AUM for each custody in BPS, all zeros disable
+    // weight-based liquidity fees
+    pub target_weights: Vec<u64>,
+    // minimum time between an LP deposit and the removal of liquidity, LPs without
+    // a deposit record wait for it through withdrawal requests
+    pub lp_cooldown_sec: i64,
+    // time after the cooldown during which a queued withdrawal can be executed, kept
+    // short so that requests are executed at the first opportunity
+    pub lp_withdrawal_window_sec: i64,
+    // share of the escrowed lp tokens burned when an expired withdrawal is
+    // cancelled, in BPS
+    pub lp_expired_cancel_fee: u64,
+}
+
+// layout of the initial release, used to upgrade deployed pool accounts
+#[account]
+#[derive(Default, Debug)]
+pub struct DeprecatedPool {
+    pub name: String,
+    pub custodies: Vec<Pubkey>,
+    pub aum_usd: u128,
+
+    pub bump: u8,
+    pub lp_token_bump: u8,
+    pub inception_time: i64,
 }
 
 // Normalizes such that (at least) one of profit or loss is zero
@@ -38,41 +69,45 @@ fn normalize_pnl(profit: u64, loss: u64) -> Result<(u64, u64)> {
     }
 }
 
-fn get_pnl_before_fees_usd(size_usd: u64, entry: u64, exit: u64) -> Result<(u64, u64)> {
-    if exit > entry {
-        let price_diff_profit = math::checked_sub(exit, entry)?;
+fn get_pnl_before_fees_usd(size_usd: u64, entry: u64, exit: u64, side: Side) -> Result<(u64, u64)> {
+    if exit == entry {
+        // Prices are equal
+        return Ok((0, 0));
+    }
+
+    let price_diff = if exit > entry {
+        math::checked_sub(exit, entry)?
+    } else {
+        math::checked_sub(entry, exit)?
+    };
+
+    // Longs profit when the price rises, shorts when it falls
+    if (exit > entry) == (side == Side::Long) {
         let profit_usd = math::checked_as_u64(math::checked_div(
-            math::checked_mul(size_usd as u128, price_diff_profit as u128)?,
+            math::checked_mul(size_usd as u128, price_diff as u128)?,
             entry as u128,
         )?)?;
-        return Ok((profit_usd, 0));
-    }
-
-    if exit < entry {
-        let price_diff_loss = math::checked_sub(entry, exit)?;
+        Ok((profit_usd, 0))
+    } else {
         let loss_usd = math::checked_as_u64(math::checked_ceil_div(
-            math::checked_mul(size_usd as u128, price_diff_loss as u128)?,
+            math::checked_mul(size_usd as u128, price_diff as u128)?,
             entry as u128,
         )?)?;
-
-        return Ok((0, loss_usd));
+        Ok((0, loss_usd))
     }
-
-    // Prices are equal
-    Ok((0, 0))
 }
 
 // Does it matter if its usd or tokens as long as its consistent?
-fn get_exit_fee_usd(_size: u64, profit: u64) -> Result<u64> {
-    if profit <= 0 {
-        return Ok(0);
-    }
+fn get_exit_fee_usd(fees: &Fees, size: u64, profit: u64) -> Result<u64> {
+    // Take a share of the position size and a share of the profit as exit fee
+    math::checked_add(
+        Pool::get_fee_amount(fees.close_position, size)?,
+        Pool::get_fee_amount(fees.close_position_profit_share, profit)?,
+    )
+}
 
-    // Take 1% of profit as exit fee
-    Ok(math::checked_ceil_div(
-        math::checked_mul(profit, 100)?,
-        10u64.pow(Perpetuals::BPS_DECIMALS as u32),
-    )?)
+impl DeprecatedPool {
+    pub const LEN: usize = 8 + 64 + std::mem::size_of::<DeprecatedPool>();
 }
 
 /// Token Pool
@@ -81,6 +116,7 @@ fn get_exit_fee_usd(_size: u64, profit: u64) -> Result<u64> {
 ///
 impl Pool {
     pub const LEN: usize = 8 + 64 + std::mem::size_of::<Pool>();
+    pub const MAX_LP_WITHDRAWAL_WINDOW_SEC: i64 = 60;
 
     pub fn validate(&self) -> bool {
         // check custodies are unique
@@ -90,7 +126,16 @@ impl Pool {
             }
         }
 
-        !self.name.is_empty() && self.name.len() <= 64
+        let total_weight: u128 = self.target_weights.iter().map(|&w| w as u128).sum();
+
+        !self.name.is_empty()
+            && self.name.len() <= 64
+            && self.target_weights.len() == self.custodies.len()
+            && (total_weight == 0 || total_weight == Perpetuals::BPS_POWER)
+            && self.lp_cooldown_sec >= 0
+            && self.lp_withdrawal_window_sec > 0
+            && self.lp_withdrawal_window_sec <= Self::MAX_LP_WITHDRAWAL_WINDOW_SEC
+            && self.lp_expired_cancel_fee as u128 <= Perpetuals::BPS_POWER
     }
 
     pub fn get_token_id(&self, custody: &Pubkey) -> Result<usize> {
@@ -100,8 +145,27 @@ impl Pool {
             .ok_or_else(|| PerpetualsError::UnsupportedToken.into())
     }
 
-    pub fn get_entry_price(&self, token_price: &OraclePrice, custody: &Custody) -> Result<u64> {
-        let price = self.get_price(token_price, Side::Long, custody.pricing.trade_spread_long)?;
+    pub fn get_entry_price(
+        &self,
+        token_price: &OraclePrice,
+        custody: &Custody,
+        side: Side,
+        size_usd: u64,
+    ) -> Result<u64> {
+        let price_impact = self.get_price_impact(custody, side, size_usd, false)?;
+        let price = if side == Side::Long {
+            self.get_price(
+                token_price,
+                Side::Long,
+                math::checked_add(custody.pricing.trade_spread_long, price_impact)?,
+            )?
+        } else {
+            self.get_price(
+                token_price,
+                Side::Short,
+                math::checked_add(custody.pricing.trade_spread_short, price_impact)?,
+            )?
+        };
         require_gt!(price.price, 0, PerpetualsError::MaxPriceSlippage);
 
         Ok(price
@@ -164,24 +228,123 @@ impl Pool {
         Ok(size_fee)
     }
 
-    pub fn get_exit_price(&self, token_price: &OraclePrice, custody: &Custody) -> Result<u64> {
-        let price = self.get_price(token_price, Side::Short, custody.pricing.trade_spread_short)?;
+    // returns entry fee at full utilization, an upper bound for get_entry_fee()
+    pub fn get_max_entry_fee(&self, base_fee: u64, size: u64, custody: &Custody) -> Result<u64> {
+        let size_fee = Self::get_fee_amount(base_fee, size)?;
+        math::checked_as_u64(math::checked_div(
+            math::checked_mul(
+                size_fee as u128,
+                math::checked_add(Perpetuals::BPS_POWER, custody.fees.utilization_mult as u128)?,
+            )?,
+            Perpetuals::BPS_POWER,
+        )?)
+    }
+
+    pub fn get_exit_price(
+        &self,
+        token_price: &OraclePrice,
+        custody: &Custody,
+        side: Side,
+        size_usd: u64,
+    ) -> Result<u64> {
+        let price_impact = self.get_price_impact(custody, side, size_usd, true)?;
+        let price = if side == Side::Long {
+            self.get_price(
+                token_price,
+                Side::Short,
+                math::checked_add(custody.pricing.trade_spread_short, price_impact)?,
+            )?
+        } else {
+            self.get_price(
+                token_price,
+                Side::Long,
+                math::checked_add(custody.pricing.trade_spread_long, price_impact)?,
+            )?
+        };
 
         Ok(price
             .scale_to_exponent(-(Perpetuals::PRICE_DECIMALS as i32))?
             .price)
     }
 
+    // returns spread in BPS added for the price impact of opening or closing size_usd,
+    // impact = size_usd / virtual_depth_usd * (1 + oi_skew), where oi_skew is the
+    // open interest imbalance the trade leaves in its own direction
+    pub fn get_price_impact(
+        &self,
+        custody: &Custody,
+        side: Side,
+        size_usd: u64,
+        closing: bool,
+    ) -> Result<u64> {
+        if custody.pricing.virtual_depth_usd == 0 || size_usd == 0 {
+            return Ok(0);
+        }
+
+        let mut oi_long_usd = custody.trade_stats.oi_long_usd;
+        let mut oi_short_usd = custody.trade_stats.oi_short_usd;
+        match (side, closing) {
+            (Side::Long, false) => oi_long_usd = math::checked_add(oi_long_usd, size_usd)?,
+            (Side::Long, true) => oi_long_usd = oi_long_usd.saturating_sub(size_usd),
+            (Side::Short, false) => oi_short_usd = math::checked_add(oi_short_usd, size_usd)?,
+            (Side::Short, true) => oi_short_usd = oi_short_usd.saturating_sub(size_usd),
+            (Side::None, _) => return Err(PerpetualsError::InvalidPositionState.into()),
+        }
+
+        // opening longs and closing shorts push the price up, the other trades push it down
+        let (heavy_oi_usd, light_oi_usd) = if (side == Side::Long) != closing {
+            (oi_long_usd, oi_short_usd)
+        } else {
+            (oi_short_usd, oi_long_usd)
+        };
+        let oi_skew = if heavy_oi_usd > light_oi_usd {
+            math::checked_div(
+                math::checked_mul((heavy_oi_usd - light_oi_usd) as u128, Perpetuals::BPS_POWER)?,
+                math::checked_add(heavy_oi_usd as u128, light_oi_usd as u128)?,
+            )?
+        } else {
+            0
+        };
+
+        let size_impact = math::checked_div(
+            math::checked_mul(size_usd as u128, Perpetuals::BPS_POWER)?,
+            custody.pricing.virtual_depth_usd as u128,
+        )?;
+        let price_impact = math::checked_div(
+            math::checked_mul(
+                size_impact,
+                math::checked_add(Perpetuals::BPS_POWER, oi_skew)?,
+            )?,
+            Perpetuals::BPS_POWER,
+        )?;
+
+        math::checked_as_u64(std::cmp::min(
+            price_impact,
+            custody.pricing.max_price_impact as u128,
+        ))
+    }
+
+    // returns (close_amount, fee_amount, profit_usd, loss_usd), token amounts are in collateral custody decimals
+    #[allow(clippy::too_many_arguments)]
     pub fn get_close_amount(
         &self,
         position: &Position,
         token_price: &OraclePrice,
         custody: &Custody,
+        collateral_token_price: &OraclePrice,
+        collateral_custody: &Custody,
         curtime: i64,
         liquidation: bool,
     ) -> Result<(u64, u64, u64, u64)> {
-        let (profit_usd, loss_usd, fee_amount) =
-            self.get_pnl_usd(position, token_price, custody, curtime, liquidation)?;
+        let (profit_usd, loss_usd, fee_amount) = self.get_pnl_usd(
+            position,
+            token_price,
+            custody,
+            collateral_token_price,
+            collateral_custody,
+            curtime,
+            liquidation,
+        )?;
 
         let available_amount_usd = if profit_usd > 0 {
             math::checked_add(position.collateral_usd, profit_usd)?
@@ -191,7 +354,8 @@ impl Pool {
             0
         };
 
-        let close_amount = token_price.get_token_amount(available_amount_usd, custody.decimals)?;
+        let close_amount = collateral_token_price
+            .get_token_amount(available_amount_usd, collateral_custody.decimals)?;
         let max_amount = math::checked_add(
             position.locked_amount.saturating_sub(fee_amount),
             position.collateral_amount,
@@ -205,6 +369,57 @@ impl Pool {
         ))
     }
 
+    pub fn get_swap_amount(
+        &self,
+        token_price_in: &OraclePrice,
+        token_price_out: &OraclePrice,
+        custody_in: &Custody,
+        custody_out: &Custody,
+        amount_in: u64,
+    ) -> Result<u64> {
+        // the pool buys the incoming token below and sells the outgoing token above the oracle price
+        let price_in =
+            self.get_price(token_price_in, Side::Short, custody_in.pricing.swap_spread)?;
+        let price_out =
+            self.get_price(token_price_out, Side::Long, custody_out.pricing.swap_spread)?;
+
+        let amount_in_usd = price_in.get_asset_amount_usd(amount_in, custody_in.decimals)?;
+        price_out.get_token_amount(amount_in_usd, custody_out.decimals)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn get_swap_fees(
+        &self,
+        token_id_in: usize,
+        token_id_out: usize,
+        amount_in: u64,
+        amount_out: u64,
+        custody_in: &Custody,
+        token_price_in: &OraclePrice,
+        custody_out: &Custody,
+        token_price_out: &OraclePrice,
+    ) -> Result<(u64, u64)> {
+        let swap_in_fee = self.get_fee(
+            token_id_in,
+            custody_in.fees.swap_in,
+            amount_in,
+            0u64,
+            custody_in,
+            token_price_in,
+        )?;
+
+        let swap_out_fee = self.get_fee(
+            token_id_out,
+            custody_out.fees.swap_out,
+            0u64,
+            amount_out,
+            custody_out,
+            token_price_out,
+        )?;
+
+        Ok((swap_in_fee, swap_out_fee))
+    }
+
     pub fn get_add_liquidity_fee(
         &self,
         token_id: usize,
@@ -239,6 +454,48 @@ impl Pool {
         )
     }
 
+    // splits a proportional redemption across custodies by their share of the owned value,
+    // which leaves the pool composition unchanged, returns the token amount of each custody
+    pub fn get_remove_liquidity_proportional_amounts(
+        remove_amount_usd: u128,
+        custodies: &[Custody],
+        token_prices: &[OraclePrice],
+    ) -> Result<Vec<u64>> {
+        let mut owned_amounts_usd = Vec::with_capacity(custodies.len());
+        for (custody, token_price) in custodies.iter().zip(token_prices) {
+            owned_amounts_usd.push(
+                token_price.get_asset_amount_usd(custody.assets.owned, custody.decimals)? as u128,
+            );
+        }
+        let total_owned_usd = owned_amounts_usd.iter().sum::<u128>();
+        require!(
+            remove_amount_usd <= total_owned_usd,
+            PerpetualsError::CustodyAmountLimit
+        );
+
+        let mut amounts = Vec::with_capacity(custodies.len());
+        for (custody, &owned_amount_usd) in custodies.iter().zip(&owned_amounts_usd) {
+            if owned_amount_usd == 0 {
+                amounts.push(0);
+                continue;
+            }
+            let amount = math::checked_as_u64(math::checked_div(
+                math::checked_mul(custody.assets.owned as u128, remove_amount_usd)?,
+                total_owned_usd,
+            )?)?;
+
+            // locked funds stay in the pool, a custody that can't pay its share
+            // fails the whole redemption
+            require!(
+                amount <= math::checked_sub(custody.assets.owned, custody.assets.locked)?,
+                PerpetualsError::CustodyAmountLimit
+            );
+            amounts.push(amount);
+        }
+
+        Ok(amounts)
+    }
+
     pub fn get_liquidation_fee(&self, size: u64, custody: &Custody) -> Result<u64> {
         Self::get_fee_amount(custody.fees.liquidation, size)
     }
@@ -256,10 +513,19 @@ impl Pool {
         position: &Position,
         token_price: &OraclePrice,
         custody: &Custody,
+        collateral_token_price: &OraclePrice,
+        collateral_custody: &Custody,
         curtime: i64,
     ) -> Result<u64> {
-        let (profit_usd, loss_usd, _) =
-            self.get_pnl_usd(position, token_price, custody, curtime, false)?;
+        let (profit_usd, loss_usd, _) = self.get_pnl_usd(
+            position,
+            token_price,
+            custody,
+            collateral_token_price,
+            collateral_custody,
+            curtime,
+            false,
+        )?;
 
         let current_margin_usd = if profit_usd > 0 {
             math::checked_add(position.collateral_usd, profit_usd)?
@@ -279,49 +545,210 @@ impl Pool {
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn check_leverage(
         &self,
         position: &Position,
         token_price: &OraclePrice,
         custody: &Custody,
+        collateral_token_price: &OraclePrice,
+        collateral_custody: &Custody,
         curtime: i64,
         initial: bool,
     ) -> Result<bool> {
-        let current_leverage = self.get_leverage(position, token_price, custody, curtime)?;
+        let current_leverage = self.get_leverage(
+            position,
+            token_price,
+            custody,
+            collateral_token_price,
+            collateral_custody,
+            curtime,
+        )?;
+
+        Ok(
+            current_leverage <= custody.pricing.get_max_leverage(position.size_usd)
+                && (!initial
+                    || (current_leverage >= custody.pricing.min_initial_leverage
+                        && current_leverage <= custody.pricing.max_initial_leverage)),
+        )
+    }
+
+    // returns the position size to liquidate to bring its leverage back to the target,
+    // or the whole position size if partial liquidations are disabled or not possible
+    #[allow(clippy::too_many_arguments)]
+    pub fn get_liquidation_size_usd(
+        &self,
+        position: &Position,
+        token_price: &OraclePrice,
+        custody: &Custody,
+        collateral_token_price: &OraclePrice,
+        collateral_custody: &Custody,
+        curtime: i64,
+    ) -> Result<u64> {
+        let target_leverage = custody.pricing.liquidation_target_leverage as u128;
+        if target_leverage == 0 {
+            return Ok(position.size_usd);
+        }
+
+        let (profit_usd, loss_usd, _) = self.get_pnl_usd(
+            position,
+            token_price,
+            custody,
+            collateral_token_price,
+            collateral_custody,
+            curtime,
+            true,
+        )?;
+
+        // the liquidation fee is only charged on the liquidated size, so add back
+        // the fee for the whole position that is included in the loss
+        let liquidation_fee_usd =
+            Self::get_fee_amount(custody.fees.liquidation, position.size_usd)?;
+        let margin_usd = math::checked_add(
+            math::checked_add(position.collateral_usd, profit_usd)?,
+            liquidation_fee_usd,
+        )?;
+        if margin_usd <= loss_usd {
+            return Ok(position.size_usd);
+        }
+        let margin_usd = math::checked_sub(margin_usd, loss_usd)? as u128;
+
+        // (size - liquidation_size) / (margin - fee_rate * liquidation_size) = target_leverage
+        // where fee_rate covers both the liquidation fee and the liquidator reward
+        let size_usd = math::checked_mul(position.size_usd as u128, Perpetuals::BPS_POWER)?;
+        let target_size_usd = math::checked_mul(margin_usd, target_leverage)?;
+        let fee_rate = math::checked_mul(custody.fees.liquidation as u128, 2)?;
+        let fee_leverage = math::checked_mul(target_leverage, fee_rate)?;
+        let bps_power_squared = math::checked_mul(Perpetuals::BPS_POWER, Perpetuals::BPS_POWER)?;
+        if size_usd <= target_size_usd || bps_power_squared <= fee_leverage {
+            return Ok(position.size_usd);
+        }
+
+        let liquidation_size_usd = math::checked_as_u64(math::checked_ceil_div(
+            math::checked_mul(
+                math::checked_sub(size_usd, target_size_usd)?,
+                Perpetuals::BPS_POWER,
+            )?,
+            math::checked_sub(bps_power_squared, fee_leverage)?,
+        )?)?;
+
+        Ok(std::cmp::min(liquidation_size_usd, position.size_usd))
+    }
+
+    // ranks positions for auto-deleveraging by their profit ratio times leverage,
+    // returns zero for positions that are not in profit
+    #[allow(clippy::too_many_arguments)]
+    pub fn get_adl_score(
+        &self,
+        position: &Position,
+        token_price: &OraclePrice,
+        custody: &Custody,
+        collateral_token_price: &OraclePrice,
+        collateral_custody: &Custody,
+        curtime: i64,
+    ) -> Result<u64> {
+        let (profit_usd, _, _) = self.get_pnl_usd(
+            position,
+            token_price,
+            custody,
+            collateral_token_price,
+            collateral_custody,
+            curtime,
+            false,
+        )?;
+        if profit_usd == 0 || position.collateral_usd == 0 {
+            return Ok(0);
+        }
+
+        let leverage = self.get_leverage(
+            position,
+            token_price,
+            custody,
+            collateral_token_price,
+            collateral_custody,
+            curtime,
+        )?;
+
+        math::checked_as_u64(math::checked_div(
+            math::checked_mul(profit_usd as u128, leverage as u128)?,
+            position.collateral_usd as u128,
+        )?)
+    }
+
+    // returns the position size to close to bring the custody solvency back to the
+    // auto-deleverage threshold, given the amount paid out when closing the whole position
+    pub fn get_auto_deleverage_size_usd(
+        position: &Position,
+        transfer_amount: u64,
+        collateral_custody: &Custody,
+        threshold: u64,
+    ) -> Result<u64> {
+        // solvency = owned / locked, closing a share of the position releases that share
+        // of the locked funds and its collateral, and pays out that share of the transfer
+        let owned = math::checked_mul(
+            collateral_custody.assets.owned as u128,
+            Perpetuals::BPS_POWER,
+        )?;
+        let target =
+            math::checked_mul(collateral_custody.assets.locked as u128, threshold as u128)?;
+        if owned >= target {
+            return Ok(0);
+        }
+        let deficit = math::checked_sub(target, owned)?;
+
+        let released = math::checked_add(
+            math::checked_mul(position.locked_amount as u128, threshold as u128)?,
+            math::checked_mul(position.collateral_amount as u128, Perpetuals::BPS_POWER)?,
+        )?;
+        let paid = math::checked_mul(transfer_amount as u128, Perpetuals::BPS_POWER)?;
+        if released <= paid || math::checked_sub(released, paid)? <= deficit {
+            return Ok(position.size_usd);
+        }
+
+        let size_usd = math::checked_as_u64(math::checked_ceil_div(
+            math::checked_mul(position.size_usd as u128, deficit)?,
+            math::checked_sub(released, paid)?,
+        )?)?;
 
-        Ok(current_leverage <= custody.pricing.max_leverage
-            && (!initial
-                || (current_leverage >= custody.pricing.min_initial_leverage
-                    && current_leverage <= custody.pricing.max_initial_leverage)))
+        Ok(std::cmp::min(size_usd, position.size_usd))
     }
 
     pub fn get_liquidation_price(
         &self,
         position: &Position,
         custody: &Custody,
+        collateral_custody: &Custody,
         curtime: i64,
     ) -> Result<u64> {
-        // liq_price = pos_price +- (collateral + unreal_profit - unreal_loss - exit_fee - interest - size/max_leverage) * pos_price / size
+        // liq_price = pos_price +- (collateral + unreal_profit + funding_received - unreal_loss - exit_fee - interest - funding_paid - size/max_leverage) * pos_price / size
+        // where the sign is negative for longs and positive for shorts
 
         if position.size_usd == 0 || position.price == 0 {
             return Ok(0);
         }
 
         let exit_fee_usd = 0; // With PNL mode, in liquidation there will be no exit fees
-        let interest_usd = custody.get_interest_amount_usd(position, curtime)?;
+        let interest_usd = collateral_custody.get_interest_amount_usd(position, curtime)?;
+        let (funding_received_usd, funding_paid_usd) =
+            custody.get_funding_amount_usd(position, curtime)?;
         let unrealized_loss_usd = math::checked_add(
-            math::checked_add(exit_fee_usd, interest_usd)?,
+            math::checked_add(
+                exit_fee_usd,
+                math::checked_add(interest_usd, funding_paid_usd)?,
+            )?,
             position.unrealized_loss_usd,
         )?;
 
         let max_loss_usd = math::checked_as_u64(math::checked_div(
             math::checked_mul(position.size_usd as u128, Perpetuals::BPS_POWER)?,
-            custody.pricing.max_leverage as u128,
+            custody.pricing.get_max_leverage(position.size_usd) as u128,
         )?)?;
         let max_loss_usd = math::checked_add(max_loss_usd, unrealized_loss_usd)?;
 
-        let margin_usd =
-            math::checked_add(position.collateral_usd, position.unrealized_profit_usd)?;
+        let margin_usd = math::checked_add(
+            position.collateral_usd,
+            math::checked_add(position.unrealized_profit_usd, funding_received_usd)?,
+        )?;
 
         let max_price_diff = if max_loss_usd >= margin_usd {
             math::checked_sub(max_loss_usd, margin_usd)?
@@ -346,7 +773,11 @@ impl Pool {
             -(Perpetuals::PRICE_DECIMALS as i32),
         )?;
 
-        if max_loss_usd >= margin_usd {
+        // price moves against longs when it falls and against shorts when it rises
+        let price_rises_to_liquidation =
+            (max_loss_usd >= margin_usd) == (position.side == Side::Long);
+
+        if price_rises_to_liquidation {
             math::checked_add(position.price, max_price_diff)
         } else if position.price > max_price_diff {
             math::checked_sub(position.price, max_price_diff)
@@ -355,12 +786,15 @@ impl Pool {
         }
     }
 
-    // returns (profit_usd, loss_usd, fee_amount)
+    // returns (profit_usd, loss_usd, fee_amount), fee_amount is in collateral custody decimals
+    #[allow(clippy::too_many_arguments)]
     pub fn get_pnl_usd(
         &self,
         position: &Position,
         token_price: &OraclePrice,
         custody: &Custody,
+        collateral_token_price: &OraclePrice,
+        collateral_custody: &Custody,
         curtime: i64,
         liquidation: bool,
     ) -> Result<(u64, u64, u64)> {
@@ -368,25 +802,36 @@ impl Pool {
             return Ok((0, 0, 0));
         }
 
-        let exit_price = self.get_exit_price(token_price, custody)?;
+        let exit_price =
+            self.get_exit_price(token_price, custody, position.side, position.size_usd)?;
         let size = token_price.get_token_amount(position.size_usd, custody.decimals)?;
 
-        let max_profit_usd = if curtime <= position.open_time {
-            0
+        // Stablecoin collateral is locked at a fixed value equal to the borrowed size
+        let max_profit_usd = if position.has_stable_collateral() {
+            position.borrow_size_usd
         } else {
             token_price.get_asset_amount_usd(position.locked_amount, custody.decimals)?
         };
 
         let (profit_usd, loss_usd) =
-            get_pnl_before_fees_usd(position.size_usd, position.price, exit_price)?;
+            get_pnl_before_fees_usd(position.size_usd, position.price, exit_price, position.side)?;
 
-        // Add unrealized and interest costs to pnl
-        let interest_usd = custody.get_interest_amount_usd(position, curtime)?;
+        // Add unrealized, interest and funding costs to pnl,
+        // interest is charged by the collateral custody the locked funds are borrowed from
+        let interest_usd = collateral_custody.get_interest_amount_usd(position, curtime)?;
+        let (funding_received_usd, funding_paid_usd) =
+            custody.get_funding_amount_usd(position, curtime)?;
         let (profit_usd, loss_usd) = normalize_pnl(
-            math::checked_add(profit_usd, position.unrealized_profit_usd)?,
+            math::checked_add(
+                profit_usd,
+                math::checked_add(position.unrealized_profit_usd, funding_received_usd)?,
+            )?,
             math::checked_add(
                 loss_usd,
-                math::checked_add(position.unrealized_loss_usd, interest_usd)?,
+                math::checked_add(
+                    position.unrealized_loss_usd,
+                    math::checked_add(interest_usd, funding_paid_usd)?,
+                )?,
             )?,
         )?;
 
@@ -394,17 +839,24 @@ impl Pool {
             let exit_fee = self.get_liquidation_fee(size, custody)?;
             token_price.get_asset_amount_usd(exit_fee, custody.decimals)?
         } else {
-            get_exit_fee_usd(position.size_usd, profit_usd)?
+            get_exit_fee_usd(&custody.fees, position.size_usd, profit_usd)?
         };
 
         // Recalculate PNL now including exit fee
         let (profit_usd, loss_usd) =
             normalize_pnl(profit_usd, math::checked_add(loss_usd, exit_fee_usd)?)?;
 
-        Ok((
+        // Profit is capped and only paid out once vested
+        let profit_usd = custody.get_vested_profit_usd(
+            position,
             std::cmp::min(max_profit_usd, profit_usd),
+            curtime,
+        )?;
+
+        Ok((
+            profit_usd,
             loss_usd,
-            token_price.get_token_amount(exit_fee_usd, custody.decimals)?,
+            collateral_token_price.get_token_amount(exit_fee_usd, collateral_custody.decimals)?,
         ))
     }
 
@@ -428,72 +880,501 @@ impl Pool {
             let token_price =
                 OraclePrice::new_from_oracle(&accounts[oracle_idx], &custody.oracle, curtime)?;
 
-            let token_amount_usd =
-                token_price.get_asset_amount_usd(custody.assets.owned, custody.decimals)?;
+            pool_amount_usd =
+                self.add_custody_amount_usd(pool_amount_usd, &custody, &token_price, curtime)?;
+        }
+
+        Ok(pool_amount_usd)
+    }
 
-            pool_amount_usd = math::checked_add(pool_amount_usd, token_amount_usd as u128)?;
+    // adds the value of the custody to the running pool amount, split out of
+    // get_assets_under_management_usd for callers that already hold the custody prices
+    pub fn add_custody_amount_usd(
+        &self,
+        pool_amount_usd: u128,
+        custody: &Custody,
+        token_price: &OraclePrice,
+        curtime: i64,
+    ) -> Result<u128> {
+        let token_amount_usd =
+            token_price.get_asset_amount_usd(custody.assets.owned, custody.decimals)?;
 
-            if custody.pricing.use_unrealized_pnl_in_aum {
-                // compute aggregate unrealized pnl
-                let (long_profit, long_loss, _) = self.get_pnl_usd(
-                    &custody.get_collective_position()?,
-                    &token_price,
-                    &custody,
-                    curtime,
-                    false,
-                )?;
+        let mut pool_amount_usd = math::checked_add(pool_amount_usd, token_amount_usd as u128)?;
+
+        if custody.pricing.use_unrealized_pnl_in_aum {
+            // compute aggregate unrealized pnl
+            for side in [Side::Long, Side::Short] {
+                let (profit, loss) =
+                    self.get_collective_pnl_usd(custody, side, token_price, curtime)?;
                 // adjust pool amount by collective profit/loss
-                pool_amount_usd = math::checked_add(pool_amount_usd, long_loss as u128)?;
-                pool_amount_usd = pool_amount_usd.saturating_sub(long_profit as u128);
+                pool_amount_usd = math::checked_add(pool_amount_usd, loss as u128)?;
+                pool_amount_usd = pool_amount_usd.saturating_sub(profit as u128);
             }
+
+            // interest is owed to the custody the positions borrow from
+            pool_amount_usd = math::checked_add(
+                pool_amount_usd,
+                custody.get_borrow_interest_usd(curtime)? as u128,
+            )?;
         }
 
         Ok(pool_amount_usd)
     }
 
-    pub fn get_fee_amount(fee: u64, amount: u64) -> Result<u64> {
-        if fee == 0 || amount == 0 {
-            return Ok(0);
+    // returns (profit_usd, loss_usd) of all open positions of the custody on the given side,
+    // interest is excluded as it is accounted by the custodies the positions borrow from
+    pub fn get_collective_pnl_usd(
+        &self,
+        custody: &Custody,
+        side: Side,
+        token_price: &OraclePrice,
+        curtime: i64,
+    ) -> Result<(u64, u64)> {
+        let position = custody.get_collective_position(side)?;
+        if position.size_usd == 0 || position.price == 0 {
+            return Ok((0, 0));
         }
-        math::checked_as_u64(math::checked_ceil_div(
-            math::checked_mul(amount as u128, fee as u128)?,
-            Perpetuals::BPS_POWER,
-        )?)
+
+        let exit_price = self.get_exit_price(token_price, custody, side, position.size_usd)?;
+
+        // profit is capped by the custody tokens locked at the current price and
+        // the stablecoins locked at a fixed value equal to the borrowed size
+        let max_profit_usd = custody.get_position_stats_locked_usd(side, token_price)?;
+
+        let (profit_usd, loss_usd) =
+            get_pnl_before_fees_usd(position.size_usd, position.price, exit_price, side)?;
+
+        // Add unrealized and funding costs to pnl
+        let (funding_received_usd, funding_paid_usd) =
+            custody.get_funding_amount_usd(&position, curtime)?;
+        let (profit_usd, loss_usd) = normalize_pnl(
+            math::checked_add(
+                profit_usd,
+                math::checked_add(position.unrealized_profit_usd, funding_received_usd)?,
+            )?,
+            math::checked_add(
+                loss_usd,
+                math::checked_add(position.unrealized_loss_usd, funding_paid_usd)?,
+            )?,
+        )?;
+
+        let exit_fee_usd = get_exit_fee_usd(&custody.fees, position.size_usd, profit_usd)?;
+        let (profit_usd, loss_usd) =
+            normalize_pnl(profit_usd, math::checked_add(loss_usd, exit_fee_usd)?)?;
+
+        Ok((std::cmp::min(max_profit_usd, profit_usd), loss_usd))
     }
 
-    fn get_price(
+    // returns the equity and the margin requirement of a cross margin account, the accounts
+    // are expected as [position, custody, custody oracle, collateral custody, collateral
+    // custody oracle] for each account position followed by [custody, custody oracle] for
+    // each collateral, positions closed since they were opened are dropped from the account
+    pub fn get_margin_account_health<'info>(
         &self,
-        token_price: &OraclePrice,
-        side: Side, // Note: Long implies opening positions, short implies closing position
-        spread: u64,
-    ) -> Result<OraclePrice> {
-        if side == Side::Long {
-            Ok(OraclePrice {
-                price: math::checked_add(
-                    token_price.price,
-                    math::checked_decimal_ceil_mul(
-                        token_price.price,
-                        token_price.exponent,
-                        spread,
-                        -(Perpetuals::BPS_DECIMALS as i32),
-                        token_price.exponent,
-                    )?,
-                )?,
-                exponent: token_price.exponent,
-            })
-        } else {
-            let spread = math::checked_decimal_mul(
-                token_price.price,
-                token_price.exponent,
-                spread,
-                -(Perpetuals::BPS_DECIMALS as i32),
-                token_price.exponent,
+        margin_account: &mut MarginAccount,
+        margin_account_key: &Pubkey,
+        accounts: &'info [AccountInfo<'info>],
+        curtime: i64,
+        initial: bool,
+    ) -> Result<(u64, u64)> {
+        let positions = margin_account.get_positions().to_vec();
+        let collaterals = margin_account.get_collaterals().to_vec();
+        let collaterals_idx = positions.len() * 5;
+        if collaterals_idx + collaterals.len() * 2 > accounts.len() {
+            return Err(ProgramError::NotEnoughAccountKeys.into());
+        }
+
+        let mut equity_usd: u64 = 0;
+        let mut loss_usd: u64 = 0;
+        let mut requirement_usd: u64 = 0;
+        for (idx, key) in positions.iter().enumerate() {
+            let accounts = &accounts[idx * 5..idx * 5 + 5];
+            require_keys_eq!(accounts[0].key(), *key);
+            if Perpetuals::is_empty_account(&accounts[0])? {
+                margin_account.remove_position(key);
+                continue;
+            }
+            let position = Account::<Position>::try_from(&accounts[0])?;
+            if position.margin_account != *margin_account_key {
+                margin_account.remove_position(key);
+                continue;
+            }
+
+            require_keys_eq!(accounts[1].key(), position.custody);
+            let custody = Account::<Custody>::try_from(&accounts[1])?;
+            require_keys_eq!(accounts[2].key(), custody.oracle.oracle_account);
+            require_keys_eq!(accounts[3].key(), position.collateral_custody);
+            let collateral_custody = Account::<Custody>::try_from(&accounts[3])?;
+            require_keys_eq!(accounts[4].key(), collateral_custody.oracle.oracle_account);
+
+            let token_price = OraclePrice::new_from_oracle(&accounts[2], &custody.oracle, curtime)?;
+            let collateral_token_price =
+                OraclePrice::new_from_oracle(&accounts[4], &collateral_custody.oracle, curtime)?;
+
+            let (profit_usd, position_loss_usd, _) = self.get_pnl_usd(
+                &position,
+                &token_price,
+                &custody,
+                &collateral_token_price,
+                &collateral_custody,
+                curtime,
+                false,
             )?;
+            equity_usd = math::checked_add(
+                math::checked_add(equity_usd, position.collateral_usd)?,
+                profit_usd,
+            )?;
+            loss_usd = math::checked_add(loss_usd, position_loss_usd)?;
 
-            let price = if spread < token_price.price {
-                math::checked_sub(token_price.price, spread)?
+            let max_leverage = if initial {
+                custody.pricing.max_initial_leverage
             } else {
-                0
+                custody.pricing.get_max_leverage(position.size_usd)
+            };
+            requirement_usd = math::checked_add(
+                requirement_usd,
+                math::checked_as_u64(math::checked_ceil_div(
+                    math::checked_mul(position.size_usd as u128, Perpetuals::BPS_POWER)?,
+                    max_leverage as u128,
+                )?)?,
+            )?;
+        }
+
+        for (idx, collateral) in collaterals.iter().enumerate() {
+            let accounts = &accounts[collaterals_idx + idx * 2..collaterals_idx + idx * 2 + 2];
+            require_keys_eq!(accounts[0].key(), collateral.custody);
+            let custody = Account::<Custody>::try_from(&accounts[0])?;
+            require_keys_eq!(accounts[1].key(), custody.oracle.oracle_account);
+
+            let token_price = OraclePrice::new_from_oracle(&accounts[1], &custody.oracle, curtime)?;
+            equity_usd = math::checked_add(
+                equity_usd,
+                token_price.get_asset_amount_usd(collateral.amount, custody.decimals)?,
+            )?;
+        }
+
+        Ok((equity_usd.saturating_sub(loss_usd), requirement_usd))
+    }
+
+    // charges the loss of a cross position exceeding its collateral to the margin account,
+    // starting with collateral in the position collateral token, other collaterals are moved
+    // to the owned assets of their custodies and are expected as [custody, custody oracle]
+    // for each margin account collateral, returns the loss that could not be covered
+    #[allow(clippy::too_many_arguments)]
+    pub fn charge_margin_shortfall<'info>(
+        margin_account: &mut MarginAccount,
+        shortfall_usd: u64,
+        custody: &mut Account<Custody>,
+        token_price: &OraclePrice,
+        collateral_custody: &mut Account<Custody>,
+        collateral_token_price: &OraclePrice,
+        accounts: &'info [AccountInfo<'info>],
+        curtime: i64,
+    ) -> Result<u64> {
+        let collateral_custody_key = collateral_custody.key();
+        let mut shortfall_usd = Self::take_margin_collateral(
+            margin_account,
+            shortfall_usd,
+            &collateral_custody_key,
+            collateral_custody,
+            collateral_token_price,
+        )?;
+        if shortfall_usd == 0 {
+            return Ok(0);
+        }
+
+        let collaterals = margin_account.get_collaterals().to_vec();
+        if collaterals.len() * 2 > accounts.len() {
+            return Err(ProgramError::NotEnoughAccountKeys.into());
+        }
+        for (idx, collateral) in collaterals.iter().enumerate() {
+            if shortfall_usd == 0 {
+                break;
+            }
+            if collateral.custody == collateral_custody_key {
+                continue;
+            }
+            let accounts = &accounts[idx * 2..idx * 2 + 2];
+            require_keys_eq!(accounts[0].key(), collateral.custody);
+
+            // the position custody is updated by the caller, other custodies are saved here
+            if collateral.custody == custody.key() {
+                require_keys_eq!(accounts[1].key(), custody.oracle.oracle_account);
+                shortfall_usd = Self::take_margin_collateral(
+                    margin_account,
+                    shortfall_usd,
+                    &collateral.custody,
+                    custody,
+                    token_price,
+                )?;
+            } else {
+                let mut other_custody = Account::<Custody>::try_from(&accounts[0])?;
+                require_keys_eq!(accounts[1].key(), other_custody.oracle.oracle_account);
+                let other_token_price =
+                    OraclePrice::new_from_oracle(&accounts[1], &other_custody.oracle, curtime)?;
+                shortfall_usd = Self::take_margin_collateral(
+                    margin_account,
+                    shortfall_usd,
+                    &collateral.custody,
+                    &mut other_custody,
+                    &other_token_price,
+                )?;
+                other_custody.exit(&crate::ID)?;
+            }
+        }
+
+        Ok(shortfall_usd)
+    }
+
+    pub fn take_margin_collateral(
+        margin_account: &mut MarginAccount,
+        shortfall_usd: u64,
+        custody_key: &Pubkey,
+        custody: &mut Custody,
+        token_price: &OraclePrice,
+    ) -> Result<u64> {
+        let amount = token_price.get_token_amount(shortfall_usd, custody.decimals)?;
+        let taken_amount = margin_account.take_collateral(custody_key, amount)?;
+        if taken_amount == 0 {
+            return Ok(shortfall_usd);
+        }
+        msg!("Margin collateral charged: {}", taken_amount);
+
+        custody.assets.collateral = math::checked_sub(custody.assets.collateral, taken_amount)?;
+        custody.assets.owned = math::checked_add(custody.assets.owned, taken_amount)?;
+        if taken_amount == amount {
+            return Ok(0);
+        }
+        Ok(shortfall_usd
+            .saturating_sub(token_price.get_asset_amount_usd(taken_amount, custody.decimals)?))
+    }
+
+    /// Settles the closed part of a position with the pool, token transfers are left to
+    /// the caller. `amount_out` leaves the position collateral, `collateral_amount` of it
+    /// stays in the custody as collateral of the remaining position or the margin account.
+    /// Returns the protocol fee collected.
+    #[allow(clippy::too_many_arguments)]
+    pub fn settle_position<'info>(
+        &self,
+        position: &Account<Position>,
+        prev_position: &Position,
+        closed_position: &Position,
+        (amount_out, fee_amount, profit_usd, loss_usd): (u64, u64, u64, u64),
+        collateral_amount: u64,
+        liquidation: bool,
+        custody: &mut Account<Custody>,
+        token_price: &OraclePrice,
+        collateral_custody: &mut Account<Custody>,
+        collateral_token_price: &OraclePrice,
+        insurance_fund: &mut InsuranceFund,
+        margin_account: Option<&mut MarginAccount>,
+        margin_collateral_accounts: &'info [AccountInfo<'info>],
+        curtime: i64,
+    ) -> Result<u64> {
+        let full_close = closed_position.size_usd == prev_position.size_usd;
+
+        // unlock pool funds
+        collateral_custody.unlock_funds(closed_position.locked_amount)?;
+
+        // check pool constraints
+        msg!("Check pool constraints");
+        require!(
+            self.check_available_amount(amount_out, collateral_custody)?,
+            PerpetualsError::CustodyAmountLimit
+        );
+
+        // cross margin payouts stay in custody as margin account collateral
+        let mut margin_account = margin_account;
+        if let Some(margin_account) = margin_account.as_deref_mut() {
+            margin_account.add_collateral(&collateral_custody.key(), collateral_amount)?;
+            if full_close {
+                margin_account.remove_position(&position.key());
+            }
+        }
+
+        // update custody stats
+        msg!("Update custody stats");
+        if amount_out > closed_position.collateral_amount {
+            let amount_lost = amount_out.saturating_sub(closed_position.collateral_amount);
+            collateral_custody.assets.owned =
+                math::checked_sub(collateral_custody.assets.owned, amount_lost)?;
+        } else {
+            let amount_gained = closed_position.collateral_amount.saturating_sub(amount_out);
+            collateral_custody.assets.owned =
+                math::checked_add(collateral_custody.assets.owned, amount_gained)?;
+        }
+        collateral_custody.assets.collateral = math::checked_add(
+            math::checked_sub(
+                collateral_custody.assets.collateral,
+                closed_position.collateral_amount,
+            )?,
+            collateral_amount,
+        )?;
+
+        let protocol_fee = Self::get_fee_amount(custody.fees.protocol_share, fee_amount)?;
+
+        // Pay protocol_fee from custody if possible, otherwise no protocol_fee
+        if self.check_available_amount(protocol_fee, collateral_custody)? {
+            collateral_custody.assets.protocol_fees =
+                math::checked_add(collateral_custody.assets.protocol_fees, protocol_fee)?;
+
+            collateral_custody.assets.owned =
+                math::checked_sub(collateral_custody.assets.owned, protocol_fee)?;
+        }
+
+        let insurance_fee = Self::get_fee_amount(custody.fees.insurance_share, fee_amount)?;
+
+        // Pay insurance_fee from custody if possible, otherwise no insurance_fee
+        let collateral_token_id = self.get_token_id(&collateral_custody.key())?;
+        if self.check_available_amount(insurance_fee, collateral_custody)? {
+            insurance_fund.add_funds(collateral_token_id, insurance_fee)?;
+
+            collateral_custody.assets.owned =
+                math::checked_sub(collateral_custody.assets.owned, insurance_fee)?;
+        }
+
+        // cover the loss exceeding the closed collateral from the margin account collateral
+        // of cross positions, then from the insurance fund
+        let mut bad_debt_usd = loss_usd.saturating_sub(closed_position.collateral_usd);
+        if let Some(margin_account) = margin_account {
+            bad_debt_usd = Self::charge_margin_shortfall(
+                margin_account,
+                bad_debt_usd,
+                custody,
+                token_price,
+                collateral_custody,
+                collateral_token_price,
+                margin_collateral_accounts,
+                curtime,
+            )?;
+        }
+        let bad_debt_covered_usd = if bad_debt_usd > 0 {
+            let bad_debt = collateral_token_price
+                .get_token_amount(bad_debt_usd, collateral_custody.decimals)?;
+            let bad_debt_covered =
+                insurance_fund.cover_bad_debt(collateral_token_id, bad_debt, collateral_custody)?;
+            msg!("Bad debt: {}, covered: {}", bad_debt, bad_debt_covered);
+            collateral_token_price
+                .get_asset_amount_usd(bad_debt_covered, collateral_custody.decimals)?
+        } else {
+            0
+        };
+
+        // update borrow stats of the custody lending the locked funds
+        collateral_custody.remove_borrow(prev_position, curtime)?;
+        if !full_close {
+            collateral_custody.add_borrow(position, curtime)?;
+        }
+
+        // if custody and collateral_custody accounts are the same, ensure that data is in sync
+        if custody.key() == collateral_custody.key() {
+            custody.set_inner((**collateral_custody).clone());
+        }
+
+        let fee_amount_usd =
+            collateral_token_price.get_asset_amount_usd(fee_amount, collateral_custody.decimals)?;
+        if liquidation {
+            custody.collected_fees.liquidation_usd = custody
+                .collected_fees
+                .liquidation_usd
+                .wrapping_add(fee_amount_usd);
+
+            custody.volume_stats.liquidation_usd = custody
+                .volume_stats
+                .liquidation_usd
+                .wrapping_add(closed_position.size_usd);
+        } else {
+            custody.collected_fees.close_position_usd = custody
+                .collected_fees
+                .close_position_usd
+                .wrapping_add(fee_amount_usd);
+
+            custody.volume_stats.close_position_usd = custody
+                .volume_stats
+                .close_position_usd
+                .wrapping_add(closed_position.size_usd);
+        }
+
+        if closed_position.side == Side::Long {
+            custody.trade_stats.oi_long_usd = custody
+                .trade_stats
+                .oi_long_usd
+                .saturating_sub(closed_position.size_usd);
+        } else {
+            custody.trade_stats.oi_short_usd = custody
+                .trade_stats
+                .oi_short_usd
+                .saturating_sub(closed_position.size_usd);
+        }
+
+        custody.trade_stats.profit_usd = custody.trade_stats.profit_usd.wrapping_add(profit_usd);
+        custody.trade_stats.loss_usd = custody.trade_stats.loss_usd.wrapping_add(loss_usd);
+        custody.trade_stats.bad_debt_usd =
+            custody.trade_stats.bad_debt_usd.wrapping_add(bad_debt_usd);
+        custody.trade_stats.bad_debt_covered_usd = custody
+            .trade_stats
+            .bad_debt_covered_usd
+            .wrapping_add(bad_debt_covered_usd);
+
+        custody.remove_position(prev_position, curtime)?;
+        if !full_close {
+            custody.add_position(position, token_price, curtime)?;
+        }
+        custody.update_borrow_rate(curtime)?;
+        custody.update_funding_rate(curtime)?;
+
+        if custody.key() == collateral_custody.key() {
+            collateral_custody.set_inner((**custody).clone());
+        } else {
+            collateral_custody.update_borrow_rate(curtime)?;
+        }
+
+        Ok(protocol_fee)
+    }
+
+    pub fn get_fee_amount(fee: u64, amount: u64) -> Result<u64> {
+        if fee == 0 || amount == 0 {
+            return Ok(0);
+        }
+        math::checked_as_u64(math::checked_ceil_div(
+            math::checked_mul(amount as u128, fee as u128)?,
+            Perpetuals::BPS_POWER,
+        )?)
+    }
+
+    fn get_price(
+        &self,
+        token_price: &OraclePrice,
+        side: Side, // Note: Long adds the spread (opening long, closing short), Short subtracts it
+        spread: u64,
+    ) -> Result<OraclePrice> {
+        if side == Side::Long {
+            Ok(OraclePrice {
+                price: math::checked_add(
+                    token_price.price,
+                    math::checked_decimal_ceil_mul(
+                        token_price.price,
+                        token_price.exponent,
+                        spread,
+                        -(Perpetuals::BPS_DECIMALS as i32),
+                        token_price.exponent,
+                    )?,
+                )?,
+                exponent: token_price.exponent,
+            })
+        } else {
+            let spread = math::checked_decimal_mul(
+                token_price.price,
+                token_price.exponent,
+                spread,
+                -(Perpetuals::BPS_DECIMALS as i32),
+                token_price.exponent,
+            )?;
+
+            let price = if spread < token_price.price {
+                math::checked_sub(token_price.price, spread)?
+            } else {
+                0
             };
 
             Ok(OraclePrice {
@@ -503,16 +1384,95 @@ impl Pool {
         }
     }
 
+    // scales the base fee by how the change moves the custody share of the pool AUM
+    // relative to its target weight: moving away from the target costs up to twice the
+    // base fee, moving toward it earns a rebate of up to the whole base fee
     fn get_fee(
         &self,
-        _token_id: usize,
+        token_id: usize,
         base_fee: u64,
         amount_add: u64,
         amount_remove: u64,
-        _custody: &Custody,
-        _token_price: &OraclePrice,
+        custody: &Custody,
+        token_price: &OraclePrice,
     ) -> Result<u64> {
-        Self::get_fee_amount(base_fee, std::cmp::max(amount_add, amount_remove))
+        let amount = std::cmp::max(amount_add, amount_remove);
+        if base_fee == 0
+            || self.aum_usd == 0
+            || token_id >= self.target_weights.len()
+            || self.target_weights.iter().all(|&weight| weight == 0)
+        {
+            return Self::get_fee_amount(base_fee, amount);
+        }
+
+        let target_weight = self.target_weights[token_id] as u128;
+        let custody_usd =
+            token_price.get_asset_amount_usd(custody.assets.owned, custody.decimals)? as u128;
+        let amount_usd = token_price.get_asset_amount_usd(amount, custody.decimals)? as u128;
+
+        let current_weight = Self::get_weight(custody_usd, self.aum_usd)?;
+        let new_weight = if amount_add > 0 {
+            Self::get_weight(
+                math::checked_add(custody_usd, amount_usd)?,
+                math::checked_add(self.aum_usd, amount_usd)?,
+            )?
+        } else {
+            Self::get_weight(
+                custody_usd.saturating_sub(amount_usd),
+                self.aum_usd.saturating_sub(amount_usd),
+            )?
+        };
+
+        let current_diff = current_weight.abs_diff(target_weight);
+        let new_diff = new_weight.abs_diff(target_weight);
+        let fee_mult = if new_diff < current_diff {
+            Perpetuals::BPS_POWER - Self::get_weight_deviation(current_diff, target_weight)?
+        } else {
+            math::checked_add(
+                Perpetuals::BPS_POWER,
+                Self::get_weight_deviation(
+                    math::checked_add(current_diff, new_diff)? / 2,
+                    target_weight,
+                )?,
+            )?
+        };
+        let fee = math::checked_div(
+            math::checked_mul(base_fee as u128, fee_mult)?,
+            Perpetuals::BPS_POWER,
+        )?;
+
+        Self::get_fee_amount(
+            math::checked_as_u64(std::cmp::min(fee, Perpetuals::BPS_POWER))?,
+            amount,
+        )
+    }
+
+    // returns the share of the total in BPS
+    fn get_weight(amount_usd: u128, total_usd: u128) -> Result<u128> {
+        if total_usd == 0 {
+            return Ok(0);
+        }
+        math::checked_div(
+            math::checked_mul(amount_usd, Perpetuals::BPS_POWER)?,
+            total_usd,
+        )
+    }
+
+    // returns the weight difference relative to the target in BPS, capped at one
+    fn get_weight_deviation(diff: u128, target_weight: u128) -> Result<u128> {
+        if diff == 0 {
+            return Ok(0);
+        }
+        if target_weight == 0 {
+            return Ok(Perpetuals::BPS_POWER);
+        }
+        Ok(std::cmp::min(
+            math::checked_div(
+                math::checked_mul(diff, Perpetuals::BPS_POWER)?,
+                target_weight,
+            )?,
+            Perpetuals::BPS_POWER,
+        ))
     }
 }
 
@@ -521,7 +1481,9 @@ mod test {
     use {
         super::*,
         crate::state::{
-            custody::{BorrowRateParams, Fees, PricingParams},
+            custody::{
+                Assets, BorrowRateParams, DeprecatedCustody, DeprecatedFees, Fees, PricingParams,
+            },
             oracle::{OracleParams, OracleType},
             perpetuals::Permissions,
         },
@@ -541,16 +1503,25 @@ mod test {
             use_unrealized_pnl_in_aum: true,
             trade_spread_long: 100,
             trade_spread_short: 100,
+            swap_spread: 200,
+            max_price_impact: 0,
             min_initial_leverage: 10_000,
             max_initial_leverage: 100_000,
             max_leverage: 100_000,
+            leverage_tiers: Default::default(),
+            liquidation_target_leverage: 0,
             max_payoff_mult: 10_000,
             max_utilization: 0,
             max_position_locked_usd: 0,
             max_total_locked_usd: 0,
+            virtual_depth_usd: 0,
+            auto_deleverage_threshold: 0,
+            min_hold_time_sec: 0,
+            max_request_age_sec: 60,
         };
 
         let permissions = Permissions {
+            allow_swap: true,
             allow_add_liquidity: true,
             allow_remove_liquidity: true,
             allow_open_position: true,
@@ -558,16 +1529,22 @@ mod test {
             allow_pnl_withdrawal: true,
             allow_collateral_withdrawal: true,
             allow_size_change: true,
+            allow_direct_position: true,
         };
 
         let fees = Fees {
             utilization_mult: 20_000,
+            swap_in: 0,
+            swap_out: 0,
             add_liquidity: 0,
             remove_liquidity: 0,
             open_position: 100,
             close_position: 0,
+            close_position_profit_share: 100,
             liquidation: 50,
+            trigger_order: 10,
             protocol_share: 25,
+            insurance_share: 0,
         };
 
         let custody = Custody {
@@ -582,6 +1559,7 @@ mod test {
         };
 
         let position = Position {
+            side: Side::Long,
             price: scale(25_000, Perpetuals::PRICE_DECIMALS),
             // x4 leverage
             size_usd: scale(100_000, Perpetuals::USD_DECIMALS),
@@ -622,6 +1600,95 @@ mod test {
         .unwrap()
     }
 
+    #[test]
+    fn test_upgrade_pool_layout() {
+        let custodies = vec![Pubkey::new_unique(), Pubkey::new_unique()];
+        let deprecated_pool = DeprecatedPool {
+            name: "Test Pool".to_string(),
+            custodies: custodies.clone(),
+            aum_usd: 1_000,
+            bump: 1,
+            lp_token_bump: 2,
+            inception_time: 3,
+        };
+        let pool = Pool {
+            name: "Test Pool".to_string(),
+            custodies,
+            aum_usd: 1_000,
+            bump: 1,
+            lp_token_bump: 2,
+            inception_time: 3,
+            target_weights: vec![5_000, 5_000],
+            lp_cooldown_sec: 60,
+            lp_withdrawal_window_sec: 60,
+            lp_expired_cancel_fee: 0,
+        };
+
+        // new fields are appended, so the upgraded pool starts with the deprecated layout
+        let mut deprecated_data = Vec::new();
+        deprecated_pool.try_serialize(&mut deprecated_data).unwrap();
+        let mut data = Vec::new();
+        pool.try_serialize(&mut data).unwrap();
+        assert!(data[8..].starts_with(&deprecated_data[8..]));
+        assert!(pool.validate());
+
+        let pool = Pool {
+            lp_withdrawal_window_sec: Pool::MAX_LP_WITHDRAWAL_WINDOW_SEC + 1,
+            ..pool
+        };
+        assert!(!pool.validate());
+    }
+
+    #[test]
+    fn test_get_remove_liquidity_proportional_amounts() {
+        let (_pool, custody, _position, _token_price) = get_fixture();
+        let custodies = [
+            Custody {
+                decimals: 9,
+                assets: Assets {
+                    owned: 3_000_000_000,
+                    locked: 2_000_000_000,
+                    ..Assets::default()
+                },
+                ..custody
+            },
+            Custody {
+                decimals: 6,
+                assets: Assets {
+                    owned: 10_000_000,
+                    ..Assets::default()
+                },
+                ..custody
+            },
+        ];
+        let token_prices = [OraclePrice::new(2_000, -3), OraclePrice::new(1_000, -3)];
+
+        // $6 + $10 owned, a quarter of the value is paid out of each custody
+        assert_eq!(
+            Pool::get_remove_liquidity_proportional_amounts(
+                4_000_000_000,
+                &custodies,
+                &token_prices
+            )
+            .unwrap(),
+            vec![750_000_000, 2_500_000]
+        );
+
+        // the first custody can't pay its share out of unlocked funds
+        assert!(Pool::get_remove_liquidity_proportional_amounts(
+            6_000_000_000,
+            &custodies,
+            &token_prices
+        )
+        .is_err());
+        assert!(Pool::get_remove_liquidity_proportional_amounts(
+            17_000_000_000,
+            &custodies,
+            &token_prices
+        )
+        .is_err());
+    }
+
     #[test]
     fn test_get_price() {
         let (pool, custody, _position, token_price) = get_fixture();
@@ -649,6 +1716,63 @@ mod test {
         );
     }
 
+    #[test_case(     0,      0, Side::Long,   10_000, false, 200; "Open long on empty book")]
+    #[test_case(     0, 30_000, Side::Long,   10_000, false, 100; "Open long reducing skew")]
+    #[test_case(10_000, 30_000, Side::Short,  10_000, false, 160; "Open short increasing skew")]
+    #[test_case(     0, 30_000, Side::Short,  10_000, true,  100; "Close short")]
+    #[test_case(50_000, 10_000, Side::Long,   10_000, true,  100; "Close long reducing skew")]
+    #[test_case(     0,      0, Side::Long,  100_000, false, 500; "Impact is capped")]
+    fn test_get_price_impact(
+        oi_long: u64,
+        oi_short: u64,
+        side: Side,
+        size: u64,
+        closing: bool,
+        expected: u64,
+    ) {
+        let (pool, mut custody, _position, _token_price) = get_fixture();
+        custody.pricing.virtual_depth_usd = scale(1_000_000, Perpetuals::USD_DECIMALS);
+        custody.pricing.max_price_impact = 500;
+        custody.trade_stats.oi_long_usd = scale(oi_long, Perpetuals::USD_DECIMALS);
+        custody.trade_stats.oi_short_usd = scale(oi_short, Perpetuals::USD_DECIMALS);
+
+        let size_usd = scale(size, Perpetuals::USD_DECIMALS);
+        assert_eq!(
+            pool.get_price_impact(&custody, side, size_usd, closing)
+                .unwrap(),
+            expected
+        );
+
+        custody.pricing.virtual_depth_usd = 0;
+        assert_eq!(
+            pool.get_price_impact(&custody, side, size_usd, closing)
+                .unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_get_entry_price_with_price_impact() {
+        let (pool, mut custody, _position, token_price) = get_fixture();
+        custody.pricing.virtual_depth_usd = scale(1_000_000, Perpetuals::USD_DECIMALS);
+        custody.pricing.max_price_impact = 500;
+
+        // 1% trade spread and 2% price impact
+        let size_usd = scale(10_000, Perpetuals::USD_DECIMALS);
+        assert_eq!(
+            pool.get_entry_price(&token_price, &custody, Side::Long, size_usd)
+                .unwrap(),
+            scale(25_750, Perpetuals::PRICE_DECIMALS)
+        );
+
+        // closing leaves no skew, only the size impact applies
+        assert_eq!(
+            pool.get_exit_price(&token_price, &custody, Side::Short, size_usd)
+                .unwrap(),
+            scale(25_500, Perpetuals::PRICE_DECIMALS)
+        );
+    }
+
     #[test_case(20_000,   500_000_000,       0,     0; "case A0")]
     #[test_case(20_000,   500_000_000, 100_000, 1_000; "case A1")]
     #[test_case(20_000,   500_000_000, 150_000, 3_000; "case A2")]
@@ -682,7 +1806,7 @@ mod test {
             pool.get_entry_fee(
                 custody.fees.open_position,
                 size,
-                custody.get_locked_amount(size).unwrap(),
+                custody.get_locked_amount(size, Side::Long).unwrap(),
                 &custody
             )
             .unwrap(),
@@ -690,6 +1814,22 @@ mod test {
         );
     }
 
+    #[test_case(20_000, 100_000, 3_000; "case A1")]
+    #[test_case(20_000, 300_000, 9_000; "case A4")]
+    #[test_case(10_000, 150_000, 3_000; "case B2")]
+    #[test_case( 5_000, 200_000, 3_000; "case C3")]
+    fn test_get_max_entry_fee(utilization_mul: u64, size: u64, expected: u64) {
+        let (pool, mut custody, _position, _token_price) = get_fixture();
+
+        custody.fees.utilization_mult = utilization_mul;
+
+        assert_eq!(
+            pool.get_max_entry_fee(custody.fees.open_position, size, &custody)
+                .unwrap(),
+            expected,
+        );
+    }
+
     #[test]
     fn test_get_fee() {
         let (pool, custody, _position, token_price) = get_fixture();
@@ -708,6 +1848,76 @@ mod test {
         );
     }
 
+    #[test_case(10, 0, 6_000, 0.083; "Add toward target")]
+    #[test_case(10, 0, 4_000, 0.127; "Add away from target")]
+    #[test_case(10, 0, 5_000, 0.102; "Add at target")]
+    #[test_case(0, 10, 4_000, 0.075; "Remove toward target")]
+    #[test_case(0, 10, 6_000, 0.118; "Remove away from target")]
+    #[test_case(10, 0, 0, 0.2; "Add to zero target")]
+    fn test_get_fee_with_target_weights(
+        amount_add: u64,
+        amount_remove: u64,
+        target_weight: u64,
+        expected: f64,
+    ) {
+        let (mut pool, mut custody, _position, token_price) = get_fixture();
+        // custody holds half of the pool
+        custody.assets.owned = scale(100, custody.decimals);
+        pool.aum_usd = scale(5_000_000, Perpetuals::USD_DECIMALS) as u128;
+        pool.target_weights = vec![target_weight, 10_000 - target_weight];
+
+        assert_eq!(
+            pool.get_fee(
+                0,
+                100,
+                scale(amount_add, custody.decimals),
+                scale(amount_remove, custody.decimals),
+                &custody,
+                &token_price
+            )
+            .unwrap(),
+            scale_f64(expected, custody.decimals),
+        );
+    }
+
+    #[test]
+    fn test_get_swap_amount() {
+        let (pool, custody, _position, token_price) = get_fixture();
+        let mut stable_custody = custody.clone();
+        stable_custody.decimals = 6;
+        stable_custody.pricing.swap_spread = 0;
+        let stable_price = OraclePrice {
+            price: 1_000,
+            exponent: -3,
+        };
+
+        // 1 token at 25_000 with a 2% swap spread
+        assert_eq!(
+            pool.get_swap_amount(
+                &token_price,
+                &stable_price,
+                &custody,
+                &stable_custody,
+                scale(1, custody.decimals)
+            )
+            .unwrap(),
+            scale(24_500, stable_custody.decimals)
+        );
+
+        // 25_000 stable tokens bought back at 25_500
+        assert_eq!(
+            pool.get_swap_amount(
+                &stable_price,
+                &token_price,
+                &stable_custody,
+                &custody,
+                scale(25_500, stable_custody.decimals)
+            )
+            .unwrap(),
+            scale(1, custody.decimals)
+        );
+    }
+
     #[test_case(0, 0, (0,0); "No Profit or loss")]
     #[test_case(100, 0, (100,0); "Only Profit")]
     #[test_case(0, 100, (0,100); "Only Loss")]
@@ -717,15 +1927,25 @@ mod test {
         assert_eq!(normalize_pnl(profit, loss).unwrap(), expected)
     }
 
-    #[test_case(100, 1000, 1000, (0,0); "Prices are the same")]
-    #[test_case(100, 1000, 1100, (10,0); "In profit")]
-    #[test_case(100, 1000, 900, (0,10); "In loss")]
-    fn test_get_pnl_before_fees_usd(size_usd: u64, entry: u64, exit: u64, expected: (u64, u64)) {
+    #[test_case(100, 1000, 1000, Side::Long, (0,0); "Prices are the same")]
+    #[test_case(100, 1000, 1100, Side::Long, (10,0); "In profit")]
+    #[test_case(100, 1000, 900, Side::Long, (0,10); "In loss")]
+    #[test_case(100, 1000, 1000, Side::Short, (0,0); "Short prices are the same")]
+    #[test_case(100, 1000, 900, Side::Short, (10,0); "Short in profit")]
+    #[test_case(100, 1000, 1100, Side::Short, (0,10); "Short in loss")]
+    fn test_get_pnl_before_fees_usd(
+        size_usd: u64,
+        entry: u64,
+        exit: u64,
+        side: Side,
+        expected: (u64, u64),
+    ) {
         assert_eq!(
             get_pnl_before_fees_usd(
                 scale(size_usd, Perpetuals::USD_DECIMALS),
                 scale(entry, Perpetuals::PRICE_DECIMALS),
                 scale(exit, Perpetuals::PRICE_DECIMALS),
+                side,
             )
             .unwrap(),
             (
@@ -735,19 +1955,64 @@ mod test {
         )
     }
 
-    #[test_case(100_000_000_000, 0, 0; "Break even")]
-    #[test_case(100_000_000_000, 0, 0; "At a loss")]
-    #[test_case(100_000_000_000, 1_000_000, 10_000; "In profit")]
-    #[test_case(100_000_000_000, 90, 1; "Minor profit is rounded up")]
-    fn test_get_exit_fee_for_pnl_mode_usd(size_usd: u64, profit_usd: u64, expected: u64) {
-        assert_eq!(get_exit_fee_usd(size_usd, profit_usd).unwrap(), expected)
+    #[test_case(0, 100, 100_000_000_000, 0, 0; "Break even")]
+    #[test_case(0, 100, 100_000_000_000, 1_000_000, 10_000; "In profit")]
+    #[test_case(0, 100, 100_000_000_000, 90, 1; "Minor profit is rounded up")]
+    #[test_case(10, 0, 100_000_000_000, 1_000_000, 100_000_000; "Size based fee")]
+    #[test_case(10, 100, 100_000_000_000, 1_000_000, 100_010_000; "Size based fee and profit share")]
+    #[test_case(0, 0, 100_000_000_000, 1_000_000, 0; "No exit fee")]
+    fn test_get_exit_fee_usd(
+        close_position: u64,
+        close_position_profit_share: u64,
+        size_usd: u64,
+        profit_usd: u64,
+        expected: u64,
+    ) {
+        let fees = Fees {
+            close_position,
+            close_position_profit_share,
+            ..Fees::default()
+        };
+        assert_eq!(
+            get_exit_fee_usd(&fees, size_usd, profit_usd).unwrap(),
+            expected
+        )
     }
 
-    #[test_case(25_000, 0.0, 1000.0, 0.0; "Initial pnl at a loss")]
-    #[test_case(25_400, 0.0, 2_559.055118111, 0.0; "Losing long position, opening price higher than current")]
-    #[test_case(24_500, 1010.204081632, 0.0, 0.000408163; "Winning long position opening price lower than current")]
+    #[test_case(100_000_000_000, 0)]
+    #[test_case(100_000_000_000, 90)]
+    #[test_case(100_000_000_000, 1_000_000)]
+    fn test_upgrade_custody_exit_fee(size_usd: u64, profit_usd: u64) {
+        let deprecated_custody = DeprecatedCustody {
+            fees: DeprecatedFees {
+                close_position: 50,
+                ..DeprecatedFees::default()
+            },
+            ..DeprecatedCustody::default()
+        };
+        let custody = Custody::from(deprecated_custody);
+
+        // initial release exit fee, 1% of the profit rounded up
+        let deprecated_exit_fee_usd = math::checked_ceil_div(
+            math::checked_mul(profit_usd, 100).unwrap(),
+            Perpetuals::BPS_POWER as u64,
+        )
+        .unwrap();
+        assert_eq!(
+            get_exit_fee_usd(&custody.fees, size_usd, profit_usd).unwrap(),
+            deprecated_exit_fee_usd
+        );
+    }
+
+    #[test_case(25_000, Side::Long, 0.0, 1000.0, 0.0; "Initial pnl at a loss")]
+    #[test_case(25_400, Side::Long, 0.0, 2_559.055118111, 0.0; "Losing long position, opening price higher than current")]
+    #[test_case(24_500, Side::Long, 1010.204081632, 0.0, 0.000408163; "Winning long position opening price lower than current")]
+    #[test_case(25_000, Side::Short, 0.0, 1000.0, 0.0; "Initial short pnl at a loss")]
+    #[test_case(25_400, Side::Short, 584.645669290, 0.0, 0.000236220; "Winning short position, opening price higher than current")]
+    #[test_case(24_500, Side::Short, 0.0, 3_061.224489796, 0.0; "Losing short position, opening price lower than current")]
     fn test_get_pnl_usd(
         price: u64,
+        side: Side,
         expected_profit_usd: f64,
         expected_loss_usd: f64,
         expected_fees_token: f64,
@@ -756,9 +2021,18 @@ mod test {
 
         // initial PnL at loss
         position.price = scale(price, Perpetuals::PRICE_DECIMALS);
+        position.side = side;
         assert_eq!(
-            pool.get_pnl_usd(&position, &token_price, &custody, 1, false)
-                .unwrap(),
+            pool.get_pnl_usd(
+                &position,
+                &token_price,
+                &custody,
+                &token_price,
+                &custody,
+                1,
+                false
+            )
+            .unwrap(),
             (
                 scale_f64(expected_profit_usd, Perpetuals::USD_DECIMALS),
                 scale_f64(expected_loss_usd, Perpetuals::USD_DECIMALS),
@@ -767,47 +2041,229 @@ mod test {
         );
     }
 
-    #[test_case(25_000, 4.1666; "Default leverage")]
-    #[test_case(20_000, 2.0613; "Lower price should lower leverage for long position 1")]
-    #[test_case(15_000, 1.1191; "Lower price should lower leverage for long position 2")]
-    #[test_case(27_000, 6.0000; "Higher price should increase leverage for long position 1")]
-    #[test_case(32_000, 42.6666; "Higher price should increase leverage for long position 2")]
-    #[test_case(0, 4.000; "No Price should return raw leverage")]
-    #[test_case(40_000, f64::MAX; "Leverage out of limit")]
-    fn test_get_leverage(price: u64, expected_leverage: f64) {
+    #[test]
+    fn test_get_pnl_usd_stable_collateral() {
+        let (pool, custody, mut position, token_price) = get_fixture();
+
+        // price doubled since opening, with half of the position size locked
+        position.price = scale(12_500, Perpetuals::PRICE_DECIMALS);
+        position.borrow_size_usd = scale(50_000, Perpetuals::USD_DECIMALS);
+
+        let get_profit_usd = |position: &Position| {
+            pool.get_pnl_usd(
+                position,
+                &token_price,
+                &custody,
+                &token_price,
+                &custody,
+                1,
+                false,
+            )
+            .unwrap()
+            .0
+        };
+
+        // position token collateral is capped by the value of the locked tokens
+        assert!(position.borrow_size_usd < get_profit_usd(&position));
+
+        // stablecoin collateral is capped by the borrowed size
+        position.collateral_custody = Pubkey::new_unique();
+        assert!(position.has_stable_collateral());
+        assert_eq!(get_profit_usd(&position), position.borrow_size_usd);
+    }
+
+    #[test_case(25_000, Side::Long, 4.1666; "Default leverage")]
+    #[test_case(20_000, Side::Long, 2.0613; "Lower price should lower leverage for long position 1")]
+    #[test_case(15_000, Side::Long, 1.1191; "Lower price should lower leverage for long position 2")]
+    #[test_case(27_000, Side::Long, 6.0000; "Higher price should increase leverage for long position 1")]
+    #[test_case(32_000, Side::Long, 42.6666; "Higher price should increase leverage for long position 2")]
+    #[test_case(0, Side::Long, 4.000; "No Price should return raw leverage")]
+    #[test_case(40_000, Side::Long, f64::MAX; "Leverage out of limit")]
+    #[test_case(25_000, Side::Short, 4.1666; "Default short leverage")]
+    #[test_case(27_000, Side::Short, 3.1830; "Higher price should lower leverage for short position")]
+    #[test_case(24_000, Side::Short, 5.0526; "Lower price should increase leverage for short position")]
+    #[test_case(20_000, Side::Short, f64::MAX; "Short leverage out of limit")]
+    fn test_get_leverage(price: u64, side: Side, expected_leverage: f64) {
         let (pool, custody, mut position, token_price) = get_fixture();
 
         position.price = scale(price, Perpetuals::PRICE_DECIMALS);
+        position.side = side;
         assert_eq!(
-            pool.get_leverage(&position, &token_price, &custody, 1)
+            pool.get_leverage(&position, &token_price, &custody, &token_price, &custody, 1)
                 .unwrap(),
             scale_f64(expected_leverage, Perpetuals::BPS_DECIMALS),
         );
     }
 
-    #[test_case(25_000, 21_250; "At opening")]
-    #[test_case(24_500, 20_825; "Low price should lower liquidation price")]
-    #[test_case(20_000, 17_000; "Even lower price should lower liquidation price")]
-    #[test_case(26_000, 22_100; "High price should increase liquidation price")]
-    #[test_case(35_000, 29_750; "Even higher price should increase liquidation price")]
-    #[test_case(0, 0; "Dead price")]
-    fn test_get_liquidation_price(price: u64, expected: u64) {
+    #[test_case(25_000, Side::Long, 21_250; "At opening")]
+    #[test_case(24_500, Side::Long, 20_825; "Low price should lower liquidation price")]
+    #[test_case(20_000, Side::Long, 17_000; "Even lower price should lower liquidation price")]
+    #[test_case(26_000, Side::Long, 22_100; "High price should increase liquidation price")]
+    #[test_case(35_000, Side::Long, 29_750; "Even higher price should increase liquidation price")]
+    #[test_case(0, Side::Long, 0; "Dead price")]
+    #[test_case(25_000, Side::Short, 28_750; "Short at opening")]
+    #[test_case(20_000, Side::Short, 23_000; "Low price should lower short liquidation price")]
+    #[test_case(30_000, Side::Short, 34_500; "High price should increase short liquidation price")]
+    fn test_get_liquidation_price(price: u64, side: Side, expected: u64) {
         let (pool, custody, mut position, _) = get_fixture();
 
         position.price = scale(price, Perpetuals::PRICE_DECIMALS);
+        position.side = side;
         assert_eq!(
-            pool.get_liquidation_price(&position, &custody, 1).unwrap(),
+            pool.get_liquidation_price(&position, &custody, &custody, 1)
+                .unwrap(),
             scale(expected, Perpetuals::PRICE_DECIMALS),
         );
     }
 
+    #[test]
+    fn test_get_liquidation_size_usd() {
+        let (pool, mut custody, mut position, token_price) = get_fixture();
+
+        // x42 leverage, above max_leverage
+        position.price = scale(32_000, Perpetuals::PRICE_DECIMALS);
+        let get_liquidation_size_usd = |position: &Position, custody: &Custody| {
+            pool.get_liquidation_size_usd(position, &token_price, custody, &token_price, custody, 1)
+                .unwrap()
+        };
+
+        // partial liquidations disabled
+        assert_eq!(
+            get_liquidation_size_usd(&position, &custody),
+            position.size_usd
+        );
+
+        // (100_000 - 5 * 2_343.75) / (1 - 5 * 2 * 0.005)
+        custody.pricing.liquidation_target_leverage = 50_000;
+        assert_eq!(
+            get_liquidation_size_usd(&position, &custody),
+            scale_f64(92_927.631578948, Perpetuals::USD_DECIMALS)
+        );
+
+        // margin is exhausted
+        position.price = scale(40_000, Perpetuals::PRICE_DECIMALS);
+        assert_eq!(
+            get_liquidation_size_usd(&position, &custody),
+            position.size_usd
+        );
+    }
+
+    #[test]
+    fn test_get_adl_score() {
+        let (pool, custody, mut position, token_price) = get_fixture();
+
+        let get_adl_score = |position: &Position| {
+            pool.get_adl_score(position, &token_price, &custody, &token_price, &custody, 1)
+                .unwrap()
+        };
+
+        // positions at a loss are never deleveraged
+        assert_eq!(get_adl_score(&position), 0);
+
+        // profitable positions are ranked by profit and leverage
+        position.price = scale(20_000, Perpetuals::PRICE_DECIMALS);
+        let score = get_adl_score(&position);
+        assert!(score > 0);
+
+        position.collateral_usd /= 2;
+        assert!(get_adl_score(&position) > score);
+
+        position.price = scale(15_000, Perpetuals::PRICE_DECIMALS);
+        assert!(get_adl_score(&position) > score);
+    }
+
+    #[test]
+    fn test_get_auto_deleverage_size_usd() {
+        let (_pool, mut custody, position, _token_price) = get_fixture();
+        let transfer_amount = scale(3, 9);
+        custody.assets.locked = scale(10, 9);
+
+        // custody is solvent
+        custody.assets.owned = scale(20, 9);
+        assert_eq!(
+            Pool::get_auto_deleverage_size_usd(&position, transfer_amount, &custody, 15_000)
+                .unwrap(),
+            0
+        );
+
+        // closing the whole position brings solvency from 1.4 to (14 - 2) / 6 = 2,
+        // closing a quarter of it brings solvency to (14 - 0.5) / 9 = 1.5
+        custody.assets.owned = scale(14, 9);
+        assert_eq!(
+            Pool::get_auto_deleverage_size_usd(&position, transfer_amount, &custody, 15_000)
+                .unwrap(),
+            position.size_usd / 4
+        );
+
+        // the whole position is closed if that is not enough to restore solvency
+        assert_eq!(
+            Pool::get_auto_deleverage_size_usd(&position, transfer_amount, &custody, 25_000)
+                .unwrap(),
+            position.size_usd
+        );
+    }
+
+    #[test]
+    fn test_take_margin_collateral() {
+        let (_pool, mut custody, _position, token_price) = get_fixture();
+        let custody_key = Pubkey::new_unique();
+        let mut margin_account = MarginAccount::default();
+        margin_account
+            .add_collateral(&custody_key, scale(2, 9))
+            .unwrap();
+        custody.assets.collateral = scale(2, 9);
+
+        // the loss is covered with 1.2 tokens of margin collateral
+        assert_eq!(
+            Pool::take_margin_collateral(
+                &mut margin_account,
+                scale(30_000, Perpetuals::USD_DECIMALS),
+                &custody_key,
+                &mut custody,
+                &token_price,
+            )
+            .unwrap(),
+            0
+        );
+        assert_eq!(
+            margin_account.get_collateral_amount(&custody_key),
+            scale_f64(0.8, 9)
+        );
+        assert_eq!(custody.assets.collateral, scale_f64(0.8, 9));
+        assert_eq!(custody.assets.owned, scale_f64(1.2, 9));
+
+        // the remaining collateral only covers part of the loss
+        assert_eq!(
+            Pool::take_margin_collateral(
+                &mut margin_account,
+                scale(30_000, Perpetuals::USD_DECIMALS),
+                &custody_key,
+                &mut custody,
+                &token_price,
+            )
+            .unwrap(),
+            scale(10_000, Perpetuals::USD_DECIMALS)
+        );
+        assert_eq!(margin_account.get_collateral_amount(&custody_key), 0);
+        assert_eq!(custody.assets.collateral, 0);
+        assert_eq!(custody.assets.owned, scale(2, 9));
+    }
+
     #[test]
     fn test_get_close_amount() {
         let (pool, custody, position, token_price) = get_fixture();
 
         assert_eq!(
-            pool.get_close_amount(&position, &token_price, &custody, 1, false)
-                .unwrap(),
+            pool.get_close_amount(
+                &position,
+                &token_price,
+                &custody,
+                &token_price,
+                &custody,
+                1,
+                false
+            )
+            .unwrap(),
             (
                 scale_f64(0.960000000, custody.decimals),
                 0,
@@ -826,6 +2282,7 @@ mod test {
             slope1: 80_000,
             slope2: 120_000,
             optimal_utilization: 800_000_000,
+            max_funding_rate: 0,
         };
         custody.assets.locked = scale(9, 9);
         custody.assets.owned = scale(10, 9);
@@ -1,6 +1,7 @@
 use {
-    crate::{math, state::perpetuals::Perpetuals},
+    crate::{error::PerpetualsError, math, state::perpetuals::Perpetuals},
     anchor_lang::prelude::*,
+    anchor_spl::token::TokenAccount,
 };
 
 #[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Debug)]
@@ -35,18 +36,32 @@ pub struct Position {
     pub owner: Pubkey,
     pub pool: Pubkey,
     pub custody: Pubkey,
+    pub collateral_custody: Pubkey,
 
     pub open_time: i64,
     pub update_time: i64,
+    // profit vesting start, blended with the time of each size increase
+    pub vesting_start_time: i64,
+    pub side: Side,
+    pub index: u64,
     pub price: u64,
     pub size_usd: u64,
     pub borrow_size_usd: u64,
     pub collateral_usd: u64,
-    pub unrealized_profit_usd: u64, // I believe this is always 0
-    pub unrealized_loss_usd: u64,   // I believe this is always 0
-    pub cumulative_interest_snapshot: u128, // I believe this is never updated as position cannot be modified
+    // interest and funding settled when the position is increased, paid out on close
+    pub unrealized_profit_usd: u64,
+    pub unrealized_loss_usd: u64,
+    // reset when the position is increased, after settling the accrued interest
+    pub cumulative_interest_snapshot: u128,
+    pub cumulative_funding_snapshot: i128,
     pub locked_amount: u64,
     pub collateral_amount: u64,
+    // mint of the 1-of-1 token representing the position, default if not tokenized
+    pub token_mint: Pubkey,
+    // cross margin account backing the position, default for isolated margin
+    pub margin_account: Pubkey,
+    // trigger orders placed since the position was opened and not yet executed or cancelled
+    pub trigger_orders: u16,
 
     pub bump: u8,
 }
@@ -60,4 +75,150 @@ impl Position {
             self.collateral_usd as u128,
         )?)
     }
+
+    pub fn is_tokenized(&self) -> bool {
+        self.token_mint != Pubkey::default()
+    }
+
+    pub fn is_cross_margin(&self) -> bool {
+        self.margin_account != Pubkey::default()
+    }
+
+    // returns the effective owner of the position, which is the holder of the
+    // position token if the position has been tokenized
+    pub fn get_holder(
+        &self,
+        position_token_account: Option<&Account<TokenAccount>>,
+    ) -> Result<Pubkey> {
+        if !self.is_tokenized() {
+            return Ok(self.owner);
+        }
+        match position_token_account {
+            Some(token_account)
+                if token_account.mint == self.token_mint && token_account.amount == 1 =>
+            {
+                Ok(token_account.owner)
+            }
+            _ => err!(PerpetualsError::InvalidPositionTokenAccount),
+        }
+    }
+
+    // shorts, and longs collateralized with a stablecoin, lock stablecoins in the
+    // collateral custody rather than the position token in the trading custody
+    pub fn has_stable_collateral(&self) -> bool {
+        self.side == Side::Short || self.custody != self.collateral_custody
+    }
+
+    // returns the share of the position that corresponds to the given size
+    pub fn get_partial_position(&self, size_usd: u64) -> Result<Position> {
+        let get_share = |amount: u64| -> Result<u64> {
+            math::checked_as_u64(math::checked_div(
+                math::checked_mul(amount as u128, size_usd as u128)?,
+                self.size_usd as u128,
+            )?)
+        };
+
+        Ok(Position {
+            size_usd,
+            borrow_size_usd: get_share(self.borrow_size_usd)?,
+            collateral_usd: get_share(self.collateral_usd)?,
+            unrealized_profit_usd: get_share(self.unrealized_profit_usd)?,
+            unrealized_loss_usd: get_share(self.unrealized_loss_usd)?,
+            locked_amount: get_share(self.locked_amount)?,
+            collateral_amount: get_share(self.collateral_amount)?,
+            ..self.clone()
+        })
+    }
+}
+
+// layout of the initial release, used to upgrade deployed position accounts
+#[account]
+#[derive(Default, Debug)]
+pub struct DeprecatedPosition {
+    pub owner: Pubkey,
+    pub pool: Pubkey,
+    pub custody: Pubkey,
+
+    pub open_time: i64,
+    pub update_time: i64,
+    pub price: u64,
+    pub size_usd: u64,
+    pub borrow_size_usd: u64,
+    pub collateral_usd: u64,
+    pub unrealized_profit_usd: u64,
+    pub unrealized_loss_usd: u64,
+    pub cumulative_interest_snapshot: u128,
+    pub locked_amount: u64,
+    pub collateral_amount: u64,
+
+    pub bump: u8,
+}
+
+impl DeprecatedPosition {
+    pub const LEN: usize = 8 + std::mem::size_of::<DeprecatedPosition>();
+}
+
+impl From<DeprecatedPosition> for Position {
+    // positions of the initial release are longs collateralized with the position token,
+    // the funding snapshot starts at zero like the cumulative funding of upgraded custodies
+    fn from(position: DeprecatedPosition) -> Self {
+        Self {
+            owner: position.owner,
+            pool: position.pool,
+            custody: position.custody,
+            collateral_custody: position.custody,
+            open_time: position.open_time,
+            update_time: position.update_time,
+            vesting_start_time: position.open_time,
+            side: Side::Long,
+            index: 0,
+            price: position.price,
+            size_usd: position.size_usd,
+            borrow_size_usd: position.borrow_size_usd,
+            collateral_usd: position.collateral_usd,
+            unrealized_profit_usd: position.unrealized_profit_usd,
+            unrealized_loss_usd: position.unrealized_loss_usd,
+            cumulative_interest_snapshot: position.cumulative_interest_snapshot,
+            cumulative_funding_snapshot: 0,
+            locked_amount: position.locked_amount,
+            collateral_amount: position.collateral_amount,
+            token_mint: Pubkey::default(),
+            margin_account: Pubkey::default(),
+            trigger_orders: 0,
+            bump: position.bump,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_upgrade_position() {
+        let owner = Pubkey::new_unique();
+        let custody = Pubkey::new_unique();
+        let deprecated_position = DeprecatedPosition {
+            owner,
+            custody,
+            open_time: 100,
+            size_usd: 50_000,
+            collateral_usd: 5_000,
+            locked_amount: 25,
+            collateral_amount: 5,
+            ..DeprecatedPosition::default()
+        };
+
+        let position = Position::from(deprecated_position);
+        assert_eq!(position.owner, owner);
+        assert_eq!(position.collateral_custody, custody);
+        assert_eq!(position.side, Side::Long);
+        assert_eq!(position.index, 0);
+        assert_eq!(position.vesting_start_time, 100);
+        assert_eq!(position.size_usd, 50_000);
+        assert_eq!(position.locked_amount, 25);
+        assert!(!position.has_stable_collateral());
+        assert!(!position.is_tokenized());
+        assert!(!position.is_cross_margin());
+    }
 }
@@ -0,0 +1,103 @@
+use {
+    crate::{math, state::position::Side},
+    anchor_lang::prelude::*,
+};
+
+#[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Debug)]
+pub enum RequestType {
+    Open,
+    Close,
+}
+
+impl Default for RequestType {
+    fn default() -> Self {
+        Self::Open
+    }
+}
+
+#[account]
+#[derive(Default, Debug)]
+pub struct PositionRequest {
+    pub owner: Pubkey,
+    pub pool: Pubkey,
+    pub custody: Pubkey,
+    pub collateral_custody: Pubkey,
+
+    pub request_type: RequestType,
+    pub side: Side,
+    // index of the position to be opened or closed
+    pub position_index: u64,
+    // worst acceptable execution price
+    pub price: u64,
+    // size and collateral of the position to be opened, unused by close requests
+    pub size: u64,
+    pub collateral_amount: u64,
+    // escrowed on top of the collateral, unused part is refunded on execution
+    pub max_fee: u64,
+    pub create_time: i64,
+    // requests can only be executed until expiry and only be cancelled after it
+    pub expiry_time: i64,
+
+    pub bump: u8,
+    pub escrow_bump: u8,
+}
+
+impl PositionRequest {
+    pub const LEN: usize = 8 + std::mem::size_of::<PositionRequest>();
+
+    pub fn validate(&self, max_request_age_sec: u64) -> bool {
+        self.price > 0
+            && self.side != Side::None
+            && self.expiry_time > self.create_time
+            && (self.expiry_time - self.create_time) as u64 <= max_request_age_sec
+            && (self.request_type == RequestType::Close
+                || (self.size > 0 && self.collateral_amount > 0))
+    }
+
+    pub fn get_escrow_amount(&self) -> Result<u64> {
+        math::checked_add(self.collateral_amount, self.max_fee)
+    }
+
+    // execution must use an oracle price published after the request was made
+    pub fn is_executable(&self, publish_time: i64, curtime: i64) -> bool {
+        publish_time > self.create_time && curtime <= self.expiry_time
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let request = PositionRequest {
+            price: 1,
+            side: Side::Long,
+            request_type: RequestType::Close,
+            create_time: 100,
+            expiry_time: 160,
+            ..PositionRequest::default()
+        };
+        assert!(request.validate(60));
+        assert!(!request.validate(59));
+    }
+
+    #[test]
+    fn test_is_executable() {
+        let request = PositionRequest {
+            create_time: 100,
+            expiry_time: 160,
+            ..PositionRequest::default()
+        };
+
+        // oracle price known at request time
+        assert!(!request.is_executable(100, 110));
+        assert!(!request.is_executable(90, 110));
+
+        assert!(request.is_executable(101, 110));
+        assert!(request.is_executable(101, 160));
+
+        // expired
+        assert!(!request.is_executable(150, 161));
+    }
+}
@@ -0,0 +1,116 @@
+use {
+    crate::state::position::{Position, Side},
+    anchor_lang::prelude::*,
+};
+
+#[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Debug)]
+pub enum TriggerOrderType {
+    None,
+    TakeProfit,
+    StopLoss,
+}
+
+impl Default for TriggerOrderType {
+    fn default() -> Self {
+        Self::None
+    }
+}
+
+#[account]
+#[derive(Default, Debug)]
+pub struct TriggerOrder {
+    pub owner: Pubkey,
+    pub position: Pubkey,
+
+    pub order_type: TriggerOrderType,
+    pub index: u8,
+    pub trigger_price: u64,
+    // size to close, zero closes the whole position
+    pub size_usd: u64,
+    pub create_time: i64,
+
+    pub bump: u8,
+}
+
+impl TriggerOrder {
+    pub const LEN: usize = 8 + std::mem::size_of::<TriggerOrder>();
+
+    pub fn validate(&self) -> bool {
+        self.order_type != TriggerOrderType::None && self.trigger_price > 0
+    }
+
+    // returns true if the price (with PRICE_DECIMALS) has crossed the trigger price
+    pub fn is_triggered(&self, position: &Position, price: u64) -> bool {
+        // take profit triggers when the price moves in favor of the position,
+        // stop loss when it moves against it
+        let price_rises_to_trigger =
+            (self.order_type == TriggerOrderType::TakeProfit) == (position.side == Side::Long);
+
+        if price_rises_to_trigger {
+            price >= self.trigger_price
+        } else {
+            price <= self.trigger_price
+        }
+    }
+
+    // returns size of the position to close, full size if order size is zero or exceeds it
+    pub fn get_close_size_usd(&self, position: &Position) -> u64 {
+        if self.size_usd == 0 || self.size_usd >= position.size_usd {
+            position.size_usd
+        } else {
+            self.size_usd
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn get_order(order_type: TriggerOrderType) -> TriggerOrder {
+        TriggerOrder {
+            order_type,
+            trigger_price: 25_000,
+            ..TriggerOrder::default()
+        }
+    }
+
+    #[test]
+    fn test_is_triggered() {
+        let long = Position {
+            side: Side::Long,
+            ..Position::default()
+        };
+        let short = Position {
+            side: Side::Short,
+            ..Position::default()
+        };
+
+        let take_profit = get_order(TriggerOrderType::TakeProfit);
+        assert!(take_profit.is_triggered(&long, 25_000));
+        assert!(take_profit.is_triggered(&long, 26_000));
+        assert!(!take_profit.is_triggered(&long, 24_000));
+        assert!(take_profit.is_triggered(&short, 24_000));
+        assert!(!take_profit.is_triggered(&short, 26_000));
+
+        let stop_loss = get_order(TriggerOrderType::StopLoss);
+        assert!(stop_loss.is_triggered(&long, 24_000));
+        assert!(!stop_loss.is_triggered(&long, 26_000));
+        assert!(stop_loss.is_triggered(&short, 26_000));
+        assert!(!stop_loss.is_triggered(&short, 24_000));
+    }
+
+    #[test]
+    fn test_get_close_size_usd() {
+        let position = Position {
+            size_usd: 1_000,
+            ..Position::default()
+        };
+        let mut order = get_order(TriggerOrderType::TakeProfit);
+        assert_eq!(order.get_close_size_usd(&position), 1_000);
+        order.size_usd = 400;
+        assert_eq!(order.get_close_size_usd(&position), 400);
+        order.size_usd = 2_000;
+        assert_eq!(order.get_close_size_usd(&position), 1_000);
+    }
+}
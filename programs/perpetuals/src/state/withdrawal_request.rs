@@ -0,0 +1,80 @@
+use anchor_lang::prelude::*;
+
+#[account]
+#[derive(Default, Debug)]
+pub struct WithdrawalRequest {
+    pub owner: Pubkey,
+    pub pool: Pubkey,
+    // custody of the token paid out on execution, default for proportional
+    // withdrawals paid out in every pool custody
+    pub custody: Pubkey,
+    // escrowed until the request is executed or cancelled
+    pub lp_amount: u64,
+    // minimum value in USD for proportional withdrawals
+    pub min_amount_out: u64,
+    pub create_time: i64,
+    // end of the pool cooldown, the request is processed at the pool value from then on
+    pub execute_time: i64,
+    // end of the execution window, the request can only be cancelled from then on
+    pub expire_time: i64,
+
+    pub bump: u8,
+    pub escrow_bump: u8,
+}
+
+impl WithdrawalRequest {
+    pub const LEN: usize = 8 + std::mem::size_of::<WithdrawalRequest>();
+
+    pub fn validate(&self) -> bool {
+        self.lp_amount > 0
+            && self.execute_time >= self.create_time
+            && self.expire_time > self.execute_time
+    }
+
+    pub fn is_proportional(&self) -> bool {
+        self.custody == Pubkey::default()
+    }
+
+    pub fn is_executable(&self, curtime: i64) -> bool {
+        curtime >= self.execute_time && curtime < self.expire_time
+    }
+
+    pub fn is_expired(&self, curtime: i64) -> bool {
+        curtime >= self.expire_time
+    }
+
+    pub fn is_cancellable(&self, curtime: i64) -> bool {
+        curtime < self.execute_time || self.is_expired(curtime)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_is_executable() {
+        let request = WithdrawalRequest {
+            lp_amount: 1,
+            create_time: 100,
+            execute_time: 160,
+            expire_time: 220,
+            ..WithdrawalRequest::default()
+        };
+        assert!(request.validate());
+        assert!(request.is_proportional());
+
+        assert!(!request.is_executable(100));
+        assert!(!request.is_executable(159));
+        assert!(request.is_executable(160));
+        assert!(request.is_executable(219));
+        assert!(!request.is_executable(220));
+
+        assert!(request.is_cancellable(100));
+        assert!(request.is_cancellable(159));
+        assert!(!request.is_cancellable(160));
+        assert!(!request.is_cancellable(219));
+        assert!(request.is_cancellable(220));
+        assert!(request.is_expired(220));
+    }
+}
@@ -87,6 +87,7 @@ pub async fn test_add_custody(
                 transfer_authority: transfer_authority_pda,
                 perpetuals: perpetuals_pda,
                 pool: *pool_pda,
+                insurance_fund: pda::get_insurance_fund_pda(pool_pda).0,
                 custody: custody_pda,
                 custody_token_account: custody_token_account_pda,
                 custody_token_mint: *custody_token_mint,
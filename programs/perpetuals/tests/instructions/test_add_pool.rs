@@ -48,6 +48,7 @@ pub async fn test_add_pool(
                 perpetuals: perpetuals_pda,
                 pool: pool_pda,
                 lp_token_mint: lp_token_mint_pda,
+                insurance_fund: pda::get_insurance_fund_pda(&pool_pda).0,
                 system_program: anchor_lang::system_program::ID,
                 token_program: anchor_spl::token::ID,
                 rent: solana_program::sysvar::rent::ID,
@@ -70,6 +71,9 @@ pub async fn test_add_pool(
             perpetuals::instruction::AddPool {
                 params: AddPoolParams {
                     name: String::from_str(pool_name).unwrap(),
+                    lp_cooldown_sec: 0,
+                    lp_withdrawal_window_sec: 60,
+                    lp_expired_cancel_fee: 0,
                 },
             },
             Some(&payer.pubkey()),
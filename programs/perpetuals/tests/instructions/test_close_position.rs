@@ -42,15 +42,22 @@ pub async fn test_close_position(
     utils::create_and_execute_perpetuals_ix(
         program_test_ctx,
         perpetuals::accounts::ClosePosition {
+            authority: owner.pubkey(),
             owner: owner.pubkey(),
+            delegate_authority: None,
+            position_token_account: None,
             receiving_account: receiving_account_address,
+            margin_account: None,
             transfer_authority: transfer_authority_pda,
             perpetuals: perpetuals_pda,
             pool: *pool_pda,
+            insurance_fund: pda::get_insurance_fund_pda(pool_pda).0,
             position: *position_pda,
             custody: custody_pda,
             custody_oracle_account: custody_oracle_account_address,
-            custody_token_account: custody_token_account_pda,
+            collateral_custody: custody_pda,
+            collateral_custody_oracle_account: custody_oracle_account_address,
+            collateral_custody_token_account: custody_token_account_pda,
             token_program: anchor_spl::token::ID,
         }
         .to_account_metas(None),
@@ -66,6 +66,7 @@ pub async fn test_init(
     {
         let p = perpetuals_account.permissions;
 
+        assert_eq!(p.allow_swap, params.allow_swap);
         assert_eq!(p.allow_add_liquidity, params.allow_add_liquidity);
         assert_eq!(p.allow_remove_liquidity, params.allow_remove_liquidity);
         assert_eq!(p.allow_open_position, params.allow_open_position);
@@ -76,6 +77,7 @@ pub async fn test_init(
             params.allow_collateral_withdrawal
         );
         assert_eq!(p.allow_size_change, params.allow_size_change);
+        assert_eq!(p.allow_direct_position, params.allow_direct_position);
     }
 
     assert_eq!(
@@ -56,13 +56,18 @@ pub async fn test_liquidate(
             signer: liquidator.pubkey(),
             rewards_receiving_account: rewards_receiving_account_address,
             receiving_account: receiving_account_address,
+            position_token_account: None,
+            margin_account: None,
             transfer_authority: transfer_authority_pda,
             perpetuals: perpetuals_pda,
             pool: *pool_pda,
+            insurance_fund: pda::get_insurance_fund_pda(pool_pda).0,
             position: *position_pda,
             custody: custody_pda,
             custody_oracle_account: custody_oracle_account_address,
-            custody_token_account: custody_token_account_pda,
+            collateral_custody: custody_pda,
+            collateral_custody_oracle_account: custody_oracle_account_address,
+            collateral_custody_token_account: custody_token_account_pda,
             token_program: anchor_spl::token::ID,
         }
         .to_account_metas(None),
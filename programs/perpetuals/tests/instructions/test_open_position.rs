@@ -28,8 +28,13 @@ pub async fn test_open_position(
     let custody_token_account_pda =
         pda::get_custody_token_account_pda(pool_pda, custody_token_mint).0;
 
-    let (position_pda, position_bump) =
-        pda::get_position_pda(&owner.pubkey(), pool_pda, &custody_pda);
+    let (position_pda, position_bump) = pda::get_position_pda(
+        &owner.pubkey(),
+        pool_pda,
+        &custody_pda,
+        params.side,
+        params.index,
+    );
 
     let funding_account_address =
         utils::find_associated_token_account(&owner.pubkey(), custody_token_mint).0;
@@ -46,15 +51,21 @@ pub async fn test_open_position(
     utils::create_and_execute_perpetuals_ix(
         program_test_ctx,
         perpetuals::accounts::OpenPosition {
+            authority: owner.pubkey(),
             owner: owner.pubkey(),
+            delegate_authority: None,
+            margin_account: None,
             funding_account: funding_account_address,
             transfer_authority: transfer_authority_pda,
             perpetuals: perpetuals_pda,
             pool: *pool_pda,
+            insurance_fund: pda::get_insurance_fund_pda(pool_pda).0,
             position: position_pda,
             custody: custody_pda,
             custody_oracle_account: custody_oracle_account_address,
-            custody_token_account: custody_token_account_pda,
+            collateral_custody: custody_pda,
+            collateral_custody_oracle_account: custody_oracle_account_address,
+            collateral_custody_token_account: custody_token_account_pda,
             system_program: anchor_lang::system_program::ID,
             token_program: anchor_spl::token::ID,
         }
@@ -86,6 +97,9 @@ pub async fn test_open_position(
         assert_eq!(position_account.owner, owner.pubkey());
         assert_eq!(position_account.pool, *pool_pda);
         assert_eq!(position_account.custody, custody_pda);
+        assert_eq!(position_account.collateral_custody, custody_pda);
+        assert_eq!(position_account.side, params.side);
+        assert_eq!(position_account.index, params.index);
         // Need to handle test/not test case
         // assert_eq!(
         //     position_account.open_time,
@@ -30,6 +30,7 @@ pub async fn test_remove_liquidity(
     let custody_token_account_pda =
         pda::get_custody_token_account_pda(pool_pda, custody_token_mint).0;
     let lp_token_mint_pda = pda::get_lp_token_mint_pda(pool_pda).0;
+    let lp_account_pda = pda::get_lp_account_pda(&owner.pubkey(), pool_pda).0;
 
     let receiving_account_address =
         utils::find_associated_token_account(&owner.pubkey(), custody_token_mint).0;
@@ -59,6 +60,7 @@ pub async fn test_remove_liquidity(
             custody_oracle_account: custody_oracle_account_address,
             custody_token_account: custody_token_account_pda,
             lp_token_mint: lp_token_mint_pda,
+            lp_account: Some(lp_account_pda),
             token_program: anchor_spl::token::ID,
         };
 
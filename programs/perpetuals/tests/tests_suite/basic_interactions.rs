@@ -1,7 +1,10 @@
 use {
     crate::{instructions, utils},
     maplit::hashmap,
-    perpetuals::instructions::{ClosePositionParams, OpenPositionParams, RemoveLiquidityParams},
+    perpetuals::{
+        instructions::{ClosePositionParams, OpenPositionParams, RemoveLiquidityParams},
+        state::position::Side,
+    },
     solana_sdk::signer::Signer,
 };
 
@@ -97,6 +100,8 @@ async fn basic_interactions() {
                 price: utils::scale(1_550, PRICE_DECIMALS),
                 collateral: utils::scale_f64(0.1, ETH_DECIMALS),
                 size: utils::scale_f64(0.1, ETH_DECIMALS),
+                side: Side::Long,
+                index: 0,
             },
         )
         .await
@@ -3,7 +3,7 @@ use {
     maplit::hashmap,
     perpetuals::{
         instructions::{OpenPositionParams, SetCustomOraclePriceParams},
-        state::custody::PricingParams,
+        state::{custody::PricingParams, position::Side},
     },
     solana_sdk::signer::Signer,
 };
@@ -104,6 +104,8 @@ async fn position_liquidate() {
             price: utils::scale(1_550, ETH_DECIMALS),
             collateral: utils::scale(1, ETH_DECIMALS),
             size: utils::scale(5, ETH_DECIMALS),
+            side: Side::Long,
+            index: 0,
         },
     )
     .await
@@ -3,7 +3,7 @@ use {
     maplit::hashmap,
     perpetuals::{
         instructions::{ClosePositionParams, OpenPositionParams, SetCustomOraclePriceParams},
-        state::custody::PricingParams,
+        state::{custody::PricingParams, position::Side},
     },
     solana_sdk::signer::Signer,
 };
@@ -98,6 +98,8 @@ async fn position_max_user_profit() {
             price: utils::scale(1_550, ETH_DECIMALS),
             collateral: utils::scale(1, ETH_DECIMALS),
             size: utils::scale(5, ETH_DECIMALS),
+            side: Side::Long,
+            index: 0,
         },
     )
     .await
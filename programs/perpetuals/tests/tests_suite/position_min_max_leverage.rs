@@ -1,7 +1,10 @@
 use {
     crate::{instructions, utils},
     maplit::hashmap,
-    perpetuals::{instructions::OpenPositionParams, state::custody::PricingParams},
+    perpetuals::{
+        instructions::OpenPositionParams,
+        state::{custody::PricingParams, position::Side},
+    },
 };
 
 const ETH_DECIMALS: u8 = 9;
@@ -93,6 +96,8 @@ async fn position_min_max_leverage() {
             price: utils::scale(1_550, ETH_DECIMALS),
             collateral: utils::scale(1, ETH_DECIMALS),
             size: utils::scale(10, ETH_DECIMALS),
+            side: Side::Long,
+            index: 0,
         },
     )
     .await
@@ -110,6 +115,8 @@ async fn position_min_max_leverage() {
             price: utils::scale(1_550, ETH_DECIMALS),
             collateral: utils::scale(1, ETH_DECIMALS),
             size: utils::scale_f64(0.5, ETH_DECIMALS),
+            side: Side::Long,
+            index: 0,
         },
     )
     .await
@@ -14,6 +14,7 @@ use {
 
 pub fn permissions_full() -> Permissions {
     Permissions {
+        allow_swap: true,
         allow_add_liquidity: true,
         allow_remove_liquidity: true,
         allow_open_position: true,
@@ -21,6 +22,7 @@ pub fn permissions_full() -> Permissions {
         allow_pnl_withdrawal: true,
         allow_collateral_withdrawal: true,
         allow_size_change: true,
+        allow_direct_position: true,
     }
 }
 
@@ -30,18 +32,24 @@ pub fn borrow_rate_regular() -> BorrowRateParams {
         slope1: 80_000,
         slope2: 120_000,
         optimal_utilization: 800_000_000,
+        max_funding_rate: 0,
     }
 }
 
 pub fn fees_linear_regular() -> Fees {
     Fees {
         utilization_mult: 20_000,
+        swap_in: 100,
+        swap_out: 100,
         add_liquidity: 200,
         remove_liquidity: 300,
         open_position: 100,
         close_position: 100,
+        close_position_profit_share: 100,
         liquidation: 50,
+        trigger_order: 10,
         protocol_share: 25,
+        insurance_share: 0,
     }
 }
 
@@ -50,13 +58,21 @@ pub fn pricing_params_regular() -> PricingParams {
         use_unrealized_pnl_in_aum: true,
         trade_spread_long: 100,
         trade_spread_short: 100,
+        swap_spread: 200,
+        max_price_impact: 0,
         min_initial_leverage: 10_000,
         max_initial_leverage: 100_000,
         max_leverage: 100_000,
+        leverage_tiers: Default::default(),
+        liquidation_target_leverage: 0,
         max_payoff_mult: 10_000,
         max_utilization: 0,
         max_position_locked_usd: 0,
         max_total_locked_usd: 0,
+        virtual_depth_usd: 0,
+        auto_deleverage_threshold: 0,
+        min_hold_time_sec: 0,
+        max_request_age_sec: 60,
     }
 }
 
@@ -73,6 +89,7 @@ pub fn oracle_params_regular(oracle_account: Pubkey) -> OracleParams {
 pub fn init_params_permissions_full(min_signatures: u8) -> InitParams {
     InitParams {
         min_signatures,
+        allow_swap: true,
         allow_add_liquidity: true,
         allow_remove_liquidity: true,
         allow_open_position: true,
@@ -80,5 +97,6 @@ pub fn init_params_permissions_full(min_signatures: u8) -> InitParams {
         allow_pnl_withdrawal: true,
         allow_collateral_withdrawal: true,
         allow_size_change: true,
+        allow_direct_position: true,
     }
 }
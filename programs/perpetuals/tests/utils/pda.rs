@@ -30,6 +30,20 @@ pub fn get_lp_token_mint_pda(pool_pda: &Pubkey) -> (Pubkey, u8) {
     )
 }
 
+pub fn get_insurance_fund_pda(pool_pda: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &["insurance_fund".as_ref(), pool_pda.as_ref()],
+        &perpetuals::id(),
+    )
+}
+
+pub fn get_lp_account_pda(owner: &Pubkey, pool_pda: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &["lp_account".as_ref(), owner.as_ref(), pool_pda.as_ref()],
+        &perpetuals::id(),
+    )
+}
+
 pub fn get_custody_pda(pool_pda: &Pubkey, custody_token_mint: &Pubkey) -> (Pubkey, u8) {
     Pubkey::find_program_address(
         &[
@@ -41,14 +55,21 @@ pub fn get_custody_pda(pool_pda: &Pubkey, custody_token_mint: &Pubkey) -> (Pubke
     )
 }
 
-pub fn get_position_pda(owner: &Pubkey, pool_pda: &Pubkey, custody_pda: &Pubkey) -> (Pubkey, u8) {
+pub fn get_position_pda(
+    owner: &Pubkey,
+    pool_pda: &Pubkey,
+    custody_pda: &Pubkey,
+    side: Side,
+    index: u64,
+) -> (Pubkey, u8) {
     Pubkey::find_program_address(
         &[
             "position".as_ref(),
             owner.as_ref(),
             pool_pda.as_ref(),
             custody_pda.as_ref(),
-            &[Side::Long as u8],
+            &[side as u8],
+            &index.to_le_bytes(),
         ],
         &perpetuals::id(),
     )
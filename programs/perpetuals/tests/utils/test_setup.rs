@@ -246,7 +246,7 @@ impl TestSetup {
         let custodies_info: Vec<SetupCustodyInfo> = {
             let mut custodies_info: Vec<SetupCustodyInfo> = Vec::new();
 
-            for (_idx, custody_param) in custodies_params.iter().enumerate() {
+            for (idx, custody_param) in custodies_params.iter().enumerate() {
                 let mint_info = mints
                     .get(&custody_param.setup_custody_params.mint_name.to_string())
                     .unwrap();
@@ -256,6 +256,7 @@ impl TestSetup {
 
                 let custody_pda = {
                     let add_custody_params = AddCustodyParams {
+                        is_stable: false,
                         oracle: fixtures::oracle_params_regular(custom_oracle_pda),
                         pricing: custody_param
                             .setup_custody_params